@@ -4,22 +4,45 @@
 
 //! Main application
 
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::TryLockError;
 use std::future::Future;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 
-use log::{error, info};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{Local, NaiveDateTime, TimeDelta, Utc};
+use log::{error, info, warn};
+use tokio::sync::RwLock;
 use tokio::sync::mpsc::{Receiver, Sender, channel};
 
-use crate::model::auth::{Authentication, RegistryUserToken, RegistryUserTokenWithSecret};
+use crate::model::attestation::{AttestationEnvelope, AttestationPublicKey, AttestationStatement, PublishPredicate};
+use crate::model::audit::{AUDIT_ACTOR_SCIM, AUDIT_DEFAULT_LIMIT, AuditEvent, AuditQuery};
+use crate::model::auth::{
+    Authentication, AuthenticationPrincipal, OAuthLoginState, RegistryUserPublicKey, RegistryUserToken,
+    RegistryUserTokenWithSecret, TokenOperation, TokenScopes, TrustedPublisher, TrustedPublisherSpec, TrustedPublishingToken,
+    check_token_expiry,
+};
 use crate::model::cargo::{
-    CrateUploadData, CrateUploadResult, OwnersQueryResult, RegistryUser, SearchResults, YesNoMsgResult, YesNoResult,
+    CrateUploadData, CrateUploadResult, IndexCrateMetadata, OwnersQueryResult, RegistryUser, SearchResults, YesNoMsgResult,
+    YesNoResult,
 };
 use crate::model::config::Configuration;
 use crate::model::deps::DepsAnalysis;
+use crate::model::diff::{CrateVersionsDiff, DIFF_MAX_FILES, DIFF_MAX_TOTAL_SIZE};
 use crate::model::docs::{DocGenEvent, DocGenJob, DocGenJobSpec, DocGenTrigger};
-use crate::model::packages::{CrateInfo, CrateInfoTarget};
+use crate::model::index::IndexCheckReport;
+use crate::model::names::{CrateNamePolicy, CrateNamePolicySpec};
+use crate::model::packages::{
+    CrateDeprecation, CrateFile, CrateInfo, CrateInfoTarget, CratePendingVersion, CrateReaders, CrateVisibility,
+};
+use crate::model::scim::{ScimGroup, ScimListQuery, ScimListResponse, ScimPatchRequest, ScimUser, parse_scim_id};
+use crate::model::semver_checks::{SemverCheckJob, SemverCheckJobState};
 use crate::model::stats::{DownloadStats, GlobalStats};
+use crate::model::teams::Team;
 use crate::model::worker::{WorkerEvent, WorkerPublicData, WorkersManager};
 use crate::model::{AppEvent, CrateVersion, RegistryInformation};
 use crate::services::ServiceProvider;
@@ -27,12 +50,25 @@ use crate::services::database::{Database, db_transaction_read, db_transaction_wr
 use crate::services::deps::DepsChecker;
 use crate::services::docs::DocsGenerator;
 use crate::services::emails::EmailSender;
-use crate::services::index::Index;
+use crate::services::index::{self, Index};
+use crate::services::policy::PublishPolicy;
 use crate::services::rustsec::RustSecChecker;
-use crate::services::storage::Storage;
-use crate::utils::apierror::{ApiError, error_forbidden, error_invalid_request, error_unauthorized, specialize};
+use crate::services::semver_checks::SemverChecker;
+use crate::services::storage::{
+    Storage, extract_crate_file, extract_crate_files, get_crate_file_content_type, list_crate_files,
+};
+use crate::utils::apierror::{
+    ApiError, error_conflict, error_forbidden, error_invalid_request, error_not_found, error_too_many_requests,
+    error_unauthorized, specialize,
+};
+use crate::utils::attestation::AttestationSigner;
 use crate::utils::axum::auth::{AuthData, Token};
 use crate::utils::db::RwSqlitePool;
+use crate::utils::hashes::sha256;
+use crate::utils::jwt::{JwksCache, Jwt, check_registered_claims};
+use crate::utils::paseto::{PasetoMutation, PasetoToken};
+use crate::utils::ratelimit::RateLimiter;
+use crate::utils::token::{check_hash, hash_token};
 
 /// The state of this application for axum
 pub struct Application {
@@ -54,50 +90,121 @@ pub struct Application {
     service_email_sender: Arc<dyn EmailSender + Send + Sync>,
     /// The service to generator documentation
     service_docs_generator: Arc<dyn DocsGenerator + Send + Sync>,
+    /// The service to check crates against the policies of the organisation
+    service_publish_policy: Arc<dyn PublishPolicy + Send + Sync>,
+    /// The service to check the semver compatibility of new versions
+    service_semver_checker: Arc<dyn SemverChecker + Send + Sync>,
+    /// The signer of the attestations for published crates, if enabled
+    attestation_signer: Option<AttestationSigner>,
     /// Sender to use to notify about events that will be asynchronously handled
     app_events_sender: Sender<AppEvent>,
     /// The connected worker nodes
     pub worker_nodes: WorkersManager,
+    /// The limiter for failed authentications, per client IP and per login
+    pub auth_failures_limiter: RateLimiter,
+    /// The limiter for requests to expensive endpoints, per client IP and per login
+    pub requests_limiter: RateLimiter,
+    /// The cache of the JWKS for the issuers of trusted publishers
+    jwks_cache: JwksCache,
+    /// Serializes the checks of the index with its changes, shared by the changes and held exclusively by the checks
+    index_lock: RwLock<()>,
+    /// The file locked for as long as this application uses the index, so that the command line cannot change it
+    #[expect(dead_code)]
+    index_lock_file: std::fs::File,
 }
 
 /// The empty database
 const DB_EMPTY: &[u8] = include_bytes!("empty.db");
 
+/// How a version came to be published, to roll back its publication when it fails
+#[derive(Debug, Clone, Copy)]
+enum PublishOrigin {
+    /// A version uploaded and published directly
+    Upload,
+    /// A version uploaded and held back until approved
+    Staged,
+    /// A version published after being approved
+    Approval,
+}
+
+/// Gets whether a crate is in the list of the hidden crates
+fn is_hidden_crate(hidden: &[String], package: &str) -> bool {
+    hidden.iter().any(|name| name.eq_ignore_ascii_case(package))
+}
+
+/// Locks the index for this process, so that the registry and the command line do not change it concurrently
+/// The lock is released when the returned file is closed, at the latest when the process exits
+fn lock_index(configuration: &Configuration) -> Result<std::fs::File, ApiError> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(configuration.get_index_lock_filename())?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(specialize(
+            error_conflict(),
+            String::from("the index is in use by another process, for example a running registry"),
+        )),
+        Err(TryLockError::Error(error)) => Err(error.into()),
+    }
+}
+
+/// Opens the connection pool to the database, creating and migrating it if appropriate
+async fn open_database(configuration: &Configuration) -> Result<RwSqlitePool, ApiError> {
+    let db_filename = configuration.get_database_filename();
+    if tokio::fs::metadata(&db_filename).await.is_err() {
+        // write the file
+        info!("db file is inaccessible => attempt to create an empty one");
+        tokio::fs::write(&db_filename, DB_EMPTY).await?;
+    }
+    let service_db_pool = RwSqlitePool::new(&configuration.get_database_url())?;
+    // migrate the database, if appropriate
+    db_transaction_write(&service_db_pool, "migrate_to_last", |database| async move {
+        crate::migrations::migrate_to_last(database.transaction).await
+    })
+    .await?;
+    Ok(service_db_pool)
+}
+
 impl Application {
     /// Creates a new application
-    pub async fn launch<P: ServiceProvider>(configuration: Configuration) -> Result<Arc<Self>, ApiError> {
+    pub async fn launch<P: ServiceProvider>(mut configuration: Configuration) -> Result<Arc<Self>, ApiError> {
         // load configuration
+        configuration.discover_oauth_endpoints().await?;
         let configuration = Arc::new(configuration);
 
-        // connection pool to the database
-        let db_filename = configuration.get_database_filename();
-        if tokio::fs::metadata(&db_filename).await.is_err() {
-            // write the file
-            info!("db file is inaccessible => attempt to create an empty one");
-            tokio::fs::write(&db_filename, DB_EMPTY).await?;
-        }
-        let service_db_pool = RwSqlitePool::new(&configuration.get_database_url())?;
-        // migrate the database, if appropriate
-        db_transaction_write(&service_db_pool, "migrate_to_last", |database| async move {
-            crate::migrations::migrate_to_last(database.transaction).await
-        })
-        .await?;
+        let service_db_pool = open_database(&configuration).await?;
+        let index_lock_file = lock_index(&configuration)?;
 
         let worker_nodes = WorkersManager::default();
 
         let db_is_empty =
             db_transaction_read(&service_db_pool, |database| async move { database.get_is_empty().await }).await?;
         let service_storage = P::get_storage(&configuration.deref().clone());
-        let service_index = P::get_index(&configuration, db_is_empty).await?;
+        let service_index = P::get_index(&configuration, db_is_empty, service_db_pool.clone(), service_storage.clone()).await?;
         let service_rustsec = P::get_rustsec(&configuration);
         let service_deps_checker = P::get_deps_checker(configuration.clone(), service_index.clone(), service_rustsec.clone());
         let service_email_sender = P::get_email_sender(configuration.clone());
+        let service_publish_policy = P::get_publish_policy(configuration.clone());
         let service_docs_generator = P::get_docs_generator(
             configuration.clone(),
             service_db_pool.clone(),
             service_storage.clone(),
             worker_nodes.clone(),
         );
+        let service_semver_checker = P::get_semver_checker(
+            configuration.clone(),
+            service_db_pool.clone(),
+            service_storage.clone(),
+            service_email_sender.clone(),
+            worker_nodes.clone(),
+        );
+
+        let attestation_signer = match &configuration.attestation_key_file {
+            Some(path) => Some(AttestationSigner::load(std::path::Path::new(path)).await?),
+            None => None,
+        };
 
         // check undocumented packages
         let default_target = &configuration.self_toolchain_host;
@@ -127,9 +234,24 @@ impl Application {
             service_email_sender.clone(),
             service_db_pool.clone(),
         );
+        // tokens expiration worker
+        crate::services::tokens::create_tokens_worker(
+            configuration.clone(),
+            service_email_sender.clone(),
+            service_db_pool.clone(),
+        );
 
         let (app_events_sender, app_events_receiver) = channel(64);
 
+        let auth_failures_limiter = RateLimiter::new(
+            configuration.rate_limit_auth_failures,
+            Duration::from_secs(configuration.rate_limit_auth_window),
+        );
+        let requests_limiter = RateLimiter::new(
+            configuration.rate_limit_requests,
+            Duration::from_secs(configuration.rate_limit_requests_window),
+        );
+
         let this = Arc::new(Self {
             configuration,
             service_db_pool,
@@ -139,10 +261,21 @@ impl Application {
             service_deps_checker,
             service_email_sender,
             service_docs_generator,
+            service_publish_policy,
+            service_semver_checker,
+            attestation_signer,
             app_events_sender,
             worker_nodes,
+            auth_failures_limiter,
+            requests_limiter,
+            jwks_cache: JwksCache::default(),
+            index_lock: RwLock::new(()),
+            index_lock_file,
         });
 
+        // complete or roll back the publications interrupted by the last shutdown
+        this.resume_crate_version_publishes().await?;
+
         let _handle = {
             let app = this.clone();
             tokio::spawn(async move {
@@ -153,6 +286,30 @@ impl Application {
         Ok(this)
     }
 
+    /// Checks the consistency of the index for the command line, and optionally rebuilds it
+    /// Only the services required for the check are created, no worker is started and no publication is resumed
+    /// The check is refused while a registry uses the same index, and a rebuilt index is pushed before returning
+    pub async fn check_index_standalone<P: ServiceProvider>(
+        configuration: &Configuration,
+        rebuild: bool,
+    ) -> Result<IndexCheckReport, ApiError> {
+        let service_db_pool = open_database(configuration).await?;
+        let _index_lock_file = lock_index(configuration)?;
+        let db_is_empty =
+            db_transaction_read(&service_db_pool, |database| async move { database.get_is_empty().await }).await?;
+        let service_storage = P::get_storage(configuration);
+        let service_index = P::get_index(configuration, db_is_empty, service_db_pool.clone(), service_storage.clone()).await?;
+        let mut report =
+            index::check_consistency(&service_db_pool, service_storage.as_ref(), service_index.as_ref(), rebuild).await?;
+        if report.rebuilt {
+            // no registry is running to push the rebuilt index later
+            if let Err(error) = service_index.wait_for_pushes().await {
+                report.push_error = Some(error.details.unwrap_or(error.message));
+            }
+        }
+        Ok(report)
+    }
+
     /// Gets the storage service
     #[must_use]
     pub fn get_service_storage(&self) -> Arc<dyn Storage + Send + Sync> {
@@ -254,15 +411,70 @@ impl Application {
             .await
     }
 
+    /// Checks that a user can read a crate, including its documentation
+    pub async fn check_can_read_crate(&self, auth_data: &AuthData, package: &str) -> Result<(), ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(Some(&authentication), package).await
+        })
+        .await
+    }
+
+    /// Checks that a user can read a file in the index
+    /// The files for crates that cannot be read are reported as not found
+    pub async fn check_can_read_index_file(&self, auth_data: &AuthData, path: &str) -> Result<(), ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate_for_read(auth_data).await?;
+            if path == "/config.json" {
+                return Ok(());
+            }
+            let package = path.rsplit('/').next().unwrap_or_default();
+            app.check_can_read_crate(authentication.as_ref(), package).await
+        })
+        .await
+    }
+
+    /// Checks that the git index can be read
+    /// The git index cannot be filtered, so that it is refused when some restricted crates cannot be read
+    pub async fn check_can_read_git_index(&self, auth_data: &AuthData) -> Result<(), ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate_for_read(auth_data).await?;
+            if app.get_hidden_crates(authentication.as_ref()).await?.is_empty() {
+                Ok(())
+            } else {
+                Err(specialize(
+                    error_forbidden(),
+                    String::from("the git index is not available for this authentication, use the sparse index"),
+                ))
+            }
+        })
+        .await
+    }
+
     /// Gets the registry configuration
     pub async fn get_registry_information(&self, auth_data: &AuthData) -> Result<RegistryInformation, ApiError> {
-        let _authentication = self.authenticate(auth_data).await?;
+        let can_admin_registry = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                // the status of the index is only shown to the administrators of the registry
+                Ok::<_, ApiError>(
+                    authentication.can_admin
+                        && authentication.scopes.is_unrestricted()
+                        && app.database.get_is_admin(authentication.uid()?).await?,
+                )
+            })
+            .await?;
         Ok(RegistryInformation {
             registry_name: self.configuration.self_local_name.clone(),
             toolchain_host: self.configuration.self_toolchain_host.clone(),
             toolchain_version_stable: self.configuration.self_toolchain_version_stable.clone(),
             toolchain_version_nightly: self.configuration.self_toolchain_version_nightly.clone(),
             toolchain_targets: self.configuration.self_known_targets.clone(),
+            index_push: if can_admin_registry {
+                self.service_index.get_push_status()
+            } else {
+                None
+            },
         })
     }
 
@@ -286,6 +498,40 @@ impl Application {
         Ok(receiver)
     }
 
+    /// Checks the consistency between the database, the index and the storage, and optionally rebuilds the index
+    pub async fn check_index(&self, auth_data: &AuthData, rebuild: bool) -> Result<IndexCheckReport, ApiError> {
+        let authentication = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                app.check_can_admin_registry(&authentication).await?;
+                Ok::<_, ApiError>(authentication)
+            })
+            .await?;
+        // no change to the index until the check is complete
+        let _index_guard = self.index_lock.write().await;
+        let report = index::check_consistency(
+            &self.service_db_pool,
+            self.service_storage.as_ref(),
+            self.service_index.as_ref(),
+            rebuild,
+        )
+        .await?;
+        if rebuild {
+            let details = format!(
+                "versions: {}, issues: {}, unresolved: {}",
+                report.versions,
+                report.issues.len(),
+                report.unresolved.len()
+            );
+            self.db_transaction_write("check_index", |app| async move {
+                app.audit(auth_data, &authentication, "rebuild_index", "index", Some(details))
+                    .await
+            })
+            .await?;
+        }
+        Ok(report)
+    }
+
     /// Gets the data about the current user
     pub async fn get_current_user(&self, auth_data: &AuthData) -> Result<RegistryUser, ApiError> {
         self.db_transaction_read(|app| async move {
@@ -295,12 +541,89 @@ impl Application {
         .await
     }
 
+    /// Checks that the client is not locked out after too many failed authentications
+    fn check_auth_failures(&self, auth_data: &AuthData) -> Result<(), ApiError> {
+        self.auth_failures_limiter
+            .check(&auth_data.auth_failure_keys())
+            .map_err(|retry_after| {
+                specialize(
+                    error_too_many_requests(),
+                    format!(
+                        "too many failed authentications, retry in {} seconds",
+                        retry_after.as_secs() + 1
+                    ),
+                )
+            })
+    }
+
+    /// Records a failed authentication for the client
+    fn record_auth_failure(&self, auth_data: &AuthData) {
+        self.auth_failures_limiter.record(&auth_data.auth_failure_keys());
+    }
+
+    /// Checks and records a request of the client to an expensive operation
+    fn check_rate_limit(&self, auth_data: &AuthData) -> Result<(), ApiError> {
+        self.requests_limiter
+            .hit(&auth_data.rate_limit_keys())
+            .map_err(|retry_after| {
+                specialize(
+                    error_too_many_requests(),
+                    format!("too many requests, retry in {} seconds", retry_after.as_secs() + 1),
+                )
+            })
+    }
+
+    /// Checks and records a request of an authenticated user to an expensive operation
+    fn check_user_rate_limit(&self, authentication: &Authentication) -> Result<(), ApiError> {
+        self.requests_limiter
+            .hit(&[format!("user:{}", authentication.actor())])
+            .map_err(|retry_after| {
+                specialize(
+                    error_too_many_requests(),
+                    format!("too many requests, retry in {} seconds", retry_after.as_secs() + 1),
+                )
+            })
+    }
+
+    /// Gets the duration after which a client that reached a limit can retry, if any
+    #[must_use]
+    pub fn get_retry_after(&self, auth_data: &AuthData) -> Option<Duration> {
+        let auth_failures = self.auth_failures_limiter.check(&auth_data.auth_failure_keys()).err();
+        let requests = self.requests_limiter.check(&auth_data.rate_limit_keys()).err();
+        auth_failures.max(requests)
+    }
+
     /// Attempts to login using an OAuth code
-    pub async fn login_with_oauth_code(&self, code: &str) -> Result<RegistryUser, ApiError> {
-        self.db_transaction_write("login_with_oauth_code", |app| async move {
-            app.database.login_with_oauth_code(&self.configuration, code).await
-        })
-        .await
+    pub async fn login_with_oauth_code(
+        &self,
+        auth_data: &AuthData,
+        code: &str,
+        login_state: &OAuthLoginState,
+    ) -> Result<RegistryUser, ApiError> {
+        self.check_auth_failures(auth_data)?;
+        self.check_rate_limit(auth_data)?;
+        let result: Result<RegistryUser, ApiError> = self
+            .db_transaction_write("login_with_oauth_code", |app| async move {
+                let user = app
+                    .database
+                    .login_with_oauth_code(&self.configuration, code, login_state)
+                    .await?;
+                app.audit_as(
+                    auth_data,
+                    user.email.clone(),
+                    None,
+                    "login_with_oauth_code",
+                    &user.email,
+                    None,
+                )
+                .await?;
+                Ok(user)
+            })
+            .await;
+        if result.as_ref().is_err_and(|error| error.http == 401) {
+            self.record_auth_failure(auth_data);
+        }
+        result
     }
 
     /// Gets the known users
@@ -320,13 +643,24 @@ impl Application {
             let principal_uid = authentication.uid()?;
             let can_admin = if target.id == principal_uid {
                 // same user
-                authentication.can_admin && app.database.get_is_admin(principal_uid).await?
+                authentication.can_admin
+                    && authentication.scopes.is_unrestricted()
+                    && app.database.get_is_admin(principal_uid).await?
             } else {
                 // different users, requires admin
                 app.check_can_admin_registry(&authentication).await?;
                 true
             };
-            app.database.update_user(principal_uid, target, can_admin).await
+            let user = app.database.update_user(Some(principal_uid), target, can_admin).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "update_user",
+                &user.email,
+                Some(format!("name: {}, roles: {}", user.name, user.roles)),
+            )
+            .await?;
+            Ok(user)
         })
         .await
     }
@@ -336,7 +670,8 @@ impl Application {
         self.db_transaction_write("deactivate_user", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
             let principal_uid = app.check_can_admin_registry(&authentication).await?;
-            app.database.deactivate_user(principal_uid, target).await
+            app.database.deactivate_user(Some(principal_uid), target).await?;
+            app.audit(auth_data, &authentication, "deactivate_user", target, None).await
         })
         .await
     }
@@ -346,7 +681,8 @@ impl Application {
         self.db_transaction_write("reactivate_user", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
             app.check_can_admin_registry(&authentication).await?;
-            app.database.reactivate_user(target).await
+            app.database.reactivate_user(target).await?;
+            app.audit(auth_data, &authentication, "reactivate_user", target, None).await
         })
         .await
     }
@@ -356,7 +692,8 @@ impl Application {
         self.db_transaction_write("delete_user", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
             let principal_uid = app.check_can_admin_registry(&authentication).await?;
-            app.database.delete_user(principal_uid, target).await
+            app.database.delete_user(Some(principal_uid), target).await?;
+            app.audit(auth_data, &authentication, "delete_user", target, None).await
         })
         .await
     }
@@ -378,13 +715,28 @@ impl Application {
         name: &str,
         can_write: bool,
         can_admin: bool,
+        scopes: TokenScopes,
+        expires_on: Option<NaiveDateTime>,
     ) -> Result<RegistryUserTokenWithSecret, ApiError> {
         self.db_transaction_write("create_token", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
             authentication.check_can_admin()?;
-            app.database
-                .create_token(authentication.uid()?, name, can_write, can_admin)
-                .await
+            // a scoped token shall not be used to create a token that would escape its scopes
+            authentication.scopes.check_is_unrestricted()?;
+            check_token_expiry(expires_on, self.configuration.tokens_max_validity)?;
+            let token = app
+                .database
+                .create_token(authentication.uid()?, name, can_write, can_admin, scopes, expires_on)
+                .await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "create_token",
+                name,
+                Some(format!("id: {}, canWrite: {can_write}, canAdmin: {can_admin}", token.id)),
+            )
+            .await?;
+            Ok(token)
         })
         .await
     }
@@ -394,220 +746,1386 @@ impl Application {
         self.db_transaction_write("revoke_token", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
             authentication.check_can_admin()?;
-            app.database.revoke_token(authentication.uid()?, token_id).await
+            app.database.revoke_token(authentication.uid()?, token_id).await?;
+            app.audit(auth_data, &authentication, "revoke_token", &token_id.to_string(), None)
+                .await
         })
         .await
     }
 
-    /// Gets the global tokens for the registry, usually for CI purposes
-    pub async fn get_global_tokens(&self, auth_data: &AuthData) -> Result<Vec<RegistryUserToken>, ApiError> {
+    /// Gets the public keys registered by a user for asymmetric tokens
+    pub async fn get_public_keys(&self, auth_data: &AuthData) -> Result<Vec<RegistryUserPublicKey>, ApiError> {
         self.db_transaction_read(|app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_admin_registry(&authentication).await?;
-            app.database.get_global_tokens().await
+            authentication.check_can_admin()?;
+            app.database.get_public_keys(authentication.uid()?).await
         })
         .await
     }
 
-    /// Creates a global token for the registry
-    pub async fn create_global_token(&self, auth_data: &AuthData, name: &str) -> Result<RegistryUserTokenWithSecret, ApiError> {
-        self.db_transaction_write("create_global_token", |app| async move {
+    /// Registers a public key for asymmetric tokens for the current user
+    pub async fn add_public_key(
+        &self,
+        auth_data: &AuthData,
+        name: &str,
+        public_key: &str,
+    ) -> Result<RegistryUserPublicKey, ApiError> {
+        self.db_transaction_write("add_public_key", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_admin_registry(&authentication).await?;
-            app.database.create_global_token(name).await
+            authentication.check_can_admin()?;
+            authentication.scopes.check_is_unrestricted()?;
+            let key = app.database.add_public_key(authentication.uid()?, name, public_key).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "add_public_key",
+                name,
+                Some(format!("id: {}, keyId: {}", key.id, key.key_id)),
+            )
+            .await?;
+            Ok(key)
         })
         .await
     }
 
-    /// Revokes a global token for the registry
-    pub async fn revoke_global_token(&self, auth_data: &AuthData, token_id: i64) -> Result<(), ApiError> {
-        self.db_transaction_write("revoke_global_token", |app| async move {
+    /// Removes a public key for the current user
+    pub async fn remove_public_key(&self, auth_data: &AuthData, key_id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("remove_public_key", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_admin_registry(&authentication).await?;
-            app.database.revoke_global_token(token_id).await
+            authentication.check_can_admin()?;
+            app.database.remove_public_key(authentication.uid()?, key_id).await?;
+            app.audit(auth_data, &authentication, "remove_public_key", &key_id.to_string(), None)
+                .await
         })
         .await
     }
 
-    /// Publish a crate
-    pub async fn publish_crate_version(&self, auth_data: &AuthData, content: &[u8]) -> Result<CrateUploadResult, ApiError> {
-        // deserialize payload
-        let package = CrateUploadData::new(content)?;
-        let index_data = package.build_index_data();
-
-        let (user, result, targets, capabilities) = {
-            let package = &package;
-            self.db_transaction_write("publish_crate_version", |app| async move {
-                let authentication = app.authenticate(auth_data).await?;
-                authentication.check_can_write()?;
-                let user = app.database.get_user_profile(authentication.uid()?).await?;
-                // publish
-                let result = app.database.publish_crate_version(user.id, package).await?;
-                let mut targets = app.database.get_crate_targets(&package.metadata.name).await?;
-                if targets.is_empty() {
-                    targets.push(CrateInfoTarget {
-                        target: self.configuration.self_toolchain_host.clone(),
-                        docs_use_native: true,
-                    });
-                }
-                for info in &targets {
-                    app.database
-                        .set_crate_documentation(&package.metadata.name, &package.metadata.vers, &info.target, false, false)
-                        .await?;
-                }
-                let capabilities = app.database.get_crate_required_capabilities(&package.metadata.name).await?;
-                Ok::<_, ApiError>((user, result, targets, capabilities))
-            })
-            .await
-        }?;
-
-        self.service_storage.store_crate(&package.metadata, package.content).await?;
-        self.service_index.publish_crate_version(&index_data).await?;
-        for info in targets {
-            self.service_docs_generator
-                .queue(
-                    &DocGenJobSpec {
-                        package: index_data.name.clone(),
-                        version: index_data.vers.clone(),
-                        target: info.target,
-                        use_native: info.docs_use_native,
-                        capabilities: capabilities.clone(),
-                    },
-                    &DocGenTrigger::Upload { by: user.clone() },
-                )
-                .await?;
-        }
-        Ok(result)
-    }
-
-    /// Gets all the data about a crate
-    pub async fn get_crate_info(&self, auth_data: &AuthData, package: &str) -> Result<CrateInfo, ApiError> {
-        let info = self
-            .db_transaction_read(|app| async move {
-                let _authentication = app.authenticate(auth_data).await?;
-                app.database
-                    .get_crate_info(package, self.service_index.get_crate_data(package).await?)
-                    .await
-            })
-            .await?;
-        let metadata = self
-            .service_storage
-            .download_crate_metadata(package, &info.versions.last().unwrap().index.vers)
-            .await?;
-        Ok(CrateInfo { metadata, ..info })
-    }
-
-    /// Downloads the last README for a crate
-    pub async fn get_crate_last_readme(&self, auth_data: &AuthData, package: &str) -> Result<Vec<u8>, ApiError> {
-        let version = self
-            .db_transaction_read(|app| async move {
-                let _authentication = app.authenticate(auth_data).await?;
-                let version = app.database.get_crate_last_version(package).await?;
-                Ok::<_, ApiError>(version)
-            })
-            .await?;
-        let readme = self.service_storage.download_crate_readme(package, &version).await?;
-        Ok(readme)
-    }
-
-    /// Downloads the README for a crate
-    pub async fn get_crate_readme(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<Vec<u8>, ApiError> {
-        let _authentication = self.authenticate(auth_data).await?;
-        let readme = self.service_storage.download_crate_readme(package, version).await?;
-        Ok(readme)
-    }
-
-    /// Downloads the content for a crate
-    pub async fn get_crate_content(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<Vec<u8>, ApiError> {
-        let public_read = self.configuration.self_public_read;
+    /// Gets the trusted publishers for a crate
+    pub async fn get_trusted_publishers(&self, auth_data: &AuthData, package: &str) -> Result<Vec<TrustedPublisher>, ApiError> {
         self.db_transaction_read(|app| async move {
-            if !public_read {
-                let _authentication = app.authenticate(auth_data).await?;
-            }
-            app.database.check_crate_exists(package, version).await?;
-            Ok::<_, ApiError>(())
-        })
-        .await?;
-        let content = self.service_storage.download_crate(package, version).await?;
-        self.app_events_sender
-            .send(AppEvent::CrateDownload(CrateVersion {
-                package: package.to_string(),
-                version: version.to_string(),
-            }))
-            .await?;
-        Ok(content)
-    }
-
-    /// Completely removes a version from the registry
-    pub async fn remove_crate_version(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<(), ApiError> {
-        self.db_transaction_write("remove_crate_version", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package).await?;
-            app.database.remove_crate_version(package, version).await?;
-            self.service_index.remove_crate_version(package, version).await?;
-            Ok(())
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.get_trusted_publishers(package).await
         })
         .await
     }
 
-    /// Yank a crate version
-    pub async fn yank_crate_version(
+    /// Adds a trusted publisher for a crate
+    pub async fn add_trusted_publisher(
         &self,
         auth_data: &AuthData,
         package: &str,
-        version: &str,
-    ) -> Result<YesNoResult, ApiError> {
-        self.db_transaction_write("yank_crate_version", |app| async move {
+        spec: &TrustedPublisherSpec,
+    ) -> Result<TrustedPublisher, ApiError> {
+        if spec.issuer.is_empty() || spec.jwks.is_empty() || spec.repository.is_empty() {
+            return Err(specialize(
+                error_invalid_request(),
+                String::from("the issuer, JWKS and repository are required for a trusted publisher"),
+            ));
+        }
+        self.db_transaction_write("add_trusted_publisher", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package).await?;
-            app.database.yank_crate_version(package, version).await
+            // the JWKS location is loaded by the registry, only administrators configure it
+            let principal_uid = app.check_can_admin_registry(&authentication).await?;
+            if !app.database.get_crate_exists(package).await? {
+                return Err(error_not_found());
+            }
+            let publisher = app.database.add_trusted_publisher(principal_uid, package, spec).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "add_trusted_publisher",
+                package,
+                Some(format!(
+                    "id: {}, issuer: {}, repository: {}",
+                    publisher.id, publisher.issuer, publisher.repository
+                )),
+            )
+            .await?;
+            Ok(publisher)
         })
         .await
     }
 
-    /// Unyank a crate version
-    pub async fn unyank_crate_version(
+    /// Removes a trusted publisher for a crate
+    pub async fn remove_trusted_publisher(
         &self,
         auth_data: &AuthData,
         package: &str,
-        version: &str,
-    ) -> Result<YesNoResult, ApiError> {
-        self.db_transaction_write("unyank_crate_version", |app| async move {
+        publisher_id: i64,
+    ) -> Result<(), ApiError> {
+        self.db_transaction_write("remove_trusted_publisher", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package).await?;
-            app.database.unyank_crate_version(package, version).await
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.remove_trusted_publisher(package, publisher_id).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "remove_trusted_publisher",
+                package,
+                Some(format!("id: {publisher_id}")),
+            )
+            .await
         })
         .await
     }
 
-    /// Gets the packages that need documentation generation
-    pub async fn get_undocumented_crates(&self, auth_data: &AuthData) -> Result<Vec<DocGenJobSpec>, ApiError> {
-        self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
-            app.database
-                .get_undocumented_crates(&self.configuration.self_toolchain_host)
-                .await
+    /// Exchanges an OIDC token from a CI pipeline for a short-lived token to publish a crate
+    /// The crate must be specified when the OIDC token matches trusted publishers for multiple crates
+    pub async fn exchange_trusted_publishing_token(
+        &self,
+        auth_data: &AuthData,
+        jwt: &str,
+        package: Option<&str>,
+    ) -> Result<TrustedPublishingToken, ApiError> {
+        self.check_auth_failures(auth_data)?;
+        self.check_rate_limit(auth_data)?;
+        let jwt = Jwt::parse(jwt)?;
+        let issuer = jwt
+            .unverified_claims()?
+            .get("iss")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(error_unauthorized)?;
+        let publishers = self
+            .db_transaction_read(|app| async move { app.database.get_trusted_publishers_for_issuer(&issuer).await })
+            .await?;
+        let now = Utc::now().timestamp();
+        let mut matching = Vec::new();
+        for publisher in publishers {
+            if package.is_some_and(|package| !publisher.package.eq_ignore_ascii_case(package)) {
+                continue;
+            }
+            let jwks = match self.jwks_cache.get(&publisher.issuer, &publisher.jwks).await {
+                Ok(jwks) => jwks,
+                Err(error) => {
+                    // a broken publisher shall not prevent the others
+                    warn!(
+                        "failed to load the JWKS for trusted publisher {} at {}: {error}",
+                        publisher.id, publisher.jwks
+                    );
+                    continue;
+                }
+            };
+            let Ok(claims) = jwt.verify(&jwks) else { continue };
+            if check_registered_claims(&claims, &publisher.issuer, &self.configuration.web_public_uri, now).is_ok()
+                && publisher.matches_claims(&claims)
+            {
+                matching.push(publisher);
+            }
+        }
+        let publisher = match matching.len() {
+            0 => {
+                self.record_auth_failure(auth_data);
+                return Err(specialize(
+                    error_unauthorized(),
+                    String::from("no trusted publisher matches this token"),
+                ));
+            }
+            1 => matching.remove(0),
+            _ => {
+                return Err(specialize(
+                    error_invalid_request(),
+                    String::from("the token matches trusted publishers for multiple crates, the crate must be specified"),
+                ));
+            }
+        };
+        let expires_on = Local::now().naive_local() + TimeDelta::minutes(self.configuration.trusted_publishing_token_validity);
+        let (login, secret) = self
+            .db_transaction_write("exchange_trusted_publishing_token", |app| async move {
+                let (login, secret) = app.database.create_trusted_publishing_token(publisher.id, expires_on).await?;
+                app.audit_as(
+                    auth_data,
+                    format!("trusted-publisher:{}", publisher.id),
+                    None,
+                    "exchange_trusted_publishing_token",
+                    &publisher.package,
+                    Some(format!("login: {login}")),
+                )
+                .await?;
+                Ok::<_, ApiError>((login, secret))
+            })
+            .await?;
+        Ok(TrustedPublishingToken {
+            token: format!("Basic {}", STANDARD.encode(format!("{login}:{secret}"))),
+            expires_on,
         })
-        .await
+    }
+
+    /// Gets the global tokens for the registry, usually for CI purposes
+    pub async fn get_global_tokens(&self, auth_data: &AuthData) -> Result<Vec<RegistryUserToken>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.get_global_tokens().await
+        })
+        .await
+    }
+
+    /// Creates a global token for the registry
+    pub async fn create_global_token(
+        &self,
+        auth_data: &AuthData,
+        name: &str,
+        expires_on: Option<NaiveDateTime>,
+    ) -> Result<RegistryUserTokenWithSecret, ApiError> {
+        self.db_transaction_write("create_global_token", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            check_token_expiry(expires_on, self.configuration.tokens_max_validity)?;
+            let token = app.database.create_global_token(name, expires_on).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "create_global_token",
+                name,
+                Some(format!("id: {}", token.id)),
+            )
+            .await?;
+            Ok(token)
+        })
+        .await
+    }
+
+    /// Revokes a global token for the registry
+    pub async fn revoke_global_token(&self, auth_data: &AuthData, token_id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("revoke_global_token", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.revoke_global_token(token_id).await?;
+            app.audit(auth_data, &authentication, "revoke_global_token", &token_id.to_string(), None)
+                .await
+        })
+        .await
+    }
+
+    /// Gets the events in the audit log matching a query, the most recent first
+    /// Without a limit, a default number of events is returned
+    pub async fn get_audit_events(&self, auth_data: &AuthData, query: &AuditQuery) -> Result<Vec<AuditEvent>, ApiError> {
+        let query = AuditQuery {
+            limit: Some(query.limit.unwrap_or(AUDIT_DEFAULT_LIMIT)),
+            ..query.clone()
+        };
+        self.export_audit_events(auth_data, &query).await
+    }
+
+    /// Exports all the events in the audit log matching a query, the most recent first
+    pub async fn export_audit_events(&self, auth_data: &AuthData, query: &AuditQuery) -> Result<Vec<AuditEvent>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.get_audit_events(query).await
+        })
+        .await
+    }
+
+    /// Gets the policies for the names of new crates
+    pub async fn get_crate_name_policies(&self, auth_data: &AuthData) -> Result<Vec<CrateNamePolicy>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.get_crate_name_policies().await
+        })
+        .await
+    }
+
+    /// Creates a policy for the names of new crates
+    pub async fn create_crate_name_policy(
+        &self,
+        auth_data: &AuthData,
+        spec: &CrateNamePolicySpec,
+    ) -> Result<CrateNamePolicy, ApiError> {
+        self.db_transaction_write("create_crate_name_policy", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            let policy = app.database.create_crate_name_policy(spec).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "create_crate_name_policy",
+                &policy.pattern,
+                policy.team.as_ref().map(|team| format!("team: {team}")),
+            )
+            .await?;
+            Ok(policy)
+        })
+        .await
+    }
+
+    /// Deletes a policy for the names of new crates
+    pub async fn delete_crate_name_policy(&self, auth_data: &AuthData, policy_id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("delete_crate_name_policy", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            let pattern = app.database.delete_crate_name_policy(policy_id).await?;
+            app.audit(auth_data, &authentication, "delete_crate_name_policy", &pattern, None)
+                .await
+        })
+        .await
+    }
+
+    /// Gets the teams of users
+    pub async fn get_teams(&self, auth_data: &AuthData) -> Result<Vec<Team>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.get_teams().await
+        })
+        .await
+    }
+
+    /// Creates a new team of users
+    pub async fn create_team(&self, auth_data: &AuthData, name: &str) -> Result<Team, ApiError> {
+        self.db_transaction_write("create_team", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            let team = app.database.create_team(name).await?;
+            app.audit(auth_data, &authentication, "create_team", name, None).await?;
+            Ok(team)
+        })
+        .await
+    }
+
+    /// Deletes a team of users
+    pub async fn delete_team(&self, auth_data: &AuthData, name: &str) -> Result<(), ApiError> {
+        self.db_transaction_write("delete_team", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.delete_team(name).await?;
+            app.audit(auth_data, &authentication, "delete_team", name, None).await
+        })
+        .await
+    }
+
+    /// Adds members to a team
+    pub async fn add_team_members(&self, auth_data: &AuthData, name: &str, emails: &[String]) -> Result<(), ApiError> {
+        self.db_transaction_write("add_team_members", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.add_team_members(name, emails).await?;
+            app.audit(auth_data, &authentication, "add_team_members", name, Some(emails.join(", ")))
+                .await
+        })
+        .await
+    }
+
+    /// Removes members from a team
+    pub async fn remove_team_members(&self, auth_data: &AuthData, name: &str, emails: &[String]) -> Result<(), ApiError> {
+        self.db_transaction_write("remove_team_members", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.remove_team_members(name, emails).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "remove_team_members",
+                name,
+                Some(emails.join(", ")),
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Checks the token of the identity provider for SCIM provisioning
+    fn check_scim_token(&self, auth_data: &AuthData) -> Result<(), ApiError> {
+        let Some(scim_token) = &self.configuration.scim_token else {
+            return Err(specialize(
+                error_forbidden(),
+                String::from("SCIM provisioning is not enabled"),
+            ));
+        };
+        self.check_auth_failures(auth_data)?;
+        let bearer = auth_data.bearer.as_deref().ok_or_else(error_unauthorized)?;
+        check_hash(bearer, &hash_token(scim_token)).inspect_err(|_| self.record_auth_failure(auth_data))
+    }
+
+    /// Gets the users, for SCIM provisioning
+    pub async fn scim_get_users(
+        &self,
+        auth_data: &AuthData,
+        query: &ScimListQuery,
+    ) -> Result<ScimListResponse<ScimUser>, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let filter = query.parse_filter()?;
+        if let Some((attribute, _)) = &filter
+            && !attribute.eq_ignore_ascii_case("userName")
+        {
+            return Err(specialize(
+                error_invalid_request(),
+                format!("unsupported filter on {attribute}"),
+            ));
+        }
+        let base_uri = &self.configuration.web_public_uri;
+        let users = self
+            .db_transaction_read(|app| async move { app.database.get_users().await })
+            .await?
+            .into_iter()
+            .filter(|user| {
+                filter
+                    .as_ref()
+                    .is_none_or(|(_, value)| user.email.eq_ignore_ascii_case(value))
+            })
+            .map(|user| ScimUser::from_user(&user, base_uri))
+            .collect();
+        Ok(ScimListResponse::new(users, query))
+    }
+
+    /// Gets a user, for SCIM provisioning
+    pub async fn scim_get_user(&self, auth_data: &AuthData, id: &str) -> Result<ScimUser, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let uid = parse_scim_id(id)?;
+        let user = self
+            .db_transaction_read(|app| async move { app.database.get_user_profile(uid).await })
+            .await?;
+        Ok(ScimUser::from_user(&user, &self.configuration.web_public_uri))
+    }
+
+    /// Provisions a new user
+    pub async fn scim_create_user(&self, auth_data: &AuthData, user: &ScimUser) -> Result<ScimUser, ApiError> {
+        self.check_scim_token(auth_data)?;
+        self.db_transaction_write("scim_create_user", |app| async move {
+            let email = user.email();
+            if app.database.get_users().await?.iter().any(|existing| existing.email == email) {
+                return Err(specialize(error_conflict(), format!("user {email} already exists")));
+            }
+            let user = app
+                .database
+                .create_user(email, user.full_name().as_deref(), user.active)
+                .await?;
+            app.audit_scim(auth_data, "scim_create_user", email, None).await?;
+            Ok(ScimUser::from_user(&user, &app.application.configuration.web_public_uri))
+        })
+        .await
+    }
+
+    /// Replaces the attributes of a provisioned user
+    pub async fn scim_replace_user(&self, auth_data: &AuthData, id: &str, user: &ScimUser) -> Result<ScimUser, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let uid = parse_scim_id(id)?;
+        self.db_transaction_write("scim_replace_user", |app| async move {
+            let user = app.scim_apply_user(uid, user).await?;
+            app.audit_scim(
+                auth_data,
+                "scim_replace_user",
+                &user.user_name,
+                Some(format!("active: {}", user.active)),
+            )
+            .await?;
+            Ok(user)
+        })
+        .await
+    }
+
+    /// Patches the attributes of a provisioned user
+    pub async fn scim_patch_user(
+        &self,
+        auth_data: &AuthData,
+        id: &str,
+        patch: &ScimPatchRequest,
+    ) -> Result<ScimUser, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let uid = parse_scim_id(id)?;
+        self.db_transaction_write("scim_patch_user", |app| async move {
+            let user = app.database.get_user_profile(uid).await?;
+            let mut desired = ScimUser::from_user(&user, &app.application.configuration.web_public_uri);
+            desired.apply_patch(patch)?;
+            let user = app.scim_apply_user(uid, &desired).await?;
+            app.audit_scim(
+                auth_data,
+                "scim_patch_user",
+                &user.user_name,
+                Some(format!("active: {}", user.active)),
+            )
+            .await?;
+            Ok(user)
+        })
+        .await
+    }
+
+    /// Deprovisions a user
+    pub async fn scim_delete_user(&self, auth_data: &AuthData, id: &str) -> Result<(), ApiError> {
+        self.check_scim_token(auth_data)?;
+        let uid = parse_scim_id(id)?;
+        self.db_transaction_write("scim_delete_user", |app| async move {
+            let user = app.database.get_user_profile(uid).await?;
+            app.database.delete_user(None, &user.email).await?;
+            app.audit_scim(auth_data, "scim_delete_user", &user.email, None).await
+        })
+        .await
+    }
+
+    /// Gets the groups, for SCIM provisioning
+    pub async fn scim_get_groups(
+        &self,
+        auth_data: &AuthData,
+        query: &ScimListQuery,
+    ) -> Result<ScimListResponse<ScimGroup>, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let filter = query.parse_filter()?;
+        if let Some((attribute, _)) = &filter
+            && !attribute.eq_ignore_ascii_case("displayName")
+        {
+            return Err(specialize(
+                error_invalid_request(),
+                format!("unsupported filter on {attribute}"),
+            ));
+        }
+        let base_uri = &self.configuration.web_public_uri;
+        let groups = self
+            .db_transaction_read(|app| async move { app.database.get_teams().await })
+            .await?
+            .into_iter()
+            .filter(|team| filter.as_ref().is_none_or(|(_, value)| &team.name == value))
+            .map(|team| ScimGroup::from_team(&team, base_uri))
+            .collect();
+        Ok(ScimListResponse::new(groups, query))
+    }
+
+    /// Gets a group, for SCIM provisioning
+    pub async fn scim_get_group(&self, auth_data: &AuthData, id: &str) -> Result<ScimGroup, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let team_id = parse_scim_id(id)?;
+        let team = self
+            .db_transaction_read(|app| async move { app.database.get_team(team_id).await })
+            .await?;
+        Ok(ScimGroup::from_team(&team, &self.configuration.web_public_uri))
+    }
+
+    /// Provisions a new group, as a team
+    pub async fn scim_create_group(&self, auth_data: &AuthData, group: &ScimGroup) -> Result<ScimGroup, ApiError> {
+        self.check_scim_token(auth_data)?;
+        self.db_transaction_write("scim_create_group", |app| async move {
+            let team = app.database.create_team(&group.display_name).await?;
+            let group = app.scim_apply_group(team.id, group).await?;
+            app.audit_scim(
+                auth_data,
+                "scim_create_group",
+                &group.display_name,
+                Some(group.member_names()),
+            )
+            .await?;
+            Ok(group)
+        })
+        .await
+    }
+
+    /// Replaces the name and members of a provisioned group
+    pub async fn scim_replace_group(&self, auth_data: &AuthData, id: &str, group: &ScimGroup) -> Result<ScimGroup, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let team_id = parse_scim_id(id)?;
+        self.db_transaction_write("scim_replace_group", |app| async move {
+            let group = app.scim_apply_group(team_id, group).await?;
+            app.audit_scim(
+                auth_data,
+                "scim_replace_group",
+                &group.display_name,
+                Some(group.member_names()),
+            )
+            .await?;
+            Ok(group)
+        })
+        .await
+    }
+
+    /// Patches the name and members of a provisioned group
+    pub async fn scim_patch_group(
+        &self,
+        auth_data: &AuthData,
+        id: &str,
+        patch: &ScimPatchRequest,
+    ) -> Result<ScimGroup, ApiError> {
+        self.check_scim_token(auth_data)?;
+        let team_id = parse_scim_id(id)?;
+        self.db_transaction_write("scim_patch_group", |app| async move {
+            let team = app.database.get_team(team_id).await?;
+            let mut desired = ScimGroup::from_team(&team, &app.application.configuration.web_public_uri);
+            desired.apply_patch(patch)?;
+            let group = app.scim_apply_group(team_id, &desired).await?;
+            app.audit_scim(auth_data, "scim_patch_group", &group.display_name, Some(group.member_names()))
+                .await?;
+            Ok(group)
+        })
+        .await
+    }
+
+    /// Deprovisions a group
+    pub async fn scim_delete_group(&self, auth_data: &AuthData, id: &str) -> Result<(), ApiError> {
+        self.check_scim_token(auth_data)?;
+        let team_id = parse_scim_id(id)?;
+        self.db_transaction_write("scim_delete_group", |app| async move {
+            let team = app.database.get_team(team_id).await?;
+            app.database.delete_team(&team.name).await?;
+            app.audit_scim(auth_data, "scim_delete_group", &team.name, None).await
+        })
+        .await
+    }
+
+    /// Publish a crate
+    pub async fn publish_crate_version(&self, auth_data: &AuthData, content: &[u8]) -> Result<CrateUploadResult, ApiError> {
+        self.check_rate_limit(auth_data)?;
+        // deserialize payload
+        let mut package = CrateUploadData::new(content)?;
+        let mut result = package.validate(&self.configuration.crate_categories, self.configuration.crate_max_file_size)?;
+        let index_data = package.build_index_data();
+
+        // check against the policies of the organisation, before anything is stored
+        let (uploader, is_new_crate) = {
+            let package = &package;
+            let index_data = &index_data;
+            self.db_transaction_read(|app| async move {
+                let (authentication, user, is_new_crate) =
+                    app.authenticate_publish(auth_data, package, &index_data.cksum).await?;
+                self.check_user_rate_limit(&authentication)?;
+                Ok::<_, ApiError>((user, is_new_crate))
+            })
+            .await?
+        };
+        let verdict = self
+            .service_publish_policy
+            .check_crate(&package, &uploader, is_new_crate)
+            .await?;
+        if !verdict.allowed {
+            let message = verdict.message.unwrap_or_else(|| String::from("no reason given"));
+            return Err(specialize(
+                error_forbidden(),
+                format!("Publication rejected by the registry policy: {message}"),
+            ));
+        }
+        result.warnings.other.extend(verdict.warnings);
+        // only the names of new crates are checked against the crates.io dependencies
+        let external_names = if is_new_crate {
+            self.service_deps_checker.get_crates_io_dependencies().await?
+        } else {
+            Vec::new()
+        };
+        let semver_check = self.check_crate_semver(&package, &mut result).await?;
+
+        // the index is not checked while the version is published
+        let _index_guard = self.index_lock.read().await;
+        let (user, token_id, docs) = {
+            let package = &package;
+            let index_data = &index_data;
+            let external_names = &external_names;
+            self.db_transaction_write("publish_crate_version", |app| async move {
+                let (authentication, user, _) = app.authenticate_publish(auth_data, package, &index_data.cksum).await?;
+                let token_id = authentication.token_id.clone();
+                if app.database.get_crate_requires_approval(&package.metadata.name).await? {
+                    // hold back the version until approved
+                    app.database.stage_crate_version(user.id, package, index_data).await?;
+                    app.audit(
+                        auth_data,
+                        &authentication,
+                        "stage_crate_version",
+                        &package.metadata.name,
+                        Some(format!("version: {}", package.metadata.vers)),
+                    )
+                    .await?;
+                    return Ok((user, token_id, None));
+                }
+                // publish
+                app.database
+                    .publish_crate_version(user.id, package, index_data, external_names)
+                    .await?;
+                app.audit(
+                    auth_data,
+                    &authentication,
+                    "publish_crate_version",
+                    &package.metadata.name,
+                    Some(format!("version: {}", package.metadata.vers)),
+                )
+                .await?;
+                let docs = app
+                    .prepare_crate_version_docs(&package.metadata.name, &package.metadata.vers)
+                    .await?;
+                Ok::<_, ApiError>((user, token_id, Some(docs)))
+            })
+            .await
+        }?;
+
+        // the version only becomes visible once it is stored and in the index, it is rolled back otherwise
+        let origin = if docs.is_some() {
+            PublishOrigin::Upload
+        } else {
+            PublishOrigin::Staged
+        };
+        if let Err(error) = self.store_crate_version(package, &index_data, &user, token_id).await {
+            self.rollback_crate_version_publish(&index_data, origin, false).await;
+            return Err(error);
+        }
+        let Some((targets, capabilities)) = docs else {
+            self.record_crate_semver(semver_check.as_ref()).await?;
+            result.warnings.other.push(format!(
+                "{} {} is pending approval by another owner before being published to the index",
+                index_data.name, index_data.vers
+            ));
+            return Ok(result);
+        };
+        if let Err(error) = self.complete_crate_version_publish(&index_data).await {
+            self.rollback_crate_version_publish(&index_data, origin, true).await;
+            return Err(error);
+        }
+        self.record_crate_semver(semver_check.as_ref()).await?;
+        self.queue_crate_version_jobs(&index_data, &user, targets, &capabilities)
+            .await?;
+        Ok(result)
+    }
+
+    /// Stores the .crate package of a new version and its signed attestation, when enabled
+    async fn store_crate_version(
+        &self,
+        package: CrateUploadData,
+        index_data: &IndexCrateMetadata,
+        user: &RegistryUser,
+        token_id: Option<String>,
+    ) -> Result<(), ApiError> {
+        let attestation = match &self.attestation_signer {
+            Some(signer) => {
+                let predicate = PublishPredicate {
+                    registry: self.configuration.web_public_uri.clone(),
+                    publisher: user.into(),
+                    token: token_id,
+                    published_at: Local::now().naive_local(),
+                    vcs: package.get_vcs_info()?,
+                };
+                let statement = AttestationStatement::new(&index_data.name, &index_data.vers, &index_data.cksum, predicate);
+                Some(serde_json::to_vec(&signer.sign(&statement)?)?)
+            }
+            None => None,
+        };
+        self.service_storage.store_crate(&package.metadata, package.content).await?;
+        if let Some(attestation) = attestation {
+            self.service_storage
+                .store_crate_attestation(&index_data.name, &index_data.vers, attestation)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Completes the publication of a stored version by appending it to the index, so that it becomes visible
+    async fn complete_crate_version_publish(&self, index_data: &IndexCrateMetadata) -> Result<(), ApiError> {
+        self.service_index.publish_crate_version(index_data).await?;
+        self.db_transaction_write("complete_crate_version_publish", |app| async move {
+            app.database
+                .complete_crate_version_publish(&index_data.name, &index_data.vers)
+                .await
+        })
+        .await?;
+        self.service_deps_checker.add_crates_io_dependencies(index_data).await
+    }
+
+    /// Rolls back a publication that could not be completed, so that it can be retried
+    /// The .crate package is deleted from the storage, unless the version returns to pending approval
+    async fn rollback_crate_version_publish(&self, index_data: &IndexCrateMetadata, origin: PublishOrigin, indexed: bool) {
+        let package = index_data.name.as_str();
+        let version = index_data.vers.as_str();
+        error!("failed to publish {package} {version}, rolling back");
+        let result = self
+            .db_transaction_write("rollback_crate_version_publish", |app| async move {
+                match origin {
+                    PublishOrigin::Upload => app.database.discard_crate_version_publish(package, version).await,
+                    PublishOrigin::Staged => app.database.reject_crate_version(package, version).await,
+                    PublishOrigin::Approval => app.database.revert_crate_version_approval(package, version).await,
+                }
+            })
+            .await;
+        if let Err(error) = result {
+            error!("failed to roll back {package} {version} in the database: {error}");
+        }
+        if indexed && let Err(error) = self.service_index.remove_crate_version(package, version).await {
+            error!("failed to roll back {package} {version} in the index: {error}");
+        }
+        if !matches!(origin, PublishOrigin::Approval)
+            && let Err(error) = self.service_storage.delete_crate(package, version).await
+        {
+            error!("failed to roll back {package} {version} in the storage: {error}");
+        }
+    }
+
+    /// Checks whether the .crate package of a version is stored, with the expected checksum
+    async fn is_crate_version_stored(&self, index_data: &IndexCrateMetadata) -> bool {
+        self.service_storage
+            .download_crate(&index_data.name, &index_data.vers)
+            .await
+            .is_ok_and(|content| sha256(&content) == index_data.cksum)
+    }
+
+    /// Resumes the publications that were interrupted, for example by a restart
+    /// A publication is completed when the .crate package was stored, or rolled back otherwise
+    /// Likewise, a version pending approval is discarded when its .crate package was not stored
+    pub(crate) async fn resume_crate_version_publishes(&self) -> Result<(), ApiError> {
+        let _index_guard = self.index_lock.read().await;
+        let (versions, pending) = self
+            .db_transaction_read(|app| async move {
+                let versions = app.database.get_crate_versions_publishing().await?;
+                let pending = app.database.get_all_pending_versions().await?;
+                Ok::<_, ApiError>((versions, pending))
+            })
+            .await?;
+        for index_data in pending {
+            if !self.is_crate_version_stored(&index_data).await {
+                self.rollback_crate_version_publish(&index_data, PublishOrigin::Staged, false)
+                    .await;
+            }
+        }
+        for (uploaded_by, index_data) in versions {
+            if !self.is_crate_version_stored(&index_data).await {
+                self.rollback_crate_version_publish(&index_data, PublishOrigin::Upload, false)
+                    .await;
+                continue;
+            }
+            info!("resuming the publication of {} {}", index_data.name, index_data.vers);
+            if let Err(error) = self.complete_crate_version_publish(&index_data).await {
+                error!("{error}");
+                self.rollback_crate_version_publish(&index_data, PublishOrigin::Upload, true)
+                    .await;
+                continue;
+            }
+            let (uploaded_by, (targets, capabilities)) = self
+                .db_transaction_write("resume_crate_version_publish", |app| {
+                    let index_data = &index_data;
+                    async move {
+                        let uploaded_by = app.database.get_user_profile(uploaded_by).await?;
+                        let docs = app.prepare_crate_version_docs(&index_data.name, &index_data.vers).await?;
+                        Ok::<_, ApiError>((uploaded_by, docs))
+                    }
+                })
+                .await?;
+            self.queue_crate_version_jobs(&index_data, &uploaded_by, targets, &capabilities)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Checks the semver compatibility of a new version before it is published, when the checks block the publication
+    /// Breaking changes reject the publication, a failure to check is only reported as a warning
+    /// The check is only recorded once the version is stored, see `record_crate_semver`
+    async fn check_crate_semver(
+        &self,
+        package: &CrateUploadData,
+        result: &mut CrateUploadResult,
+    ) -> Result<Option<SemverCheckJob>, ApiError> {
+        if !self.configuration.semver_checks || !self.configuration.semver_checks_block {
+            return Ok(None);
+        }
+        let Some(job) = self
+            .service_semver_checker
+            .check_content(&package.metadata.name, &package.metadata.vers, &package.content)
+            .await?
+        else {
+            return Ok(None);
+        };
+        match job.state {
+            SemverCheckJobState::Breaking => Err(specialize(
+                error_invalid_request(),
+                format!(
+                    "Publication rejected because of breaking changes compared to {}, a new major version is required:\n{}",
+                    job.baseline, job.output
+                ),
+            )),
+            SemverCheckJobState::Compatible => Ok(Some(job)),
+            _ => {
+                result.warnings.other.push(format!(
+                    "failed to check the semver compatibility against {}, see the crate's page for details",
+                    job.baseline
+                ));
+                Ok(Some(job))
+            }
+        }
+    }
+
+    /// Records the check of the semver compatibility of a new version, once it is stored
+    async fn record_crate_semver(&self, job: Option<&SemverCheckJob>) -> Result<(), ApiError> {
+        match job {
+            Some(job) => self.service_semver_checker.record(job).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Queues the jobs for a published version, for its documentation and semver checks
+    async fn queue_crate_version_jobs(
+        &self,
+        index_data: &IndexCrateMetadata,
+        uploaded_by: &RegistryUser,
+        targets: Vec<CrateInfoTarget>,
+        capabilities: &[String],
+    ) -> Result<(), ApiError> {
+        if self.configuration.semver_checks && !self.configuration.semver_checks_block {
+            self.service_semver_checker.queue(&index_data.name, &index_data.vers).await?;
+        }
+        for info in targets {
+            self.service_docs_generator
+                .queue(
+                    &DocGenJobSpec {
+                        package: index_data.name.clone(),
+                        version: index_data.vers.clone(),
+                        target: info.target,
+                        use_native: info.docs_use_native,
+                        capabilities: capabilities.to_vec(),
+                    },
+                    &DocGenTrigger::Upload { by: uploaded_by.clone() },
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Sets whether new versions of a crate must be approved by another owner before being published
+    /// Only the administrators can turn the approval off
+    pub async fn set_crate_requires_approval(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        requires_approval: bool,
+    ) -> Result<(), ApiError> {
+        self.db_transaction_write("set_crate_requires_approval", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            if !requires_approval && app.database.get_crate_requires_approval(package).await? {
+                // an owner shall not bypass the approval by another owner
+                app.check_can_admin_registry(&authentication).await?;
+            }
+            app.database.set_crate_requires_approval(package, requires_approval).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "set_crate_requires_approval",
+                package,
+                Some(format!("requiresApproval: {requires_approval}")),
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Gets the versions of a crate that are pending approval
+    pub async fn get_crate_pending_versions(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+    ) -> Result<Vec<CratePendingVersion>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.get_crate_pending_versions(package).await
+        })
+        .await
+    }
+
+    /// Downloads the content of a version of a crate that is pending approval
+    pub async fn get_crate_pending_content(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+    ) -> Result<Vec<u8>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.check_crate_pending_version_exists(package, version).await
+        })
+        .await?;
+        self.service_storage.download_crate(package, version).await
+    }
+
+    /// Approves a version of a crate that is pending approval, publishing it to the index
+    pub async fn approve_crate_version(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<(), ApiError> {
+        let _index_guard = self.index_lock.read().await;
+        let (index_data, uploaded_by, (targets, capabilities)) = self
+            .db_transaction_write("approve_crate_version", |app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                let uid = app
+                    .check_can_manage_crate(&authentication, package, Some(TokenOperation::PublishUpdate))
+                    .await?;
+                let (uploaded_by, index_data) = app.database.approve_crate_version(uid, package, version).await?;
+                let uploaded_by = app.database.get_user_profile(uploaded_by).await?;
+                app.audit(
+                    auth_data,
+                    &authentication,
+                    "approve_crate_version",
+                    package,
+                    Some(format!("version: {version}")),
+                )
+                .await?;
+                let docs = app.prepare_crate_version_docs(package, version).await?;
+                Ok::<_, ApiError>((index_data, uploaded_by, docs))
+            })
+            .await?;
+        if !self.is_crate_version_stored(&index_data).await {
+            // the upload of the version was not completed, it is back to pending approval and must be rejected
+            self.rollback_crate_version_publish(&index_data, PublishOrigin::Approval, false)
+                .await;
+            return Err(specialize(
+                error_conflict(),
+                format!("The .crate package for {package} {version} is missing in the storage, the version must be rejected"),
+            ));
+        }
+        if let Err(error) = self.complete_crate_version_publish(&index_data).await {
+            self.rollback_crate_version_publish(&index_data, PublishOrigin::Approval, true)
+                .await;
+            return Err(error);
+        }
+        self.queue_crate_version_jobs(&index_data, &uploaded_by, targets, &capabilities)
+            .await
+    }
+
+    /// Rejects a version of a crate that is pending approval, it is discarded
+    pub async fn reject_crate_version(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<(), ApiError> {
+        self.db_transaction_write("reject_crate_version", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, Some(TokenOperation::PublishUpdate))
+                .await?;
+            app.database.reject_crate_version(package, version).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "reject_crate_version",
+                package,
+                Some(format!("version: {version}")),
+            )
+            .await
+        })
+        .await?;
+        self.service_storage.delete_crate(package, version).await
+    }
+
+    /// Gets all the data about a crate
+    pub async fn get_crate_info(&self, auth_data: &AuthData, package: &str) -> Result<CrateInfo, ApiError> {
+        let info = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                app.check_can_read_crate(Some(&authentication), package).await?;
+                app.database
+                    .get_crate_info(package, self.service_index.get_crate_data(package).await?)
+                    .await
+            })
+            .await?;
+        let last = info.versions.last().ok_or_else(error_not_found)?;
+        let metadata = self
+            .service_storage
+            .download_crate_metadata(package, &last.index.vers)
+            .await?;
+        Ok(CrateInfo { metadata, ..info })
+    }
+
+    /// Downloads the last README for a crate
+    pub async fn get_crate_last_readme(&self, auth_data: &AuthData, package: &str) -> Result<Vec<u8>, ApiError> {
+        let version = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                app.check_can_read_crate(Some(&authentication), package).await?;
+                let version = app.database.get_crate_last_version(package).await?;
+                Ok::<_, ApiError>(version)
+            })
+            .await?;
+        let readme = self.service_storage.download_crate_readme(package, &version).await?;
+        Ok(readme)
+    }
+
+    /// Downloads the README for a crate
+    pub async fn get_crate_readme(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<Vec<u8>, ApiError> {
+        self.check_can_read_crate(auth_data, package).await?;
+        let readme = self.service_storage.download_crate_readme(package, version).await?;
+        Ok(readme)
+    }
+
+    /// Downloads the content for a crate
+    pub async fn get_crate_content(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<Vec<u8>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate_for_read(auth_data).await?;
+            app.check_can_read_crate(authentication.as_ref(), package).await?;
+            app.database.check_crate_exists(package, version).await?;
+            Ok::<_, ApiError>(())
+        })
+        .await?;
+        let content = self.service_storage.download_crate(package, version).await?;
+        self.app_events_sender
+            .send(AppEvent::CrateDownload(CrateVersion {
+                package: package.to_string(),
+                version: version.to_string(),
+            }))
+            .await?;
+        Ok(content)
+    }
+
+    /// Lists the files in the package of a crate version
+    pub async fn get_crate_files(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+    ) -> Result<Vec<CrateFile>, ApiError> {
+        self.check_can_read_crate_version(auth_data, package, version).await?;
+        let content = self.service_storage.download_crate(package, version).await?;
+        list_crate_files(&content)
+    }
+
+    /// Gets the content of a file in the package of a crate version, along with its content type
+    pub async fn get_crate_file(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+        path: &str,
+    ) -> Result<(&'static str, Vec<u8>), ApiError> {
+        self.check_can_read_crate_version(auth_data, package, version).await?;
+        let content = self.service_storage.download_crate(package, version).await?;
+        let file = extract_crate_file(&content, path, self.configuration.crate_browse_max_file_size)?;
+        Ok((get_crate_file_content_type(path, &file), file))
+    }
+
+    /// Gets the differences between two versions of a crate
+    pub async fn get_crate_versions_diff(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<CrateVersionsDiff, ApiError> {
+        self.check_can_read_crate_version(auth_data, package, from).await?;
+        self.check_can_read_crate_version(auth_data, package, to).await?;
+        let content_from = self.service_storage.download_crate(package, from).await?;
+        let content_to = self.service_storage.download_crate(package, to).await?;
+        let (package, from, to) = (package.to_string(), from.to_string(), to.to_string());
+        let max_file_size = self.configuration.crate_browse_max_file_size;
+        // extracting and comparing the packages is CPU-bound, do not block the async executor
+        tokio::task::spawn_blocking(move || {
+            let files_from = extract_crate_files(&content_from, DIFF_MAX_FILES, DIFF_MAX_TOTAL_SIZE)?;
+            let files_to = extract_crate_files(&content_to, DIFF_MAX_FILES, DIFF_MAX_TOTAL_SIZE)?;
+            CrateVersionsDiff::compute(&package, &from, &to, &files_from, &files_to, max_file_size)
+        })
+        .await?
+    }
+
+    /// Gets the checks of the semver compatibility of the versions of a crate
+    pub async fn get_crate_semver_checks(&self, auth_data: &AuthData, package: &str) -> Result<Vec<SemverCheckJob>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate_for_read(auth_data).await?;
+            app.check_can_read_crate(authentication.as_ref(), package).await
+        })
+        .await?;
+        self.service_semver_checker.get_checks(package).await
+    }
+
+    /// Checks that a crate version exists and can be read
+    async fn check_can_read_crate_version(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<(), ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate_for_read(auth_data).await?;
+            app.check_can_read_crate(authentication.as_ref(), package).await?;
+            app.database.check_crate_exists(package, version).await
+        })
+        .await
+    }
+
+    /// Gets the signed attestation for the publication of a crate version
+    pub async fn get_crate_attestation(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+    ) -> Result<AttestationEnvelope, ApiError> {
+        self.check_can_read_crate_version(auth_data, package, version).await?;
+        let content = self
+            .service_storage
+            .download_crate_attestation(package, version)
+            .await?
+            .ok_or_else(|| specialize(error_not_found(), format!("no attestation for {package} {version}")))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Gets the public key to verify the attestations of published crates
+    pub fn get_attestation_public_key(&self) -> Result<AttestationPublicKey, ApiError> {
+        self.attestation_signer
+            .as_ref()
+            .map(AttestationSigner::public_key)
+            .ok_or_else(|| specialize(error_not_found(), String::from("attestations are not enabled")))
+    }
+
+    /// Completely removes a version from the registry
+    pub async fn remove_crate_version(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<(), ApiError> {
+        let _index_guard = self.index_lock.read().await;
+        self.db_transaction_write("remove_crate_version", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.remove_crate_version(package, version).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "remove_crate_version",
+                package,
+                Some(format!("version: {version}")),
+            )
+            .await
+        })
+        .await?;
+        self.service_index.remove_crate_version(package, version).await
+    }
+
+    /// Yank a crate version
+    pub async fn yank_crate_version(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+        reason: Option<&str>,
+    ) -> Result<YesNoResult, ApiError> {
+        let _index_guard = self.index_lock.read().await;
+        let result = self
+            .db_transaction_write("yank_crate_version", |app| async move {
+                let authentication = app
+                    .authenticate_mutation(
+                        auth_data,
+                        &PasetoMutation {
+                            mutation: "yank",
+                            name: package,
+                            vers: Some(version),
+                            cksum: None,
+                        },
+                    )
+                    .await?;
+                app.check_can_manage_crate(&authentication, package, Some(TokenOperation::Yank))
+                    .await?;
+                let result = app.database.yank_crate_version(package, version, reason).await?;
+                let details = reason.map_or_else(
+                    || format!("version: {version}"),
+                    |reason| format!("version: {version}, reason: {reason}"),
+                );
+                app.audit(auth_data, &authentication, "yank_crate_version", package, Some(details))
+                    .await?;
+                Ok::<_, ApiError>(result)
+            })
+            .await?;
+        self.service_index.set_crate_version_yanked(package, version, true).await?;
+        Ok(result)
+    }
+
+    /// Unyank a crate version
+    pub async fn unyank_crate_version(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+    ) -> Result<YesNoResult, ApiError> {
+        let _index_guard = self.index_lock.read().await;
+        let result = self
+            .db_transaction_write("unyank_crate_version", |app| async move {
+                let authentication = app
+                    .authenticate_mutation(
+                        auth_data,
+                        &PasetoMutation {
+                            mutation: "unyank",
+                            name: package,
+                            vers: Some(version),
+                            cksum: None,
+                        },
+                    )
+                    .await?;
+                app.check_can_manage_crate(&authentication, package, Some(TokenOperation::Yank))
+                    .await?;
+                let result = app.database.unyank_crate_version(package, version).await?;
+                app.audit(
+                    auth_data,
+                    &authentication,
+                    "unyank_crate_version",
+                    package,
+                    Some(format!("version: {version}")),
+                )
+                .await?;
+                Ok::<_, ApiError>(result)
+            })
+            .await?;
+        self.service_index.set_crate_version_yanked(package, version, false).await?;
+        Ok(result)
+    }
+
+    /// Gets the packages that need documentation generation
+    pub async fn get_undocumented_crates(&self, auth_data: &AuthData) -> Result<Vec<DocGenJobSpec>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            let hidden = app.get_hidden_crates(Some(&authentication)).await?;
+            let mut jobs = app
+                .database
+                .get_undocumented_crates(&self.configuration.self_toolchain_host)
+                .await?;
+            jobs.retain(|job| !hidden.contains(&job.package));
+            Ok(jobs)
+        })
+        .await
     }
 
     /// Gets the documentation jobs
     pub async fn get_doc_gen_jobs(&self, auth_data: &AuthData) -> Result<Vec<DocGenJob>, ApiError> {
-        let _authentication = self.authenticate(auth_data).await?;
-        self.service_docs_generator.get_jobs().await
+        let hidden = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                app.get_hidden_crates(Some(&authentication)).await
+            })
+            .await?;
+        let mut jobs = self.service_docs_generator.get_jobs().await?;
+        jobs.retain(|job| !is_hidden_crate(&hidden, &job.package));
+        Ok(jobs)
     }
 
     /// Gets the log for a documentation generation job
     pub async fn get_doc_gen_job_log(&self, auth_data: &AuthData, job_id: i64) -> Result<String, ApiError> {
-        let _authentication = self.authenticate(auth_data).await?;
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            let job = app.database.get_docgen_job(job_id).await?;
+            app.check_can_read_crate(Some(&authentication), &job.package).await
+        })
+        .await?;
         self.service_docs_generator.get_job_log(job_id).await
     }
 
     /// Adds a listener to job updates
+    /// The updates for the jobs of the crates that cannot be read are filtered out
     pub async fn get_doc_gen_job_updates(&self, auth_data: &AuthData) -> Result<Receiver<DocGenEvent>, ApiError> {
-        let _authentication = self.authenticate(auth_data).await?;
+        let hidden = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                app.get_hidden_crates(Some(&authentication)).await
+            })
+            .await?;
         let (sender, receiver) = channel(16);
-        self.service_docs_generator.add_listener(sender).await?;
+        if hidden.is_empty() {
+            self.service_docs_generator.add_listener(sender).await?;
+            return Ok(receiver);
+        }
+        let (all_sender, mut all_receiver) = channel(16);
+        self.service_docs_generator.add_listener(all_sender).await?;
+        let service_db_pool = self.service_db_pool.clone();
+        let _handle = tokio::spawn(async move {
+            // the crates of the jobs, the updates only have the job identifier
+            let mut packages = HashMap::new();
+            while let Some(event) = all_receiver.recv().await {
+                let job_id = match &event {
+                    DocGenEvent::Queued(job) => {
+                        packages.insert(job.id, job.package.clone());
+                        job.id
+                    }
+                    DocGenEvent::Update(update) => update.job_id,
+                };
+                if let Entry::Vacant(entry) = packages.entry(job_id) {
+                    let Ok(job) =
+                        db_transaction_read(
+                            &service_db_pool,
+                            |database| async move { database.get_docgen_job(job_id).await },
+                        )
+                        .await
+                    else {
+                        continue;
+                    };
+                    entry.insert(job.package);
+                }
+                if is_hidden_crate(&hidden, &packages[&job_id]) {
+                    continue;
+                }
+                if sender.send(event).await.is_err() {
+                    // the client is gone
+                    break;
+                }
+            }
+        });
         Ok(receiver)
     }
 
@@ -621,13 +2139,21 @@ impl Application {
         let (user, targets, capabilities) = self
             .db_transaction_write("regen_crate_version_doc", |app| async move {
                 let authentication = app.authenticate(auth_data).await?;
-                let principal_uid = app.check_can_manage_crate(&authentication, package).await?;
+                let principal_uid = app.check_can_manage_crate(&authentication, package, None).await?;
                 let user = app.database.get_user_profile(principal_uid).await?;
                 let targets = app
                     .database
                     .regen_crate_version_doc(package, version, &self.configuration.self_toolchain_host)
                     .await?;
                 let capabilities = app.database.get_crate_required_capabilities(package).await?;
+                app.audit(
+                    auth_data,
+                    &authentication,
+                    "regen_crate_version_doc",
+                    package,
+                    Some(format!("version: {version}")),
+                )
+                .await?;
                 Ok::<_, ApiError>((user, targets, capabilities))
             })
             .await?;
@@ -655,8 +2181,11 @@ impl Application {
     /// Gets all the packages that are outdated while also being the latest version
     pub async fn get_crates_outdated_heads(&self, auth_data: &AuthData) -> Result<Vec<CrateVersion>, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
-            app.database.get_crates_outdated_heads().await
+            let authentication = app.authenticate(auth_data).await?;
+            let hidden = app.get_hidden_crates(Some(&authentication)).await?;
+            let mut heads = app.database.get_crates_outdated_heads().await?;
+            heads.retain(|head| !hidden.contains(&head.package));
+            Ok(heads)
         })
         .await
     }
@@ -664,7 +2193,8 @@ impl Application {
     /// Gets the download statistics for a crate
     pub async fn get_crate_dl_stats(&self, auth_data: &AuthData, package: &str) -> Result<DownloadStats, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(Some(&authentication), package).await?;
             app.database.get_crate_dl_stats(package).await
         })
         .await
@@ -672,11 +2202,9 @@ impl Application {
 
     /// Gets the list of owners for a package
     pub async fn get_crate_owners(&self, auth_data: &AuthData, package: &str) -> Result<OwnersQueryResult, ApiError> {
-        let public_read = self.configuration.self_public_read;
         self.db_transaction_read(|app| async move {
-            if !public_read {
-                let _authentication = app.authenticate(auth_data).await?;
-            }
+            let authentication = app.authenticate_for_read(auth_data).await?;
+            app.check_can_read_crate(authentication.as_ref(), package).await?;
             app.database.get_crate_owners(package).await
         })
         .await
@@ -690,9 +2218,29 @@ impl Application {
         new_users: &[String],
     ) -> Result<YesNoMsgResult, ApiError> {
         self.db_transaction_write("add_crate_owners", |app| async move {
-            let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package).await?;
-            app.database.add_crate_owners(package, new_users).await
+            let authentication = app
+                .authenticate_mutation(
+                    auth_data,
+                    &PasetoMutation {
+                        mutation: "owners",
+                        name: package,
+                        vers: None,
+                        cksum: None,
+                    },
+                )
+                .await?;
+            app.check_can_manage_crate(&authentication, package, Some(TokenOperation::ChangeOwners))
+                .await?;
+            let result = app.database.add_crate_owners(package, new_users).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "add_crate_owners",
+                package,
+                Some(new_users.join(", ")),
+            )
+            .await?;
+            Ok(result)
         })
         .await
     }
@@ -705,9 +2253,29 @@ impl Application {
         old_users: &[String],
     ) -> Result<YesNoResult, ApiError> {
         self.db_transaction_write("remove_crate_owners", |app| async move {
-            let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package).await?;
-            app.database.remove_crate_owners(package, old_users).await
+            let authentication = app
+                .authenticate_mutation(
+                    auth_data,
+                    &PasetoMutation {
+                        mutation: "owners",
+                        name: package,
+                        vers: None,
+                        cksum: None,
+                    },
+                )
+                .await?;
+            app.check_can_manage_crate(&authentication, package, Some(TokenOperation::ChangeOwners))
+                .await?;
+            let result = app.database.remove_crate_owners(package, old_users).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "remove_crate_owners",
+                package,
+                Some(old_users.join(", ")),
+            )
+            .await?;
+            Ok(result)
         })
         .await
     }
@@ -715,7 +2283,8 @@ impl Application {
     /// Gets the targets for a crate
     pub async fn get_crate_targets(&self, auth_data: &AuthData, package: &str) -> Result<Vec<CrateInfoTarget>, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(Some(&authentication), package).await?;
             app.database.get_crate_targets(package).await
         })
         .await
@@ -731,7 +2300,7 @@ impl Application {
         let (user, jobs) = self
             .db_transaction_write("set_crate_targets", |app| async move {
                 let authentication = app.authenticate(auth_data).await?;
-                let principal_uid = app.check_can_manage_crate(&authentication, package).await?;
+                let principal_uid = app.check_can_manage_crate(&authentication, package, None).await?;
                 let user = app.database.get_user_profile(principal_uid).await?;
                 for info in targets {
                     if !self.configuration.self_known_targets.contains(&info.target) {
@@ -742,6 +2311,14 @@ impl Application {
                     }
                 }
                 let jobs = app.database.set_crate_targets(package, targets).await?;
+                app.audit(
+                    auth_data,
+                    &authentication,
+                    "set_crate_targets",
+                    package,
+                    Some(targets.iter().map(|info| info.target.as_str()).collect::<Vec<_>>().join(", ")),
+                )
+                .await?;
                 for job in &jobs {
                     app.database
                         .set_crate_documentation(&job.package, &job.version, &job.target, false, false)
@@ -761,7 +2338,8 @@ impl Application {
     /// Gets the required capabilities for a crate
     pub async fn get_crate_required_capabilities(&self, auth_data: &AuthData, package: &str) -> Result<Vec<String>, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(Some(&authentication), package).await?;
             app.database.get_crate_required_capabilities(package).await
         })
         .await
@@ -776,8 +2354,16 @@ impl Application {
     ) -> Result<(), ApiError> {
         self.db_transaction_write("set_crate_required_capabilities", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            let _ = app.check_can_manage_crate(&authentication, package).await?;
+            let _ = app.check_can_manage_crate(&authentication, package, None).await?;
             app.database.set_crate_required_capabilities(package, capabilities).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "set_crate_required_capabilities",
+                package,
+                Some(capabilities.join(", ")),
+            )
+            .await?;
             Ok::<_, ApiError>(())
         })
         .await?;
@@ -785,11 +2371,22 @@ impl Application {
     }
 
     /// Sets the deprecation status on a crate
-    pub async fn set_crate_deprecation(&self, auth_data: &AuthData, package: &str, deprecated: bool) -> Result<(), ApiError> {
+    pub async fn set_crate_deprecation(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        deprecation: &CrateDeprecation,
+    ) -> Result<(), ApiError> {
         self.db_transaction_write("set_crate_deprecation", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package).await?;
-            app.database.set_crate_deprecation(package, deprecated).await
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.set_crate_deprecation(package, deprecation).await?;
+            let details = deprecation.use_instead.as_ref().map_or_else(
+                || format!("deprecated: {}", deprecation.is_deprecated),
+                |use_instead| format!("deprecated: {}, useInstead: {use_instead}", deprecation.is_deprecated),
+            );
+            app.audit(auth_data, &authentication, "set_crate_deprecation", package, Some(details))
+                .await
         })
         .await
     }
@@ -798,8 +2395,85 @@ impl Application {
     pub async fn set_crate_can_remove(&self, auth_data: &AuthData, package: &str, can_remove: bool) -> Result<(), ApiError> {
         self.db_transaction_write("set_crate_can_remove", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package).await?;
-            app.database.set_crate_can_remove(package, can_remove).await
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.set_crate_can_remove(package, can_remove).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "set_crate_can_remove",
+                package,
+                Some(format!("canRemove: {can_remove}")),
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Gets the readers of a crate, in addition to its owners
+    pub async fn get_crate_readers(&self, auth_data: &AuthData, package: &str) -> Result<CrateReaders, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.get_crate_readers(package).await
+        })
+        .await
+    }
+
+    /// Adds readers to a crate
+    pub async fn add_crate_readers(&self, auth_data: &AuthData, package: &str, readers: &[String]) -> Result<(), ApiError> {
+        self.db_transaction_write("add_crate_readers", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.add_crate_readers(package, readers).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "add_crate_readers",
+                package,
+                Some(readers.join(", ")),
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Removes readers from a crate
+    pub async fn remove_crate_readers(&self, auth_data: &AuthData, package: &str, readers: &[String]) -> Result<(), ApiError> {
+        self.db_transaction_write("remove_crate_readers", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.remove_crate_readers(package, readers).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "remove_crate_readers",
+                package,
+                Some(readers.join(", ")),
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Sets who can read a crate
+    pub async fn set_crate_visibility(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        visibility: CrateVisibility,
+    ) -> Result<(), ApiError> {
+        self.db_transaction_write("set_crate_visibility", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None).await?;
+            app.database.set_crate_visibility(package, visibility).await?;
+            app.audit(
+                auth_data,
+                &authentication,
+                "set_crate_visibility",
+                package,
+                Some(format!("visibility: {visibility}")),
+            )
+            .await
         })
         .await
     }
@@ -807,8 +2481,9 @@ impl Application {
     /// Gets the global statistics for the registry
     pub async fn get_crates_stats(&self, auth_data: &AuthData) -> Result<GlobalStats, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
-            app.database.get_crates_stats().await
+            let authentication = app.authenticate(auth_data).await?;
+            let hidden = app.get_hidden_crates(Some(&authentication)).await?;
+            app.database.get_crates_stats(&hidden).await
         })
         .await
     }
@@ -821,12 +2496,14 @@ impl Application {
         per_page: Option<usize>,
         deprecated: Option<bool>,
     ) -> Result<SearchResults, ApiError> {
-        let public_read = self.configuration.self_public_read;
+        self.check_rate_limit(auth_data)?;
         self.db_transaction_read(|app| async move {
-            if !public_read {
-                let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate_for_read(auth_data).await?;
+            if let Some(authentication) = &authentication {
+                self.check_user_rate_limit(authentication)?;
             }
-            app.database.search_crates(query, per_page, deprecated).await
+            let hidden = app.get_hidden_crates(authentication.as_ref()).await?;
+            app.database.search_crates(query, per_page, deprecated, &hidden).await
         })
         .await
     }
@@ -838,15 +2515,24 @@ impl Application {
         package: &str,
         version: &str,
     ) -> Result<DepsAnalysis, ApiError> {
+        self.check_rate_limit(auth_data)?;
         let targets = self
             .db_transaction_read(|app| async move {
-                let _authentication = app.authenticate(auth_data).await?;
+                let authentication = app.authenticate(auth_data).await?;
+                self.check_user_rate_limit(&authentication)?;
+                app.check_can_read_crate(Some(&authentication), package).await?;
                 app.database.check_crate_exists(package, version).await?;
                 app.database.get_crate_targets(package).await
             })
             .await?;
         let targets = targets.into_iter().map(|info| info.target).collect::<Vec<_>>();
-        self.service_deps_checker.check_crate(package, version, &targets).await
+        let mut analysis = self.service_deps_checker.check_crate(package, version, &targets).await?;
+        self.db_transaction_read(|app| {
+            let analysis = &mut analysis;
+            async move { app.database.complete_deps_analysis(analysis).await }
+        })
+        .await?;
+        Ok(analysis)
     }
 }
 
@@ -861,12 +2547,79 @@ pub(crate) struct ApplicationWithTransaction<'a> {
 impl ApplicationWithTransaction<'_> {
     /// Attempts the authentication of a user
     async fn authenticate(&self, auth_data: &AuthData) -> Result<Authentication, ApiError> {
-        if let Some(token) = &auth_data.token {
+        self.authenticate_for(auth_data, None).await
+    }
+
+    /// Attempts the authentication of a user for a mutation on a crate
+    /// An asymmetric token must then claim this exact mutation
+    async fn authenticate_mutation(
+        &self,
+        auth_data: &AuthData,
+        mutation: &PasetoMutation<'_>,
+    ) -> Result<Authentication, ApiError> {
+        self.authenticate_for(auth_data, Some(mutation)).await
+    }
+
+    /// Attempts the authentication of a user for publishing a crate version
+    /// Returns the authentication, the profile of the user and whether the crate is new
+    async fn authenticate_publish(
+        &self,
+        auth_data: &AuthData,
+        package: &CrateUploadData,
+        cksum: &str,
+    ) -> Result<(Authentication, RegistryUser, bool), ApiError> {
+        let authentication = self
+            .authenticate_mutation(
+                auth_data,
+                &PasetoMutation {
+                    mutation: "publish",
+                    name: &package.metadata.name,
+                    vers: Some(&package.metadata.vers),
+                    cksum: Some(cksum),
+                },
+            )
+            .await?;
+        let is_new_crate = !self.database.get_crate_exists(&package.metadata.name).await?;
+        let operation = if is_new_crate {
+            TokenOperation::PublishNew
+        } else {
+            TokenOperation::PublishUpdate
+        };
+        authentication.check_can_operate_on_crate(&package.metadata.name, Some(operation))?;
+        let user = self.database.get_user_profile(authentication.uid()?).await?;
+        Ok((authentication, user, is_new_crate))
+    }
+
+    /// Attempts the authentication of a user, with the mutation expected for asymmetric tokens, if any
+    async fn authenticate_for(
+        &self,
+        auth_data: &AuthData,
+        mutation: Option<&PasetoMutation<'_>>,
+    ) -> Result<Authentication, ApiError> {
+        let result = if let Some(paseto) = &auth_data.paseto {
+            self.application.check_auth_failures(auth_data)?;
+            self.authenticate_paseto(paseto, mutation).await
+        } else if let Some(token) = &auth_data.token {
+            self.application.check_auth_failures(auth_data)?;
             self.authenticate_token(token).await
         } else {
             let authentication = auth_data.try_authenticate_cookie()?.ok_or_else(error_unauthorized)?;
             self.database.check_is_user(authentication.email()?).await?;
-            Ok(authentication)
+            return Ok(authentication);
+        };
+        if result.as_ref().is_err_and(|error| error.http == 401) {
+            self.application.record_auth_failure(auth_data);
+        }
+        result
+    }
+
+    /// Attempts the authentication of a user for reading crates
+    /// When the registry can be publicly read, the authentication is optional
+    async fn authenticate_for_read(&self, auth_data: &AuthData) -> Result<Option<Authentication>, ApiError> {
+        if self.application.configuration.self_public_read {
+            Ok(self.authenticate(auth_data).await.ok())
+        } else {
+            self.authenticate(auth_data).await.map(Some)
         }
     }
 
@@ -891,19 +2644,206 @@ impl ApplicationWithTransaction<'_> {
         Ok(user)
     }
 
+    /// Tries to authenticate using an asymmetric token
+    async fn authenticate_paseto(
+        &self,
+        paseto: &PasetoToken,
+        mutation: Option<&PasetoMutation<'_>>,
+    ) -> Result<Authentication, ApiError> {
+        let footer = paseto.footer()?;
+        // the token must be intended for this registry, either through the sparse or the git index
+        let expected_url = self.application.configuration.web_public_uri.trim_end_matches('/');
+        let url = footer.url.strip_prefix("sparse+").unwrap_or(&footer.url);
+        if url.trim_end_matches('/') != expected_url {
+            return Err(specialize(
+                error_unauthorized(),
+                String::from("the asymmetric token is not intended for this registry"),
+            ));
+        }
+        let (authentication, claims) = self
+            .database
+            .check_paseto_token(paseto, &footer.kip, &|usage| async move {
+                self.application
+                    .app_events_sender
+                    .send(AppEvent::TokenUse(usage))
+                    .await
+                    .unwrap();
+            })
+            .await?;
+        claims.check(Utc::now().naive_utc(), mutation)?;
+        Ok(authentication)
+    }
+
     /// Checks that the given authentication can perform admin tasks
     async fn check_can_admin_registry(&self, authentication: &Authentication) -> Result<i64, ApiError> {
         authentication.check_can_admin()?;
+        // a token scoped to some crates or operations shall not administrate the registry
+        authentication.scopes.check_is_unrestricted()?;
         let principal_uid = authentication.uid()?;
         self.database.check_is_admin(principal_uid).await?;
         Ok(principal_uid)
     }
 
+    /// Gets the restricted crates that cannot be read with the given authentication, if any
+    async fn get_hidden_crates(&self, authentication: Option<&Authentication>) -> Result<Vec<String>, ApiError> {
+        let uid = match authentication.map(|authentication| &authentication.principal) {
+            None => None,
+            // the registry itself and services through global tokens read all crates
+            Some(AuthenticationPrincipal::SelfAuth | AuthenticationPrincipal::Service { .. }) => return Ok(Vec::new()),
+            Some(AuthenticationPrincipal::User { uid, .. } | AuthenticationPrincipal::TrustedPublisher { uid, .. }) => {
+                if self.database.get_is_admin(*uid).await? {
+                    return Ok(Vec::new());
+                }
+                Some(*uid)
+            }
+        };
+        self.database.get_hidden_crates(uid).await
+    }
+
+    /// Checks that the given authentication, if any, can read a crate
+    /// A crate that cannot be read is reported as not found
+    async fn check_can_read_crate(&self, authentication: Option<&Authentication>, package: &str) -> Result<(), ApiError> {
+        let hidden = self.get_hidden_crates(authentication).await?;
+        if is_hidden_crate(&hidden, package) {
+            return Err(error_not_found());
+        }
+        Ok(())
+    }
+
     /// Checks that the given authentication can manage a given crate
-    async fn check_can_manage_crate(&self, authentication: &Authentication, package: &str) -> Result<i64, ApiError> {
-        authentication.check_can_write()?;
+    /// When no specific operation is given, the authentication must not be restricted to specific operations
+    async fn check_can_manage_crate(
+        &self,
+        authentication: &Authentication,
+        package: &str,
+        operation: Option<TokenOperation>,
+    ) -> Result<i64, ApiError> {
+        authentication.check_can_operate_on_crate(package, operation)?;
         let principal_uid = authentication.uid()?;
         self.database.check_is_crate_manager(principal_uid, package).await?;
         Ok(principal_uid)
     }
+
+    /// Marks the documentation of a newly published crate version as pending for its targets
+    /// Returns the targets and the required capabilities for the documentation jobs
+    async fn prepare_crate_version_docs(
+        &self,
+        package: &str,
+        version: &str,
+    ) -> Result<(Vec<CrateInfoTarget>, Vec<String>), ApiError> {
+        let mut targets = self.database.get_crate_targets(package).await?;
+        if targets.is_empty() {
+            targets.push(CrateInfoTarget {
+                target: self.application.configuration.self_toolchain_host.clone(),
+                docs_use_native: true,
+            });
+        }
+        for info in &targets {
+            self.database
+                .set_crate_documentation(package, version, &info.target, false, false)
+                .await?;
+        }
+        let capabilities = self.database.get_crate_required_capabilities(package).await?;
+        Ok((targets, capabilities))
+    }
+
+    /// Records an action performed with an authentication in the audit log
+    async fn audit(
+        &self,
+        auth_data: &AuthData,
+        authentication: &Authentication,
+        action: &str,
+        target: &str,
+        details: Option<String>,
+    ) -> Result<(), ApiError> {
+        self.audit_as(
+            auth_data,
+            authentication.actor(),
+            authentication.token_id.clone(),
+            action,
+            target,
+            details,
+        )
+        .await
+    }
+
+    /// Records an action performed by the identity provider through SCIM in the audit log
+    async fn audit_scim(
+        &self,
+        auth_data: &AuthData,
+        action: &str,
+        target: &str,
+        details: Option<String>,
+    ) -> Result<(), ApiError> {
+        self.audit_as(auth_data, String::from(AUDIT_ACTOR_SCIM), None, action, target, details)
+            .await
+    }
+
+    /// Records an action performed by an actor in the audit log
+    async fn audit_as(
+        &self,
+        auth_data: &AuthData,
+        actor: String,
+        token_id: Option<String>,
+        action: &str,
+        target: &str,
+        details: Option<String>,
+    ) -> Result<(), ApiError> {
+        self.database
+            .insert_audit_event(&AuditEvent {
+                id: 0,
+                timestamp: Local::now().naive_local(),
+                actor,
+                token_id,
+                client_ip: auth_data.client_ip.map(|ip| ip.to_string()),
+                action: action.to_string(),
+                target: target.to_string(),
+                details,
+            })
+            .await
+    }
+
+    /// Applies the state of a user, as provisioned by the identity provider
+    /// The email of a user cannot be changed
+    async fn scim_apply_user(&self, uid: i64, desired: &ScimUser) -> Result<ScimUser, ApiError> {
+        let mut user = self.database.get_user_profile(uid).await?;
+        if let Some(full_name) = desired.full_name() {
+            user.name = full_name;
+        }
+        self.database.update_user(None, &user, true).await?;
+        if desired.active != user.is_active {
+            if desired.active {
+                self.database.reactivate_user(&user.email).await?;
+            } else {
+                self.database.deactivate_user(None, &user.email).await?;
+            }
+            user.is_active = desired.active;
+        }
+        Ok(ScimUser::from_user(&user, &self.application.configuration.web_public_uri))
+    }
+
+    /// Applies the state of a group, as provisioned by the identity provider
+    /// The members of the group are then all synced from the identity provider
+    async fn scim_apply_group(&self, team_id: i64, desired: &ScimGroup) -> Result<ScimGroup, ApiError> {
+        let team = self.database.get_team(team_id).await?;
+        if team.name != desired.display_name {
+            self.database.rename_team(team_id, &desired.display_name).await?;
+        }
+        let mut members = Vec::with_capacity(desired.members.len());
+        for member in &desired.members {
+            let uid = parse_scim_id(&member.value)?;
+            self.database.get_user_profile(uid).await?;
+            members.push(uid);
+        }
+        for member in &team.members {
+            if !members.contains(&member.id) {
+                self.database.remove_team_member(team_id, member.id).await?;
+            }
+        }
+        for uid in members {
+            self.database.add_team_member(team_id, uid, true).await?;
+        }
+        let team = self.database.get_team(team_id).await?;
+        Ok(ScimGroup::from_team(&team, &self.application.configuration.web_public_uri))
+    }
 }
@@ -9,13 +9,14 @@ use std::pin::pin;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use axum::Router;
 use axum::extract::DefaultBodyLimit;
 use axum::routing::{delete, get, patch, post, put};
+use axum::{Router, middleware};
 use cookie::Key;
 use log::info;
 
 use crate::application::Application;
+use crate::model::config::Configuration;
 use crate::routes::AxumState;
 use crate::services::ServiceProvider;
 use crate::utils::sigterm::waiting_sigterm;
@@ -68,22 +69,54 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
         .route("/me", get(routes::webapp_me))
         // serve the documentation
         .route("/docs/{*path}", get(routes::get_docs_resource))
+        // SCIM provisioning by the identity provider
+        .nest(
+            "/scim/v2",
+            Router::new()
+                .route("/Users", get(routes::scim_get_users))
+                .route("/Users", post(routes::scim_create_user))
+                .route("/Users/{id}", get(routes::scim_get_user))
+                .route("/Users/{id}", put(routes::scim_replace_user))
+                .route("/Users/{id}", patch(routes::scim_patch_user))
+                .route("/Users/{id}", delete(routes::scim_delete_user))
+                .route("/Groups", get(routes::scim_get_groups))
+                .route("/Groups", post(routes::scim_create_group))
+                .route("/Groups/{id}", get(routes::scim_get_group))
+                .route("/Groups/{id}", put(routes::scim_replace_group))
+                .route("/Groups/{id}", patch(routes::scim_patch_group))
+                .route("/Groups/{id}", delete(routes::scim_delete_group)),
+        )
         // API
         .nest(
             "/api/v1",
             Router::new()
                 .route("/version", get(routes::get_version))
                 .route("/registry-information", get(routes::api_v1_get_registry_information))
+                .route("/attestation-key", get(routes::api_v1_get_attestation_public_key))
                 .nest(
                     "/me",
-                    Router::new().route("/", get(routes::api_v1_get_current_user)).nest(
-                        "/tokens",
-                        Router::new()
-                            .route("/", get(routes::api_v1_get_user_tokens))
-                            .route("/", put(routes::api_v1_create_user_token))
-                            .route("/{token_id}", delete(routes::api_v1_revoke_user_token)),
-                    ),
+                    Router::new()
+                        .route("/", get(routes::api_v1_get_current_user))
+                        .nest(
+                            "/tokens",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_user_tokens))
+                                .route("/", put(routes::api_v1_create_user_token))
+                                .route("/{token_id}", delete(routes::api_v1_revoke_user_token)),
+                        )
+                        .nest(
+                            "/keys",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_user_public_keys))
+                                .route("/", put(routes::api_v1_add_user_public_key))
+                                .route("/{key_id}", delete(routes::api_v1_remove_user_public_key)),
+                        ),
                 )
+                .route(
+                    "/trusted_publishing/tokens",
+                    post(routes::api_v1_exchange_trusted_publishing_token),
+                )
+                .route("/oauth/login", get(routes::api_v1_oauth_login))
                 .route("/oauth/code", post(routes::api_v1_login_with_oauth_code))
                 .route("/logout", post(routes::api_v1_logout))
                 .nest(
@@ -105,12 +138,32 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                                 .route("/", put(routes::api_v1_create_global_token))
                                 .route("/{token_id}", delete(routes::api_v1_revoke_global_token)),
                         )
+                        .nest(
+                            "/teams",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_teams))
+                                .route("/{team}", put(routes::api_v1_create_team))
+                                .route("/{team}", delete(routes::api_v1_delete_team))
+                                .route("/{team}/members", put(routes::api_v1_add_team_members))
+                                .route("/{team}/members", delete(routes::api_v1_remove_team_members)),
+                        )
+                        .nest(
+                            "/names",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_crate_name_policies))
+                                .route("/", put(routes::api_v1_create_crate_name_policy))
+                                .route("/{policy_id}", delete(routes::api_v1_delete_crate_name_policy)),
+                        )
+                        .route("/audit", get(routes::api_v1_get_audit_events))
+                        .route("/audit/export", get(routes::api_v1_export_audit_events))
                         .route("/jobs/docgen", get(routes::api_v1_get_doc_gen_jobs))
                         .route("/jobs/docgen/updates", get(routes::api_v1_get_doc_gen_job_updates))
                         .route("/jobs/docgen/{job_id}/log", get(routes::api_v1_get_doc_gen_job_log))
                         .route("/workers", get(routes::api_v1_get_workers))
                         .route("/workers/updates", get(routes::api_v1_get_workers_updates))
-                        .route("/workers/connect", get(routes::api_v1_worker_connect)),
+                        .route("/workers/connect", get(routes::api_v1_worker_connect))
+                        .route("/index/check", get(routes::api_v1_check_index))
+                        .route("/index/rebuild", post(routes::api_v1_rebuild_index)),
                 )
                 .nest(
                     "/crates",
@@ -125,14 +178,31 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                         .route("/{package}/{version}", delete(routes::api_v1_remove_crate_version))
                         .route("/{package}/{version}/readme", get(routes::api_v1_get_crate_readme))
                         .route("/{package}/{version}/download", get(routes::api_v1_download_crate))
+                        .route("/{package}/{version}/attestation", get(routes::api_v1_get_crate_attestation))
+                        .route("/{package}/{version}/files", get(routes::api_v1_get_crate_files))
+                        .route("/{package}/{version}/files/{*path}", get(routes::api_v1_get_crate_file))
                         .route("/{package}/{version}/yank", delete(routes::api_v1_cargo_yank))
                         .route("/{package}/{version}/unyank", put(routes::api_v1_cargo_unyank))
                         .route("/{package}/{version}/docsregen", post(routes::api_v1_regen_crate_version_doc))
                         .route("/{package}/{version}/checkdeps", get(routes::api_v1_check_crate_version))
+                        .route("/{package}/diff/{from}/{to}", get(routes::api_v1_get_crate_versions_diff))
+                        .route("/{package}/semverchecks", get(routes::api_v1_get_crate_semver_checks))
                         .route("/{package}/dlstats", get(routes::api_v1_get_crate_dl_stats))
                         .route("/{package}/owners", get(routes::api_v1_cargo_get_crate_owners))
                         .route("/{package}/owners", put(routes::api_v1_cargo_add_crate_owners))
                         .route("/{package}/owners", delete(routes::api_v1_cargo_remove_crate_owners))
+                        .route(
+                            "/{package}/trustedpublishers",
+                            get(routes::api_v1_get_crate_trusted_publishers),
+                        )
+                        .route(
+                            "/{package}/trustedpublishers",
+                            put(routes::api_v1_add_crate_trusted_publisher),
+                        )
+                        .route(
+                            "/{package}/trustedpublishers/{publisher_id}",
+                            delete(routes::api_v1_remove_crate_trusted_publisher),
+                        )
                         .route("/{package}/targets", get(routes::api_v1_get_crate_targets))
                         .route("/{package}/targets", patch(routes::api_v1_set_crate_targets))
                         .route("/{package}/capabilities", get(routes::api_v1_get_crate_required_capabilities))
@@ -141,12 +211,28 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                             patch(routes::api_v1_set_crate_required_capabilities),
                         )
                         .route("/{package}/deprecated", patch(routes::api_v1_set_crate_deprecation))
-                        .route("/{package}/canremove", patch(routes::api_v1_set_crate_can_remove)),
+                        .route("/{package}/canremove", patch(routes::api_v1_set_crate_can_remove))
+                        .route("/{package}/visibility", patch(routes::api_v1_set_crate_visibility))
+                        .route("/{package}/approval", patch(routes::api_v1_set_crate_requires_approval))
+                        .route("/{package}/pending", get(routes::api_v1_get_crate_pending_versions))
+                        .route("/{package}/pending/{version}", delete(routes::api_v1_reject_crate_version))
+                        .route(
+                            "/{package}/pending/{version}/download",
+                            get(routes::api_v1_download_crate_pending_version),
+                        )
+                        .route(
+                            "/{package}/pending/{version}/approve",
+                            post(routes::api_v1_approve_crate_version),
+                        )
+                        .route("/{package}/readers", get(routes::api_v1_get_crate_readers))
+                        .route("/{package}/readers", put(routes::api_v1_add_crate_readers))
+                        .route("/{package}/readers", delete(routes::api_v1_remove_crate_readers)),
                 ),
         )
         // fall back to serving the index
         .fallback(routes::index_serve)
         .layer(DefaultBodyLimit::max(body_limit))
+        .layer(middleware::from_fn_with_state(state.clone(), routes::add_retry_after))
         .with_state(state);
     axum::serve(
         tokio::net::TcpListener::bind(socket_addr)
@@ -161,9 +247,9 @@ fn setup_log() {
     let log_date_time_format =
         std::env::var("REGISTRY_LOG_DATE_TIME_FORMAT").unwrap_or_else(|_| String::from("[%Y-%m-%d %H:%M:%S]"));
 
-    let log_level = std::env::var("REGISTRY_LOG_LEVEL")
-        .map(|v| log::LevelFilter::from_str(&v).expect("invalid REGISTRY_LOG_LEVEL"))
-        .unwrap_or(log::LevelFilter::Info);
+    let log_level = std::env::var("REGISTRY_LOG_LEVEL").map_or(log::LevelFilter::Info, |v| {
+        log::LevelFilter::from_str(&v).expect("invalid REGISTRY_LOG_LEVEL")
+    });
 
     fern::Dispatch::new()
         .filter(move |metadata| {
@@ -184,13 +270,29 @@ fn setup_log() {
         .expect("log configuration failed");
 }
 
+/// Checks the consistency between the database, the index and the storage, and optionally rebuilds the index
+/// Prints the report and exits with an error code when inconsistencies remain
+async fn main_check_index(configuration: &Configuration, rebuild: bool) {
+    let report = Application::check_index_standalone::<services::StandardServiceProvider>(configuration, rebuild)
+        .await
+        .unwrap();
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    if !report.unresolved.is_empty() || (!report.issues.is_empty() && !report.rebuilt) || report.push_error.is_some() {
+        std::process::exit(1);
+    }
+}
+
 /// Main entry point
 #[tokio::main]
 async fn main() {
     setup_log();
     info!("{CRATE_NAME} commit={GIT_HASH} tag={GIT_TAG}");
     let configuration = services::StandardServiceProvider::get_configuration().await.unwrap();
-    if configuration.self_role.is_worker() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|command| command == "check-index") {
+        let rebuild = args.iter().skip(1).any(|arg| arg == "--rebuild");
+        main_check_index(&configuration, rebuild).await;
+    } else if configuration.self_role.is_worker() {
         let _ = waiting_sigterm(pin!(worker::main_worker(configuration))).await;
     } else {
         // standalone or master
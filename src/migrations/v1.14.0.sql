@@ -0,0 +1,160 @@
+ALTER TABLE RegistryUserToken
+    ADD COLUMN crateScopes TEXT NOT NULL DEFAULT '';
+ALTER TABLE RegistryUserToken
+    ADD COLUMN operationScopes TEXT NOT NULL DEFAULT '';
+ALTER TABLE RegistryUserToken
+    ADD COLUMN expiresOn TIMESTAMP;
+ALTER TABLE RegistryUserToken
+    ADD COLUMN expiryNotified BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE RegistryGlobalToken
+    ADD COLUMN expiresOn TIMESTAMP;
+ALTER TABLE RegistryGlobalToken
+    ADD COLUMN expiryNotified BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE RegistryUserPublicKey (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user INTEGER NOT NULL REFERENCES RegistryUser(id),
+    name TEXT NOT NULL,
+    keyId TEXT NOT NULL,
+    publicKey TEXT NOT NULL,
+    lastUsed TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IndexRegistryUserPublicKey ON RegistryUserPublicKey (keyId);
+
+CREATE TABLE TrustedPublisher (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    issuer TEXT NOT NULL,
+    jwks TEXT NOT NULL,
+    repository TEXT NOT NULL,
+    branch TEXT,
+    workflow TEXT,
+    createdBy INTEGER NOT NULL REFERENCES RegistryUser(id)
+);
+
+CREATE INDEX IndexTrustedPublisher ON TrustedPublisher (package);
+
+CREATE TABLE TrustedPublishingToken (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    publisher INTEGER NOT NULL REFERENCES TrustedPublisher(id),
+    token TEXT NOT NULL,
+    expiresOn TIMESTAMP NOT NULL
+);
+
+CREATE TABLE Team (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL
+);
+
+CREATE INDEX IndexTeam ON Team (name);
+
+CREATE TABLE TeamMember (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    team INTEGER NOT NULL REFERENCES Team(id),
+    member INTEGER NOT NULL REFERENCES RegistryUser(id),
+    isSynced BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexTeamMember ON TeamMember (member);
+
+CREATE TABLE PackageTeamOwner (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    team INTEGER NOT NULL REFERENCES Team(id)
+);
+
+CREATE INDEX IndexPackageTeamOwner ON PackageTeamOwner (package);
+
+ALTER TABLE Package
+    ADD COLUMN visibility TEXT NOT NULL DEFAULT 'internal';
+
+CREATE TABLE PackageReader (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    reader INTEGER NOT NULL REFERENCES RegistryUser(id)
+);
+
+CREATE INDEX IndexPackageReader ON PackageReader (package);
+
+CREATE TABLE PackageTeamReader (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    team INTEGER NOT NULL REFERENCES Team(id)
+);
+
+CREATE INDEX IndexPackageTeamReader ON PackageTeamReader (package);
+
+CREATE TABLE AuditEvent (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    timestamp TIMESTAMP NOT NULL,
+    actor TEXT NOT NULL,
+    tokenId TEXT,
+    clientIp TEXT,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    details TEXT
+);
+
+CREATE INDEX IndexAuditEventTimestamp ON AuditEvent (timestamp);
+
+CREATE TABLE CrateNamePolicy (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    pattern TEXT NOT NULL,
+    team INTEGER REFERENCES Team(id),
+    reason TEXT
+);
+
+ALTER TABLE Package
+    ADD COLUMN requiresApproval BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE PackagePendingVersion (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    description TEXT NOT NULL,
+    upload TIMESTAMP NOT NULL,
+    uploadedBy INTEGER NOT NULL REFERENCES RegistryUser(id),
+    indexData TEXT NOT NULL
+);
+
+CREATE INDEX IndexPackagePendingVersion ON PackagePendingVersion(package);
+
+ALTER TABLE Package
+    ADD COLUMN deprecationMessage TEXT;
+
+ALTER TABLE Package
+    ADD COLUMN deprecationUseInstead TEXT;
+
+ALTER TABLE PackageVersion
+    ADD COLUMN yankReason TEXT;
+
+ALTER TABLE PackageVersion
+    ADD COLUMN depsHasYanked BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE PackageVersion
+    ADD COLUMN depsHasDeprecated BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE SemverCheckJob (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    baseline TEXT NOT NULL,
+    state INTEGER NOT NULL,
+    queuedOn TIMESTAMP NOT NULL,
+    startedOn TIMESTAMP NOT NULL,
+    finishedOn TIMESTAMP NOT NULL,
+    lastUpdate TIMESTAMP NOT NULL,
+    output TEXT NOT NULL
+);
+
+CREATE INDEX IndexSemverCheckJob ON SemverCheckJob (package);
+
+ALTER TABLE PackageVersion
+    ADD COLUMN indexData TEXT;
+
+ALTER TABLE PackageVersion
+    ADD COLUMN isPublishing BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE PackageVersion
+    ADD COLUMN indexUpdate TIMESTAMP;
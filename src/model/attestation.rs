@@ -0,0 +1,145 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for the signed attestations of published crates
+//! Attestations are in-toto statements, wrapped in DSSE envelopes
+
+use chrono::NaiveDateTime;
+use serde_derive::{Deserialize, Serialize};
+
+use super::cargo::{CrateVcsInfo, RegistryUser};
+
+/// The type of in-toto statements
+pub const IN_TOTO_STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+/// The type of the payload for DSSE envelopes containing in-toto statements
+pub const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+/// The type of the predicate for the publication of a crate version
+pub const PUBLISH_PREDICATE_TYPE: &str = "https://github.com/cenotelie/cratery/attestation/publish/v1";
+/// The signature algorithm for attestations
+pub const ATTESTATION_ALGORITHM: &str = "ed25519";
+
+/// An in-toto statement about the publication of a crate version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationStatement {
+    /// The type of statement, always `IN_TOTO_STATEMENT_TYPE`
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    /// The artifacts the statement is about
+    pub subject: Vec<AttestationSubject>,
+    /// The type of the predicate, always `PUBLISH_PREDICATE_TYPE`
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    /// The predicate about the publication
+    pub predicate: PublishPredicate,
+}
+
+impl AttestationStatement {
+    /// Creates the statement for the publication of a crate version
+    #[must_use]
+    pub fn new(name: &str, version: &str, cksum: &str, predicate: PublishPredicate) -> Self {
+        Self {
+            statement_type: IN_TOTO_STATEMENT_TYPE.to_string(),
+            subject: vec![AttestationSubject {
+                name: format!("{name}-{version}.crate"),
+                digest: AttestationDigest {
+                    sha256: cksum.to_string(),
+                },
+            }],
+            predicate_type: PUBLISH_PREDICATE_TYPE.to_string(),
+            predicate,
+        }
+    }
+}
+
+/// An artifact in a statement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationSubject {
+    /// The name of the artifact, the `.crate` package
+    pub name: String,
+    /// The digests of the artifact
+    pub digest: AttestationDigest,
+}
+
+/// The digests of an artifact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationDigest {
+    /// The SHA-256 digest, as for the checksum in the index
+    pub sha256: String,
+}
+
+/// The predicate about the publication of a crate version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishPredicate {
+    /// The public URI of the registry
+    pub registry: String,
+    /// The user that published the crate version
+    pub publisher: AttestationPublisher,
+    /// The identifier of the token used for the publication, if any, as `<kind>:<id>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// The date time of the publication
+    #[serde(rename = "publishedAt")]
+    pub published_at: NaiveDateTime,
+    /// The information about the version control system, as packaged by cargo, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vcs: Option<CrateVcsInfo>,
+}
+
+/// The user that published a crate version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationPublisher {
+    /// The unique identifier of the user
+    pub id: i64,
+    /// The login of the user
+    pub login: String,
+    /// The email of the user
+    pub email: String,
+    /// The name of the user
+    pub name: String,
+}
+
+impl From<&RegistryUser> for AttestationPublisher {
+    fn from(user: &RegistryUser) -> Self {
+        Self {
+            id: user.id,
+            login: user.login.clone(),
+            email: user.email.clone(),
+            name: user.name.clone(),
+        }
+    }
+}
+
+/// A DSSE envelope for a signed statement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationEnvelope {
+    /// The type of the payload, always `IN_TOTO_PAYLOAD_TYPE`
+    #[serde(rename = "payloadType")]
+    pub payload_type: String,
+    /// The serialized statement, encoded in base64
+    pub payload: String,
+    /// The signatures of the payload
+    pub signatures: Vec<AttestationSignature>,
+}
+
+/// A signature in a DSSE envelope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationSignature {
+    /// The identifier of the key, the SHA-256 of the raw public key
+    pub keyid: String,
+    /// The signature, encoded in base64
+    pub sig: String,
+}
+
+/// The public key to verify the attestations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationPublicKey {
+    /// The identifier of the key, as found in the signatures
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    /// The signature algorithm
+    pub algorithm: String,
+    /// The public key, in the PEM format
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+}
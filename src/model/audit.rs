@@ -0,0 +1,54 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for the audit log of security-relevant actions
+
+use chrono::NaiveDateTime;
+use serde_derive::{Deserialize, Serialize};
+
+/// The default maximum number of events returned by a query on the audit log
+pub const AUDIT_DEFAULT_LIMIT: i64 = 100;
+
+/// The actor recorded for operations performed by the identity provider through SCIM
+pub const AUDIT_ACTOR_SCIM: &str = "scim";
+
+/// An event in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// The unique identifier
+    pub id: i64,
+    /// The time of the event
+    pub timestamp: NaiveDateTime,
+    /// The principal that performed the action, usually the email of a user
+    pub actor: String,
+    /// The token used by the actor, if any, as `<kind>:<id>`
+    #[serde(rename = "tokenId")]
+    pub token_id: Option<String>,
+    /// The IP of the client, if known
+    #[serde(rename = "clientIp")]
+    pub client_ip: Option<String>,
+    /// The action that was performed, e.g. `yank_crate_version`
+    pub action: String,
+    /// The target of the action, e.g. a crate, a user or a team
+    pub target: String,
+    /// Additional details, if any
+    pub details: Option<String>,
+}
+
+/// The filters for a query on the audit log
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    /// Only the events for this actor
+    pub actor: Option<String>,
+    /// Only the events for this action
+    pub action: Option<String>,
+    /// Only the events for this target
+    pub target: Option<String>,
+    /// Only the events at or after this time
+    pub since: Option<NaiveDateTime>,
+    /// Only the events at or before this time
+    pub until: Option<NaiveDateTime>,
+    /// The maximum number of events, the most recent first
+    pub limit: Option<i64>,
+}
@@ -4,14 +4,21 @@
 
 //! Objects related to authentication
 
-use chrono::NaiveDateTime;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use chrono::{Local, NaiveDateTime, TimeDelta};
 use serde_derive::{Deserialize, Serialize};
 
 use crate::utils::apierror::{ApiError, error_forbidden, error_invalid_request, specialize};
+use crate::utils::comma_sep_to_vec;
 
 /// The admin role
 pub const ROLE_ADMIN: &str = "admin";
 
+/// The prefix for the login of short-lived tokens obtained by trusted publishers
+pub const TRUSTED_PUBLISHING_LOGIN_PREFIX: &str = "trusted-publishing-";
+
 /// Represents a data about a successful authentication
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Authentication {
@@ -23,6 +30,12 @@ pub struct Authentication {
     /// Whether administration can be done
     #[serde(rename = "canAdmin")]
     pub can_admin: bool,
+    /// The scopes restricting the operations on crates
+    #[serde(default)]
+    pub scopes: TokenScopes,
+    /// The identifier of the token used for the authentication, if any, as `<kind>:<id>`
+    #[serde(rename = "tokenId", default, skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<String>,
 }
 
 impl Authentication {
@@ -33,6 +46,8 @@ impl Authentication {
             principal: AuthenticationPrincipal::SelfAuth,
             can_write: false,
             can_admin: false,
+            scopes: TokenScopes::unrestricted(),
+            token_id: None,
         }
     }
 
@@ -43,6 +58,8 @@ impl Authentication {
             principal: AuthenticationPrincipal::Service { token_id },
             can_write: false,
             can_admin: false,
+            scopes: TokenScopes::unrestricted(),
+            token_id: None,
         }
     }
 
@@ -53,12 +70,17 @@ impl Authentication {
             principal: AuthenticationPrincipal::User { uid, email },
             can_write: true,
             can_admin: true,
+            scopes: TokenScopes::unrestricted(),
+            token_id: None,
         }
     }
 
     /// Gets the uid of the associated user
+    /// For a trusted publisher, this is the user that configured it
     pub fn uid(&self) -> Result<i64, ApiError> {
-        if let AuthenticationPrincipal::User { uid, email: _ } = &self.principal {
+        if let AuthenticationPrincipal::User { uid, email: _ } | AuthenticationPrincipal::TrustedPublisher { uid, .. } =
+            &self.principal
+        {
             Ok(*uid)
         } else {
             Err(specialize(
@@ -68,6 +90,17 @@ impl Authentication {
         }
     }
 
+    /// Gets a description of the principal, as recorded in the audit log
+    #[must_use]
+    pub fn actor(&self) -> String {
+        match &self.principal {
+            AuthenticationPrincipal::User { email, .. } => email.clone(),
+            AuthenticationPrincipal::Service { token_id } => format!("service:{token_id}"),
+            AuthenticationPrincipal::SelfAuth => String::from("self"),
+            AuthenticationPrincipal::TrustedPublisher { publisher_id, .. } => format!("trusted-publisher:{publisher_id}"),
+        }
+    }
+
     /// Gets the email of the associated user
     pub fn email(&self) -> Result<&str, ApiError> {
         if let AuthenticationPrincipal::User { uid: _, email } = &self.principal {
@@ -92,6 +125,13 @@ impl Authentication {
         }
     }
 
+    /// Checks that this authentication enables an operation on a crate
+    /// When no specific operation is given, the authentication must not be restricted to specific operations
+    pub fn check_can_operate_on_crate(&self, package: &str, operation: Option<TokenOperation>) -> Result<(), ApiError> {
+        self.check_can_write()?;
+        self.scopes.check_can_operate_on_crate(package, operation)
+    }
+
     /// Checks that this authentication enables admin tasks
     pub fn check_can_admin(&self) -> Result<(), ApiError> {
         if self.can_admin {
@@ -114,6 +154,86 @@ pub enum AuthenticationPrincipal {
     Service { token_id: String },
     /// The registry itself when connecting to itself
     SelfAuth,
+    /// A CI pipeline through a short-lived token obtained from a trusted publisher
+    TrustedPublisher { publisher_id: i64, uid: i64 },
+}
+
+/// A trusted publisher for a crate
+/// A CI pipeline presenting an OIDC token that matches can exchange it for a short-lived token to publish the crate
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrustedPublisher {
+    /// The unique identifier
+    pub id: i64,
+    /// The crate that can be published
+    pub package: String,
+    /// The expected issuer of the OIDC tokens
+    pub issuer: String,
+    /// The location of the JWKS for the issuer, either a local file or an HTTP(S) URL
+    pub jwks: String,
+    /// The expected repository (`repository` or `project_path` claims)
+    pub repository: String,
+    /// The expected branch (`ref` claim), if any
+    pub branch: Option<String>,
+    /// The expected workflow (`workflow`, `workflow_ref` or `job_workflow_ref` claims), if any
+    pub workflow: Option<String>,
+    /// The uid of the user who configured the publisher and on behalf of whom the crate is published
+    #[serde(rename = "createdBy")]
+    pub created_by: i64,
+}
+
+impl TrustedPublisher {
+    /// Checks whether the claims of a verified OIDC token match this publisher
+    #[must_use]
+    pub fn matches_claims(&self, claims: &serde_json::Value) -> bool {
+        let claim = |name: &str| claims.get(name).and_then(serde_json::Value::as_str);
+        let repository = claim("repository").or_else(|| claim("project_path"));
+        if repository != Some(self.repository.as_str()) {
+            return false;
+        }
+        if let Some(branch) = &self.branch {
+            let git_ref = claim("ref").unwrap_or_default();
+            if git_ref != branch && git_ref.strip_prefix("refs/heads/") != Some(branch.as_str()) {
+                return false;
+            }
+        }
+        if let Some(workflow) = &self.workflow {
+            let in_ref = |name: &str| {
+                claim(name).is_some_and(|value| {
+                    let path = value.split_once('@').map_or(value, |(path, _)| path);
+                    path.rsplit('/').next() == Some(workflow.as_str())
+                })
+            };
+            if claim("workflow") != Some(workflow.as_str()) && !in_ref("workflow_ref") && !in_ref("job_workflow_ref") {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The specification for a new trusted publisher
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrustedPublisherSpec {
+    /// The expected issuer of the OIDC tokens
+    pub issuer: String,
+    /// The location of the JWKS for the issuer, either a local file or an HTTP(S) URL
+    pub jwks: String,
+    /// The expected repository
+    pub repository: String,
+    /// The expected branch, if any
+    pub branch: Option<String>,
+    /// The expected workflow, if any
+    pub workflow: Option<String>,
+}
+
+/// A short-lived token obtained by a trusted publisher
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrustedPublishingToken {
+    /// The value to use for the `authorization` header, e.g. in `CARGO_REGISTRY_TOKEN`
+    pub token: String,
+    /// The time when the token expires
+    #[serde(rename = "expiresOn")]
+    pub expires_on: NaiveDateTime,
 }
 
 /// A token for a registry user
@@ -126,12 +246,35 @@ pub struct RegistryUserToken {
     /// The last time the token was used
     #[serde(rename = "lastUsed")]
     pub last_used: NaiveDateTime,
+    /// The time when the token expires, if any
+    #[serde(rename = "expiresOn")]
+    pub expires_on: Option<NaiveDateTime>,
     /// Whether a crate can be uploaded using this token
     #[serde(rename = "canWrite")]
     pub can_write: bool,
     /// Whether administration can be done using this token through the API
     #[serde(rename = "canAdmin")]
     pub can_admin: bool,
+    /// The scopes restricting the operations on crates using this token
+    pub scopes: TokenScopes,
+}
+
+/// A public key registered by a user for cargo asymmetric tokens
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegistryUserPublicKey {
+    /// The unique identifier
+    pub id: i64,
+    /// The key name
+    pub name: String,
+    /// The PASERK identifier for the key
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    /// The public key in the PASERK format
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    /// The last time the key was used
+    #[serde(rename = "lastUsed")]
+    pub last_used: NaiveDateTime,
 }
 
 /// A token for a registry user
@@ -146,12 +289,205 @@ pub struct RegistryUserTokenWithSecret {
     /// The last time the token was used
     #[serde(rename = "lastUsed")]
     pub last_used: NaiveDateTime,
+    /// The time when the token expires, if any
+    #[serde(rename = "expiresOn")]
+    pub expires_on: Option<NaiveDateTime>,
     /// Whether a crate can be uploaded using this token
     #[serde(rename = "canWrite")]
     pub can_write: bool,
     /// Whether administration can be done using this token through the API
     #[serde(rename = "canAdmin")]
     pub can_admin: bool,
+    /// The scopes restricting the operations on crates using this token
+    pub scopes: TokenScopes,
+}
+
+/// Checks the expiry date requested for a new token
+/// When `max_validity` (in days) is strictly positive, tokens must expire within this duration
+pub fn check_token_expiry(expires_on: Option<NaiveDateTime>, max_validity: i64) -> Result<(), ApiError> {
+    let now = Local::now().naive_local();
+    match expires_on {
+        Some(expires_on) if expires_on <= now => Err(specialize(
+            error_invalid_request(),
+            String::from("the expiry date for the token must be in the future"),
+        )),
+        Some(expires_on) if max_validity > 0 && expires_on > now + TimeDelta::days(max_validity) => Err(specialize(
+            error_invalid_request(),
+            format!("tokens cannot be valid for more than {max_validity} days"),
+        )),
+        None if max_validity > 0 => Err(specialize(
+            error_invalid_request(),
+            format!("tokens must expire within {max_validity} days"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// An operation on a crate that a token can be restricted to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TokenOperation {
+    /// Publish the first version of a new crate
+    #[serde(rename = "publish-new")]
+    PublishNew,
+    /// Publish a new version of an existing crate
+    #[serde(rename = "publish-update")]
+    PublishUpdate,
+    /// Yank and unyank versions of a crate
+    #[serde(rename = "yank")]
+    Yank,
+    /// Add and remove owners of a crate
+    #[serde(rename = "change-owners")]
+    ChangeOwners,
+}
+
+impl TokenOperation {
+    /// Gets the serialized name of this operation
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::PublishNew => "publish-new",
+            Self::PublishUpdate => "publish-update",
+            Self::Yank => "yank",
+            Self::ChangeOwners => "change-owners",
+        }
+    }
+}
+
+impl Display for TokenOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TokenOperation {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "publish-new" => Ok(Self::PublishNew),
+            "publish-update" => Ok(Self::PublishUpdate),
+            "yank" => Ok(Self::Yank),
+            "change-owners" => Ok(Self::ChangeOwners),
+            _ => Err(specialize(error_invalid_request(), format!("Unknown token operation: {s}"))),
+        }
+    }
+}
+
+/// The scopes restricting what can be done with a token
+/// Empty lists mean that the token is not restricted
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct TokenScopes {
+    /// The patterns for the names of the crates the token is restricted to
+    /// A pattern is either a crate name, or a prefix followed by `*`
+    pub crates: Vec<String>,
+    /// The operations the token is restricted to
+    pub operations: Vec<TokenOperation>,
+}
+
+impl TokenScopes {
+    /// Gets the scopes for a token that is not restricted
+    #[must_use]
+    pub const fn unrestricted() -> Self {
+        Self {
+            crates: Vec::new(),
+            operations: Vec::new(),
+        }
+    }
+
+    /// Parses the scopes from comma-separated lists of crate patterns and operations
+    pub fn parse(crates: &str, operations: &str) -> Result<Self, ApiError> {
+        let crates = comma_sep_to_vec(crates);
+        for pattern in &crates {
+            Self::validate_crate_pattern(pattern)?;
+        }
+        let operations = comma_sep_to_vec(operations)
+            .iter()
+            .map(|op| op.parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { crates, operations })
+    }
+
+    /// Validates a pattern for crate names
+    fn validate_crate_pattern(pattern: &str) -> Result<(), ApiError> {
+        if pattern.is_empty() {
+            return Err(specialize(error_invalid_request(), String::from("Empty crate pattern")));
+        }
+        let prefix = pattern.strip_suffix('*').unwrap_or(pattern);
+        if prefix.chars().any(|c| !c.is_ascii_alphanumeric() && c != '-' && c != '_') {
+            return Err(specialize(
+                error_invalid_request(),
+                format!(
+                    "Invalid crate pattern: {pattern}, only alphanumeric characters, `-`, `_` and a trailing `*` are allowed"
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gets whether these scopes do not restrict anything
+    #[must_use]
+    pub const fn is_unrestricted(&self) -> bool {
+        self.crates.is_empty() && self.operations.is_empty()
+    }
+
+    /// Gets the crate patterns as a comma-separated list
+    #[must_use]
+    pub fn crates_to_string(&self) -> String {
+        self.crates.join(",")
+    }
+
+    /// Gets the operations as a comma-separated list
+    #[must_use]
+    pub fn operations_to_string(&self) -> String {
+        self.operations.iter().map(|op| op.as_str()).collect::<Vec<_>>().join(",")
+    }
+
+    /// Gets whether a crate matches the crate patterns
+    #[must_use]
+    pub fn matches_crate(&self, package: &str) -> bool {
+        let package = package.to_ascii_lowercase();
+        self.crates.is_empty()
+            || self.crates.iter().any(|pattern| {
+                let pattern = pattern.to_ascii_lowercase();
+                pattern
+                    .strip_suffix('*')
+                    .map_or_else(|| package == pattern, |prefix| package.starts_with(prefix))
+            })
+    }
+
+    /// Checks that the scopes allow an operation on a crate
+    /// When no specific operation is given, the scopes must not be restricted to specific operations
+    pub fn check_can_operate_on_crate(&self, package: &str, operation: Option<TokenOperation>) -> Result<(), ApiError> {
+        if !self.matches_crate(package) {
+            return Err(specialize(
+                error_forbidden(),
+                format!("this authentication is not allowed to operate on crate {package}"),
+            ));
+        }
+        if self.operations.is_empty() || operation.is_some_and(|operation| self.operations.contains(&operation)) {
+            Ok(())
+        } else {
+            Err(specialize(
+                error_forbidden(),
+                format!(
+                    "this authentication is not allowed to {} on crate {package}",
+                    operation.map_or("manage settings", TokenOperation::as_str)
+                ),
+            ))
+        }
+    }
+
+    /// Checks that the scopes do not restrict anything
+    pub fn check_is_unrestricted(&self) -> Result<(), ApiError> {
+        if self.is_unrestricted() {
+            Ok(())
+        } else {
+            Err(specialize(
+                error_forbidden(),
+                String::from("this operation is forbidden for a scoped authentication"),
+            ))
+        }
+    }
 }
 
 /// An OAuth access token
@@ -167,16 +503,59 @@ pub struct OAuthToken {
     pub refresh_token: Option<String>,
     /// The grant scope
     pub scope: Option<String>,
+    /// The OIDC id token, if any
+    pub id_token: Option<String>,
+}
+
+/// The state of a pending OAuth login, kept in a private cookie until the callback
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OAuthLoginState {
+    /// The state passed to the provider, to be checked against the one given back to the callback
+    pub state: String,
+    /// The nonce passed to the provider, expected in the id token
+    pub nonce: String,
+    /// The PKCE code verifier, if used
+    pub verifier: Option<String>,
+}
+
+/// The OIDC provider metadata, obtained through discovery
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenIdConfiguration {
+    /// The issuer identifier
+    pub issuer: String,
+    /// The uri of the authorization endpoint
+    pub authorization_endpoint: String,
+    /// The uri of the token endpoint
+    pub token_endpoint: String,
+    /// The uri of the userinfo endpoint, if any
+    pub userinfo_endpoint: Option<String>,
+    /// The uri of the JWKS for the verification of id tokens
+    pub jwks_uri: String,
 }
 
 /// Finds a field in a JSON blob
 #[must_use]
 pub fn find_field_in_blob<'v>(blob: &'v serde_json::Value, path: &str) -> Option<&'v str> {
+    find_value_in_blob(blob, path)?.as_str()
+}
+
+/// Find the values of a field in a JSON blob, either an array of strings or a comma-separated list
+#[must_use]
+pub fn find_fields_in_blob<'v>(blob: &'v serde_json::Value, path: &str) -> Vec<&'v str> {
+    match find_value_in_blob(blob, path) {
+        Some(serde_json::Value::Array(items)) => items.iter().filter_map(serde_json::Value::as_str).collect(),
+        Some(serde_json::Value::String(value)) => value.split(',').map(str::trim).filter(|v| !v.is_empty()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Find a value in a JSON blob following a path of fields separated by dots
+fn find_value_in_blob<'v>(blob: &'v serde_json::Value, path: &str) -> Option<&'v serde_json::Value> {
     let mut last = blob;
     for item in path.split('.') {
         last = last.as_object()?.get(item)?;
     }
-    last.as_str()
+    Some(last)
 }
 
 /// The kind of auth token
@@ -186,6 +565,23 @@ pub enum TokenKind {
     User,
     /// A registry-wide token
     Registry,
+    /// A user public key for asymmetric tokens
+    PublicKey,
+}
+
+/// A notice about a token that is about to expire
+#[derive(Debug, Clone)]
+pub struct TokenExpiryNotice {
+    /// The kind of token
+    pub kind: TokenKind,
+    /// The unique identifier for the token
+    pub token_id: i64,
+    /// The token name
+    pub name: String,
+    /// The time when the token expires
+    pub expires_on: NaiveDateTime,
+    /// The emails of the users to notify
+    pub recipients: Vec<String>,
 }
 
 /// Event when a token was used
@@ -5,12 +5,16 @@
 //! Data model for the Cargo web API
 
 use std::collections::HashMap;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
+use std::path::{Component, Path};
 use std::str::FromStr;
 
 use byteorder::{LittleEndian, ReadBytesExt};
+use flate2::bufread::GzDecoder;
 use serde_derive::{Deserialize, Serialize};
+use tar::Archive;
 
+use crate::model::teams::Team;
 use crate::utils::apierror::{ApiError, error_invalid_request, specialize};
 use crate::utils::hashes::sha256;
 
@@ -103,12 +107,29 @@ impl YesNoMsgResult {
 pub struct OwnersQueryResult {
     /// The list of owners
     pub users: Vec<RegistryUser>,
+    /// The teams that own the crate
+    #[serde(default)]
+    pub teams: Vec<Team>,
+}
+
+impl OwnersQueryResult {
+    /// Gets the emails of the owners, including the members of the owning teams
+    #[must_use]
+    pub fn emails(self) -> Vec<String> {
+        let mut emails = self.users.into_iter().map(|user| user.email).collect::<Vec<_>>();
+        for member in self.teams.into_iter().flat_map(|team| team.members) {
+            if !emails.contains(&member.email) {
+                emails.push(member.email);
+            }
+        }
+        emails
+    }
 }
 
 /// The query for adding/removing owners to a crate
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct OwnersChangeQuery {
-    /// The login of the users
+    /// The login of the users, or `team:<name>` for teams
     pub users: Vec<String>,
 }
 
@@ -190,11 +211,108 @@ pub struct CrateMetadata {
     pub rust_version: Option<String>,
 }
 
+/// The maximum number of keywords for a crate
+const MAX_KEYWORDS: usize = 5;
+/// The maximum length of a keyword
+const MAX_KEYWORD_LENGTH: usize = 20;
+/// The maximum number of categories for a crate
+const MAX_CATEGORIES: usize = 5;
+
 impl CrateMetadata {
     /// Validate the crate's metadata
-    pub fn validate(&self) -> Result<CrateUploadResult, ApiError> {
+    /// When categories are allowed, the others are reported as invalid in the warnings
+    pub fn validate(&self, allowed_categories: &[String]) -> Result<CrateUploadResult, ApiError> {
         self.validate_name()?;
-        Ok(CrateUploadResult::default())
+        self.validate_version()?;
+        self.validate_keywords()?;
+        self.validate_license()?;
+        self.validate_rust_version()?;
+        let invalid_categories = self.validate_categories(allowed_categories)?;
+        Ok(CrateUploadResult {
+            warnings: CrateUploadWarnings {
+                invalid_categories,
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Validates the version, that must follow semver
+    fn validate_version(&self) -> Result<(), ApiError> {
+        if let Err(error) = semver::Version::parse(&self.vers) {
+            return validation_error(&format!("Version {} is not a valid semver version: {error}", self.vers));
+        }
+        Ok(())
+    }
+
+    /// Validates the keywords
+    fn validate_keywords(&self) -> Result<(), ApiError> {
+        if self.keywords.len() > MAX_KEYWORDS {
+            return validation_error(&format!("Expected at most {MAX_KEYWORDS} keywords"));
+        }
+        for keyword in &self.keywords {
+            if keyword.is_empty() || keyword.len() > MAX_KEYWORD_LENGTH {
+                return validation_error(&format!(
+                    "Keyword {keyword} must have between 1 and {MAX_KEYWORD_LENGTH} characters"
+                ));
+            }
+            if !keyword.starts_with(|c: char| c.is_ascii_alphanumeric())
+                || !keyword
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '+')
+            {
+                return validation_error(&format!(
+                    "Keyword {keyword} must start with an ASCII letter or digit and only contain alphanumeric, -, _, +"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the categories
+    /// Returns the categories that are not allowed, if categories are restricted
+    fn validate_categories(&self, allowed_categories: &[String]) -> Result<Vec<String>, ApiError> {
+        if self.categories.len() > MAX_CATEGORIES {
+            return Err(specialize(
+                error_invalid_request(),
+                format!("Expected at most {MAX_CATEGORIES} categories"),
+            ));
+        }
+        if allowed_categories.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .categories
+            .iter()
+            .filter(|category| !allowed_categories.contains(category))
+            .cloned()
+            .collect())
+    }
+
+    /// Validates the license, that must be a SPDX expression
+    fn validate_license(&self) -> Result<(), ApiError> {
+        if let Some(license) = &self.license
+            && let Err(error) = spdx::Expression::parse_mode(license, spdx::ParseMode::LAX)
+        {
+            return validation_error(&format!("License {license} is not a valid SPDX expression: {error}"));
+        }
+        Ok(())
+    }
+
+    /// Validates the minimal supported Rust version, that must be a bare version such as `1.70` or `1.70.0`
+    fn validate_rust_version(&self) -> Result<(), ApiError> {
+        if let Some(rust_version) = &self.rust_version {
+            let parts = rust_version.split('.').collect::<Vec<_>>();
+            if parts.len() > 3
+                || parts
+                    .iter()
+                    .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+            {
+                return validation_error(&format!(
+                    "Rust version {rust_version} must be a bare version without operator, e.g. 1.70 or 1.70.0"
+                ));
+            }
+        }
+        Ok(())
     }
 
     /// Validates the package name
@@ -225,8 +343,36 @@ pub fn validation_error(details: &str) -> Result<(), ApiError> {
     Err(specialize(error_invalid_request(), details.to_string()))
 }
 
+/// Creates a validation error for an invalid .crate package
+#[expect(clippy::needless_pass_by_value)]
+fn invalid_content(error: std::io::Error) -> ApiError {
+    specialize(error_invalid_request(), format!("Invalid .crate package: {error}"))
+}
+
+/// Checks that a path in a .crate package is within the root directory
+fn is_in_root(path: &Path, root: &str) -> bool {
+    let mut components = path.components();
+    components.next() == Some(Component::Normal(root.as_ref()))
+        && components.all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Checks that the target of a symbolic link in a .crate package remains within the root directory
+fn is_relative_link_in_root(path: &Path, target: &Path) -> bool {
+    // depth of the directory containing the link, relative to the root
+    let mut depth = path.components().count().saturating_sub(2);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
 /// The kind of dependency
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum DependencyKind {
     /// A normal dependency
     #[default]
@@ -304,6 +450,27 @@ pub struct CrateUploadWarnings {
     pub other: Vec<String>,
 }
 
+/// The information about the version control system, packaged by cargo in `.cargo_vcs_info.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateVcsInfo {
+    /// The information about the git commit, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<CrateVcsGitInfo>,
+    /// The path of the package within the repository
+    #[serde(default)]
+    pub path_in_vcs: String,
+}
+
+/// The information about the git commit a crate was packaged from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateVcsGitInfo {
+    /// The hash of the commit
+    pub sha1: String,
+    /// Whether the working directory had uncommitted changes
+    #[serde(default)]
+    pub dirty: bool,
+}
+
 /// The upload data for publishing a crate
 pub struct CrateUploadData {
     /// The metadata
@@ -313,6 +480,107 @@ pub struct CrateUploadData {
 }
 
 impl CrateUploadData {
+    /// Validates the metadata and the content of the .crate package
+    /// The categories that are not allowed are removed and reported in the warnings
+    pub fn validate(&mut self, allowed_categories: &[String], max_file_size: u64) -> Result<CrateUploadResult, ApiError> {
+        let result = self.metadata.validate(allowed_categories)?;
+        self.metadata
+            .categories
+            .retain(|category| !result.warnings.invalid_categories.contains(category));
+        self.validate_content(max_file_size)?;
+        Ok(result)
+    }
+
+    /// Validates the content of the .crate package
+    /// All files must be in a single top-level `name-version` directory, with a `Cargo.toml` manifest consistent with the metadata
+    fn validate_content(&self, max_file_size: u64) -> Result<(), ApiError> {
+        let root = format!("{}-{}", self.metadata.name, self.metadata.vers);
+        let mut manifest = None;
+        let mut archive = Archive::new(GzDecoder::new(self.content.as_slice()));
+        for entry in archive.entries().map_err(invalid_content)? {
+            let mut entry = entry.map_err(invalid_content)?;
+            let path = entry.path().map_err(invalid_content)?.into_owned();
+            if !is_in_root(&path, &root) {
+                return validation_error(&format!("File {} is not in the {root} directory", path.display()));
+            }
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                let target = entry.link_name().map_err(invalid_content)?.unwrap_or_default().into_owned();
+                // the targets of hard links are relative to the root of the archive, the symbolic links to their directory
+                let is_valid = if entry_type.is_hard_link() {
+                    is_in_root(&target, &root)
+                } else {
+                    is_relative_link_in_root(&path, &target)
+                };
+                if !is_valid {
+                    return validation_error(&format!(
+                        "Link {} to {} escapes the {root} directory",
+                        path.display(),
+                        target.display()
+                    ));
+                }
+            }
+            if entry.size() > max_file_size {
+                return validation_error(&format!(
+                    "File {} exceeds the maximum size of {max_file_size} bytes",
+                    path.display()
+                ));
+            }
+            if entry_type.is_file() && path == Path::new(&root).join("Cargo.toml") {
+                let mut content = String::new();
+                entry.read_to_string(&mut content).map_err(invalid_content)?;
+                manifest = Some(content);
+            }
+        }
+        let Some(manifest) = manifest else {
+            return validation_error(&format!("Missing {root}/Cargo.toml"));
+        };
+        self.validate_manifest(&manifest)
+    }
+
+    /// Gets the information about the version control system packaged by cargo, if any
+    pub fn get_vcs_info(&self) -> Result<Option<CrateVcsInfo>, ApiError> {
+        let path = Path::new(&format!("{}-{}", self.metadata.name, self.metadata.vers)).join(".cargo_vcs_info.json");
+        let mut archive = Archive::new(GzDecoder::new(self.content.as_slice()));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() && entry.path()? == path {
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content)?;
+                return Ok(Some(serde_json::from_slice(&content)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Validates that the `Cargo.toml` manifest is consistent with the metadata
+    fn validate_manifest(&self, manifest: &str) -> Result<(), ApiError> {
+        let manifest = manifest
+            .parse::<toml::Table>()
+            .map_err(|error| specialize(error_invalid_request(), format!("Invalid Cargo.toml: {error}")))?;
+        let package = manifest.get("package").and_then(toml::Value::as_table);
+        let name = package.and_then(|package| package.get("name")).and_then(toml::Value::as_str);
+        if name != Some(self.metadata.name.as_str()) {
+            return validation_error(&format!(
+                "The name in Cargo.toml ({}) does not match the published crate {}",
+                name.unwrap_or_default(),
+                self.metadata.name
+            ));
+        }
+        // the version may be omitted and is then 0.0.0
+        let version = package
+            .and_then(|package| package.get("version"))
+            .and_then(toml::Value::as_str)
+            .unwrap_or("0.0.0");
+        if version != self.metadata.vers {
+            return validation_error(&format!(
+                "The version in Cargo.toml ({version}) does not match the published version {}",
+                self.metadata.vers
+            ));
+        }
+        Ok(())
+    }
+
     /// Deserialize the content of an input payload
     pub fn new(buffer: &[u8]) -> Result<Self, ApiError> {
         let mut cursor = Cursor::new(buffer);
@@ -329,20 +597,9 @@ impl CrateUploadData {
     }
 
     /// Builds the metadata to be index for this version
+    #[must_use]
     pub fn build_index_data(&self) -> IndexCrateMetadata {
-        let cksum = sha256(&self.content);
-        IndexCrateMetadata {
-            name: self.metadata.name.clone(),
-            vers: self.metadata.vers.clone(),
-            deps: self.metadata.deps.iter().map(IndexCrateDependency::from).collect(),
-            cksum,
-            features: HashMap::new(),
-            yanked: false,
-            links: self.metadata.links.clone(),
-            v: Some(2),
-            features2: Some(self.metadata.features.clone()),
-            rust_version: self.metadata.rust_version.clone(),
-        }
+        IndexCrateMetadata::new(&self.metadata, &self.content)
     }
 }
 
@@ -409,6 +666,23 @@ pub struct IndexCrateMetadata {
 }
 
 impl IndexCrateMetadata {
+    /// Builds the metadata to be indexed for a version, from its metadata and the content of its .crate package
+    #[must_use]
+    pub fn new(metadata: &CrateMetadata, content: &[u8]) -> Self {
+        Self {
+            name: metadata.name.clone(),
+            vers: metadata.vers.clone(),
+            deps: metadata.deps.iter().map(IndexCrateDependency::from).collect(),
+            cksum: sha256(content),
+            features: HashMap::new(),
+            yanked: false,
+            links: metadata.links.clone(),
+            v: Some(2),
+            features2: Some(metadata.features.clone()),
+            rust_version: metadata.rust_version.clone(),
+        }
+    }
+
     /// Gets the value associated to a requested feature
     pub fn get_feature(&self, feature: &str) -> Option<&[String]> {
         self.features2
@@ -17,6 +17,7 @@ use tokio::fs::File;
 use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::process::Command;
 
+use super::auth::OpenIdConfiguration;
 use super::{CHANNEL_NIGHTLY, CHANNEL_STABLE};
 use crate::model::errors::MissingEnvVar;
 use crate::utils::apierror::{ApiError, error_backend_failure, specialize};
@@ -121,31 +122,30 @@ impl StorageConfig {
     /// Loads the configuration for a registry from the environment
     fn from_env() -> Result<Self, MissingEnvVar> {
         let storage_kind = get_var("REGISTRY_STORAGE")?;
-        let retry_params = get_var("REGISTRY_STORAGE_RETRY_ENABLED")
-            .map(|v| {
-                if v.eq_ignore_ascii_case("true") || v == "1" {
-                    Some(RetryParams {
-                        max_times: get_var("REGISTRY_STORAGE_RETRY_MAX_TIMES")
-                            .map(|s| s.parse().expect("invalid REGISTRY_STORAGE_RETRY_MAX_TIMES"))
-                            .unwrap_or(RetryParams::DEFAULT_MAX_TIMES),
-                        min_delay_ms: get_var("REGISTRY_STORAGE_RETRY_MIN_DELAY_MS")
-                            .map(|s| s.parse().expect("invalid REGISTRY_STORAGE_RETRY_MIN_DELAY_MS"))
-                            .unwrap_or(RetryParams::DEFAULT_MIN_DELAY_MS),
-                        max_delay_ms: get_var("REGISTRY_STORAGE_RETRY_MAX_DELAY_MS")
-                            .map(|s| s.parse().expect("invalid REGISTRY_STORAGE_RETRY_MAX_DELAY_MS"))
-                            .unwrap_or(RetryParams::DEFAULT_MAX_DELAY_MS),
-                        factor: get_var("REGISTRY_STORAGE_RETRY_FACTOR")
-                            .map(|s| s.parse().expect("invalid REGISTRY_STORAGE_RETRY_FACTOR"))
-                            .unwrap_or(RetryParams::DEFAULT_FACTOR),
-                        jitter: get_var("REGISTRY_STORAGE_RETRY_JITTER")
-                            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
-                            .unwrap_or(RetryParams::DEFAULT_JITTER),
-                    })
-                } else {
-                    None
-                }
-            })
-            .unwrap_or(None);
+        let retry_params = get_var("REGISTRY_STORAGE_RETRY_ENABLED").ok().and_then(|v| {
+            if v.eq_ignore_ascii_case("true") || v == "1" {
+                Some(RetryParams {
+                    max_times: get_var("REGISTRY_STORAGE_RETRY_MAX_TIMES").map_or(RetryParams::DEFAULT_MAX_TIMES, |s| {
+                        s.parse().expect("invalid REGISTRY_STORAGE_RETRY_MAX_TIMES")
+                    }),
+                    min_delay_ms: get_var("REGISTRY_STORAGE_RETRY_MIN_DELAY_MS")
+                        .map_or(RetryParams::DEFAULT_MIN_DELAY_MS, |s| {
+                            s.parse().expect("invalid REGISTRY_STORAGE_RETRY_MIN_DELAY_MS")
+                        }),
+                    max_delay_ms: get_var("REGISTRY_STORAGE_RETRY_MAX_DELAY_MS")
+                        .map_or(RetryParams::DEFAULT_MAX_DELAY_MS, |s| {
+                            s.parse().expect("invalid REGISTRY_STORAGE_RETRY_MAX_DELAY_MS")
+                        }),
+                    factor: get_var("REGISTRY_STORAGE_RETRY_FACTOR").map_or(RetryParams::DEFAULT_FACTOR, |s| {
+                        s.parse().expect("invalid REGISTRY_STORAGE_RETRY_FACTOR")
+                    }),
+                    jitter: get_var("REGISTRY_STORAGE_RETRY_JITTER")
+                        .map_or(RetryParams::DEFAULT_JITTER, |v| v.eq_ignore_ascii_case("true") || v == "1"),
+                })
+            } else {
+                None
+            }
+        });
         Ok(match storage_kind.as_str() {
             "s3" | "S3" => Self::S3 {
                 params: S3Params {
@@ -217,12 +217,25 @@ pub struct S3Params {
     pub root: String,
 }
 
+/// The backend used to manage the index
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum IndexBackend {
+    /// A local git repository, required for the git protocol
+    #[default]
+    Git,
+    /// Index files generated from the database, only for the sparse protocol
+    Database,
+}
+
 /// The configuration in the index
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IndexConfig {
     /// The home directory where the .cargo, .git are expected to be located
     #[serde(rename = "homeDir")]
     pub home_dir: String,
+    /// The backend used to manage the index
+    #[serde(default)]
+    pub backend: IndexBackend,
     /// The location in the file system
     pub location: String,
     /// Whether to allow the git protocol for clients fetching the index
@@ -253,21 +266,37 @@ pub struct IndexConfig {
 impl IndexConfig {
     /// Loads the configuration for a registry from the environment
     fn from_env(home_dir: &str, data_dir: &str, web_public_uri: &str) -> Result<Self, MissingEnvVar> {
+        let backend = if get_var("REGISTRY_INDEX_BACKEND").is_ok_and(|v| v.eq_ignore_ascii_case("database")) {
+            IndexBackend::Database
+        } else {
+            IndexBackend::Git
+        };
+        // the git user is only required when the index is a git repository
+        let (user_name, user_email) = if backend == IndexBackend::Git {
+            (get_var("REGISTRY_GIT_USER_NAME")?, get_var("REGISTRY_GIT_USER_EMAIL")?)
+        } else {
+            (
+                get_var("REGISTRY_GIT_USER_NAME").unwrap_or_default(),
+                get_var("REGISTRY_GIT_USER_EMAIL").unwrap_or_default(),
+            )
+        };
         Ok(Self {
             home_dir: home_dir.to_string(),
+            backend,
             location: format!("{data_dir}/index"),
-            allow_protocol_git: get_var("REGISTRY_INDEX_PROTOCOL_GIT").map(|v| v == "true").unwrap_or(false),
-            allow_protocol_sparse: get_var("REGISTRY_INDEX_PROTOCOL_SPARSE").map(|v| v == "true").unwrap_or(true),
+            allow_protocol_git: get_var("REGISTRY_INDEX_PROTOCOL_GIT").is_ok_and(|v| v == "true"),
+            allow_protocol_sparse: get_var("REGISTRY_INDEX_PROTOCOL_SPARSE").map_or(true, |v| v == "true"),
             remote_origin: get_var("REGISTRY_GIT_REMOTE").ok(),
             remote_ssh_key_file_name: get_var("REGISTRY_GIT_REMOTE_SSH_KEY_FILENAME").ok(),
             remote_push_changes: get_var("REGISTRY_GIT_REMOTE_PUSH_CHANGES")
                 .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true")),
-            user_name: get_var("REGISTRY_GIT_USER_NAME")?,
-            user_email: get_var("REGISTRY_GIT_USER_EMAIL")?,
+            user_name,
+            user_email,
             public: IndexPublicConfig {
                 dl: format!("{web_public_uri}/api/v1/crates"),
                 api: web_public_uri.to_string(),
                 auth_required: true,
+                asymmetric_tokens: true,
             },
         })
     }
@@ -283,6 +312,9 @@ pub struct IndexPublicConfig {
     /// Whether authentication is always required
     #[serde(rename = "auth-required")]
     pub auth_required: bool,
+    /// Whether asymmetric tokens (PASETO v3.public) are accepted
+    #[serde(rename = "asymmetric-tokens", default)]
+    pub asymmetric_tokens: bool,
 }
 
 /// The SMTP configuration to use to send emails
@@ -303,9 +335,7 @@ impl SmtpConfig {
     fn from_env() -> Result<Self, MissingEnvVar> {
         Ok(Self {
             host: get_var("REGISTRY_EMAIL_SMTP_HOST")?,
-            port: get_var("REGISTRY_EMAIL_SMTP_PORT")
-                .map(|s| s.parse().expect("invalid REGISTRY_EMAIL_SMTP_PORT"))
-                .unwrap_or(465),
+            port: get_var("REGISTRY_EMAIL_SMTP_PORT").map_or(465, |s| s.parse().expect("invalid REGISTRY_EMAIL_SMTP_PORT")),
             login: get_var("REGISTRY_EMAIL_SMTP_LOGIN")?,
             password: get_var("REGISTRY_EMAIL_SMTP_PASSWORD")?,
         })
@@ -333,6 +363,54 @@ impl EmailConfig {
     }
 }
 
+/// The external hook that checks crates against the policies of the organisation before they are published
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum PublishPolicyHook {
+    /// A local executable, receiving the request on its standard input and answering on its standard output
+    Command(String),
+    /// An HTTP endpoint receiving the request in a `POST`
+    Http {
+        /// The URI of the endpoint
+        uri: String,
+        /// The bearer token to use, if any
+        token: Option<String>,
+    },
+}
+
+/// The configuration for the checks of crates against the policies of the organisation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublishPolicyConfig {
+    /// The hook to call
+    pub hook: PublishPolicyHook,
+    /// Whether to send the content of the `.crate` package to the hook, along with the metadata
+    #[serde(rename = "withContent")]
+    pub with_content: bool,
+    /// The number of seconds after which the hook is deemed failed
+    pub timeout: u64,
+}
+
+impl PublishPolicyConfig {
+    /// Loads the configuration for the policy hook from the environment, if any
+    fn from_env() -> Option<Self> {
+        let hook = if let Ok(command) = get_var("REGISTRY_PUBLISH_POLICY_COMMAND") {
+            PublishPolicyHook::Command(command)
+        } else {
+            PublishPolicyHook::Http {
+                uri: get_var("REGISTRY_PUBLISH_POLICY_URI").ok()?,
+                token: get_var("REGISTRY_PUBLISH_POLICY_TOKEN").ok(),
+            }
+        };
+        Some(Self {
+            hook,
+            with_content: get_var("REGISTRY_PUBLISH_POLICY_WITH_CONTENT")
+                .ok()
+                .is_some_and(|s| s.parse().expect("invalid REGISTRY_PUBLISH_POLICY_WITH_CONTENT")),
+            timeout: get_var("REGISTRY_PUBLISH_POLICY_TIMEOUT")
+                .map_or(10, |s| s.parse().expect("invalid REGISTRY_PUBLISH_POLICY_TIMEOUT")),
+        })
+    }
+}
+
 /// The configuration specific to master nodes
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct NodeRoleMaster {
@@ -434,6 +512,9 @@ pub struct Configuration {
     /// The path to the local resources to serve as the web app
     #[serde(rename = "webHotReloadPath")]
     pub web_hot_reload_path: Option<String>,
+    /// The IPs of the trusted reverse proxies, the `X-Forwarded-For` header is only used for requests from them
+    #[serde(rename = "webTrustedProxies")]
+    pub web_trusted_proxies: Vec<IpAddr>,
     /// The home directory where the .cargo, .git are expected to be located
     #[serde(rename = "homeDir")]
     pub home_dir: String,
@@ -448,6 +529,9 @@ pub struct Configuration {
     /// Timeout (in milli-seconds) to use when interacting with the storage
     #[serde(rename = "storageTimeout")]
     pub storage_timeout: u64,
+    /// The OIDC issuer, used for the discovery of the endpoints, if any
+    #[serde(rename = "oauthIssuer")]
+    pub oauth_issuer: Option<String>,
     /// The uri of the OAuth login page
     #[serde(rename = "oauthLoginUri")]
     pub oauth_login_uri: String,
@@ -466,6 +550,9 @@ pub struct Configuration {
     /// Path to the full name field in the JSON blob returned at the userinfo URI
     #[serde(rename = "oauthUserInfoPathFullName")]
     pub oauth_userinfo_path_fullname: String,
+    /// Path to the teams field in the JSON blob returned at the userinfo URI, when teams are synced on login
+    #[serde(rename = "oauthUserInfoPathTeams")]
+    pub oauth_userinfo_path_teams: Option<String>,
     /// The identifier of the client to use
     #[serde(rename = "oauthClientId")]
     pub oauth_client_id: String,
@@ -475,6 +562,12 @@ pub struct Configuration {
     /// The secret for the client to use
     #[serde(rename = "oauthClientScope")]
     pub oauth_client_scope: String,
+    /// The uri of the JWKS for the verification of the id tokens returned by the OIDC provider, if any
+    #[serde(rename = "oauthJwksUri")]
+    pub oauth_jwks_uri: Option<String>,
+    /// Whether to use PKCE (Proof Key for Code Exchange) when logging in
+    #[serde(rename = "oauthPkce")]
+    pub oauth_pkce: bool,
     /// The known external registries that require authentication
     #[serde(rename = "externalRegistries")]
     pub external_registries: Vec<ExternalRegistry>,
@@ -484,6 +577,15 @@ pub struct Configuration {
     /// Whether to auto-install missing targets on documentation generation
     #[serde(rename = "docsAutoinstallTargets")]
     pub docs_autoinstall_targets: bool,
+    /// Whether to check the semver compatibility of new versions against the previous compatible version
+    #[serde(rename = "semverChecks")]
+    pub semver_checks: bool,
+    /// Whether to reject the publication of versions with breaking changes, checked synchronously on this node
+    #[serde(rename = "semverChecksBlock")]
+    pub semver_checks_block: bool,
+    /// Whether to send a notification by email to the owners of a crate when breaking changes are detected
+    #[serde(rename = "semverChecksNotify")]
+    pub semver_checks_notify: bool,
     /// Number of seconds between each check
     #[serde(rename = "depsCheckPeriod")]
     pub deps_check_period: u64,
@@ -500,6 +602,58 @@ pub struct Configuration {
     /// Whether to send a notification by email to the owners of a crate when CVEs are discovered in its dependencies
     #[serde(rename = "depsNotifyCVEs")]
     pub deps_notify_cves: bool,
+    /// Whether to send a notification by email to the owners of a crate when some of its dependencies resolve to yanked versions
+    #[serde(rename = "depsNotifyYanked")]
+    pub deps_notify_yanked: bool,
+    /// Whether to send a notification by email to the owners of a crate when some of its dependencies become deprecated
+    #[serde(rename = "depsNotifyDeprecated")]
+    pub deps_notify_deprecated: bool,
+    /// Number of seconds between each check for expired tokens
+    #[serde(rename = "tokensCheckPeriod")]
+    pub tokens_check_period: u64,
+    /// Maximum number of days a token can be valid
+    /// A zero or negative number allows tokens that never expire
+    #[serde(rename = "tokensMaxValidity")]
+    pub tokens_max_validity: i64,
+    /// Number of days before the expiry of a token when a notification is sent by email to its owners
+    /// A zero or negative number deactivates the notifications
+    #[serde(rename = "tokensNotifyExpiry")]
+    pub tokens_notify_expiry: i64,
+    /// Number of minutes a token obtained by a trusted publisher is valid
+    #[serde(rename = "trustedPublishingTokenValidity")]
+    pub trusted_publishing_token_validity: i64,
+    /// The token expected from the identity provider for the provisioning of users through SCIM, if enabled
+    #[serde(rename = "scimToken")]
+    pub scim_token: Option<String>,
+    /// Maximum number of failed authentications per client IP and per login within the window
+    /// Zero deactivates the limit
+    #[serde(rename = "rateLimitAuthFailures")]
+    pub rate_limit_auth_failures: usize,
+    /// Number of seconds for the sliding window of failed authentications
+    #[serde(rename = "rateLimitAuthWindow")]
+    pub rate_limit_auth_window: u64,
+    /// Maximum number of requests to expensive endpoints (publish, search, dependency checks, login) per client IP and per authenticated user within the window
+    /// Zero deactivates the limit
+    #[serde(rename = "rateLimitRequests")]
+    pub rate_limit_requests: usize,
+    /// Number of seconds for the sliding window of requests to expensive endpoints
+    #[serde(rename = "rateLimitRequestsWindow")]
+    pub rate_limit_requests_window: u64,
+    /// The categories that published crates can use, any category is accepted when empty
+    #[serde(rename = "crateCategories")]
+    pub crate_categories: Vec<String>,
+    /// The maximum size in bytes of a file in a published crate
+    #[serde(rename = "crateMaxFileSize")]
+    pub crate_max_file_size: u64,
+    /// The maximum size in bytes of a file in a published crate that can be fetched when browsing its sources
+    #[serde(rename = "crateBrowseMaxFileSize")]
+    pub crate_browse_max_file_size: u64,
+    /// The hook checking crates against the policies of the organisation before they are published, if any
+    #[serde(rename = "publishPolicy")]
+    pub publish_policy: Option<PublishPolicyConfig>,
+    /// The path to the file of the registry key (Ed25519, PEM PKCS#8) signing the attestations of published crates, if enabled
+    #[serde(rename = "attestationKeyFile")]
+    pub attestation_key_file: Option<String>,
     /// The configuration for sending emails
     pub email: EmailConfig,
     /// The name to use for the local registry in cargo and git config
@@ -548,10 +702,12 @@ impl Default for Configuration {
             web_domain: String::from("localhost"),
             web_body_limit: 10 * 1024 * 1024,
             web_hot_reload_path: None,
+            web_trusted_proxies: Vec::new(),
             home_dir: String::from("/home/cratery"),
             data_dir: String::from("/data"),
             index: IndexConfig {
                 home_dir: String::from("/home/cratery"),
+                backend: IndexBackend::Git,
                 location: String::from("/data/index"),
                 allow_protocol_git: true,
                 allow_protocol_sparse: true,
@@ -564,27 +720,51 @@ impl Default for Configuration {
                     dl: String::from("http://localhost/api/v1/crates"),
                     api: String::from("http://localhost"),
                     auth_required: true,
+                    asymmetric_tokens: true,
                 },
             },
             storage: StorageConfig::FileSystem { retry_params: None },
             storage_timeout: 3000,
+            oauth_issuer: None,
             oauth_login_uri: String::new(),
             oauth_token_uri: String::new(),
             oauth_callback_uri: String::new(),
             oauth_userinfo_uri: String::new(),
             oauth_userinfo_path_email: String::from("email"),
             oauth_userinfo_path_fullname: String::from("fullName"),
+            oauth_userinfo_path_teams: None,
             oauth_client_id: String::new(),
             oauth_client_secret: String::new(),
             oauth_client_scope: String::new(),
+            oauth_jwks_uri: None,
+            oauth_pkce: true,
             external_registries: Vec::new(),
             docs_gen_mock: true,
             docs_autoinstall_targets: false,
+            semver_checks: false,
+            semver_checks_block: false,
+            semver_checks_notify: false,
             deps_check_period: 60,
             deps_stale_registry: 60 * 1000,
             deps_stale_analysis: 24 * 60,
             deps_notify_outdated: false,
             deps_notify_cves: false,
+            deps_notify_yanked: false,
+            deps_notify_deprecated: false,
+            tokens_check_period: 60 * 60,
+            tokens_max_validity: 0,
+            tokens_notify_expiry: 0,
+            trusted_publishing_token_validity: 30,
+            scim_token: None,
+            rate_limit_auth_failures: 10,
+            rate_limit_auth_window: 5 * 60,
+            rate_limit_requests: 60,
+            rate_limit_requests_window: 60,
+            crate_categories: Vec::new(),
+            crate_max_file_size: 10 * 1024 * 1024,
+            crate_browse_max_file_size: 1024 * 1024,
+            publish_policy: None,
+            attestation_key_file: None,
             email: EmailConfig::default(),
             self_local_name: String::from("localhost"),
             self_service_login: String::new(),
@@ -607,6 +787,7 @@ impl Configuration {
     /// # Errors
     ///
     /// Return a `VarError` when an expected environment variable is not present
+    #[expect(clippy::too_many_lines)]
     pub async fn from_env() -> Result<Self, MissingEnvVar> {
         let home_dir = get_var("REGISTRY_HOME_DIR")
             .or_else(|_| get_var("HOME"))
@@ -625,9 +806,29 @@ impl Configuration {
         });
         let index = IndexConfig::from_env(&home_dir, &data_dir, &web_public_uri)?;
         let storage = StorageConfig::from_env()?;
-        let deps_notify_outdated = get_var("REGISTRY_DEPS_NOTIFY_OUTDATED").map(|v| v == "true").unwrap_or(false);
-        let deps_notify_cves = get_var("REGISTRY_DEPS_NOTIFY_CVES").map(|v| v == "true").unwrap_or(false);
-        let email = if deps_notify_outdated || deps_notify_cves {
+        let oauth_issuer = get_var("REGISTRY_OAUTH_ISSUER").ok();
+        // the endpoints are optional when they can be discovered from the issuer
+        let get_oauth_endpoint = |name: &str| {
+            get_var(name).or_else(|error| {
+                if oauth_issuer.is_some() {
+                    Ok(String::new())
+                } else {
+                    Err(error)
+                }
+            })
+        };
+        let deps_notify_outdated = get_var("REGISTRY_DEPS_NOTIFY_OUTDATED").is_ok_and(|v| v == "true");
+        let deps_notify_cves = get_var("REGISTRY_DEPS_NOTIFY_CVES").is_ok_and(|v| v == "true");
+        let deps_notify_yanked = get_var("REGISTRY_DEPS_NOTIFY_YANKED").is_ok_and(|v| v == "true");
+        let deps_notify_deprecated = get_var("REGISTRY_DEPS_NOTIFY_DEPRECATED").is_ok_and(|v| v == "true");
+        let tokens_notify_expiry =
+            get_var("REGISTRY_TOKENS_NOTIFY_EXPIRY").map_or(0, |s| s.parse().expect("invalid REGISTRY_TOKENS_NOTIFY_EXPIRY"));
+        let email = if deps_notify_outdated
+            || deps_notify_cves
+            || deps_notify_yanked
+            || deps_notify_deprecated
+            || tokens_notify_expiry > 0
+        {
             EmailConfig::from_env()?
         } else {
             EmailConfig::default()
@@ -648,46 +849,83 @@ impl Configuration {
                 |s| IpAddr::from_str(&s).expect("invalid REGISTRY_WEB_LISTENON_IP"),
             ),
             web_listenon_port: get_var("REGISTRY_WEB_LISTENON_PORT")
-                .map(|s| s.parse().expect("invalid REGISTRY_WEB_LISTENON_PORT"))
-                .unwrap_or(80),
+                .map_or(80, |s| s.parse().expect("invalid REGISTRY_WEB_LISTENON_PORT")),
             web_domain,
             web_public_uri,
             web_body_limit: get_var("REGISTRY_WEB_BODY_LIMIT")
-                .map(|s| s.parse().expect("invalid REGISTRY_WEB_BODY_LIMIT"))
-                .unwrap_or(10 * 1024 * 1024),
+                .map_or(10 * 1024 * 1024, |s| s.parse().expect("invalid REGISTRY_WEB_BODY_LIMIT")),
             web_hot_reload_path: get_var("REGISTRY_WEB_HOT_RELOAD_PATH").ok(),
+            web_trusted_proxies: get_var("REGISTRY_WEB_TRUSTED_PROXIES")
+                .ok()
+                .as_deref()
+                .map(comma_sep_to_vec)
+                .unwrap_or_default()
+                .iter()
+                .map(|s| IpAddr::from_str(s).expect("invalid REGISTRY_WEB_TRUSTED_PROXIES"))
+                .collect(),
             home_dir,
             data_dir,
             index,
             storage,
             storage_timeout: get_var("REGISTRY_STORAGE_TIMEOUT")
-                .map(|s| s.parse().expect("invalid REGISTRY_STORAGE_TIMEOUT"))
-                .unwrap_or(3000),
-            oauth_login_uri: get_var("REGISTRY_OAUTH_LOGIN_URI")?,
-            oauth_token_uri: get_var("REGISTRY_OAUTH_TOKEN_URI")?,
+                .map_or(3000, |s| s.parse().expect("invalid REGISTRY_STORAGE_TIMEOUT")),
+            oauth_login_uri: get_oauth_endpoint("REGISTRY_OAUTH_LOGIN_URI")?,
+            oauth_token_uri: get_oauth_endpoint("REGISTRY_OAUTH_TOKEN_URI")?,
             oauth_callback_uri: get_var("REGISTRY_OAUTH_CALLBACK_URI")?,
-            oauth_userinfo_uri: get_var("REGISTRY_OAUTH_USERINFO_URI")?,
+            oauth_userinfo_uri: get_oauth_endpoint("REGISTRY_OAUTH_USERINFO_URI")?,
+            oauth_issuer,
             oauth_userinfo_path_email: get_var("REGISTRY_OAUTH_USERINFO_PATH_EMAIL").unwrap_or_else(|_| String::from("email")),
             oauth_userinfo_path_fullname: get_var("REGISTRY_OAUTH_USERINFO_PATH_FULLNAME")
                 .unwrap_or_else(|_| String::from("name")),
+            oauth_userinfo_path_teams: get_var("REGISTRY_OAUTH_USERINFO_PATH_TEAMS").ok(),
             oauth_client_id: get_var("REGISTRY_OAUTH_CLIENT_ID")?,
-            oauth_client_secret: get_var("REGISTRY_OAUTH_CLIENT_SECRET")?,
+            oauth_client_secret: get_var("REGISTRY_OAUTH_CLIENT_SECRET").unwrap_or_default(),
             oauth_client_scope: get_var("REGISTRY_OAUTH_CLIENT_SCOPE")?,
-            docs_gen_mock: get_var("REGISTRY_DOCS_GEN_MOCK").map(|v| v == "true").unwrap_or(false),
-            docs_autoinstall_targets: get_var("REGISTRY_DOCS_AUTOINSTALL_TARGETS")
-                .map(|v| v == "true")
-                .unwrap_or(false),
+            oauth_jwks_uri: get_var("REGISTRY_OAUTH_JWKS_URI").ok(),
+            oauth_pkce: get_var("REGISTRY_OAUTH_PKCE").map_or(true, |v| v == "true" || v == "1"),
+            docs_gen_mock: get_var("REGISTRY_DOCS_GEN_MOCK").is_ok_and(|v| v == "true"),
+            docs_autoinstall_targets: get_var("REGISTRY_DOCS_AUTOINSTALL_TARGETS").is_ok_and(|v| v == "true"),
+            semver_checks: get_var("REGISTRY_SEMVER_CHECKS").is_ok_and(|v| v == "true"),
+            semver_checks_block: get_var("REGISTRY_SEMVER_CHECKS_BLOCK").is_ok_and(|v| v == "true"),
+            semver_checks_notify: get_var("REGISTRY_SEMVER_CHECKS_NOTIFY").is_ok_and(|v| v == "true"),
             deps_check_period: get_var("REGISTRY_DEPS_CHECK_PERIOD")
-                .map(|s| s.parse().expect("invalid REGISTRY_DEPS_CHECK_PERIOD"))
-                .unwrap_or(60), // 1 minute
+                .map_or(60, |s| s.parse().expect("invalid REGISTRY_DEPS_CHECK_PERIOD")), // 1 minute
             deps_stale_registry: get_var("REGISTRY_DEPS_STALE_REGISTRY")
-                .map(|s| s.parse().expect("invalid REGISTRY_DEPS_STALE_REGISTRY"))
-                .unwrap_or(60 * 1000), // 1 minute
+                .map_or(60 * 1000, |s| s.parse().expect("invalid REGISTRY_DEPS_STALE_REGISTRY")), // 1 minute
             deps_stale_analysis: get_var("REGISTRY_DEPS_STALE_ANALYSIS")
-                .map(|s| s.parse().expect("invalid REGISTRY_DEPS_STALE_ANALYSIS"))
-                .unwrap_or(24 * 60), // 24 hours
+                .map_or(24 * 60, |s| s.parse().expect("invalid REGISTRY_DEPS_STALE_ANALYSIS")), // 24 hours
             deps_notify_outdated,
             deps_notify_cves,
+            deps_notify_yanked,
+            deps_notify_deprecated,
+            tokens_check_period: get_var("REGISTRY_TOKENS_CHECK_PERIOD")
+                .map_or(60 * 60, |s| s.parse().expect("invalid REGISTRY_TOKENS_CHECK_PERIOD")), // 1 hour
+            tokens_max_validity: get_var("REGISTRY_TOKENS_MAX_VALIDITY")
+                .map_or(0, |s| s.parse().expect("invalid REGISTRY_TOKENS_MAX_VALIDITY")),
+            tokens_notify_expiry,
+            trusted_publishing_token_validity: get_var("REGISTRY_TRUSTED_PUBLISHING_TOKEN_VALIDITY")
+                .map_or(30, |s| s.parse().expect("invalid REGISTRY_TRUSTED_PUBLISHING_TOKEN_VALIDITY")),
+            scim_token: get_var("REGISTRY_SCIM_TOKEN").ok().filter(|token| !token.is_empty()),
+            rate_limit_auth_failures: get_var("REGISTRY_RATE_LIMIT_AUTH_FAILURES")
+                .map_or(10, |s| s.parse().expect("invalid REGISTRY_RATE_LIMIT_AUTH_FAILURES")),
+            rate_limit_auth_window: get_var("REGISTRY_RATE_LIMIT_AUTH_WINDOW")
+                .map_or(5 * 60, |s| s.parse().expect("invalid REGISTRY_RATE_LIMIT_AUTH_WINDOW")), // 5 minutes
+            rate_limit_requests: get_var("REGISTRY_RATE_LIMIT_REQUESTS")
+                .map_or(60, |s| s.parse().expect("invalid REGISTRY_RATE_LIMIT_REQUESTS")),
+            rate_limit_requests_window: get_var("REGISTRY_RATE_LIMIT_REQUESTS_WINDOW")
+                .map_or(60, |s| s.parse().expect("invalid REGISTRY_RATE_LIMIT_REQUESTS_WINDOW")), // 1 minute
+            crate_categories: get_var("REGISTRY_CRATE_CATEGORIES")
+                .ok()
+                .as_deref()
+                .map(comma_sep_to_vec)
+                .unwrap_or_default(),
+            crate_max_file_size: get_var("REGISTRY_CRATE_MAX_FILE_SIZE")
+                .map_or(10 * 1024 * 1024, |s| s.parse().expect("invalid REGISTRY_CRATE_MAX_FILE_SIZE")), // 10MB
+            crate_browse_max_file_size: get_var("REGISTRY_CRATE_BROWSE_MAX_FILE_SIZE").map_or(1024 * 1024, |s| {
+                s.parse().expect("invalid REGISTRY_CRATE_BROWSE_MAX_FILE_SIZE")
+            }), // 1MB
+            publish_policy: PublishPolicyConfig::from_env(),
+            attestation_key_file: get_var("REGISTRY_ATTESTATION_KEY_FILE").ok().filter(|path| !path.is_empty()),
             email,
             self_local_name,
             self_service_login: generate_token(16),
@@ -706,6 +944,49 @@ impl Configuration {
         })
     }
 
+    /// Resolves the OAuth endpoints through OIDC discovery, when an issuer is configured
+    /// Endpoints that are explicitly configured are kept
+    ///
+    /// # Errors
+    ///
+    /// Return an error when the provider metadata cannot be retrieved or does not match the issuer
+    pub async fn discover_oauth_endpoints(&mut self) -> Result<(), ApiError> {
+        let Some(issuer) = &self.oauth_issuer else {
+            return Ok(());
+        };
+        let issuer = issuer.trim_end_matches('/');
+        let uri = format!("{issuer}/.well-known/openid-configuration");
+        let response = reqwest::get(&uri).await?;
+        if !response.status().is_success() {
+            return Err(specialize(
+                error_backend_failure(),
+                format!("failed to retrieve the OpenID Connect configuration at {uri}"),
+            ));
+        }
+        let metadata = serde_json::from_slice::<OpenIdConfiguration>(&response.bytes().await?)?;
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(specialize(
+                error_backend_failure(),
+                format!("the OpenID Connect configuration at {uri} is for another issuer"),
+            ));
+        }
+        if self.oauth_login_uri.is_empty() {
+            self.oauth_login_uri = metadata.authorization_endpoint;
+        }
+        if self.oauth_token_uri.is_empty() {
+            self.oauth_token_uri = metadata.token_endpoint;
+        }
+        if self.oauth_userinfo_uri.is_empty() {
+            self.oauth_userinfo_uri = metadata.userinfo_endpoint.unwrap_or_default();
+        }
+        if self.oauth_jwks_uri.is_none() {
+            self.oauth_jwks_uri = Some(metadata.jwks_uri);
+        }
+        // keep the exact issuer, as used in the id tokens
+        self.oauth_issuer = Some(metadata.issuer);
+        Ok(())
+    }
+
     /// Gets the path to a file in the home folder
     #[must_use]
     pub fn get_home_path_for(&self, path: &[&str]) -> PathBuf {
@@ -722,6 +1003,12 @@ impl Configuration {
         format!("{}/registry.db", self.data_dir)
     }
 
+    /// Gets the name of the file locked by the process that uses the index
+    #[must_use]
+    pub fn get_index_lock_filename(&self) -> String {
+        format!("{}/index.lock", self.data_dir)
+    }
+
     /// Gets the corresponding database url
     #[must_use]
     pub fn get_database_url(&self) -> String {
@@ -12,6 +12,7 @@ use serde_derive::{Deserialize, Serialize};
 use super::CrateVersion;
 use super::cargo::{DependencyKind, IndexCrateDependency, IndexCrateMetadata};
 use super::osv::SimpleAdvisory;
+use super::packages::CrateDeprecation;
 use crate::utils::apierror::ApiError;
 use crate::utils::push_if_not_present;
 
@@ -21,6 +22,19 @@ pub const BUILTIN_CRATES_REGISTRY_URI: &str = "<builtin>";
 /// The list of built-in crates
 pub const BUILTIN_CRATES_LIST: &[&str] = &["core", "alloc", "std"];
 
+/// The URI identifying crates.io as the registry for a dependency
+pub const CRATES_IO_REGISTRY_URI: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// Gets the names of the crates.io crates that are direct dependencies of a version
+/// A renamed dependency is given by the name of the crate, not the alias
+pub fn get_crates_io_dependencies(version: &IndexCrateMetadata) -> impl Iterator<Item = &str> {
+    version
+        .deps
+        .iter()
+        .filter(|dep| dep.registry.as_deref() == Some(CRATES_IO_REGISTRY_URI))
+        .map(IndexCrateDependency::get_name)
+}
+
 /// The specification for a dependency analysis job
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DepsAnalysisJobSpec {
@@ -71,6 +85,24 @@ impl From<DepsAnalysisState> for CrateVersion {
     }
 }
 
+/// The flags summarizing the dependency analysis of a crate version, as saved
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[expect(clippy::struct_excessive_bools)]
+pub struct DepsAnalysisFlags {
+    /// Whether the version has outdated dependencies
+    #[serde(rename = "hasOutdated")]
+    pub has_outdated: bool,
+    /// Whether CVEs have been filed against dependencies
+    #[serde(rename = "hasCVEs")]
+    pub has_cves: bool,
+    /// Whether dependencies, direct or transitive, resolve to yanked versions
+    #[serde(rename = "hasYanked")]
+    pub has_yanked: bool,
+    /// Whether dependencies, direct or transitive, are deprecated local crates
+    #[serde(rename = "hasDeprecated")]
+    pub has_deprecated: bool,
+}
+
 /// The complete dependency analysis
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct DepsAnalysis {
@@ -79,6 +111,15 @@ pub struct DepsAnalysis {
     pub direct_dependencies: Vec<DirectDepInfo>,
     /// The advisories against dependencies
     pub advisories: Vec<DepAdvisory>,
+    /// The dependencies, direct or transitive, that resolve to a yanked version
+    #[serde(default)]
+    pub yanked: Vec<DepYanked>,
+    /// The dependencies, direct or transitive, on deprecated local crates
+    #[serde(default)]
+    pub deprecated: Vec<DepDeprecated>,
+    /// The names of the local crates in the dependency closure
+    #[serde(rename = "localDependencies", default)]
+    pub local_dependencies: Vec<String>,
 }
 
 impl DepsAnalysis {
@@ -94,18 +135,56 @@ impl DepsAnalysis {
                         .resolutions
                         .iter()
                         .find(|r| r.origins.contains(&DepsGraphCrateOrigin::Direct(dep.kind)));
-                    let is_outdated = resolved.is_some_and(|res| data.versions[res.version_index].is_outdated);
+                    let resolved = resolved.map(|res| &data.versions[res.version_index]);
                     DirectDepInfo {
                         registry: dep.registry.clone(),
                         package: dep.get_name().to_string(),
                         required: dep.req.clone(),
                         kind: dep.kind,
                         last_version: data.last_version.to_string(),
-                        is_outdated,
+                        resolved_version: resolved.map(|version| version.semver.to_string()),
+                        is_outdated: resolved.is_some_and(|version| version.is_outdated),
+                        is_yanked: resolved.is_some_and(|version| version.metadata.yanked),
+                        yank_reason: None,
+                        deprecation: None,
                     }
                 })
                 .collect(),
             advisories,
+            yanked: graph
+                .crates
+                .iter()
+                .flat_map(|data| {
+                    data.resolutions
+                        .iter()
+                        .map(|res| &data.versions[res.version_index])
+                        .filter(|version| version.metadata.yanked)
+                        .map(|version| DepYanked {
+                            registry: data.registry.clone(),
+                            package: data.name.clone(),
+                            version: version.semver.clone(),
+                            yank_reason: None,
+                        })
+                })
+                .collect(),
+            deprecated: Vec::new(),
+            local_dependencies: graph
+                .crates
+                .iter()
+                .filter(|data| data.registry.is_none() && !data.resolutions.is_empty())
+                .map(|data| data.name.clone())
+                .collect(),
+        }
+    }
+
+    /// Gets the flags summarizing this analysis
+    #[must_use]
+    pub fn flags(&self) -> DepsAnalysisFlags {
+        DepsAnalysisFlags {
+            has_outdated: self.direct_dependencies.iter().any(|info| info.is_outdated),
+            has_cves: !self.advisories.is_empty(),
+            has_yanked: !self.yanked.is_empty(),
+            has_deprecated: !self.deprecated.is_empty(),
         }
     }
 }
@@ -124,9 +203,21 @@ pub struct DirectDepInfo {
     /// The last known version
     #[serde(rename = "lastVersion")]
     pub last_version: String,
+    /// The resolved version, if any
+    #[serde(rename = "resolvedVersion", default)]
+    pub resolved_version: Option<String>,
     /// Whether the requirement leads to the resolution of an outdated version
     #[serde(rename = "isOutdated")]
     pub is_outdated: bool,
+    /// Whether the requirement leads to the resolution of a yanked version
+    #[serde(rename = "isYanked", default)]
+    pub is_yanked: bool,
+    /// The reason why the resolved version was yanked, if known (for local crates)
+    #[serde(rename = "yankReason", default, skip_serializing_if = "Option::is_none")]
+    pub yank_reason: Option<String>,
+    /// The deprecation of the dependency, if it is a deprecated local crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<CrateDeprecation>,
 }
 
 /// The advisory against a dependency resolved on crates.io
@@ -140,6 +231,29 @@ pub struct DepAdvisory {
     pub content: SimpleAdvisory,
 }
 
+/// A dependency, direct or transitive, that resolves to a yanked version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepYanked {
+    /// URI for the owning registry, `None` for the local one
+    pub registry: Option<String>,
+    /// The name of the package
+    pub package: String,
+    /// The resolved version
+    pub version: Version,
+    /// The reason why the version was yanked, if known (for local crates)
+    #[serde(rename = "yankReason", default, skip_serializing_if = "Option::is_none")]
+    pub yank_reason: Option<String>,
+}
+
+/// A dependency, direct or transitive, on a deprecated local crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepDeprecated {
+    /// The name of the package
+    pub package: String,
+    /// The deprecation of the package
+    pub deprecation: CrateDeprecation,
+}
+
 impl IndexCrateMetadata {
     /// Assumes this is the metadata for a crate in an external registry, including crates.io
     /// Find and rewrite the registry for built-in crates
@@ -391,12 +505,13 @@ impl DepsGraphCrate {
         origins: &[DepsGraphCrateOrigin],
     ) -> Option<usize> {
         let semver = dep.req.parse::<VersionReq>().unwrap();
+        // prefer the versions that are not yanked, a yanked version is only used when nothing else matches
         let version_index = self
             .versions
             .iter()
             .enumerate()
             .filter(|(_, version)| semver.matches(&version.semver))
-            .max_by(|(_, v1), (_, v2)| v1.semver.cmp(&v2.semver))
+            .max_by(|(_, v1), (_, v2)| (!v1.metadata.yanked, &v1.semver).cmp(&(!v2.metadata.yanked, &v2.semver)))
             .map(|(i, _)| i);
         let Some(version_index) = version_index else {
             self.unresolved.push(semver);
@@ -496,3 +611,91 @@ impl DepsGraphCrate {
         active_features
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CRATES_IO_REGISTRY_URI, DepsAnalysis, DepsGraph, DepsGraphCrate, DepsGraphCrateOrigin, get_crates_io_dependencies,
+    };
+    use crate::model::cargo::{DependencyKind, IndexCrateDependency, IndexCrateMetadata};
+
+    fn dependency(name: &str, req: &str, registry: Option<&str>) -> IndexCrateDependency {
+        IndexCrateDependency {
+            name: name.to_string(),
+            req: req.to_string(),
+            default_features: true,
+            registry: registry.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn version(name: &str, vers: &str, yanked: bool) -> IndexCrateMetadata {
+        IndexCrateMetadata {
+            name: name.to_string(),
+            vers: vers.to_string(),
+            yanked,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn yanked_and_local_dependencies() {
+        let direct = dependency("local", "^1.0", None);
+        let transitive = dependency("remote", "^2.0", Some(CRATES_IO_REGISTRY_URI));
+        let mut local = DepsGraphCrate::new(
+            &direct,
+            vec![version("local", "1.0.0", false), version("local", "1.1.0", false)],
+        )
+        .unwrap();
+        local.resolve(&direct, &[], &[DepsGraphCrateOrigin::Direct(DependencyKind::Normal)]);
+        let mut remote = DepsGraphCrate::new(
+            &transitive,
+            vec![version("remote", "2.0.0", false), version("remote", "2.1.0", true)],
+        )
+        .unwrap();
+        remote.resolve(&transitive, &[], &[DepsGraphCrateOrigin::NormalIndirect]);
+        // a yanked version is only resolved when no other version matches
+        let pinned = dependency("pinned", "=3.1.0", Some(CRATES_IO_REGISTRY_URI));
+        let mut pinned_crate = DepsGraphCrate::new(
+            &pinned,
+            vec![version("pinned", "3.0.0", false), version("pinned", "3.1.0", true)],
+        )
+        .unwrap();
+        pinned_crate.resolve(&pinned, &[], &[DepsGraphCrateOrigin::NormalIndirect]);
+        assert_eq!(
+            remote.versions[remote.resolutions[0].version_index].semver.to_string(),
+            "2.0.0"
+        );
+        let graph = DepsGraph {
+            crates: vec![local, remote, pinned_crate],
+            ..Default::default()
+        };
+
+        let analysis = DepsAnalysis::new(&graph, &[direct], Vec::new());
+        assert_eq!(analysis.direct_dependencies[0].resolved_version.as_deref(), Some("1.1.0"));
+        assert!(!analysis.direct_dependencies[0].is_yanked);
+        assert_eq!(analysis.yanked.len(), 1);
+        assert_eq!(analysis.yanked[0].package, "pinned");
+        assert_eq!(analysis.yanked[0].version.to_string(), "3.1.0");
+        assert_eq!(analysis.local_dependencies, vec![String::from("local")]);
+        let flags = analysis.flags();
+        assert!(flags.has_yanked);
+        assert!(!flags.has_deprecated);
+        assert!(!flags.has_outdated);
+    }
+
+    #[test]
+    fn renamed_crates_io_dependencies() {
+        let mut metadata = version("local", "1.0.0", false);
+        metadata.deps = vec![
+            dependency("serde", "^1.0", Some(CRATES_IO_REGISTRY_URI)),
+            IndexCrateDependency {
+                package: Some(String::from("rand")),
+                ..dependency("random", "^0.9", Some(CRATES_IO_REGISTRY_URI))
+            },
+            dependency("other", "^1.0", None),
+        ];
+        let names = get_crates_io_dependencies(&metadata).collect::<Vec<_>>();
+        assert_eq!(names, vec!["serde", "rand"]);
+    }
+}
@@ -0,0 +1,434 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for the differences between two versions of a crate
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+use similar::TextDiff;
+
+use super::cargo::DependencyKind;
+use crate::utils::apierror::{ApiError, error_invalid_request, specialize};
+
+/// The maximum time spent computing the diff of a single file
+const FILE_DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+/// The maximum number of files in a version to compare
+pub const DIFF_MAX_FILES: usize = 5_000;
+/// The maximum total size in bytes of the files in a version to compare
+pub const DIFF_MAX_TOTAL_SIZE: u64 = 50 * 1024 * 1024;
+
+/// The differences between two versions of a crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateVersionsDiff {
+    /// The name of the crate
+    pub package: String,
+    /// The version compared from
+    pub from: String,
+    /// The version compared to
+    pub to: String,
+    /// The files that differ between the two versions
+    pub files: Vec<CrateFileDiff>,
+    /// The changes to the dependencies in `Cargo.toml`
+    pub dependencies: Vec<DependencyDiff>,
+    /// The changes to the features in `Cargo.toml`
+    pub features: Vec<FeatureDiff>,
+}
+
+/// The kind of change for an element between two versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    /// The element only exists in the new version
+    #[serde(rename = "added")]
+    Added,
+    /// The element only exists in the old version
+    #[serde(rename = "removed")]
+    Removed,
+    /// The element exists in both versions but differs
+    #[serde(rename = "modified")]
+    Modified,
+}
+
+impl ChangeKind {
+    /// Gets the change between two optional elements, if any
+    fn between<T: PartialEq>(from: Option<&T>, to: Option<&T>) -> Option<Self> {
+        match (from, to) {
+            (None, Some(_)) => Some(Self::Added),
+            (Some(_), None) => Some(Self::Removed),
+            (Some(from), Some(to)) if from != to => Some(Self::Modified),
+            _ => None,
+        }
+    }
+}
+
+/// The differences for a file in the package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateFileDiff {
+    /// The path of the file, relative to the root directory of the package
+    pub path: String,
+    /// The kind of change
+    pub change: ChangeKind,
+    /// The size of the file in the old version, if present
+    #[serde(rename = "sizeFrom")]
+    pub size_from: Option<u64>,
+    /// The size of the file in the new version, if present
+    #[serde(rename = "sizeTo")]
+    pub size_to: Option<u64>,
+    /// Whether the file is binary
+    #[serde(rename = "isBinary")]
+    pub is_binary: bool,
+    /// Whether the file is too large for its content to be compared
+    #[serde(rename = "isTooLarge")]
+    pub is_too_large: bool,
+    /// The unified diff, only for modified textual files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// A dependency, as specified in `Cargo.toml`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestDependency {
+    /// The version requirement, or the source (path, git) when there is none
+    pub req: String,
+    /// The enabled features
+    pub features: Vec<String>,
+    /// Whether this is an optional dependency
+    pub optional: bool,
+    /// Whether the default features are enabled
+    #[serde(rename = "defaultFeatures")]
+    pub default_features: bool,
+    /// The name of the package, when the dependency is renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+}
+
+/// The change to a dependency between two versions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyDiff {
+    /// The name of the dependency, as used in the crate
+    pub name: String,
+    /// The kind of dependency
+    pub kind: DependencyKind,
+    /// The target platform, for target-specific dependencies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The kind of change
+    pub change: ChangeKind,
+    /// The dependency in the old version, if present
+    pub from: Option<ManifestDependency>,
+    /// The dependency in the new version, if present
+    pub to: Option<ManifestDependency>,
+}
+
+/// The change to a feature between two versions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureDiff {
+    /// The name of the feature
+    pub name: String,
+    /// The kind of change
+    pub change: ChangeKind,
+    /// What the feature enables in the old version, if present
+    pub from: Option<Vec<String>>,
+    /// What the feature enables in the new version, if present
+    pub to: Option<Vec<String>>,
+}
+
+/// The key of a dependency in a manifest: kind, target and name
+type DependencyKey = (DependencyKind, Option<String>, String);
+
+impl CrateVersionsDiff {
+    /// Computes the differences between the files of two versions of a crate
+    /// The files are given by their path relative to the root directory of the package
+    pub fn compute(
+        package: &str,
+        from: &str,
+        to: &str,
+        files_from: &BTreeMap<String, Vec<u8>>,
+        files_to: &BTreeMap<String, Vec<u8>>,
+        max_file_size: u64,
+    ) -> Result<Self, ApiError> {
+        let paths = files_from.keys().chain(files_to.keys()).collect::<BTreeSet<_>>();
+        let files = paths
+            .into_iter()
+            .filter_map(|path| diff_file(path, files_from.get(path), files_to.get(path), max_file_size))
+            .collect();
+        let manifest_from = parse_manifest(files_from.get("Cargo.toml"))?;
+        let manifest_to = parse_manifest(files_to.get("Cargo.toml"))?;
+        Ok(Self {
+            package: package.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            files,
+            dependencies: diff_dependencies(&manifest_from, &manifest_to),
+            features: diff_features(&manifest_from, &manifest_to),
+        })
+    }
+}
+
+/// Computes the difference for a single file
+fn diff_file(path: &str, from: Option<&Vec<u8>>, to: Option<&Vec<u8>>, max_file_size: u64) -> Option<CrateFileDiff> {
+    let change = ChangeKind::between(from, to)?;
+    let text_from = from.map(|content| as_text(content));
+    let text_to = to.map(|content| as_text(content));
+    let is_binary = text_from.as_ref().is_some_and(Option::is_none) || text_to.as_ref().is_some_and(Option::is_none);
+    let size_from = from.map(|content| content.len() as u64);
+    let size_to = to.map(|content| content.len() as u64);
+    let is_too_large = size_from.unwrap_or_default() > max_file_size || size_to.unwrap_or_default() > max_file_size;
+    let diff = match (change, text_from.flatten(), text_to.flatten()) {
+        (ChangeKind::Modified, Some(text_from), Some(text_to)) if !is_too_large => Some(
+            TextDiff::configure()
+                .timeout(FILE_DIFF_TIMEOUT)
+                .diff_lines(text_from, text_to)
+                .unified_diff()
+                .context_radius(3)
+                .header(&format!("a/{path}"), &format!("b/{path}"))
+                .to_string(),
+        ),
+        _ => None,
+    };
+    Some(CrateFileDiff {
+        path: path.to_string(),
+        change,
+        size_from,
+        size_to,
+        is_binary,
+        is_too_large,
+        diff,
+    })
+}
+
+/// Gets the content of a file as text, if it is not binary
+fn as_text(content: &[u8]) -> Option<&str> {
+    std::str::from_utf8(content).ok().filter(|text| !text.contains('\0'))
+}
+
+/// The parts of a manifest that are compared
+#[derive(Debug, Default)]
+struct ManifestData {
+    /// The dependencies
+    dependencies: BTreeMap<DependencyKey, ManifestDependency>,
+    /// The features
+    features: BTreeMap<String, Vec<String>>,
+}
+
+/// Parses the dependencies and features in a `Cargo.toml` manifest
+fn parse_manifest(content: Option<&Vec<u8>>) -> Result<ManifestData, ApiError> {
+    let Some(content) = content else {
+        return Ok(ManifestData::default());
+    };
+    let manifest = String::from_utf8_lossy(content)
+        .parse::<toml::Table>()
+        .map_err(|error| specialize(error_invalid_request(), format!("Invalid Cargo.toml: {error}")))?;
+    let mut data = ManifestData::default();
+    parse_manifest_dependencies(&manifest, None, &mut data.dependencies);
+    if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+        for (target, table) in targets {
+            if let Some(table) = table.as_table() {
+                parse_manifest_dependencies(table, Some(target), &mut data.dependencies);
+            }
+        }
+    }
+    if let Some(features) = manifest.get("features").and_then(toml::Value::as_table) {
+        for (name, values) in features {
+            let values = values
+                .as_array()
+                .map(|values| values.iter().filter_map(toml::Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default();
+            data.features.insert(name.clone(), values);
+        }
+    }
+    Ok(data)
+}
+
+/// Parses the dependency tables in a manifest, or in a target-specific section
+fn parse_manifest_dependencies(
+    table: &toml::Table,
+    target: Option<&str>,
+    dependencies: &mut BTreeMap<DependencyKey, ManifestDependency>,
+) {
+    const TABLES: [(&str, DependencyKind); 5] = [
+        ("dependencies", DependencyKind::Normal),
+        ("dev-dependencies", DependencyKind::Dev),
+        ("dev_dependencies", DependencyKind::Dev),
+        ("build-dependencies", DependencyKind::Build),
+        ("build_dependencies", DependencyKind::Build),
+    ];
+    for (key, kind) in TABLES {
+        let Some(deps) = table.get(key).and_then(toml::Value::as_table) else {
+            continue;
+        };
+        for (name, spec) in deps {
+            let dependency = match spec {
+                toml::Value::String(req) => ManifestDependency {
+                    req: req.clone(),
+                    features: Vec::new(),
+                    optional: false,
+                    default_features: true,
+                    package: None,
+                },
+                toml::Value::Table(spec) => {
+                    let get_str = |key: &str| spec.get(key).and_then(toml::Value::as_str);
+                    let get_bool = |key: &str| spec.get(key).and_then(toml::Value::as_bool);
+                    ManifestDependency {
+                        req: get_str("version")
+                            .or_else(|| get_str("path"))
+                            .or_else(|| get_str("git"))
+                            .unwrap_or("*")
+                            .to_string(),
+                        features: spec
+                            .get("features")
+                            .and_then(toml::Value::as_array)
+                            .map(|values| values.iter().filter_map(toml::Value::as_str).map(str::to_string).collect())
+                            .unwrap_or_default(),
+                        optional: get_bool("optional").unwrap_or(false),
+                        default_features: get_bool("default-features")
+                            .or_else(|| get_bool("default_features"))
+                            .unwrap_or(true),
+                        package: get_str("package").map(str::to_string),
+                    }
+                }
+                _ => continue,
+            };
+            dependencies.insert((kind, target.map(str::to_string), name.clone()), dependency);
+        }
+    }
+}
+
+/// Computes the changes to the dependencies
+fn diff_dependencies(from: &ManifestData, to: &ManifestData) -> Vec<DependencyDiff> {
+    let keys = from
+        .dependencies
+        .keys()
+        .chain(to.dependencies.keys())
+        .collect::<BTreeSet<_>>();
+    keys.into_iter()
+        .filter_map(|key| {
+            let dep_from = from.dependencies.get(key);
+            let dep_to = to.dependencies.get(key);
+            let change = ChangeKind::between(dep_from, dep_to)?;
+            let (kind, target, name) = key.clone();
+            Some(DependencyDiff {
+                name,
+                kind,
+                target,
+                change,
+                from: dep_from.cloned(),
+                to: dep_to.cloned(),
+            })
+        })
+        .collect()
+}
+
+/// Computes the changes to the features
+fn diff_features(from: &ManifestData, to: &ManifestData) -> Vec<FeatureDiff> {
+    let names = from.features.keys().chain(to.features.keys()).collect::<BTreeSet<_>>();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let feature_from = from.features.get(name);
+            let feature_to = to.features.get(name);
+            let change = ChangeKind::between(feature_from, feature_to)?;
+            Some(FeatureDiff {
+                name: name.clone(),
+                change,
+                from: feature_from.cloned(),
+                to: feature_to.cloned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{ChangeKind, CrateVersionsDiff};
+    use crate::model::cargo::DependencyKind;
+
+    fn files(entries: &[(&str, &[u8])]) -> BTreeMap<String, Vec<u8>> {
+        entries
+            .iter()
+            .map(|(path, content)| ((*path).to_string(), content.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn diff_files() {
+        let from = files(&[
+            ("src/lib.rs", b"fn a() {}\nfn b() {}\n"),
+            ("README.md", b"hello\n"),
+            ("logo.bin", &[0, 1, 2]),
+        ]);
+        let to = files(&[
+            ("src/lib.rs", b"fn a() {}\nfn c() {}\n"),
+            ("src/new.rs", b"\n"),
+            ("logo.bin", &[0, 1, 3]),
+        ]);
+        let diff = CrateVersionsDiff::compute("hello", "1.0.0", "1.1.0", &from, &to, 1024).unwrap();
+        let changes = diff
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("README.md", ChangeKind::Removed),
+                ("logo.bin", ChangeKind::Modified),
+                ("src/lib.rs", ChangeKind::Modified),
+                ("src/new.rs", ChangeKind::Added),
+            ]
+        );
+        assert!(diff.files[1].is_binary);
+        assert!(diff.files[1].diff.is_none());
+        let lib = diff.files[2].diff.as_deref().unwrap();
+        assert!(lib.starts_with("--- a/src/lib.rs\n+++ b/src/lib.rs\n"));
+        assert!(lib.contains("-fn b() {}\n+fn c() {}\n"));
+
+        // too large to be compared
+        let diff = CrateVersionsDiff::compute("hello", "1.0.0", "1.1.0", &from, &to, 8).unwrap();
+        assert!(diff.files[2].is_too_large);
+        assert!(diff.files[2].diff.is_none());
+    }
+
+    #[test]
+    fn diff_manifests() {
+        let from = files(&[(
+            "Cargo.toml",
+            b"[dependencies]\nserde = \"1.0\"\nlog = \"0.4\"\n\n[target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n\n[features]\ndefault = [\"std\"]\nstd = []\n",
+        )]);
+        let to = files(&[(
+            "Cargo.toml",
+            b"[dependencies]\nserde = { version = \"1.0\", features = [\"derive\"] }\n\n[dev-dependencies]\ntokio = \"1\"\n\n[target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n\n[features]\ndefault = []\nstd = []\nasync = [\"dep:tokio\"]\n",
+        )]);
+        let diff = CrateVersionsDiff::compute("hello", "1.0.0", "1.1.0", &from, &to, 1024).unwrap();
+        let dependencies = diff
+            .dependencies
+            .iter()
+            .map(|dep| (dep.name.as_str(), dep.kind, dep.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dependencies,
+            vec![
+                ("log", DependencyKind::Normal, ChangeKind::Removed),
+                ("serde", DependencyKind::Normal, ChangeKind::Modified),
+                ("tokio", DependencyKind::Dev, ChangeKind::Added),
+            ]
+        );
+        assert_eq!(
+            diff.dependencies[1].to.as_ref().unwrap().features,
+            vec![String::from("derive")]
+        );
+        let features = diff
+            .features
+            .iter()
+            .map(|feature| (feature.name.as_str(), feature.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            features,
+            vec![("async", ChangeKind::Added), ("default", ChangeKind::Modified)]
+        );
+    }
+}
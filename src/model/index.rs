@@ -0,0 +1,231 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for the consistency checks between the database, the index and the storage,
+//! and for the synchronization of the index with its remote origin
+
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use serde_derive::{Deserialize, Serialize};
+
+use super::cargo::IndexCrateMetadata;
+
+/// The kind of inconsistency for a crate version
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexIssueKind {
+    /// The version is in the database, but its .crate package is missing in the storage
+    MissingTarball,
+    /// The checksum in the index does not match the .crate package in the storage
+    ChecksumMismatch,
+    /// The version is in the database, but not in the index
+    MissingInIndex,
+    /// The version is in the index, but not in the database
+    MissingInDatabase,
+    /// The version is yanked in the database, but not in the index, or the reverse
+    YankedMismatch,
+    /// The data for the index of the version is not recorded and cannot be recovered from the storage
+    MissingIndexData,
+}
+
+/// An inconsistency for a crate version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexIssue {
+    /// The name of the crate
+    pub package: String,
+    /// The crate's version
+    pub version: String,
+    /// The kind of inconsistency
+    pub kind: IndexIssueKind,
+    /// Details about the inconsistency
+    pub details: String,
+}
+
+/// The report of the consistency checks between the database, the index and the storage
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IndexCheckReport {
+    /// The number of versions in the database
+    pub versions: usize,
+    /// The found inconsistencies
+    pub issues: Vec<IndexIssue>,
+    /// Whether the index was rebuilt from the database and the storage
+    pub rebuilt: bool,
+    /// The inconsistencies that the rebuild could not repair
+    pub unresolved: Vec<IndexIssue>,
+    /// The error when the rebuilt index could not be pushed to the remote origin from the command line
+    pub push_error: Option<String>,
+}
+
+/// The status of the pushes of the index to its remote origin
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IndexPushStatus {
+    /// The number of commits that are not pushed yet
+    #[serde(rename = "pendingCommits")]
+    pub pending_commits: usize,
+    /// The time of the oldest commit that is not pushed yet, if any
+    #[serde(rename = "pendingSince")]
+    pub pending_since: Option<NaiveDateTime>,
+    /// The number of seconds since the oldest commit that is not pushed yet, 0 when up to date
+    pub lag: i64,
+    /// The time of the last successful push, if any
+    #[serde(rename = "lastPush")]
+    pub last_push: Option<NaiveDateTime>,
+    /// The error for the last attempt, if it failed
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    /// The number of consecutive failed attempts
+    pub failures: u32,
+}
+
+/// A crate version, as known in the database and the storage
+#[derive(Debug, Clone)]
+pub struct StoredCrateVersion {
+    /// The name of the crate
+    pub package: String,
+    /// The crate's version
+    pub version: String,
+    /// Whether the version is yanked in the database
+    pub yanked: bool,
+    /// The checksum of the .crate package in the storage, if present
+    pub cksum: Option<String>,
+}
+
+/// Finds the inconsistencies between the versions in the database and the storage, and the versions in the index
+#[must_use]
+pub fn find_index_issues(stored: &[StoredCrateVersion], indexed: &[IndexCrateMetadata]) -> Vec<IndexIssue> {
+    let mut indexed = indexed
+        .iter()
+        .map(|data| ((data.name.to_ascii_lowercase(), data.vers.as_str()), data))
+        .collect::<HashMap<_, _>>();
+    let mut issues = Vec::new();
+    let mut add_issue = |package: &str, version: &str, kind: IndexIssueKind, details: String| {
+        issues.push(IndexIssue {
+            package: package.to_string(),
+            version: version.to_string(),
+            kind,
+            details,
+        });
+    };
+    for version in stored {
+        if version.cksum.is_none() {
+            add_issue(
+                &version.package,
+                &version.version,
+                IndexIssueKind::MissingTarball,
+                String::from("the .crate package is missing in the storage"),
+            );
+        }
+        let Some(data) = indexed.remove(&(version.package.to_ascii_lowercase(), version.version.as_str())) else {
+            add_issue(
+                &version.package,
+                &version.version,
+                IndexIssueKind::MissingInIndex,
+                String::from("the version is not in the index"),
+            );
+            continue;
+        };
+        if let Some(cksum) = version.cksum.as_ref().filter(|cksum| **cksum != data.cksum) {
+            add_issue(
+                &version.package,
+                &version.version,
+                IndexIssueKind::ChecksumMismatch,
+                format!("the checksum is {} in the index, but {cksum} in the storage", data.cksum),
+            );
+        }
+        if data.yanked != version.yanked {
+            add_issue(
+                &version.package,
+                &version.version,
+                IndexIssueKind::YankedMismatch,
+                format!(
+                    "the version is {} in the index, but {} in the database",
+                    yanked_label(data.yanked),
+                    yanked_label(version.yanked)
+                ),
+            );
+        }
+    }
+    let mut remaining = indexed.into_values().collect::<Vec<_>>();
+    remaining.sort_by(|a, b| (&a.name, &a.vers).cmp(&(&b.name, &b.vers)));
+    for data in remaining {
+        add_issue(
+            &data.name,
+            &data.vers,
+            IndexIssueKind::MissingInDatabase,
+            String::from("the version is in the index, but not in the database"),
+        );
+    }
+    issues
+}
+
+/// Gets the label for a yanked flag
+const fn yanked_label(yanked: bool) -> &'static str {
+    if yanked { "yanked" } else { "not yanked" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IndexIssueKind, StoredCrateVersion, find_index_issues};
+    use crate::model::cargo::IndexCrateMetadata;
+
+    fn stored(version: &str, yanked: bool, cksum: Option<&str>) -> StoredCrateVersion {
+        StoredCrateVersion {
+            package: String::from("Hello"),
+            version: version.to_string(),
+            yanked,
+            cksum: cksum.map(str::to_string),
+        }
+    }
+
+    fn indexed(version: &str, yanked: bool, cksum: &str) -> IndexCrateMetadata {
+        IndexCrateMetadata {
+            name: String::from("hello"),
+            vers: version.to_string(),
+            yanked,
+            cksum: cksum.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn consistent() {
+        let issues = find_index_issues(
+            &[stored("1.0.0", false, Some("a")), stored("1.1.0", true, Some("b"))],
+            &[indexed("1.0.0", false, "a"), indexed("1.1.0", true, "b")],
+        );
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn inconsistencies() {
+        let issues = find_index_issues(
+            &[
+                stored("1.0.0", false, None),
+                stored("1.1.0", true, Some("b")),
+                stored("1.2.0", false, Some("c")),
+                stored("1.3.0", false, Some("d")),
+            ],
+            &[
+                indexed("1.0.0", false, "a"),
+                indexed("1.1.0", false, "b"),
+                indexed("1.2.0", false, "x"),
+                indexed("2.0.0", false, "e"),
+            ],
+        );
+        let issues = issues
+            .iter()
+            .map(|issue| (issue.version.as_str(), issue.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                ("1.0.0", IndexIssueKind::MissingTarball),
+                ("1.1.0", IndexIssueKind::YankedMismatch),
+                ("1.2.0", IndexIssueKind::ChecksumMismatch),
+                ("1.3.0", IndexIssueKind::MissingInIndex),
+                ("2.0.0", IndexIssueKind::MissingInDatabase),
+            ]
+        );
+    }
+}
@@ -4,16 +4,25 @@
 
 //! Data model
 
+pub mod attestation;
+pub mod audit;
 pub mod auth;
 pub mod cargo;
 pub mod config;
 pub mod deps;
+pub mod diff;
 pub mod docs;
 pub mod errors;
+pub mod index;
 pub mod namegen;
+pub mod names;
 pub mod osv;
 pub mod packages;
+pub mod policy;
+pub mod scim;
+pub mod semver_checks;
 pub mod stats;
+pub mod teams;
 pub mod worker;
 
 use auth::TokenUsage;
@@ -46,6 +55,9 @@ pub struct RegistryInformation {
     /// The known built-in targets in rustc
     #[serde(rename = "toolchainTargets")]
     pub toolchain_targets: Vec<String>,
+    /// The status of the pushes of the index to its remote origin, for administrators when enabled
+    #[serde(rename = "indexPush")]
+    pub index_push: Option<index::IndexPushStatus>,
 }
 
 /// A couple describing a crate with its name and the associated version
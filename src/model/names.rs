@@ -0,0 +1,148 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types and rules for protecting crate names against squatting and confusion
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::apierror::{ApiError, error_invalid_request, specialize};
+
+/// The minimal length of names for which a single edit is deemed confusable
+/// Shorter names are too likely to be legitimately close to each other
+const MIN_LENGTH_FOR_DISTANCE: usize = 5;
+
+/// A policy restricting the names of new crates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateNamePolicy {
+    /// The unique identifier
+    pub id: i64,
+    /// The normalized name, or a prefix when ending with `*`
+    pub pattern: String,
+    /// The team that may publish new crates matching the pattern, none when the names are reserved
+    pub team: Option<String>,
+    /// The reason for the policy, shown to publishers
+    pub reason: Option<String>,
+}
+
+impl CrateNamePolicy {
+    /// Gets whether the policy applies to a normalized crate name
+    #[must_use]
+    pub fn matches(&self, normalized: &str) -> bool {
+        self.pattern
+            .strip_suffix('*')
+            .map_or_else(|| normalized == self.pattern, |prefix| normalized.starts_with(prefix))
+    }
+}
+
+/// The specification for a new policy restricting the names of new crates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateNamePolicySpec {
+    /// The name, or a prefix when ending with `*`
+    pub pattern: String,
+    /// The team that may publish new crates matching the pattern, none when the names are reserved
+    pub team: Option<String>,
+    /// The reason for the policy, shown to publishers
+    pub reason: Option<String>,
+}
+
+impl CrateNamePolicySpec {
+    /// Validates the specification and returns the normalized pattern
+    pub fn normalized_pattern(&self) -> Result<String, ApiError> {
+        let name = self.pattern.strip_suffix('*').unwrap_or(&self.pattern);
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(specialize(
+                error_invalid_request(),
+                format!(
+                    "invalid name pattern {}, expected a crate name or a prefix ending with *",
+                    self.pattern
+                ),
+            ));
+        }
+        Ok(normalize_crate_name(&self.pattern))
+    }
+}
+
+/// Normalizes a crate name, as for crates.io, `-` and `_` are equivalent and names are case-insensitive
+#[must_use]
+pub fn normalize_crate_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+/// Gets the skeleton of a normalized crate name where the characters that look alike are replaced by a common one
+fn name_skeleton(normalized: &str) -> String {
+    normalized
+        .replace('_', "")
+        .replace("rn", "m")
+        .replace("vv", "w")
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '5' => 's',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Computes the edit distance between two names, where the transposition of adjacent characters counts as a single edit
+fn edit_distance(left: &[u8], right: &[u8]) -> usize {
+    let mut previous2 = vec![0; right.len() + 1];
+    let mut previous = (0..=right.len()).collect::<Vec<_>>();
+    let mut current = vec![0; right.len() + 1];
+    for i in 1..=left.len() {
+        current[0] = i;
+        for j in 1..=right.len() {
+            let cost = usize::from(left[i - 1] != right[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                current[j] = current[j].min(previous2[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut previous2, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[right.len()]
+}
+
+/// Gets whether two distinct normalized crate names could be confused with one another
+/// This is the case when they look alike or when they are within a single edit
+#[must_use]
+pub fn are_confusable(left: &str, right: &str) -> bool {
+    if left == right {
+        return false;
+    }
+    if name_skeleton(left) == name_skeleton(right) {
+        return true;
+    }
+    left.len().min(right.len()) >= MIN_LENGTH_FOR_DISTANCE && edit_distance(left.as_bytes(), right.as_bytes()) <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{are_confusable, normalize_crate_name};
+
+    #[test]
+    fn normalized_names() {
+        assert_eq!(normalize_crate_name("Foo-Bar_baz"), "foo_bar_baz");
+    }
+
+    #[test]
+    fn confusable_homoglyphs() {
+        assert!(are_confusable("serde", "serde_"));
+        assert!(are_confusable("modern", "modem"));
+        assert!(are_confusable("tokio", "t0kio"));
+        assert!(are_confusable("clap", "c1ap"));
+        assert!(!are_confusable("serde", "serde"));
+    }
+
+    #[test]
+    fn confusable_edits() {
+        assert!(are_confusable("reqwest", "reqwset"));
+        assert!(are_confusable("reqwest", "requwest"));
+        assert!(are_confusable("reqwest", "reqwst"));
+        assert!(!are_confusable("reqwest", "request_builder"));
+        // too short
+        assert!(!are_confusable("rand", "band"));
+    }
+}
@@ -4,10 +4,15 @@
 
 //! Data types for crate information and description, in addition to Cargo types
 
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
 use chrono::NaiveDateTime;
 use serde_derive::{Deserialize, Serialize};
 
 use super::cargo::{CrateMetadata, IndexCrateMetadata, RegistryUser};
+use super::teams::Team;
+use crate::utils::apierror::{ApiError, error_invalid_request, specialize};
 
 /// Gets the last info for a crate
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +22,12 @@ pub struct CrateInfo {
     /// Whether the entire package is deprecated
     #[serde(rename = "isDeprecated")]
     pub is_deprecated: bool,
+    /// The message explaining the deprecation, if any
+    #[serde(rename = "deprecationMessage", default, skip_serializing_if = "Option::is_none")]
+    pub deprecation_message: Option<String>,
+    /// The crate to use instead of this deprecated one, if any
+    #[serde(rename = "useInstead", default, skip_serializing_if = "Option::is_none")]
+    pub use_instead: Option<String>,
     /// Whether versions of this crate can be completely removed, not simply yanked
     #[serde(rename = "canRemove")]
     pub can_remove: bool,
@@ -26,6 +37,76 @@ pub struct CrateInfo {
     pub targets: Vec<CrateInfoTarget>,
     /// The required capabilities for docs generation
     pub capabilities: Vec<String>,
+    /// Who can read the crate
+    #[serde(default)]
+    pub visibility: CrateVisibility,
+    /// Whether new versions must be approved by another owner before being published to the index
+    #[serde(rename = "requiresApproval", default)]
+    pub requires_approval: bool,
+}
+
+/// The deprecation status of a crate
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CrateDeprecation {
+    /// Whether the crate is deprecated
+    #[serde(rename = "isDeprecated")]
+    pub is_deprecated: bool,
+    /// The message explaining the deprecation, if any
+    #[serde(default)]
+    pub message: Option<String>,
+    /// The crate to use instead, if any
+    #[serde(rename = "useInstead", default)]
+    pub use_instead: Option<String>,
+}
+
+/// Who can read a crate
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CrateVisibility {
+    /// Any authenticated user can read the crate
+    #[default]
+    #[serde(rename = "internal")]
+    Internal,
+    /// Only the owners and the readers of the crate can read it
+    #[serde(rename = "restricted")]
+    Restricted,
+}
+
+impl CrateVisibility {
+    /// Gets the serialized name of this visibility
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Internal => "internal",
+            Self::Restricted => "restricted",
+        }
+    }
+}
+
+impl Display for CrateVisibility {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CrateVisibility {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "internal" => Ok(Self::Internal),
+            "restricted" => Ok(Self::Restricted),
+            _ => Err(specialize(error_invalid_request(), format!("Unknown crate visibility: {s}"))),
+        }
+    }
+}
+
+/// The readers of a restricted crate, in addition to its owners
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CrateReaders {
+    /// The users that can read the crate
+    pub users: Vec<RegistryUser>,
+    /// The teams that can read the crate
+    pub teams: Vec<Team>,
 }
 
 /// A build targets to use (for docs generation and deps analysis)
@@ -40,6 +121,7 @@ pub struct CrateInfoTarget {
 
 /// The data for a crate version
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[expect(clippy::struct_excessive_bools)]
 pub struct CrateInfoVersion {
     /// The data from the index
     pub index: IndexCrateMetadata,
@@ -48,6 +130,9 @@ pub struct CrateInfoVersion {
     /// The user that uploaded the version
     #[serde(rename = "uploadedBy")]
     pub uploaded_by: RegistryUser,
+    /// The reason why this version was yanked, if any
+    #[serde(rename = "yankReason", default, skip_serializing_if = "Option::is_none")]
+    pub yank_reason: Option<String>,
     /// The number of times this version was downloaded
     #[serde(rename = "downloadCount")]
     pub download_count: i64,
@@ -60,10 +145,28 @@ pub struct CrateInfoVersion {
     /// Flag whether CVEs have been filed against dependencies of this crate
     #[serde(rename = "depsHasCVEs")]
     pub deps_has_cves: bool,
+    /// Flag whether dependencies of this crate, direct or transitive, resolve to yanked versions
+    #[serde(rename = "depsHasYanked")]
+    pub deps_has_yanked: bool,
+    /// Flag whether dependencies of this crate, direct or transitive, are deprecated
+    #[serde(rename = "depsHasDeprecated")]
+    pub deps_has_deprecated: bool,
     /// The documentation status
     pub docs: Vec<CrateInfoVersionDocs>,
 }
 
+/// A version of a crate that is held back from the index until approved by another owner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CratePendingVersion {
+    /// The data for the index
+    pub index: IndexCrateMetadata,
+    /// The upload date time
+    pub upload: NaiveDateTime,
+    /// The user that uploaded the version
+    #[serde(rename = "uploadedBy")]
+    pub uploaded_by: RegistryUser,
+}
+
 /// The documentation status for a crate version
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CrateInfoVersionDocs {
@@ -76,3 +179,12 @@ pub struct CrateInfoVersionDocs {
     #[serde(rename = "isPresent")]
     pub is_present: bool,
 }
+
+/// A file in the `.crate` package of a crate version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateFile {
+    /// The path of the file, relative to the root directory of the package
+    pub path: String,
+    /// The size of the file, in bytes
+    pub size: u64,
+}
@@ -0,0 +1,49 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for the checks of crates against the policies of the organisation
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::cargo::{CrateMetadata, RegistryUser};
+
+/// The request sent to the policy hook for a crate version about to be published
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyCheckRequest<'a> {
+    /// The metadata of the crate version, as sent by cargo
+    pub metadata: &'a CrateMetadata,
+    /// The user publishing the crate version
+    #[serde(rename = "uploadedBy")]
+    pub uploaded_by: &'a RegistryUser,
+    /// Whether this is the first version of the crate
+    #[serde(rename = "isNewCrate")]
+    pub is_new_crate: bool,
+    /// The content of the `.crate` package, encoded in base64, when the hook is configured to receive it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+/// The answer of the policy hook for a crate version about to be published
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PolicyCheckResult {
+    /// Whether the crate version can be published
+    pub allowed: bool,
+    /// The message explaining the rejection, if any
+    #[serde(default)]
+    pub message: Option<String>,
+    /// The warnings to display to the user
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl PolicyCheckResult {
+    /// Gets a result allowing the publication without warning
+    #[must_use]
+    pub fn allowed() -> Self {
+        Self {
+            allowed: true,
+            ..Default::default()
+        }
+    }
+}
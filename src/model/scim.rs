@@ -0,0 +1,505 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for the provisioning of users and groups through SCIM 2.0 (RFC 7643 and RFC 7644)
+
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+
+use super::cargo::RegistryUser;
+use super::teams::Team;
+use crate::utils::apierror::{ApiError, error_invalid_request, specialize};
+
+/// The schema for a user resource
+pub const SCIM_SCHEMA_USER: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+/// The schema for a group resource
+pub const SCIM_SCHEMA_GROUP: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+/// The schema for a list response
+pub const SCIM_SCHEMA_LIST_RESPONSE: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+/// The schema for an error response
+pub const SCIM_SCHEMA_ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+/// The content type for SCIM messages
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+/// The metadata about a resource
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimMeta {
+    /// The type of resource, `User` or `Group`
+    #[serde(rename = "resourceType")]
+    pub resource_type: String,
+    /// The URI of the resource
+    #[serde(default)]
+    pub location: String,
+}
+
+/// The components of the name of a user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimName {
+    /// The full name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<String>,
+    /// The family name
+    #[serde(rename = "familyName", default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    /// The given name
+    #[serde(rename = "givenName", default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+}
+
+/// An email for a user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimEmail {
+    /// The email address
+    pub value: String,
+    /// Whether this is the primary email
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// A user resource
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimUser {
+    /// The schemas for the resource
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// The identifier of the user in the registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The identifier of the user for the identity provider
+    #[serde(rename = "externalId", default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    /// The unique name for the user, expected to be the email
+    #[serde(rename = "userName")]
+    pub user_name: String,
+    /// The name of the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<ScimName>,
+    /// The name to display for the user
+    #[serde(rename = "displayName", default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The emails for the user
+    #[serde(default)]
+    pub emails: Vec<ScimEmail>,
+    /// Whether the user is active
+    #[serde(default = "default_true", deserialize_with = "deserialize_scim_bool")]
+    pub active: bool,
+    /// The metadata about the resource
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+/// A reference to a member of a group
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimMember {
+    /// The identifier of the user in the registry
+    pub value: String,
+    /// The name to display for the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+}
+
+/// A group resource, mapped to a team of users
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimGroup {
+    /// The schemas for the resource
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// The identifier of the group in the registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The name of the group
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    /// The members of the group
+    #[serde(default)]
+    pub members: Vec<ScimMember>,
+    /// The metadata about the resource
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+/// A list of resources
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScimListResponse<T> {
+    /// The schemas for the message
+    pub schemas: Vec<String>,
+    /// The total number of results
+    #[serde(rename = "totalResults")]
+    pub total_results: usize,
+    /// The 1-based index of the first result
+    #[serde(rename = "startIndex")]
+    pub start_index: usize,
+    /// The number of results in this page
+    #[serde(rename = "itemsPerPage")]
+    pub items_per_page: usize,
+    /// The resources in this page
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+impl<T> ScimListResponse<T> {
+    /// Builds the page of a list response
+    #[must_use]
+    pub fn new(all: Vec<T>, query: &ScimListQuery) -> Self {
+        let total_results = all.len();
+        let start_index = query.start_index.unwrap_or(1).max(1);
+        let resources = all
+            .into_iter()
+            .skip(start_index - 1)
+            .take(query.count.unwrap_or(usize::MAX))
+            .collect::<Vec<_>>();
+        Self {
+            schemas: vec![SCIM_SCHEMA_LIST_RESPONSE.to_string()],
+            total_results,
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        }
+    }
+}
+
+/// The query parameters for listing resources
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScimListQuery {
+    /// The filter, only `attribute eq "value"` is supported
+    pub filter: Option<String>,
+    /// The 1-based index of the first result
+    #[serde(rename = "startIndex")]
+    pub start_index: Option<usize>,
+    /// The maximum number of results
+    pub count: Option<usize>,
+}
+
+impl ScimListQuery {
+    /// Parses the filter, if any, into the attribute and the expected value
+    pub fn parse_filter(&self) -> Result<Option<(String, String)>, ApiError> {
+        let Some(filter) = &self.filter else {
+            return Ok(None);
+        };
+        parse_eq_filter(filter)
+            .map(Some)
+            .ok_or_else(|| specialize(error_invalid_request(), format!("unsupported filter: {filter}")))
+    }
+}
+
+/// Parses a filter of the form `attribute eq "value"`
+fn parse_eq_filter(filter: &str) -> Option<(String, String)> {
+    let mut parts = filter.trim().splitn(3, ' ');
+    let (attribute, operator, value) = (parts.next()?, parts.next()?, parts.next()?);
+    if !operator.eq_ignore_ascii_case("eq") {
+        return None;
+    }
+    Some((attribute.to_string(), value.trim().trim_matches('"').to_string()))
+}
+
+/// A patch request
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScimPatchRequest {
+    /// The operations to apply
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOperation>,
+}
+
+/// An operation in a patch request
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScimPatchOperation {
+    /// The operation, `add`, `remove` or `replace`, case-insensitive
+    pub op: String,
+    /// The path to the target attribute, if any
+    #[serde(default)]
+    pub path: Option<String>,
+    /// The value for the operation, if any
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+}
+
+/// An error response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScimError {
+    /// The schemas for the message
+    pub schemas: Vec<String>,
+    /// The HTTP status code, as a string
+    pub status: String,
+    /// The details about the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl From<&ApiError> for ScimError {
+    fn from(error: &ApiError) -> Self {
+        Self {
+            schemas: vec![SCIM_SCHEMA_ERROR.to_string()],
+            status: error.http.to_string(),
+            detail: Some(error.details.clone().unwrap_or_else(|| error.message.clone())),
+        }
+    }
+}
+
+impl ScimUser {
+    /// Builds the resource for a user of the registry
+    #[must_use]
+    pub fn from_user(user: &RegistryUser, base_uri: &str) -> Self {
+        Self {
+            schemas: vec![SCIM_SCHEMA_USER.to_string()],
+            id: Some(user.id.to_string()),
+            external_id: None,
+            user_name: user.email.clone(),
+            name: Some(ScimName {
+                formatted: Some(user.name.clone()),
+                family_name: None,
+                given_name: None,
+            }),
+            display_name: Some(user.name.clone()),
+            emails: vec![ScimEmail {
+                value: user.email.clone(),
+                primary: true,
+            }],
+            active: user.is_active,
+            meta: Some(ScimMeta {
+                resource_type: String::from("User"),
+                location: format!("{base_uri}/scim/v2/Users/{}", user.id),
+            }),
+        }
+    }
+
+    /// Gets the email for this user, the primary one if any, or the user name
+    #[must_use]
+    pub fn email(&self) -> &str {
+        self.emails
+            .iter()
+            .find(|email| email.primary)
+            .or_else(|| self.emails.first())
+            .map_or(&self.user_name, |email| &email.value)
+    }
+
+    /// Gets the full name for this user, if any
+    #[must_use]
+    pub fn full_name(&self) -> Option<String> {
+        if let Some(display_name) = &self.display_name {
+            return Some(display_name.clone());
+        }
+        let name = self.name.as_ref()?;
+        if let Some(formatted) = &name.formatted {
+            return Some(formatted.clone());
+        }
+        match (&name.given_name, &name.family_name) {
+            (Some(given), Some(family)) => Some(format!("{given} {family}")),
+            (Some(single), None) | (None, Some(single)) => Some(single.clone()),
+            (None, None) => None,
+        }
+    }
+
+    /// Applies a patch request to this user
+    /// Only the attributes stored by the registry are considered, the others are ignored
+    pub fn apply_patch(&mut self, request: &ScimPatchRequest) -> Result<(), ApiError> {
+        let mut name_patched = false;
+        let mut display_name_patched = false;
+        for operation in &request.operations {
+            let op = operation.op.to_ascii_lowercase();
+            if op != "add" && op != "replace" {
+                return Err(specialize(
+                    error_invalid_request(),
+                    format!("unsupported operation {op} for users"),
+                ));
+            }
+            let value = operation
+                .value
+                .as_ref()
+                .ok_or_else(|| specialize(error_invalid_request(), String::from("missing value")))?;
+            let attributes = match &operation.path {
+                Some(path) => vec![(path.to_ascii_lowercase(), value)],
+                None => value
+                    .as_object()
+                    .ok_or_else(|| specialize(error_invalid_request(), String::from("expected an object")))?
+                    .iter()
+                    .map(|(path, value)| (path.to_ascii_lowercase(), value))
+                    .collect(),
+            };
+            for (path, value) in attributes {
+                let as_string = || {
+                    value
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| specialize(error_invalid_request(), format!("expected a string for {path}")))
+                };
+                match path.as_str() {
+                    "active" => {
+                        self.active = scim_value_as_bool(value).ok_or_else(|| {
+                            specialize(error_invalid_request(), String::from("expected a boolean for active"))
+                        })?;
+                    }
+                    "displayname" => {
+                        self.display_name = Some(as_string()?);
+                        display_name_patched = true;
+                    }
+                    "name" => {
+                        self.name = Some(from_scim_value(value)?);
+                        name_patched = true;
+                    }
+                    "name.formatted" => {
+                        self.name.get_or_insert_with(ScimName::default).formatted = Some(as_string()?);
+                        name_patched = true;
+                    }
+                    "name.givenname" => {
+                        let name = self.name.get_or_insert_with(ScimName::default);
+                        name.given_name = Some(as_string()?);
+                        name.formatted = None;
+                        name_patched = true;
+                    }
+                    "name.familyname" => {
+                        let name = self.name.get_or_insert_with(ScimName::default);
+                        name.family_name = Some(as_string()?);
+                        name.formatted = None;
+                        name_patched = true;
+                    }
+                    // other attributes are not stored by the registry
+                    _ => {}
+                }
+            }
+        }
+        if name_patched && !display_name_patched {
+            // the full name is then derived from the new name
+            self.display_name = None;
+        }
+        Ok(())
+    }
+}
+
+impl ScimGroup {
+    /// Applies a patch request to this group
+    pub fn apply_patch(&mut self, request: &ScimPatchRequest) -> Result<(), ApiError> {
+        for operation in &request.operations {
+            let op = operation.op.to_ascii_lowercase();
+            let path = operation.path.as_deref().map(str::to_ascii_lowercase);
+            match (op.as_str(), path.as_deref(), operation.value.as_ref()) {
+                ("add" | "replace", Some("displayname"), Some(value)) => {
+                    self.display_name = value
+                        .as_str()
+                        .ok_or_else(|| specialize(error_invalid_request(), String::from("expected a string for displayName")))?
+                        .to_string();
+                }
+                ("add", Some("members"), Some(value)) => self.add_members(from_scim_value(value)?),
+                ("replace", Some("members"), Some(value)) => self.members = from_scim_value(value)?,
+                ("remove", Some("members"), None) => self.members.clear(),
+                ("remove", Some("members"), Some(value)) => {
+                    let removed = from_scim_value::<Vec<ScimMember>>(value)?;
+                    self.members
+                        .retain(|member| removed.iter().all(|removed| removed.value != member.value));
+                }
+                ("remove", Some(path), None) if path.starts_with("members[") && path.ends_with(']') => {
+                    let (attribute, id) = parse_eq_filter(&path["members[".len()..path.len() - 1])
+                        .ok_or_else(|| specialize(error_invalid_request(), format!("unsupported path: {path}")))?;
+                    if attribute != "value" {
+                        return Err(specialize(error_invalid_request(), format!("unsupported path: {path}")));
+                    }
+                    self.members.retain(|member| member.value != id);
+                }
+                ("add" | "replace", None, Some(serde_json::Value::Object(object))) => {
+                    for (attribute, value) in object {
+                        match attribute.to_ascii_lowercase().as_str() {
+                            "displayname" => {
+                                self.display_name = value
+                                    .as_str()
+                                    .ok_or_else(|| {
+                                        specialize(error_invalid_request(), String::from("expected a string for displayName"))
+                                    })?
+                                    .to_string();
+                            }
+                            "members" if op == "add" => self.add_members(from_scim_value(value)?),
+                            "members" => self.members = from_scim_value(value)?,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {
+                    return Err(specialize(
+                        error_invalid_request(),
+                        format!("unsupported operation {op} for groups"),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds members to this group, if they are not already members
+    fn add_members(&mut self, members: Vec<ScimMember>) {
+        for member in members {
+            if self.members.iter().all(|existing| existing.value != member.value) {
+                self.members.push(member);
+            }
+        }
+    }
+
+    /// Builds the resource for a team of the registry
+    #[must_use]
+    pub fn from_team(team: &Team, base_uri: &str) -> Self {
+        Self {
+            schemas: vec![SCIM_SCHEMA_GROUP.to_string()],
+            id: Some(team.id.to_string()),
+            display_name: team.name.clone(),
+            members: team
+                .members
+                .iter()
+                .map(|member| ScimMember {
+                    value: member.id.to_string(),
+                    display: Some(member.email.clone()),
+                })
+                .collect(),
+            meta: Some(ScimMeta {
+                resource_type: String::from("Group"),
+                location: format!("{base_uri}/scim/v2/Groups/{}", team.id),
+            }),
+        }
+    }
+
+    /// Gets the list of the members, using their display name when known
+    #[must_use]
+    pub fn member_names(&self) -> String {
+        self.members
+            .iter()
+            .map(|member| member.display.as_deref().unwrap_or(&member.value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Parses the body of a SCIM request
+pub fn parse_scim_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body).map_err(|error| specialize(error_invalid_request(), error.to_string()))
+}
+
+/// Parses an attribute value in a patch operation
+fn from_scim_value<T: DeserializeOwned>(value: &serde_json::Value) -> Result<T, ApiError> {
+    T::deserialize(value).map_err(|error| specialize(error_invalid_request(), error.to_string()))
+}
+
+/// Parses the identifier of a resource
+pub fn parse_scim_id(id: &str) -> Result<i64, ApiError> {
+    id.parse()
+        .map_err(|_| specialize(error_invalid_request(), format!("invalid resource identifier: {id}")))
+}
+
+/// Interprets a value as a boolean, some identity providers send booleans as strings
+#[must_use]
+pub fn scim_value_as_bool(value: &serde_json::Value) -> Option<bool> {
+    match value {
+        serde_json::Value::Bool(value) => Some(*value),
+        serde_json::Value::String(value) => value.to_ascii_lowercase().parse().ok(),
+        _ => None,
+    }
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+/// Deserializes a boolean that may be sent as a string
+fn deserialize_scim_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = <serde_json::Value as serde::Deserialize>::deserialize(deserializer)?;
+    scim_value_as_bool(&value).ok_or_else(|| serde::de::Error::custom("expected a boolean"))
+}
@@ -0,0 +1,210 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types around the checks of semver compatibility between versions of a crate
+
+use chrono::NaiveDateTime;
+use semver::Version;
+use serde_derive::{Deserialize, Serialize};
+
+use super::worker::WorkerSelector;
+
+/// The marker in the output of `cargo-semver-checks` when breaking changes were found
+const SEMVER_CHECKS_BREAKING_MARKER: &str = "semver requires new major version";
+
+/// The specification for a semver check job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemverCheckJobSpec {
+    /// The name of the crate
+    pub package: String,
+    /// The crate's version to check
+    pub version: String,
+    /// The previous version in the same semver-compatible range, used as baseline
+    pub baseline: String,
+}
+
+/// The state of a semver check job
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SemverCheckJobState {
+    /// The job is queued
+    Queued,
+    /// The worker is working on this job
+    Working,
+    /// The job is finished and no breaking change was found
+    Compatible,
+    /// The job is finished and breaking changes were found
+    Breaking,
+    /// The worker failed to complete this job
+    Failure,
+}
+
+impl SemverCheckJobState {
+    /// Gets whether the state indicates that the job is finished
+    #[must_use]
+    pub const fn is_final(self) -> bool {
+        matches!(self, Self::Compatible | Self::Breaking | Self::Failure)
+    }
+
+    /// Gets the serialisation value for the database
+    #[must_use]
+    pub const fn value(self) -> i64 {
+        match self {
+            Self::Queued => 0,
+            Self::Working => 1,
+            Self::Compatible => 2,
+            Self::Breaking => 3,
+            Self::Failure => 4,
+        }
+    }
+
+    /// Gets the final state from the outcome of `cargo-semver-checks`
+    #[must_use]
+    pub fn from_output(success: bool, output: &str) -> Self {
+        if success {
+            Self::Compatible
+        } else if output.contains(SEMVER_CHECKS_BREAKING_MARKER) {
+            Self::Breaking
+        } else {
+            Self::Failure
+        }
+    }
+}
+
+impl From<i64> for SemverCheckJobState {
+    fn from(value: i64) -> Self {
+        match value {
+            1 => Self::Working,
+            2 => Self::Compatible,
+            3 => Self::Breaking,
+            4 => Self::Failure,
+            _ => Self::Queued,
+        }
+    }
+}
+
+/// A semver check job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemverCheckJob {
+    /// The unique identifier
+    pub id: i64,
+    /// The name of the crate
+    pub package: String,
+    /// The crate's version to check
+    pub version: String,
+    /// The previous version in the same semver-compatible range, used as baseline
+    pub baseline: String,
+    /// The state of the job
+    pub state: SemverCheckJobState,
+    /// Timestamp when the job was queued
+    #[serde(rename = "queuedOn")]
+    pub queued_on: NaiveDateTime,
+    /// Timestamp when the job started execution
+    #[serde(rename = "startedOn")]
+    pub started_on: NaiveDateTime,
+    /// Timestamp when the job terminated
+    #[serde(rename = "finishedOn")]
+    pub finished_on: NaiveDateTime,
+    /// Timestamp the last time this job was touched
+    #[serde(rename = "lastUpdate")]
+    pub last_update: NaiveDateTime,
+    /// The output of the check, once finished
+    pub output: String,
+}
+
+impl SemverCheckJob {
+    /// Gets the worker selector for this job
+    #[must_use]
+    pub fn get_worker_selector(&self) -> WorkerSelector {
+        WorkerSelector::default()
+    }
+}
+
+/// An update to a semver check job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemverCheckJobUpdate {
+    /// The unique identifier of the associated job
+    #[serde(rename = "jobId")]
+    pub job_id: i64,
+    /// The new state for the job
+    pub state: SemverCheckJobState,
+    /// The update timestamp
+    #[serde(rename = "lastUpdate")]
+    pub last_update: NaiveDateTime,
+    /// The output of the check, if any
+    pub output: Option<String>,
+}
+
+/// Finds the version to use as a baseline for checking the semver compatibility of a new version
+///
+/// This is the greatest previous version in the same semver-compatible range, if any.
+/// Pre-releases are never checked nor used as baselines.
+#[must_use]
+pub fn find_semver_baseline<'a, I>(version: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let version = Version::parse(version).ok()?;
+    if !version.pre.is_empty() || (version.major == 0 && version.minor == 0) {
+        return None;
+    }
+    candidates
+        .into_iter()
+        .filter_map(|candidate| Version::parse(candidate).ok())
+        .filter(|candidate| {
+            candidate.pre.is_empty()
+                && candidate < &version
+                && candidate.major == version.major
+                && (version.major > 0 || candidate.minor == version.minor)
+        })
+        .max()
+        .map(|candidate| candidate.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SemverCheckJobState, find_semver_baseline};
+
+    #[test]
+    fn baseline_same_major() {
+        let versions = ["0.9.0", "1.0.0", "1.1.0", "1.1.2", "2.0.0", "1.2.0-beta.1"];
+        assert_eq!(find_semver_baseline("1.2.0", versions), Some(String::from("1.1.2")));
+        assert_eq!(find_semver_baseline("1.1.1", versions), Some(String::from("1.1.0")));
+        assert_eq!(find_semver_baseline("2.1.0", versions), Some(String::from("2.0.0")));
+        assert_eq!(find_semver_baseline("3.0.0", versions), None);
+        assert_eq!(find_semver_baseline("2.0.0-rc.1", versions), None);
+    }
+
+    #[test]
+    fn baseline_zero_major() {
+        let versions = ["0.1.0", "0.1.3", "0.2.0", "0.0.1"];
+        assert_eq!(find_semver_baseline("0.1.4", versions), Some(String::from("0.1.3")));
+        assert_eq!(find_semver_baseline("0.2.1", versions), Some(String::from("0.2.0")));
+        assert_eq!(find_semver_baseline("0.3.0", versions), None);
+        assert_eq!(find_semver_baseline("0.0.2", versions), None);
+    }
+
+    #[test]
+    fn state_from_output() {
+        assert_eq!(SemverCheckJobState::from_output(true, ""), SemverCheckJobState::Compatible);
+        assert_eq!(
+            SemverCheckJobState::from_output(
+                false,
+                "     Summary semver requires new major version: 1 major and 0 minor checks failed"
+            ),
+            SemverCheckJobState::Breaking
+        );
+        assert_eq!(
+            SemverCheckJobState::from_output(false, "error: running cargo-doc failed"),
+            SemverCheckJobState::Failure
+        );
+        // a new minor version is not a breaking change
+        assert_eq!(
+            SemverCheckJobState::from_output(
+                false,
+                "     Summary semver requires new minor version: 0 major and 1 minor checks failed"
+            ),
+            SemverCheckJobState::Failure
+        );
+    }
+}
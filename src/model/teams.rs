@@ -0,0 +1,45 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for teams of users that can own crates
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::apierror::{ApiError, error_invalid_request, specialize};
+
+/// The prefix used by cargo to designate a team as an owner, as in `cargo owner --add team:platform`
+pub const TEAM_OWNER_PREFIX: &str = "team:";
+
+/// A team of users
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Team {
+    /// The unique identifier
+    pub id: i64,
+    /// The unique name for the team
+    pub name: String,
+    /// The members of the team
+    pub members: Vec<TeamMember>,
+}
+
+/// A member of a team
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamMember {
+    /// The unique identifier of the user
+    pub id: i64,
+    /// The email of the user
+    pub email: String,
+    /// The user's name
+    pub name: String,
+    /// Whether the membership is synced from the identity provider on login
+    #[serde(rename = "isSynced")]
+    pub is_synced: bool,
+}
+
+/// Checks that a name is valid for a team
+pub fn check_team_name(name: &str) -> Result<(), ApiError> {
+    if name.is_empty() || name.contains(':') || name.chars().any(char::is_whitespace) {
+        return Err(specialize(error_invalid_request(), format!("invalid team name: {name}")));
+    }
+    Ok(())
+}
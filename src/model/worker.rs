@@ -16,6 +16,7 @@ use tokio::sync::Mutex;
 use tokio::sync::mpsc::{Receiver, Sender};
 
 use super::docs::{DocGenJob, DocGenJobUpdate};
+use super::semver_checks::{SemverCheckJob, SemverCheckJobUpdate};
 use crate::model::config::{Configuration, NodeRole};
 use crate::utils::apierror::ApiError;
 use crate::utils::token::generate_token;
@@ -572,6 +573,8 @@ impl WorkersManager {
 pub enum JobIdentifier {
     /// A documentation generation job
     DocGen(i64),
+    /// A semver check job
+    SemverCheck(i64),
 }
 
 /// An specification of an job to be executed
@@ -579,6 +582,8 @@ pub enum JobIdentifier {
 pub enum JobSpecification {
     /// A documentation generation job
     DocGen(DocGenJob),
+    /// A semver check job
+    SemverCheck(SemverCheckJob),
 }
 
 impl JobSpecification {
@@ -587,6 +592,7 @@ impl JobSpecification {
     pub const fn get_id(&self) -> JobIdentifier {
         match self {
             Self::DocGen(doc_gen_job) => JobIdentifier::DocGen(doc_gen_job.id),
+            Self::SemverCheck(semver_check_job) => JobIdentifier::SemverCheck(semver_check_job.id),
         }
     }
 }
@@ -596,6 +602,8 @@ impl JobSpecification {
 pub enum JobUpdate {
     /// An update about a documentation generation job
     DocGen(DocGenJobUpdate),
+    /// An update about a semver check job
+    SemverCheck(SemverCheckJobUpdate),
 }
 
 /// An event about workers
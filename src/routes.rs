@@ -6,43 +6,61 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt::Write;
 use std::future::Future;
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use axum::Json;
 use axum::body::{Body, Bytes};
 use axum::extract::ws::{Message, WebSocket};
 use axum::extract::{FromRequest, Path, Query, State, WebSocketUpgrade};
 use axum::http::header::{HeaderName, SET_COOKIE};
 use axum::http::{HeaderValue, Request, StatusCode, header};
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
-use axum::{BoxError, Json};
+use chrono::NaiveDateTime;
 use cookie::Key;
+use data_encoding::BASE64URL_NOPAD;
 use futures::future::select_all;
-use futures::{SinkExt, Stream, StreamExt};
+use futures::{SinkExt, StreamExt};
 use log::error;
+use ring::digest::{SHA256, digest};
 use serde::Deserialize;
-use tokio::fs::File;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::channel;
 use tokio_stream::wrappers::ReceiverStream;
-use tokio_util::io::ReaderStream;
 
 use crate::application::Application;
-use crate::model::auth::{Authentication, RegistryUserToken, RegistryUserTokenWithSecret};
+use crate::model::attestation::{AttestationEnvelope, AttestationPublicKey};
+use crate::model::audit::{AuditEvent, AuditQuery};
+use crate::model::auth::{
+    Authentication, OAuthLoginState, RegistryUserPublicKey, RegistryUserToken, RegistryUserTokenWithSecret, TokenScopes,
+    TrustedPublisher, TrustedPublisherSpec, TrustedPublishingToken,
+};
 use crate::model::cargo::{
-    CrateUploadResult, OwnersChangeQuery, OwnersQueryResult, RegistryUser, SearchResults, YesNoMsgResult, YesNoResult,
+    ApiResponseErrors, CrateUploadResult, OwnersChangeQuery, OwnersQueryResult, RegistryUser, SearchResults, YesNoMsgResult,
+    YesNoResult,
 };
 use crate::model::deps::DepsAnalysis;
+use crate::model::diff::CrateVersionsDiff;
 use crate::model::docs::{DocGenJob, DocGenJobSpec};
-use crate::model::packages::{CrateInfo, CrateInfoTarget};
+use crate::model::index::IndexCheckReport;
+use crate::model::names::{CrateNamePolicy, CrateNamePolicySpec};
+use crate::model::packages::{
+    CrateDeprecation, CrateFile, CrateInfo, CrateInfoTarget, CratePendingVersion, CrateReaders, CrateVisibility,
+};
+use crate::model::scim::{SCIM_CONTENT_TYPE, ScimError, ScimGroup, ScimListQuery, ScimPatchRequest, ScimUser, parse_scim_body};
+use crate::model::semver_checks::SemverCheckJob;
 use crate::model::stats::{DownloadStats, GlobalStats};
+use crate::model::teams::Team;
 use crate::model::worker::{JobSpecification, JobUpdate, WorkerDescriptor, WorkerPublicData, WorkerRegistrationData};
 use crate::model::{AppVersion, CrateVersion, RegistryInformation};
-use crate::services::index::Index;
+use crate::services::index::{Index, IndexFile};
 use crate::utils::apierror::{
     ApiError, error_backend_failure, error_invalid_request, error_not_found, error_unauthorized, specialize,
 };
@@ -50,8 +68,11 @@ use crate::utils::axum::auth::{AuthData, AxumStateForCookies};
 use crate::utils::axum::embedded::{EmbeddedResources, WebappResource};
 use crate::utils::axum::extractors::Base64;
 use crate::utils::axum::sse::{Event, ServerSentEventStream};
-use crate::utils::axum::{ApiResult, response, response_error};
-use crate::utils::token::generate_token;
+use crate::utils::axum::{ApiResult, response, response_error, response_ok};
+use crate::utils::token::generate_url_safe_token;
+
+/// The name of the cookie for the state of a pending OAuth login
+const OAUTH_LOGIN_COOKIE: &str = "cratery-oauth";
 
 /// The state of this application for axum
 pub struct AxumState {
@@ -75,6 +96,10 @@ impl AxumStateForCookies for AxumState {
     fn get_cookie_key(&self) -> &Key {
         &self.cookie_key
     }
+
+    fn get_trusted_proxies(&self) -> &[IpAddr] {
+        &self.application.configuration.web_trusted_proxies
+    }
 }
 
 impl AxumState {
@@ -104,12 +129,50 @@ pub struct PathInfoCrate {
     package: String,
 }
 
+#[derive(Deserialize)]
+pub struct PathInfoCrateTrustedPublisher {
+    package: String,
+    publisher_id: i64,
+}
+
 #[derive(Deserialize)]
 pub struct PathInfoCrateVersion {
     package: String,
     version: String,
 }
 
+#[derive(Deserialize)]
+pub struct PathInfoCrateDiff {
+    package: String,
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+pub struct PathInfoCrateFile {
+    package: String,
+    version: String,
+    path: String,
+}
+
+/// Middleware adding the `Retry-After` header to the responses for clients that reached a rate limit
+pub async fn add_retry_after(
+    State(state): State<Arc<AxumState>>,
+    auth_data: AuthData,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    if response.status() == StatusCode::TOO_MANY_REQUESTS
+        && let Some(retry_after) = state.application.get_retry_after(&auth_data)
+    {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after.as_secs() + 1));
+    }
+    response
+}
+
 /// Response for a GET on the root
 /// Redirect to the web app
 pub async fn get_root(State(state): State<Arc<AxumState>>) -> (StatusCode, [(HeaderName, HeaderValue); 2]) {
@@ -139,26 +202,49 @@ pub async fn get_favicon(State(state): State<Arc<AxumState>>) -> (StatusCode, [(
 /// Gets the redirection response when not authenticated
 fn get_auth_redirect(state: &AxumState) -> (StatusCode, [(HeaderName, HeaderValue); 2]) {
     // redirect to login
-    let nonce = generate_token(64);
-    let oauth_state = generate_token(32);
-    let target = format!(
+    let target = format!("{}/api/v1/oauth/login", state.application.configuration.web_public_uri);
+    (
+        StatusCode::FOUND,
+        [
+            (header::LOCATION, HeaderValue::from_str(&target).unwrap()),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+    )
+}
+
+/// Starts a login by redirecting to the OAuth provider
+/// The state, nonce and PKCE verifier are kept in a private cookie to be checked on the callback
+pub async fn api_v1_oauth_login(
+    mut auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+) -> (StatusCode, [(HeaderName, HeaderValue); 3]) {
+    let configuration = &state.application.configuration;
+    let login_state = OAuthLoginState {
+        state: generate_url_safe_token(32),
+        nonce: generate_url_safe_token(64),
+        verifier: configuration.oauth_pkce.then(|| generate_url_safe_token(64)),
+    };
+    let mut target = format!(
         "{}?response_type={}&redirect_uri={}&client_id={}&scope={}&nonce={}&state={}",
-        state.application.configuration.oauth_login_uri,
+        configuration.oauth_login_uri,
         "code",
-        urlencoding::encode(&format!(
-            "{}/webapp/oauthcallback.html",
-            state.application.configuration.web_public_uri
-        )),
-        urlencoding::encode(&state.application.configuration.oauth_client_id),
-        urlencoding::encode(&state.application.configuration.oauth_client_scope),
-        nonce,
-        oauth_state
+        urlencoding::encode(&format!("{}/webapp/oauthcallback.html", configuration.web_public_uri)),
+        urlencoding::encode(&configuration.oauth_client_id),
+        urlencoding::encode(&configuration.oauth_client_scope),
+        login_state.nonce,
+        login_state.state
     );
+    if let Some(verifier) = &login_state.verifier {
+        let challenge = BASE64URL_NOPAD.encode(digest(&SHA256, verifier.as_bytes()).as_ref());
+        write!(target, "&code_challenge={challenge}&code_challenge_method=S256").unwrap();
+    }
+    let cookie = auth_data.create_cookie(OAUTH_LOGIN_COOKIE, &serde_json::to_string(&login_state).unwrap(), true);
     (
         StatusCode::FOUND,
         [
             (header::LOCATION, HeaderValue::from_str(&target).unwrap()),
             (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+            (SET_COOKIE, HeaderValue::from_str(&cookie.to_string()).unwrap()),
         ],
     )
 }
@@ -277,7 +363,11 @@ pub async fn get_docs_resource(
     // /  0            1            2           3
     // / docs / <package_name> / <version> / <file path>
     // / docs / <package_name> / <version> / <target> / <file path>
-    if elements.len() < 4 || elements[0] != "docs" || semver::Version::from_str(elements[2]).is_err() {
+    if elements.len() < 4
+        || elements[0] != "docs"
+        || semver::Version::from_str(elements[2]).is_err()
+        || state.application.check_can_read_crate(&auth_data, elements[1]).await.is_err()
+    {
         return Err((
             StatusCode::NOT_FOUND,
             [(
@@ -357,23 +447,54 @@ pub async fn api_v1_get_registry_information(
     response(state.application.get_registry_information(&auth_data).await)
 }
 
+/// Gets the public key to verify the attestations of published crates
+pub async fn api_v1_get_attestation_public_key(State(state): State<Arc<AxumState>>) -> ApiResult<AttestationPublicKey> {
+    response(state.application.get_attestation_public_key())
+}
+
 /// Get the current user
 pub async fn api_v1_get_current_user(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<RegistryUser> {
     response(state.application.get_current_user(&auth_data).await)
 }
 
+#[derive(Deserialize)]
+pub struct OAuthCodeQuery {
+    /// The authorization code given back by the OAuth provider
+    code: String,
+    /// The state given back by the OAuth provider
+    state: String,
+}
+
 /// Attempts to login using an OAuth code
 pub async fn api_v1_login_with_oauth_code(
     mut auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
-    body: Bytes,
-) -> Result<(StatusCode, [(HeaderName, HeaderValue); 1], Json<RegistryUser>), (StatusCode, Json<ApiError>)> {
-    let code = String::from_utf8_lossy(&body);
-    let registry_user = state.application.login_with_oauth_code(&code).await.map_err(response_error)?;
+    input: Json<OAuthCodeQuery>,
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Json<RegistryUser>), (StatusCode, Json<ApiError>)> {
+    // check the state against the one for the pending login to protect against CSRF
+    let login_state = auth_data
+        .get_private_cookie(OAUTH_LOGIN_COOKIE)
+        .and_then(|value| serde_json::from_str::<OAuthLoginState>(&value).ok())
+        .filter(|login_state| login_state.state == input.state)
+        .ok_or_else(|| {
+            response_error(specialize(
+                error_unauthorized(),
+                String::from("invalid state for the OAuth login"),
+            ))
+        })?;
+    let registry_user = state
+        .application
+        .login_with_oauth_code(&auth_data, &input.code, &login_state)
+        .await
+        .map_err(response_error)?;
     let cookie = auth_data.create_id_cookie(&Authentication::new_user(registry_user.id, registry_user.email.clone()));
+    let expired = auth_data.create_expired_cookie(OAUTH_LOGIN_COOKIE, true);
     Ok((
         StatusCode::OK,
-        [(SET_COOKIE, HeaderValue::from_str(&cookie.to_string()).unwrap())],
+        [
+            (SET_COOKIE, HeaderValue::from_str(&cookie.to_string()).unwrap()),
+            (SET_COOKIE, HeaderValue::from_str(&expired.to_string()).unwrap()),
+        ],
         Json(registry_user),
     ))
 }
@@ -401,16 +522,39 @@ pub struct CreateTokenQuery {
     can_write: bool,
     #[serde(rename = "canAdmin")]
     can_admin: bool,
+    /// Comma-separated list of patterns for the crates the token is restricted to
+    crates: Option<String>,
+    /// Comma-separated list of operations the token is restricted to
+    operations: Option<String>,
+    /// The time when the token expires, if any
+    #[serde(rename = "expiresOn")]
+    expires_on: Option<NaiveDateTime>,
 }
 
 /// Creates a token for the current user
 pub async fn api_v1_create_user_token(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
-    Query(CreateTokenQuery { can_write, can_admin }): Query<CreateTokenQuery>,
+    Query(CreateTokenQuery {
+        can_write,
+        can_admin,
+        crates,
+        operations,
+        expires_on,
+    }): Query<CreateTokenQuery>,
     name: String,
 ) -> ApiResult<RegistryUserTokenWithSecret> {
-    response(state.application.create_token(&auth_data, &name, can_write, can_admin).await)
+    let scopes = TokenScopes::parse(
+        crates.as_deref().unwrap_or_default(),
+        operations.as_deref().unwrap_or_default(),
+    )
+    .map_err(response_error)?;
+    response(
+        state
+            .application
+            .create_token(&auth_data, &name, can_write, can_admin, scopes, expires_on)
+            .await,
+    )
 }
 
 /// Revoke a previous token
@@ -422,6 +566,39 @@ pub async fn api_v1_revoke_user_token(
     response(state.application.revoke_token(&auth_data, token_id).await)
 }
 
+/// Gets the public keys registered by the current user for asymmetric tokens
+pub async fn api_v1_get_user_public_keys(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+) -> ApiResult<Vec<RegistryUserPublicKey>> {
+    response(state.application.get_public_keys(&auth_data).await)
+}
+
+#[derive(Deserialize)]
+pub struct AddPublicKeyQuery {
+    /// The name for the key
+    name: String,
+}
+
+/// Registers a public key (in the PASERK format) for the current user
+pub async fn api_v1_add_user_public_key(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Query(AddPublicKeyQuery { name }): Query<AddPublicKeyQuery>,
+    public_key: String,
+) -> ApiResult<RegistryUserPublicKey> {
+    response(state.application.add_public_key(&auth_data, &name, &public_key).await)
+}
+
+/// Removes a public key for the current user
+pub async fn api_v1_remove_user_public_key(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(key_id): Path<i64>,
+) -> ApiResult<()> {
+    response(state.application.remove_public_key(&auth_data, key_id).await)
+}
+
 /// Gets the global tokens for the registry, usually for CI purposes
 pub async fn api_v1_get_global_tokens(
     auth_data: AuthData,
@@ -430,13 +607,21 @@ pub async fn api_v1_get_global_tokens(
     response(state.application.get_global_tokens(&auth_data).await)
 }
 
+#[derive(Deserialize)]
+pub struct CreateGlobalTokenQuery {
+    /// The time when the token expires, if any
+    #[serde(rename = "expiresOn")]
+    expires_on: Option<NaiveDateTime>,
+}
+
 /// Creates a global token for the registry
 pub async fn api_v1_create_global_token(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
+    Query(CreateGlobalTokenQuery { expires_on }): Query<CreateGlobalTokenQuery>,
     name: String,
 ) -> ApiResult<RegistryUserTokenWithSecret> {
-    response(state.application.create_global_token(&auth_data, &name).await)
+    response(state.application.create_global_token(&auth_data, &name, expires_on).await)
 }
 
 /// Revokes a global token for the registry
@@ -448,6 +633,107 @@ pub async fn api_v1_revoke_global_token(
     response(state.application.revoke_global_token(&auth_data, token_id).await)
 }
 
+/// Gets the policies for the names of new crates
+pub async fn api_v1_get_crate_name_policies(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+) -> ApiResult<Vec<CrateNamePolicy>> {
+    response(state.application.get_crate_name_policies(&auth_data).await)
+}
+
+/// Creates a policy for the names of new crates
+pub async fn api_v1_create_crate_name_policy(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    input: Json<CrateNamePolicySpec>,
+) -> ApiResult<CrateNamePolicy> {
+    response(state.application.create_crate_name_policy(&auth_data, &input).await)
+}
+
+/// Deletes a policy for the names of new crates
+pub async fn api_v1_delete_crate_name_policy(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(policy_id): Path<i64>,
+) -> ApiResult<()> {
+    response(state.application.delete_crate_name_policy(&auth_data, policy_id).await)
+}
+
+/// Gets the events in the audit log
+pub async fn api_v1_get_audit_events(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Query(query): Query<AuditQuery>,
+) -> ApiResult<Vec<AuditEvent>> {
+    response(state.application.get_audit_events(&auth_data, &query).await)
+}
+
+/// Exports the events in the audit log as JSON lines
+pub async fn api_v1_export_audit_events(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 1], Vec<u8>), (StatusCode, Json<ApiError>)> {
+    let events = state
+        .application
+        .export_audit_events(&auth_data, &query)
+        .await
+        .map_err(response_error)?;
+    let mut data = Vec::new();
+    for event in &events {
+        serde_json::to_writer(&mut data, event).map_err(|error| response_error(error.into()))?;
+        data.push(b'\n');
+    }
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"))],
+        data,
+    ))
+}
+
+/// Gets the teams of users
+pub async fn api_v1_get_teams(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<Team>> {
+    response(state.application.get_teams(&auth_data).await)
+}
+
+/// Creates a new team of users
+pub async fn api_v1_create_team(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(team): Path<String>,
+) -> ApiResult<Team> {
+    response(state.application.create_team(&auth_data, &team).await)
+}
+
+/// Deletes a team of users
+pub async fn api_v1_delete_team(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(team): Path<String>,
+) -> ApiResult<()> {
+    response(state.application.delete_team(&auth_data, &team).await)
+}
+
+/// Adds members to a team
+pub async fn api_v1_add_team_members(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(team): Path<String>,
+    input: Json<OwnersChangeQuery>,
+) -> ApiResult<()> {
+    response(state.application.add_team_members(&auth_data, &team, &input.users).await)
+}
+
+/// Removes members from a team
+pub async fn api_v1_remove_team_members(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(team): Path<String>,
+    input: Json<OwnersChangeQuery>,
+) -> ApiResult<()> {
+    response(state.application.remove_team_members(&auth_data, &team, &input.users).await)
+}
+
 /// Gets the documentation jobs
 pub async fn api_v1_get_doc_gen_jobs(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<DocGenJob>> {
     response(state.application.get_doc_gen_jobs(&auth_data).await)
@@ -493,6 +779,16 @@ pub async fn api_v1_get_workers_updates(
     Ok(stream.into_response())
 }
 
+/// Checks the consistency between the database, the index and the storage
+pub async fn api_v1_check_index(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<IndexCheckReport> {
+    response(state.application.check_index(&auth_data, false).await)
+}
+
+/// Checks the consistency between the database, the index and the storage, then rebuilds the index
+pub async fn api_v1_rebuild_index(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<IndexCheckReport> {
+    response(state.application.check_index(&auth_data, true).await)
+}
+
 /// Endpoint for worker to connect to this host
 pub async fn api_v1_worker_connect(
     auth_data: AuthData,
@@ -729,8 +1025,17 @@ pub async fn api_v1_cargo_publish_crate_version(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     body: Bytes,
-) -> ApiResult<CrateUploadResult> {
-    response(state.application.publish_crate_version(&auth_data, &body).await)
+) -> Result<(StatusCode, Json<CrateUploadResult>), (StatusCode, Json<ApiResponseErrors>)> {
+    // errors are returned in the format expected by cargo so that their details are shown to the user
+    state
+        .application
+        .publish_crate_version(&auth_data, &body)
+        .await
+        .map(response_ok)
+        .map_err(|error| {
+            let (status, Json(error)) = response_error(error);
+            (status, Json(ApiResponseErrors::from(error)))
+        })
 }
 
 pub async fn api_v1_get_crate_info(
@@ -777,6 +1082,68 @@ pub async fn api_v1_get_crate_readme(
     ))
 }
 
+/// Lists the files in the package of a crate version
+pub async fn api_v1_get_crate_files(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+) -> ApiResult<Vec<CrateFile>> {
+    response(state.application.get_crate_files(&auth_data, &package, &version).await)
+}
+
+/// Gets the content of a file in the package of a crate version
+pub async fn api_v1_get_crate_file(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateFile { package, version, path }): Path<PathInfoCrateFile>,
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Vec<u8>), (StatusCode, Json<ApiError>)> {
+    let (content_type, data) = state
+        .application
+        .get_crate_file(&auth_data, &package, &version, &path)
+        .await
+        .map_err(response_error)?;
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+        ],
+        data,
+    ))
+}
+
+/// Gets the differences between two versions of a crate
+pub async fn api_v1_get_crate_versions_diff(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateDiff { package, from, to }): Path<PathInfoCrateDiff>,
+) -> ApiResult<CrateVersionsDiff> {
+    response(
+        state
+            .application
+            .get_crate_versions_diff(&auth_data, &package, &from, &to)
+            .await,
+    )
+}
+
+/// Gets the checks of the semver compatibility of the versions of a crate
+pub async fn api_v1_get_crate_semver_checks(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<Vec<SemverCheckJob>> {
+    response(state.application.get_crate_semver_checks(&auth_data, &package).await)
+}
+
+/// Gets the signed attestation for the publication of a crate version
+pub async fn api_v1_get_crate_attestation(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+) -> ApiResult<AttestationEnvelope> {
+    response(state.application.get_crate_attestation(&auth_data, &package, &version).await)
+}
+
 pub async fn api_v1_download_crate(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
@@ -806,12 +1173,25 @@ pub async fn api_v1_remove_crate_version(
     response(state.application.remove_crate_version(&auth_data, &package, &version).await)
 }
 
+/// The query for yanking a crate version
+#[derive(Deserialize)]
+pub struct YankQuery {
+    /// The reason for yanking, if any
+    reason: Option<String>,
+}
+
 pub async fn api_v1_cargo_yank(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+    Query(YankQuery { reason }): Query<YankQuery>,
 ) -> ApiResult<YesNoResult> {
-    response(state.application.yank_crate_version(&auth_data, &package, &version).await)
+    response(
+        state
+            .application
+            .yank_crate_version(&auth_data, &package, &version, reason.as_deref())
+            .await,
+    )
 }
 
 pub async fn api_v1_cargo_unyank(
@@ -888,6 +1268,62 @@ pub async fn api_v1_cargo_remove_crate_owners(
     )
 }
 
+/// Gets the trusted publishers for a crate
+pub async fn api_v1_get_crate_trusted_publishers(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<Vec<TrustedPublisher>> {
+    response(state.application.get_trusted_publishers(&auth_data, &package).await)
+}
+
+/// Adds a trusted publisher for a crate
+pub async fn api_v1_add_crate_trusted_publisher(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<TrustedPublisherSpec>,
+) -> ApiResult<TrustedPublisher> {
+    response(state.application.add_trusted_publisher(&auth_data, &package, &input).await)
+}
+
+/// Removes a trusted publisher for a crate
+pub async fn api_v1_remove_crate_trusted_publisher(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateTrustedPublisher { package, publisher_id }): Path<PathInfoCrateTrustedPublisher>,
+) -> ApiResult<()> {
+    response(
+        state
+            .application
+            .remove_trusted_publisher(&auth_data, &package, publisher_id)
+            .await,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct TrustedPublishingExchangeQuery {
+    /// The OIDC token issued to the CI pipeline
+    jwt: String,
+    /// The crate to publish, required when the token matches trusted publishers for multiple crates
+    #[serde(rename = "crate")]
+    package: Option<String>,
+}
+
+/// Exchanges an OIDC token issued to a CI pipeline for a short-lived token to publish a crate
+pub async fn api_v1_exchange_trusted_publishing_token(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    input: Json<TrustedPublishingExchangeQuery>,
+) -> ApiResult<TrustedPublishingToken> {
+    response(
+        state
+            .application
+            .exchange_trusted_publishing_token(&auth_data, &input.jwt, input.package.as_deref())
+            .await,
+    )
+}
+
 /// Gets the targets for a crate
 pub async fn api_v1_get_crate_targets(
     auth_data: AuthData,
@@ -936,9 +1372,9 @@ pub async fn api_v1_set_crate_deprecation(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     Path(PathInfoCrate { package }): Path<PathInfoCrate>,
-    input: Json<bool>,
+    input: Json<CrateDeprecation>,
 ) -> ApiResult<()> {
-    response(state.application.set_crate_deprecation(&auth_data, &package, input.0).await)
+    response(state.application.set_crate_deprecation(&auth_data, &package, &input).await)
 }
 
 /// Sets whether a crate can have versions completely removed
@@ -951,29 +1387,124 @@ pub async fn api_v1_set_crate_can_remove(
     response(state.application.set_crate_can_remove(&auth_data, &package, input.0).await)
 }
 
-pub async fn index_serve_inner(
-    index: &(dyn Index + Send + Sync),
-    path: &str,
-) -> Result<
-    (
-        impl Stream<Item = Result<impl Into<Bytes> + use<>, impl Into<BoxError> + use<>>> + use<>,
-        HeaderValue,
-    ),
-    ApiError,
-> {
+/// Sets whether new versions of a crate must be approved by another owner
+pub async fn api_v1_set_crate_requires_approval(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<bool>,
+) -> ApiResult<()> {
+    response(
+        state
+            .application
+            .set_crate_requires_approval(&auth_data, &package, input.0)
+            .await,
+    )
+}
+
+/// Gets the versions of a crate that are pending approval
+pub async fn api_v1_get_crate_pending_versions(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<Vec<CratePendingVersion>> {
+    response(state.application.get_crate_pending_versions(&auth_data, &package).await)
+}
+
+/// Downloads the content of a version of a crate that is pending approval
+pub async fn api_v1_download_crate_pending_version(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 1], Vec<u8>), (StatusCode, Json<ApiError>)> {
+    match state
+        .application
+        .get_crate_pending_content(&auth_data, &package, &version)
+        .await
+    {
+        Ok(data) => Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"))],
+            data,
+        )),
+        Err(error) => Err(response_error(error)),
+    }
+}
+
+/// Approves a version of a crate that is pending approval
+pub async fn api_v1_approve_crate_version(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+) -> ApiResult<()> {
+    response(state.application.approve_crate_version(&auth_data, &package, &version).await)
+}
+
+/// Rejects a version of a crate that is pending approval
+pub async fn api_v1_reject_crate_version(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+) -> ApiResult<()> {
+    response(state.application.reject_crate_version(&auth_data, &package, &version).await)
+}
+
+/// Gets the readers of a crate
+pub async fn api_v1_get_crate_readers(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<CrateReaders> {
+    response(state.application.get_crate_readers(&auth_data, &package).await)
+}
+
+/// Adds readers to a crate
+pub async fn api_v1_add_crate_readers(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<OwnersChangeQuery>,
+) -> ApiResult<()> {
+    response(state.application.add_crate_readers(&auth_data, &package, &input.users).await)
+}
+
+/// Removes readers from a crate
+pub async fn api_v1_remove_crate_readers(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<OwnersChangeQuery>,
+) -> ApiResult<()> {
+    response(
+        state
+            .application
+            .remove_crate_readers(&auth_data, &package, &input.users)
+            .await,
+    )
+}
+
+/// Sets who can read a crate
+pub async fn api_v1_set_crate_visibility(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<CrateVisibility>,
+) -> ApiResult<()> {
+    response(state.application.set_crate_visibility(&auth_data, &package, input.0).await)
+}
+
+pub async fn index_serve_inner(index: &(dyn Index + Send + Sync), path: &str) -> Result<(IndexFile, HeaderValue), ApiError> {
     let file_path: PathBuf = path.parse()?;
-    let file_path = index.get_index_file(&file_path).await?.ok_or_else(error_not_found)?;
-    let file = File::open(file_path).await.map_err(|_e| error_not_found())?;
-    let stream = ReaderStream::new(file);
+    let content = index.get_index_file(&file_path).await?.ok_or_else(error_not_found)?;
     if std::path::Path::new(path)
         .extension()
         .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
     {
-        Ok((stream, HeaderValue::from_static("application/json")))
+        Ok((content, HeaderValue::from_static("application/json")))
     } else if path == "/HEAD" || path.starts_with("/info") {
-        Ok((stream, HeaderValue::from_static("text/plain; charset=utf-8")))
+        Ok((content, HeaderValue::from_static("text/plain; charset=utf-8")))
     } else {
-        Ok((stream, HeaderValue::from_static("application/octet-stream")))
+        Ok((content, HeaderValue::from_static("application/octet-stream")))
     }
 }
 
@@ -992,44 +1523,42 @@ fn index_serve_map_err(e: ApiError, domain: &str) -> (StatusCode, [(HeaderName,
     )
 }
 
-pub async fn index_serve_check_auth(
-    application: &Application,
-    auth_data: &AuthData,
-) -> Result<(), (StatusCode, [(HeaderName, HeaderValue); 2], Json<ApiError>)> {
-    if application.configuration.self_public_read {
-        return Ok(());
-    }
-    application
-        .authenticate(auth_data)
-        .await
-        .map_err(|e| index_serve_map_err(e, &application.configuration.web_domain))?;
-    Ok(())
-}
-
 pub async fn index_serve(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     request: Request<Body>,
-) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Body), (StatusCode, [(HeaderName, HeaderValue); 2], Json<ApiError>)> {
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 4], Body), (StatusCode, [(HeaderName, HeaderValue); 2], Json<ApiError>)> {
     let map_err = |e| index_serve_map_err(e, &state.application.configuration.web_domain);
     let path = request.uri().path();
     if path != "/config.json" && !state.application.configuration.index.allow_protocol_sparse {
         // config.json is always allowed because it is always checked first by cargo
         return Err(map_err(error_not_found()));
     }
-    index_serve_check_auth(&state.application, &auth_data).await?;
-    let (stream, content_type) = index_serve_inner(state.application.get_service_index(), path)
+    state
+        .application
+        .check_can_read_index_file(&auth_data, path)
         .await
         .map_err(map_err)?;
-    let body = Body::from_stream(stream);
-    Ok((
-        StatusCode::OK,
-        [
-            (header::CONTENT_TYPE, content_type),
-            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
-        ],
-        body,
-    ))
+    let (file, content_type) = index_serve_inner(state.application.get_service_index(), path)
+        .await
+        .map_err(map_err)?;
+    let headers = [
+        (header::CONTENT_TYPE, content_type),
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        (
+            header::ETAG,
+            HeaderValue::from_str(&file.etag).map_err(|e| map_err(e.into()))?,
+        ),
+        (
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&file.get_last_modified_header()).map_err(|e| map_err(e.into()))?,
+        ),
+    ];
+    let get_header = |name| request.headers().get(name).and_then(|value| value.to_str().ok());
+    if file.is_not_modified(get_header(header::IF_NONE_MATCH), get_header(header::IF_MODIFIED_SINCE)) {
+        return Ok((StatusCode::NOT_MODIFIED, headers, Body::empty()));
+    }
+    Ok((StatusCode::OK, headers, Body::from(file.content)))
 }
 
 #[expect(clippy::implicit_hasher)]
@@ -1042,7 +1571,11 @@ pub async fn index_serve_info_refs(
     if !state.application.configuration.index.allow_protocol_git {
         return Err(map_err(error_not_found()));
     }
-    index_serve_check_auth(&state.application, &auth_data).await?;
+    state
+        .application
+        .check_can_read_git_index(&auth_data)
+        .await
+        .map_err(map_err)?;
 
     if query.get("service").map(String::as_str) == Some("git-upload-pack") {
         // smart server response
@@ -1078,7 +1611,11 @@ pub async fn index_serve_git_upload_pack(
     if !state.application.configuration.index.allow_protocol_git {
         return Err(map_err(error_not_found()));
     }
-    index_serve_check_auth(&state.application, &auth_data).await?;
+    state
+        .application
+        .check_can_read_git_index(&auth_data)
+        .await
+        .map_err(map_err)?;
     let data = state
         .application
         .get_service_index()
@@ -1109,3 +1646,143 @@ pub async fn get_version() -> ApiResult<AppVersion> {
         tag: crate::GIT_TAG.to_string(),
     }))
 }
+
+/// Produces a response for the SCIM API, with the corresponding content type and error messages
+fn scim_response<T: serde::Serialize>(status: StatusCode, result: Result<T, ApiError>) -> Response {
+    let (status, body) = match result {
+        Ok(data) => (status, serde_json::to_vec(&data)),
+        Err(error) => {
+            let (status, Json(error)) = response_error(error);
+            (status, serde_json::to_vec(&ScimError::from(&error)))
+        }
+    };
+    match body {
+        Ok(body) => (
+            status,
+            [(header::CONTENT_TYPE, HeaderValue::from_static(SCIM_CONTENT_TYPE))],
+            body,
+        )
+            .into_response(),
+        Err(error) => response_error(error.into()).into_response(),
+    }
+}
+
+/// Produces an empty response for the SCIM API, for the deletion of a resource
+fn scim_response_deleted(result: Result<(), ApiError>) -> Response {
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(error) => scim_response::<()>(StatusCode::OK, Err(error)),
+    }
+}
+
+/// Gets the provisioned users
+pub async fn scim_get_users(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Query(query): Query<ScimListQuery>,
+) -> Response {
+    scim_response(StatusCode::OK, state.application.scim_get_users(&auth_data, &query).await)
+}
+
+/// Gets a provisioned user
+pub async fn scim_get_user(auth_data: AuthData, State(state): State<Arc<AxumState>>, Path(id): Path<String>) -> Response {
+    scim_response(StatusCode::OK, state.application.scim_get_user(&auth_data, &id).await)
+}
+
+/// Provisions a new user
+pub async fn scim_create_user(auth_data: AuthData, State(state): State<Arc<AxumState>>, body: Bytes) -> Response {
+    let result = match parse_scim_body::<ScimUser>(&body) {
+        Ok(user) => state.application.scim_create_user(&auth_data, &user).await,
+        Err(error) => Err(error),
+    };
+    scim_response(StatusCode::CREATED, result)
+}
+
+/// Replaces a provisioned user
+pub async fn scim_replace_user(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = match parse_scim_body::<ScimUser>(&body) {
+        Ok(user) => state.application.scim_replace_user(&auth_data, &id, &user).await,
+        Err(error) => Err(error),
+    };
+    scim_response(StatusCode::OK, result)
+}
+
+/// Patches a provisioned user
+pub async fn scim_patch_user(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = match parse_scim_body::<ScimPatchRequest>(&body) {
+        Ok(patch) => state.application.scim_patch_user(&auth_data, &id, &patch).await,
+        Err(error) => Err(error),
+    };
+    scim_response(StatusCode::OK, result)
+}
+
+/// Deprovisions a user
+pub async fn scim_delete_user(auth_data: AuthData, State(state): State<Arc<AxumState>>, Path(id): Path<String>) -> Response {
+    scim_response_deleted(state.application.scim_delete_user(&auth_data, &id).await)
+}
+
+/// Gets the provisioned groups
+pub async fn scim_get_groups(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Query(query): Query<ScimListQuery>,
+) -> Response {
+    scim_response(StatusCode::OK, state.application.scim_get_groups(&auth_data, &query).await)
+}
+
+/// Gets a provisioned group
+pub async fn scim_get_group(auth_data: AuthData, State(state): State<Arc<AxumState>>, Path(id): Path<String>) -> Response {
+    scim_response(StatusCode::OK, state.application.scim_get_group(&auth_data, &id).await)
+}
+
+/// Provisions a new group
+pub async fn scim_create_group(auth_data: AuthData, State(state): State<Arc<AxumState>>, body: Bytes) -> Response {
+    let result = match parse_scim_body::<ScimGroup>(&body) {
+        Ok(group) => state.application.scim_create_group(&auth_data, &group).await,
+        Err(error) => Err(error),
+    };
+    scim_response(StatusCode::CREATED, result)
+}
+
+/// Replaces a provisioned group
+pub async fn scim_replace_group(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = match parse_scim_body::<ScimGroup>(&body) {
+        Ok(group) => state.application.scim_replace_group(&auth_data, &id, &group).await,
+        Err(error) => Err(error),
+    };
+    scim_response(StatusCode::OK, result)
+}
+
+/// Patches a provisioned group
+pub async fn scim_patch_group(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = match parse_scim_body::<ScimPatchRequest>(&body) {
+        Ok(patch) => state.application.scim_patch_group(&auth_data, &id, &patch).await,
+        Err(error) => Err(error),
+    };
+    scim_response(StatusCode::OK, result)
+}
+
+/// Deprovisions a group
+pub async fn scim_delete_group(auth_data: AuthData, State(state): State<Arc<AxumState>>, Path(id): Path<String>) -> Response {
+    scim_response_deleted(state.application.scim_delete_group(&auth_data, &id).await)
+}
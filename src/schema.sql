@@ -5,7 +5,7 @@ CREATE TABLE IF NOT EXISTS SchemaMetadata (
 
 CREATE INDEX IF NOT EXISTS SchemaMetadataIndex ON SchemaMetadata(name);
 
-INSERT INTO SchemaMetadata VALUES ('version', '1.11.0');
+INSERT INTO SchemaMetadata VALUES ('version', '1.14.0');
 
 CREATE TABLE RegistryUser (
     id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
@@ -26,16 +26,33 @@ CREATE TABLE RegistryUserToken (
     token TEXT NOT NULL,
     lastUsed TIMESTAMP NOT NULL,
     canWrite BOOLEAN NOT NULL,
-    canAdmin BOOLEAN NOT NULL
+    canAdmin BOOLEAN NOT NULL,
+    crateScopes TEXT NOT NULL,
+    operationScopes TEXT NOT NULL,
+    expiresOn TIMESTAMP,
+    expiryNotified BOOLEAN NOT NULL
 );
 
 CREATE INDEX IndexRegistryUserToken ON RegistryUserToken (user);
 
+CREATE TABLE RegistryUserPublicKey (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user INTEGER NOT NULL REFERENCES RegistryUser(id),
+    name TEXT NOT NULL,
+    keyId TEXT NOT NULL,
+    publicKey TEXT NOT NULL,
+    lastUsed TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IndexRegistryUserPublicKey ON RegistryUserPublicKey (keyId);
+
 CREATE TABLE RegistryGlobalToken (
     id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     name TEXT NOT NULL,
     token TEXT NOT NULL,
-    lastUsed TIMESTAMP NOT NULL
+    lastUsed TIMESTAMP NOT NULL,
+    expiresOn TIMESTAMP,
+    expiryNotified BOOLEAN NOT NULL
 );
 
 CREATE TABLE Package (
@@ -45,7 +62,11 @@ CREATE TABLE Package (
     nativeTargets TEXT NOT NULL,
     capabilities TEXT NOT NULL,
     isDeprecated BOOLEAN NOT NULL,
-    canRemove BOOLEAN NOT NULL
+    deprecationMessage TEXT,
+    deprecationUseInstead TEXT,
+    canRemove BOOLEAN NOT NULL,
+    visibility TEXT NOT NULL,
+    requiresApproval BOOLEAN NOT NULL
 );
 
 CREATE INDEX IndexPackage ON Package (name);
@@ -66,15 +87,33 @@ CREATE TABLE PackageVersion (
     upload TIMESTAMP NOT NULL,
     uploadedBy INTEGER NOT NULL REFERENCES RegistryUser(id),
     yanked BOOLEAN NOT NULL,
+    yankReason TEXT,
     downloadCount INTEGER NOT NULL,
     downloads BLOB,
     depsLastCheck TIMESTAMP NOT NULL,
     depsHasOutdated BOOLEAN NOT NULL,
-    depsHasCVEs BOOLEAN NOT NULL
+    depsHasCVEs BOOLEAN NOT NULL,
+    depsHasYanked BOOLEAN NOT NULL,
+    depsHasDeprecated BOOLEAN NOT NULL,
+    indexData TEXT,
+    isPublishing BOOLEAN NOT NULL,
+    indexUpdate TIMESTAMP
 );
 
 CREATE INDEX IndexPackageVersion ON PackageVersion(package);
 
+CREATE TABLE PackagePendingVersion (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    description TEXT NOT NULL,
+    upload TIMESTAMP NOT NULL,
+    uploadedBy INTEGER NOT NULL REFERENCES RegistryUser(id),
+    indexData TEXT NOT NULL
+);
+
+CREATE INDEX IndexPackagePendingVersion ON PackagePendingVersion(package);
+
 CREATE TABLE PackageVersionDocs (
     id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     package TEXT NOT NULL REFERENCES Package(name),
@@ -104,3 +143,98 @@ CREATE TABLE DocGenJob (
 );
 
 CREATE INDEX IndexDocGenJob ON DocGenJob (package);
+
+CREATE TABLE SemverCheckJob (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    baseline TEXT NOT NULL,
+    state INTEGER NOT NULL,
+    queuedOn TIMESTAMP NOT NULL,
+    startedOn TIMESTAMP NOT NULL,
+    finishedOn TIMESTAMP NOT NULL,
+    lastUpdate TIMESTAMP NOT NULL,
+    output TEXT NOT NULL
+);
+
+CREATE INDEX IndexSemverCheckJob ON SemverCheckJob (package);
+
+CREATE TABLE TrustedPublisher (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    issuer TEXT NOT NULL,
+    jwks TEXT NOT NULL,
+    repository TEXT NOT NULL,
+    branch TEXT,
+    workflow TEXT,
+    createdBy INTEGER NOT NULL REFERENCES RegistryUser(id)
+);
+
+CREATE INDEX IndexTrustedPublisher ON TrustedPublisher (package);
+
+CREATE TABLE TrustedPublishingToken (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    publisher INTEGER NOT NULL REFERENCES TrustedPublisher(id),
+    token TEXT NOT NULL,
+    expiresOn TIMESTAMP NOT NULL
+);
+
+CREATE TABLE Team (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL
+);
+
+CREATE INDEX IndexTeam ON Team (name);
+
+CREATE TABLE TeamMember (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    team INTEGER NOT NULL REFERENCES Team(id),
+    member INTEGER NOT NULL REFERENCES RegistryUser(id),
+    isSynced BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexTeamMember ON TeamMember (member);
+
+CREATE TABLE PackageTeamOwner (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    team INTEGER NOT NULL REFERENCES Team(id)
+);
+
+CREATE INDEX IndexPackageTeamOwner ON PackageTeamOwner (package);
+
+CREATE TABLE PackageReader (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    reader INTEGER NOT NULL REFERENCES RegistryUser(id)
+);
+
+CREATE INDEX IndexPackageReader ON PackageReader (package);
+
+CREATE TABLE PackageTeamReader (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    team INTEGER NOT NULL REFERENCES Team(id)
+);
+
+CREATE INDEX IndexPackageTeamReader ON PackageTeamReader (package);
+
+CREATE TABLE AuditEvent (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    timestamp TIMESTAMP NOT NULL,
+    actor TEXT NOT NULL,
+    tokenId TEXT,
+    clientIp TEXT,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    details TEXT
+);
+
+CREATE INDEX IndexAuditEventTimestamp ON AuditEvent (timestamp);
+
+CREATE TABLE CrateNamePolicy (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    pattern TEXT NOT NULL,
+    team INTEGER REFERENCES Team(id),
+    reason TEXT
+);
@@ -5,33 +5,43 @@
 //! Service for persisting information in the database
 //! API related to administration of the registry itself
 
-use chrono::Local;
+use chrono::{Local, NaiveDateTime};
 
 use super::Database;
-use crate::model::auth::{RegistryUserToken, RegistryUserTokenWithSecret};
+use crate::model::auth::{
+    ROLE_ADMIN, RegistryUserToken, RegistryUserTokenWithSecret, TokenExpiryNotice, TokenKind, TokenScopes,
+};
 use crate::utils::apierror::{ApiError, error_invalid_request, specialize};
 use crate::utils::token::{generate_token, hash_token};
 
 impl Database {
     /// Gets the global tokens for the registry, usually for CI purposes
     pub async fn get_global_tokens(&self) -> Result<Vec<RegistryUserToken>, ApiError> {
-        let rows = sqlx::query!("SELECT id, name, lastUsed AS last_used FROM RegistryGlobalToken ORDER BY id",)
-            .fetch_all(&mut *self.transaction.borrow().await)
-            .await?;
+        let rows = sqlx::query!(
+            "SELECT id, name, lastUsed AS last_used, expiresOn AS expires_on FROM RegistryGlobalToken ORDER BY id",
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
         Ok(rows
             .into_iter()
             .map(|row| RegistryUserToken {
                 id: row.id,
                 name: row.name,
                 last_used: row.last_used,
+                expires_on: row.expires_on,
                 can_write: false,
                 can_admin: false,
+                scopes: TokenScopes::unrestricted(),
             })
             .collect())
     }
 
     /// Creates a global token for the registry
-    pub async fn create_global_token(&self, name: &str) -> Result<RegistryUserTokenWithSecret, ApiError> {
+    pub async fn create_global_token(
+        &self,
+        name: &str,
+        expires_on: Option<NaiveDateTime>,
+    ) -> Result<RegistryUserTokenWithSecret, ApiError> {
         let row = sqlx::query!("SELECT id FROM RegistryGlobalToken WHERE name = $1 LIMIT 1", name)
             .fetch_optional(&mut *self.transaction.borrow().await)
             .await?;
@@ -45,10 +55,11 @@ impl Database {
         let token_hash = hash_token(&token_secret);
         let now = Local::now().naive_local();
         let id = sqlx::query!(
-            "INSERT INTO RegistryGlobalToken (name, token, lastUsed) VALUES ($1, $2, $3) RETURNING id",
+            "INSERT INTO RegistryGlobalToken (name, token, lastUsed, expiresOn, expiryNotified) VALUES ($1, $2, $3, $4, FALSE) RETURNING id",
             name,
             token_hash,
             now,
+            expires_on,
         )
         .fetch_one(&mut *self.transaction.borrow().await)
         .await?
@@ -58,8 +69,10 @@ impl Database {
             name: name.to_string(),
             secret: token_secret,
             last_used: now,
+            expires_on,
             can_write: false,
             can_admin: false,
+            scopes: TokenScopes::unrestricted(),
         })
     }
 
@@ -70,4 +83,54 @@ impl Database {
             .await?;
         Ok(())
     }
+
+    /// Gets the global tokens that will expire before the given limit and for which the administrators were not yet notified
+    pub async fn get_expiring_global_tokens(&self, limit: NaiveDateTime) -> Result<Vec<TokenExpiryNotice>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT id, name, expiresOn AS expires_on FROM RegistryGlobalToken
+            WHERE expiryNotified = FALSE AND expiresOn IS NOT NULL AND expiresOn <= $1",
+            limit
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        let recipients = self.get_admin_emails().await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(TokenExpiryNotice {
+                    kind: TokenKind::Registry,
+                    token_id: row.id,
+                    name: row.name,
+                    expires_on: row.expires_on?,
+                    recipients: recipients.clone(),
+                })
+            })
+            .collect())
+    }
+
+    /// Deletes the global tokens that have expired and returns the number of deleted tokens
+    pub async fn delete_expired_global_tokens(&self, now: NaiveDateTime) -> Result<u64, ApiError> {
+        let result = sqlx::query!(
+            "DELETE FROM RegistryGlobalToken WHERE expiresOn IS NOT NULL AND expiresOn <= $1",
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Gets the emails of the active administrators of the registry
+    pub async fn get_admin_emails(&self) -> Result<Vec<String>, ApiError> {
+        let rows = sqlx::query!("SELECT email, roles FROM RegistryUser WHERE isActive = TRUE")
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .filter(|row| row.roles.split(',').any(|role| role.trim() == ROLE_ADMIN))
+            .map(|row| row.email)
+            .collect())
+    }
 }
@@ -0,0 +1,179 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the approval of crate versions before their publication
+
+use chrono::Local;
+
+use super::Database;
+use crate::model::cargo::{CrateUploadData, IndexCrateMetadata};
+use crate::model::packages::CratePendingVersion;
+use crate::utils::apierror::{ApiError, error_forbidden, error_not_found, specialize};
+
+impl Database {
+    /// Gets whether new versions of a crate must be approved before being published
+    /// This is never the case for crates that do not exist yet
+    pub async fn get_crate_requires_approval(&self, package: &str) -> Result<bool, ApiError> {
+        let row = sqlx::query!(
+            "SELECT requiresApproval AS requires_approval FROM Package WHERE name = $1 LIMIT 1",
+            package
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(row.is_some_and(|row| row.requires_approval))
+    }
+
+    /// Sets whether new versions of a crate must be approved before being published
+    pub async fn set_crate_requires_approval(&self, package: &str, requires_approval: bool) -> Result<(), ApiError> {
+        sqlx::query!(
+            "UPDATE Package SET requiresApproval = $2 WHERE name = $1",
+            package,
+            requires_approval
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Stages a new version of an existing crate, pending approval
+    pub async fn stage_crate_version(
+        &self,
+        uid: i64,
+        package: &CrateUploadData,
+        index_data: &IndexCrateMetadata,
+    ) -> Result<(), ApiError> {
+        self.check_crate_version_is_new(&package.metadata.name, &package.metadata.vers)
+            .await?;
+        self.check_is_crate_manager(uid, &package.metadata.name).await?;
+        let now = Local::now().naive_local();
+        let description = package.metadata.description.as_ref().map_or("", String::as_str);
+        let index_data = serde_json::to_string(index_data)?;
+        sqlx::query!(
+            "INSERT INTO PackagePendingVersion (package, version, description, upload, uploadedBy, indexData) VALUES ($1, $2, $3, $4, $5, $6)",
+            package.metadata.name,
+            package.metadata.vers,
+            description,
+            now,
+            uid,
+            index_data
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Gets the versions of a crate that are pending approval
+    pub async fn get_crate_pending_versions(&self, package: &str) -> Result<Vec<CratePendingVersion>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT upload, uploadedBy AS uploaded_by, indexData AS index_data FROM PackagePendingVersion WHERE package = $1 ORDER BY id",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut versions = Vec::with_capacity(rows.len());
+        for row in rows {
+            versions.push(CratePendingVersion {
+                index: serde_json::from_str(&row.index_data)?,
+                upload: row.upload,
+                uploaded_by: self.get_user_profile(row.uploaded_by).await?,
+            });
+        }
+        Ok(versions)
+    }
+
+    /// Gets the data for the index of all the versions pending approval
+    pub async fn get_all_pending_versions(&self) -> Result<Vec<IndexCrateMetadata>, ApiError> {
+        let rows = sqlx::query!("SELECT indexData AS index_data FROM PackagePendingVersion ORDER BY id")
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        let mut versions = Vec::with_capacity(rows.len());
+        for row in rows {
+            versions.push(serde_json::from_str(&row.index_data)?);
+        }
+        Ok(versions)
+    }
+
+    /// Checks that a version of a crate is pending approval
+    pub async fn check_crate_pending_version_exists(&self, package: &str, version: &str) -> Result<(), ApiError> {
+        sqlx::query!(
+            "SELECT id FROM PackagePendingVersion WHERE package = $1 AND version = $2 LIMIT 1",
+            package,
+            version
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .ok_or_else(|| specialize(error_not_found(), format!("No pending version {version} for crate {package}")))?;
+        Ok(())
+    }
+
+    /// Approves a pending version of a crate so that it becomes a published version
+    /// The version remains hidden until its publication is completed
+    /// The approver must be another user than the one that uploaded the version
+    /// Returns the identifier of the user that uploaded the version and its data for the index
+    pub async fn approve_crate_version(
+        &self,
+        approver_uid: i64,
+        package: &str,
+        version: &str,
+    ) -> Result<(i64, IndexCrateMetadata), ApiError> {
+        let row = sqlx::query!(
+            "SELECT id, description, upload, uploadedBy AS uploaded_by, indexData AS index_data FROM PackagePendingVersion WHERE package = $1 AND version = $2 LIMIT 1",
+            package,
+            version
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .ok_or_else(|| specialize(error_not_found(), format!("No pending version {version} for crate {package}")))?;
+        if row.uploaded_by == approver_uid {
+            return Err(specialize(
+                error_forbidden(),
+                String::from("A version must be approved by another owner than the one who uploaded it"),
+            ));
+        }
+        let index_data = serde_json::from_str(&row.index_data)?;
+        sqlx::query!(
+            "INSERT INTO PackageVersion (package, version, description, upload, uploadedBy, yanked, downloadCount, downloads, depsLastCheck, depsHasOutdated, depsHasCVEs, depsHasYanked, depsHasDeprecated, indexData, isPublishing) VALUES ($1, $2, $3, $4, $5, false, 0, NULL, 0, false, false, false, false, $6, TRUE)",
+            package,
+            version,
+            row.description,
+            row.upload,
+            row.uploaded_by,
+            row.index_data
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        sqlx::query!("DELETE FROM PackagePendingVersion WHERE id = $1", row.id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok((row.uploaded_by, index_data))
+    }
+
+    /// Reverts the approval of a version whose publication could not be completed, so that it is pending approval again
+    pub async fn revert_crate_version_approval(&self, package: &str, version: &str) -> Result<(), ApiError> {
+        sqlx::query!(
+            "INSERT INTO PackagePendingVersion (package, version, description, upload, uploadedBy, indexData)
+            SELECT package, version, description, upload, uploadedBy, indexData FROM PackageVersion
+            WHERE package = $1 AND version = $2 AND isPublishing = TRUE AND indexData IS NOT NULL",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        self.discard_crate_version_publish(package, version).await
+    }
+
+    /// Rejects a pending version of a crate, it is simply discarded
+    pub async fn reject_crate_version(&self, package: &str, version: &str) -> Result<(), ApiError> {
+        self.check_crate_pending_version_exists(package, version).await?;
+        sqlx::query!(
+            "DELETE FROM PackagePendingVersion WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the audit log of security-relevant actions
+
+use super::Database;
+use crate::model::audit::{AuditEvent, AuditQuery};
+use crate::utils::apierror::ApiError;
+
+impl Database {
+    /// Records an event in the audit log
+    pub async fn insert_audit_event(&self, event: &AuditEvent) -> Result<(), ApiError> {
+        sqlx::query!(
+            "INSERT INTO AuditEvent (timestamp, actor, tokenId, clientIp, action, target, details) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            event.timestamp,
+            event.actor,
+            event.token_id,
+            event.client_ip,
+            event.action,
+            event.target,
+            event.details
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Gets the events in the audit log matching a query, the most recent first
+    /// Without a limit, all the matching events are returned
+    pub async fn get_audit_events(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>, ApiError> {
+        // a negative limit means no limit for SQLite
+        let limit = query.limit.unwrap_or(-1);
+        let events = sqlx::query_as!(
+            AuditEvent,
+            "SELECT id, timestamp, actor, tokenId AS token_id, clientIp AS client_ip, action, target, details
+            FROM AuditEvent
+            WHERE ($1 IS NULL OR actor = $1)
+            AND ($2 IS NULL OR action = $2)
+            AND ($3 IS NULL OR target = $3)
+            AND ($4 IS NULL OR timestamp >= $4)
+            AND ($5 IS NULL OR timestamp <= $5)
+            ORDER BY id DESC LIMIT $6",
+            query.actor,
+            query.action,
+            query.target,
+            query.since,
+            query.until,
+            limit
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(events)
+    }
+}
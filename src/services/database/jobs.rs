@@ -9,6 +9,7 @@ use chrono::Local;
 
 use super::Database;
 use crate::model::docs::{DocGenJob, DocGenJobSpec, DocGenJobState, DocGenTrigger};
+use crate::model::semver_checks::{SemverCheckJob, SemverCheckJobSpec, SemverCheckJobState};
 use crate::utils::apierror::{ApiError, error_not_found};
 use crate::utils::comma_sep_to_vec;
 
@@ -250,4 +251,138 @@ impl Database {
         }
         Ok(())
     }
+
+    /// Gets the semver check jobs for a crate
+    pub async fn get_semver_check_jobs(&self, package: &str) -> Result<Vec<SemverCheckJob>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT id, package, version, baseline, state,
+            queuedOn AS queued_on, startedOn AS started_on, finishedOn AS finished_on, lastUpdate AS last_update, output
+            FROM SemverCheckJob
+            WHERE package = $1
+            ORDER BY id DESC",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| SemverCheckJob {
+                id: row.id,
+                package: row.package,
+                version: row.version,
+                baseline: row.baseline,
+                state: SemverCheckJobState::from(row.state),
+                queued_on: row.queued_on,
+                started_on: row.started_on,
+                finished_on: row.finished_on,
+                last_update: row.last_update,
+                output: row.output,
+            })
+            .collect())
+    }
+
+    /// Creates and queue a single semver check job
+    pub async fn create_semver_check_job(&self, spec: &SemverCheckJobSpec) -> Result<SemverCheckJob, ApiError> {
+        let now = Local::now().naive_local();
+        let state_value = SemverCheckJobState::Queued.value();
+        let job_id = sqlx::query!(
+            "INSERT INTO SemverCheckJob (
+            package, version, baseline, state,
+            queuedOn, startedOn, finishedOn, lastUpdate, output
+        ) VALUES (
+            $1, $2, $3, $4,
+            $5, $5, $5, $5, ''
+        ) RETURNING id",
+            spec.package,
+            spec.version,
+            spec.baseline,
+            state_value,
+            now,
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(SemverCheckJob {
+            id: job_id,
+            package: spec.package.clone(),
+            version: spec.version.clone(),
+            baseline: spec.baseline.clone(),
+            state: SemverCheckJobState::Queued,
+            queued_on: now,
+            started_on: now,
+            finished_on: now,
+            last_update: now,
+            output: String::new(),
+        })
+    }
+
+    /// Attempts to get the next available semver check job
+    pub async fn get_next_semver_check_job(&self) -> Result<Option<SemverCheckJob>, ApiError> {
+        let state_value = SemverCheckJobState::Queued.value();
+        let row = sqlx::query!(
+            "SELECT id, package, version, baseline, state,
+            queuedOn AS queued_on, startedOn AS started_on, finishedOn AS finished_on, lastUpdate AS last_update, output
+            FROM SemverCheckJob
+            WHERE state = $1
+            ORDER BY id
+            LIMIT 1",
+            state_value
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(row.map(|row| SemverCheckJob {
+            id: row.id,
+            package: row.package,
+            version: row.version,
+            baseline: row.baseline,
+            state: SemverCheckJobState::from(row.state),
+            queued_on: row.queued_on,
+            started_on: row.started_on,
+            finished_on: row.finished_on,
+            last_update: row.last_update,
+            output: row.output,
+        }))
+    }
+
+    /// Updates an existing semver check job
+    pub async fn update_semver_check_job(
+        &self,
+        job_id: i64,
+        state: SemverCheckJobState,
+        output: Option<&str>,
+    ) -> Result<(), ApiError> {
+        let now = Local::now().naive_local();
+        let state_value = state.value();
+        if state == SemverCheckJobState::Working {
+            sqlx::query!(
+                "UPDATE SemverCheckJob SET state = $2, startedOn = $3, lastUpdate = $3 WHERE id = $1",
+                job_id,
+                state_value,
+                now
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        } else if state.is_final() {
+            let output = output.unwrap_or_default();
+            sqlx::query!(
+                "UPDATE SemverCheckJob SET state = $2, finishedOn = $3, lastUpdate = $3, output = $4 WHERE id = $1",
+                job_id,
+                state_value,
+                now,
+                output
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        } else {
+            sqlx::query!(
+                "UPDATE SemverCheckJob SET state = $2, lastUpdate = $3 WHERE id = $1",
+                job_id,
+                state_value,
+                now
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        }
+        Ok(())
+    }
 }
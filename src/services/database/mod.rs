@@ -5,10 +5,15 @@
 //! Service for persisting information in the database
 
 pub mod admin;
+pub mod approvals;
+pub mod audit;
 pub mod jobs;
+pub mod names;
 pub mod packages;
 pub mod stats;
+pub mod teams;
 pub mod users;
+pub mod visibility;
 
 use std::future::Future;
 
@@ -117,7 +122,7 @@ impl Database {
     /// Checks that a package exists
     pub async fn check_crate_exists(&self, package: &str, version: &str) -> Result<(), ApiError> {
         let _row = sqlx::query!(
-            "SELECT id FROM PackageVersion WHERE package = $1 AND version = $2 LIMIT 1",
+            "SELECT id FROM PackageVersion WHERE package = $1 AND version = $2 AND isPublishing = FALSE LIMIT 1",
             package,
             version
         )
@@ -139,6 +144,17 @@ impl Database {
         )
         .fetch_optional(&mut *self.transaction.borrow().await)
         .await?;
+        if row.is_some() {
+            return Ok(uid);
+        }
+        // owner through a team
+        let row = sqlx::query!(
+            "SELECT PackageTeamOwner.id from PackageTeamOwner INNER JOIN TeamMember ON TeamMember.team = PackageTeamOwner.team WHERE package = $1 AND member = $2 LIMIT 1",
+            package,
+            uid
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
         match row {
             Some(_) => Ok(uid),
             None => Err(specialize(
@@ -0,0 +1,142 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the protection of crate names
+
+use super::Database;
+use crate::model::names::{CrateNamePolicy, CrateNamePolicySpec, are_confusable, normalize_crate_name};
+use crate::utils::apierror::{ApiError, error_forbidden, error_invalid_request, error_not_found, specialize};
+
+impl Database {
+    /// Gets all the policies for the names of new crates
+    pub async fn get_crate_name_policies(&self) -> Result<Vec<CrateNamePolicy>, ApiError> {
+        let policies = sqlx::query_as!(
+            CrateNamePolicy,
+            "SELECT CrateNamePolicy.id, pattern, Team.name AS team, reason
+            FROM CrateNamePolicy LEFT JOIN Team ON Team.id = CrateNamePolicy.team
+            ORDER BY pattern"
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(policies)
+    }
+
+    /// Creates a policy for the names of new crates
+    pub async fn create_crate_name_policy(&self, spec: &CrateNamePolicySpec) -> Result<CrateNamePolicy, ApiError> {
+        let pattern = spec.normalized_pattern()?;
+        let team_id = match &spec.team {
+            Some(team) => Some(self.check_is_team(team).await?),
+            None => None,
+        };
+        let id = sqlx::query!(
+            "INSERT INTO CrateNamePolicy (pattern, team, reason) VALUES ($1, $2, $3) RETURNING id",
+            pattern,
+            team_id,
+            spec.reason
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(CrateNamePolicy {
+            id,
+            pattern,
+            team: spec.team.clone(),
+            reason: spec.reason.clone(),
+        })
+    }
+
+    /// Deletes a policy for the names of new crates
+    /// Returns the pattern of the deleted policy
+    pub async fn delete_crate_name_policy(&self, policy_id: i64) -> Result<String, ApiError> {
+        let row = sqlx::query!("DELETE FROM CrateNamePolicy WHERE id = $1 RETURNING pattern", policy_id)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .ok_or_else(error_not_found)?;
+        Ok(row.pattern)
+    }
+
+    /// Checks that a user may create a new crate with a name
+    /// The name must not collide with an existing crate once normalized, must comply with the policies,
+    /// and must not be confusable with other crates, either local or among the external names
+    pub async fn check_crate_name_available(&self, uid: i64, name: &str, external_names: &[String]) -> Result<(), ApiError> {
+        let normalized = normalize_crate_name(name);
+        let existing = sqlx::query!("SELECT name FROM Package")
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        if let Some(row) = existing.iter().find(|row| normalize_crate_name(&row.name) == normalized) {
+            return Err(specialize(
+                error_invalid_request(),
+                format!(
+                    "A package named {} already exists, names differing only by case, - or _ are equivalent",
+                    row.name
+                ),
+            ));
+        }
+
+        // check the policies
+        let mut is_allowed_by_policy = false;
+        for policy in self.get_crate_name_policies().await? {
+            if !policy.matches(&normalized) {
+                continue;
+            }
+            let reason = policy
+                .reason
+                .as_deref()
+                .map(|reason| format!(": {reason}"))
+                .unwrap_or_default();
+            let Some(team) = &policy.team else {
+                return Err(specialize(
+                    error_forbidden(),
+                    format!("Crate name {name} is reserved by the registry administrators{reason}"),
+                ));
+            };
+            let team_id = self.check_is_team(team).await?;
+            let is_member = sqlx::query!(
+                "SELECT id FROM TeamMember WHERE team = $1 AND member = $2 LIMIT 1",
+                team_id,
+                uid
+            )
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .is_some();
+            if !is_member {
+                return Err(specialize(
+                    error_forbidden(),
+                    format!(
+                        "New crates named {} are restricted to the members of team {team}{reason}",
+                        policy.pattern
+                    ),
+                ));
+            }
+            is_allowed_by_policy = true;
+        }
+        if is_allowed_by_policy {
+            // names explicitly granted to a team are not subject to the confusion checks
+            return Ok(());
+        }
+
+        // check names that could be confused
+        for row in &existing {
+            if are_confusable(&normalized, &normalize_crate_name(&row.name))
+                && self.check_is_crate_manager(uid, &row.name).await.is_err()
+            {
+                return Err(specialize(
+                    error_forbidden(),
+                    format!("Crate name {name} is too similar to the existing crate {}", row.name),
+                ));
+            }
+        }
+        if let Some(external) = external_names.iter().find(|external| {
+            let external = normalize_crate_name(external);
+            external == normalized || are_confusable(&normalized, &external)
+        }) {
+            return Err(specialize(
+                error_forbidden(),
+                format!("Crate name {name} could be confused with the crates.io crate {external}"),
+            ));
+        }
+        Ok(())
+    }
+}
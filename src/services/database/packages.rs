@@ -16,23 +16,26 @@ use semver::Version;
 use super::Database;
 use crate::model::CrateVersion;
 use crate::model::cargo::{
-    CrateUploadData, CrateUploadResult, IndexCrateMetadata, OwnersQueryResult, RegistryUser, SearchResultCrate, SearchResults,
-    SearchResultsMeta, YesNoMsgResult, YesNoResult,
+    CrateUploadData, IndexCrateMetadata, OwnersQueryResult, RegistryUser, SearchResultCrate, SearchResults, SearchResultsMeta,
+    YesNoMsgResult, YesNoResult,
 };
-use crate::model::deps::{DepsAnalysisJobSpec, DepsAnalysisState};
+use crate::model::deps::{DepDeprecated, DepsAnalysis, DepsAnalysisFlags, DepsAnalysisJobSpec, DepsAnalysisState};
 use crate::model::docs::DocGenJobSpec;
-use crate::model::packages::{CrateInfo, CrateInfoTarget, CrateInfoVersion, CrateInfoVersionDocs};
+use crate::model::packages::{CrateDeprecation, CrateInfo, CrateInfoTarget, CrateInfoVersion, CrateInfoVersionDocs};
 use crate::model::stats::{DownloadStats, SERIES_LENGTH};
+use crate::model::teams::TEAM_OWNER_PREFIX;
 use crate::utils::apierror::{ApiError, error_invalid_request, error_not_found, specialize};
 use crate::utils::comma_sep_to_vec;
 
 impl Database {
     /// Search for crates
+    /// The hidden crates are excluded from the results
     pub async fn search_crates(
         &self,
         query: &str,
         per_page: Option<usize>,
         deprecated: Option<bool>,
+        hidden: &[String],
     ) -> Result<SearchResults, ApiError> {
         let per_page = match per_page {
             None => 10,
@@ -42,17 +45,19 @@ impl Database {
         let pattern = format!("%{query}%");
         let deprecated_value = deprecated.unwrap_or_default();
         let deprecated_short_circuit = deprecated.is_none(); // short-circuit to true if no input
+        let hidden = serde_json::to_string(hidden)?;
         let rows = sqlx::query!(
-            "SELECT name, isDeprecated AS is_deprecated From Package WHERE name LIKE $1 AND (isDeprecated = $2 OR $3)",
+            "SELECT name, isDeprecated AS is_deprecated From Package WHERE name LIKE $1 AND (isDeprecated = $2 OR $3) AND name NOT IN (SELECT value FROM json_each($4))",
             pattern,
             deprecated_value,
-            deprecated_short_circuit
+            deprecated_short_circuit,
+            hidden
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await?;
         let mut crates = Vec::new();
         for row_name in rows {
-            let row = sqlx::query!("SELECT version, description FROM PackageVersion WHERE package = $1 AND yanked = FALSE ORDER BY id DESC LIMIT 1", row_name.name).fetch_optional(&mut *self.transaction.borrow().await).await?;
+            let row = sqlx::query!("SELECT version, description FROM PackageVersion WHERE package = $1 AND yanked = FALSE AND isPublishing = FALSE ORDER BY id DESC LIMIT 1", row_name.name).fetch_optional(&mut *self.transaction.borrow().await).await?;
             if let Some(row) = row {
                 crates.push(SearchResultCrate {
                     name: row_name.name,
@@ -81,10 +86,21 @@ impl Database {
             .is_none())
     }
 
+    /// Gets whether a package with the same (case-insensitive) name already exists
+    pub async fn get_crate_exists(&self, package: &str) -> Result<bool, ApiError> {
+        let lowercase = package.to_ascii_lowercase();
+        Ok(
+            sqlx::query!("SELECT name FROM Package WHERE lowercase = $1 LIMIT 1", lowercase)
+                .fetch_optional(&mut *self.transaction.borrow().await)
+                .await?
+                .is_some(),
+        )
+    }
+
     /// Gets the last version number for a package
     pub async fn get_crate_last_version(&self, package: &str) -> Result<String, ApiError> {
         let row = sqlx::query!(
-            "SELECT version, description FROM PackageVersion WHERE package = $1 AND yanked = FALSE ORDER BY id DESC LIMIT 1",
+            "SELECT version, description FROM PackageVersion WHERE package = $1 AND yanked = FALSE AND isPublishing = FALSE ORDER BY id DESC LIMIT 1",
             package
         )
         .fetch_optional(&mut *self.transaction.borrow().await)
@@ -93,6 +109,113 @@ impl Database {
         Ok(row.version)
     }
 
+    /// Gets the versions of a package that are not yanked
+    pub async fn get_crate_active_versions(&self, package: &str) -> Result<Vec<String>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT version FROM PackageVersion WHERE package = $1 AND yanked = FALSE AND isPublishing = FALSE",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows.into_iter().map(|row| row.version).collect())
+    }
+
+    /// Gets the versions of a package, from its lowercase name, with their yanked flag and data for the index
+    /// The data for the index is missing for versions published before it was recorded in the database
+    pub async fn get_crate_index_data(
+        &self,
+        lowercase: &str,
+    ) -> Result<Vec<(CrateVersion, bool, Option<IndexCrateMetadata>)>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT PackageVersion.package, PackageVersion.version, PackageVersion.yanked, PackageVersion.indexData AS index_data FROM PackageVersion INNER JOIN Package ON PackageVersion.package = Package.name WHERE Package.lowercase = $1 AND PackageVersion.isPublishing = FALSE ORDER BY PackageVersion.id",
+            lowercase
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut versions = Vec::with_capacity(rows.len());
+        for row in rows {
+            let index_data = match row.index_data {
+                Some(data) => Some(serde_json::from_str(&data)?),
+                None => None,
+            };
+            versions.push((
+                CrateVersion {
+                    package: row.package,
+                    version: row.version,
+                },
+                row.yanked,
+                index_data,
+            ));
+        }
+        Ok(versions)
+    }
+
+    /// Gets the last time the entry of a package in the index changed, from its lowercase name
+    /// This is the latest publication, yank or unyank of one of its versions
+    pub async fn get_crate_index_last_update(&self, lowercase: &str) -> Result<Option<NaiveDateTime>, ApiError> {
+        let row = sqlx::query!(
+            "SELECT MAX(COALESCE(PackageVersion.indexUpdate, PackageVersion.upload)) AS \"last_update?: NaiveDateTime\" FROM PackageVersion INNER JOIN Package ON PackageVersion.package = Package.name WHERE Package.lowercase = $1 AND PackageVersion.isPublishing = FALSE",
+            lowercase
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(row.last_update)
+    }
+
+    /// Gets whether the publication of a version of a package, from its lowercase name, is in progress
+    pub async fn get_crate_is_publishing(&self, lowercase: &str) -> Result<bool, ApiError> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(PackageVersion.id) FROM PackageVersion INNER JOIN Package ON PackageVersion.package = Package.name WHERE Package.lowercase = $1 AND PackageVersion.isPublishing = TRUE",
+            lowercase
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(count > 0)
+    }
+
+    /// Gets all the versions in the registry, with their yanked flag and data for the index
+    pub async fn get_crates_index_data(&self) -> Result<Vec<(CrateVersion, bool, Option<IndexCrateMetadata>)>, ApiError> {
+        let rows =
+            sqlx::query!("SELECT package, version, yanked, indexData AS index_data FROM PackageVersion WHERE isPublishing = FALSE ORDER BY package, id")
+                .fetch_all(&mut *self.transaction.borrow().await)
+                .await?;
+        let mut versions = Vec::with_capacity(rows.len());
+        for row in rows {
+            let index_data = match row.index_data {
+                Some(data) => Some(serde_json::from_str(&data)?),
+                None => None,
+            };
+            versions.push((
+                CrateVersion {
+                    package: row.package,
+                    version: row.version,
+                },
+                row.yanked,
+                index_data,
+            ));
+        }
+        Ok(versions)
+    }
+
+    /// Sets the data for the index of a crate version
+    pub async fn set_crate_version_index_data(
+        &self,
+        package: &str,
+        version: &str,
+        index_data: &IndexCrateMetadata,
+    ) -> Result<(), ApiError> {
+        let index_data = serde_json::to_string(index_data)?;
+        sqlx::query!(
+            "UPDATE PackageVersion SET indexData = $3 WHERE package = $1 AND version = $2",
+            package,
+            version,
+            index_data
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
     /// Gets all the data about a crate
     pub async fn get_crate_info(
         &self,
@@ -100,7 +223,7 @@ impl Database {
         versions_in_index: Vec<IndexCrateMetadata>,
     ) -> Result<CrateInfo, ApiError> {
         let row = sqlx::query!(
-            "SELECT isDeprecated AS is_deprecated, canRemove AS can_remove, targets, nativeTargets AS nativetargets, capabilities FROM Package WHERE name = $1 LIMIT 1",
+            "SELECT isDeprecated AS is_deprecated, deprecationMessage AS deprecation_message, deprecationUseInstead AS use_instead, canRemove AS can_remove, targets, nativeTargets AS nativetargets, capabilities, visibility, requiresApproval AS requires_approval FROM Package WHERE name = $1 LIMIT 1",
             package
         )
         .fetch_optional(&mut *self.transaction.borrow().await)
@@ -111,12 +234,14 @@ impl Database {
         let targets = comma_sep_to_vec(&row.targets);
         let native_targets = comma_sep_to_vec(&row.nativetargets);
         let capabilities = comma_sep_to_vec(&row.capabilities);
+        let visibility = row.visibility.parse()?;
 
         let rows = sqlx::query!(
-            "SELECT version, upload, uploadedBy AS uploaded_by,
+            "SELECT version, upload, uploadedBy AS uploaded_by, yankReason AS yank_reason,
                     downloadCount AS download_count,
-                    depsLastCheck AS deps_last_check, depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves
-            FROM PackageVersion WHERE package = $1 ORDER BY id",
+                    depsLastCheck AS deps_last_check, depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves,
+                    depsHasYanked AS deps_has_yanked, depsHasDeprecated AS deps_has_deprecated
+            FROM PackageVersion WHERE package = $1 AND isPublishing = FALSE ORDER BY id",
             package
         )
         .fetch_all(&mut *self.transaction.borrow().await)
@@ -129,10 +254,13 @@ impl Database {
                     index: index_data,
                     upload: row.upload,
                     uploaded_by,
+                    yank_reason: row.yank_reason.clone(),
                     download_count: row.download_count,
                     deps_last_check: row.deps_last_check,
                     deps_has_outdated: row.deps_has_outdated,
                     deps_has_cves: row.deps_has_cves,
+                    deps_has_yanked: row.deps_has_yanked,
+                    deps_has_deprecated: row.deps_has_deprecated,
                     docs: Vec::new(),
                 });
             }
@@ -157,6 +285,8 @@ impl Database {
         Ok(CrateInfo {
             metadata: None,
             is_deprecated,
+            deprecation_message: row.deprecation_message,
+            use_instead: row.use_instead,
             can_remove,
             versions,
             targets: targets
@@ -167,17 +297,33 @@ impl Database {
                 })
                 .collect(),
             capabilities,
+            visibility,
+            requires_approval: row.requires_approval,
         })
     }
 
-    /// Publish a crate
-    pub async fn publish_crate_version(&self, uid: i64, package: &CrateUploadData) -> Result<CrateUploadResult, ApiError> {
-        let warnings = package.metadata.validate()?;
-        let lowercase = package.metadata.name.to_ascii_lowercase();
+    /// Checks that a version of a crate was not already uploaded, either published or pending approval
+    pub async fn check_crate_version_is_new(&self, package: &str, version: &str) -> Result<(), ApiError> {
         let row = sqlx::query!(
             "SELECT upload FROM PackageVersion WHERE package = $1 AND version = $2 LIMIT 1",
-            package.metadata.name,
-            package.metadata.vers
+            package,
+            version
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        if let Some(row) = row {
+            return Err(specialize(
+                error_invalid_request(),
+                format!(
+                    "Package {package} already exists in version {version}, uploaded on {}",
+                    row.upload
+                ),
+            ));
+        }
+        let row = sqlx::query!(
+            "SELECT upload FROM PackagePendingVersion WHERE package = $1 AND version = $2 LIMIT 1",
+            package,
+            version
         )
         .fetch_optional(&mut *self.transaction.borrow().await)
         .await?;
@@ -185,18 +331,34 @@ impl Database {
             return Err(specialize(
                 error_invalid_request(),
                 format!(
-                    "Package {} already exists in version {}, uploaded on {}",
-                    &package.metadata.name, &package.metadata.vers, row.upload
+                    "Package {package} in version {version}, uploaded on {}, is pending approval",
+                    row.upload
                 ),
             ));
         }
+        Ok(())
+    }
+
+    /// Publish a crate
+    /// The name of a new crate is checked against the existing crates and the external names that could be confused
+    /// The version remains hidden until its publication is completed
+    pub async fn publish_crate_version(
+        &self,
+        uid: i64,
+        package: &CrateUploadData,
+        index_data: &IndexCrateMetadata,
+        external_names: &[String],
+    ) -> Result<(), ApiError> {
+        let lowercase = package.metadata.name.to_ascii_lowercase();
+        self.check_crate_version_is_new(&package.metadata.name, &package.metadata.vers)
+            .await?;
         // check whether the package already exists
         let row = sqlx::query!("SELECT name FROM Package WHERE lowercase = $1 LIMIT 1", lowercase)
             .fetch_optional(&mut *self.transaction.borrow().await)
             .await?;
         if let Some(row) = row {
             // check this is the same package
-            if row.name != lowercase {
+            if row.name != package.metadata.name {
                 return Err(specialize(
                     error_invalid_request(),
                     format!("A package named {} already exists", row.name),
@@ -205,9 +367,11 @@ impl Database {
             // check the ownership
             self.check_is_crate_manager(uid, &package.metadata.name).await?;
         } else {
+            self.check_crate_name_available(uid, &package.metadata.name, external_names)
+                .await?;
             // create the package
             sqlx::query!(
-                "INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove) VALUES ($1, $2, '', '', '', FALSE, FALSE)",
+                "INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove, visibility, requiresApproval) VALUES ($1, $2, '', '', '', FALSE, FALSE, 'internal', FALSE)",
                 package.metadata.name,
                 lowercase
             )
@@ -225,17 +389,86 @@ impl Database {
         let now = Local::now().naive_local();
         // create the version
         let description = package.metadata.description.as_ref().map_or("", String::as_str);
+        let index_data = serde_json::to_string(index_data)?;
         sqlx::query!(
-            "INSERT INTO PackageVersion (package, version, description, upload, uploadedBy, yanked, downloadCount, downloads, depsLastCheck, depsHasOutdated, depsHasCVEs) VALUES ($1, $2, $3, $4, $5, false, 0, NULL, 0, false, false)",
+            "INSERT INTO PackageVersion (package, version, description, upload, uploadedBy, yanked, downloadCount, downloads, depsLastCheck, depsHasOutdated, depsHasCVEs, depsHasYanked, depsHasDeprecated, indexData, isPublishing) VALUES ($1, $2, $3, $4, $5, false, 0, NULL, 0, false, false, false, false, $6, TRUE)",
             package.metadata.name,
             package.metadata.vers,
             description,
             now,
             uid,
+            index_data
         )
         .execute(&mut *self.transaction.borrow().await)
         .await?;
-        Ok(warnings)
+        Ok(())
+    }
+
+    /// Gets the versions for which the publication was not completed, with the user that uploaded them and their data for the index
+    pub async fn get_crate_versions_publishing(&self) -> Result<Vec<(i64, IndexCrateMetadata)>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT uploadedBy AS uploaded_by, indexData AS index_data FROM PackageVersion WHERE isPublishing = TRUE ORDER BY id"
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut versions = Vec::with_capacity(rows.len());
+        for row in rows {
+            // the data for the index is always recorded for versions being published
+            if let Some(data) = row.index_data {
+                versions.push((row.uploaded_by, serde_json::from_str(&data)?));
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Completes the publication of a crate version, once stored and in the index, so that it becomes visible
+    pub async fn complete_crate_version_publish(&self, package: &str, version: &str) -> Result<(), ApiError> {
+        let now = Local::now().naive_local();
+        sqlx::query!(
+            "UPDATE PackageVersion SET isPublishing = FALSE, indexUpdate = $3 WHERE package = $1 AND version = $2",
+            package,
+            version,
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Discards a crate version for which the publication failed
+    /// The crate itself is also discarded when this was its first version
+    pub async fn discard_crate_version_publish(&self, package: &str, version: &str) -> Result<(), ApiError> {
+        sqlx::query!(
+            "DELETE FROM PackageVersion WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM PackageVersionDocs WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        let has_versions = sqlx::query!("SELECT id FROM PackageVersion WHERE package = $1 LIMIT 1", package)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .is_some()
+            || sqlx::query!("SELECT id FROM PackagePendingVersion WHERE package = $1 LIMIT 1", package)
+                .fetch_optional(&mut *self.transaction.borrow().await)
+                .await?
+                .is_some();
+        if !has_versions {
+            sqlx::query!("DELETE FROM PackageOwner WHERE package = $1", package)
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+            sqlx::query!("DELETE FROM Package WHERE name = $1", package)
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+        }
+        Ok(())
     }
 
     /// Completely removes a version from the registry
@@ -251,7 +484,7 @@ impl Database {
         if !can_remove {
             return Err(specialize(
                 error_invalid_request(),
-                format!("Package {package} does not allow removing versions",),
+                format!("Package {package} does not allow removing versions"),
             ));
         }
         // check version exists
@@ -265,7 +498,7 @@ impl Database {
         if row.is_none() {
             return Err(specialize(
                 error_not_found(),
-                format!("Package {package}, version {version} not found",),
+                format!("Package {package}, version {version} not found"),
             ));
         }
         sqlx::query!(
@@ -287,7 +520,12 @@ impl Database {
     }
 
     /// Yank a crate version
-    pub async fn yank_crate_version(&self, package: &str, version: &str) -> Result<YesNoResult, ApiError> {
+    pub async fn yank_crate_version(
+        &self,
+        package: &str,
+        version: &str,
+        reason: Option<&str>,
+    ) -> Result<YesNoResult, ApiError> {
         let row = sqlx::query!(
             "SELECT yanked FROM PackageVersion WHERE package = $1 AND version = $2 LIMIT 1",
             package,
@@ -307,10 +545,13 @@ impl Database {
                         format!("Version {version} of crate {package} is already yanked"),
                     ))
                 } else {
+                    let now = Local::now().naive_local();
                     sqlx::query!(
-                        "UPDATE PackageVersion SET yanked = TRUE WHERE package = $1 AND version = $2",
+                        "UPDATE PackageVersion SET yanked = TRUE, yankReason = $3, indexUpdate = $4 WHERE package = $1 AND version = $2",
                         package,
-                        version
+                        version,
+                        reason,
+                        now
                     )
                     .execute(&mut *self.transaction.borrow().await)
                     .await?;
@@ -336,10 +577,12 @@ impl Database {
             )),
             Some(row) => {
                 if row.yanked {
+                    let now = Local::now().naive_local();
                     sqlx::query!(
-                        "UPDATE PackageVersion SET yanked = FALSE WHERE package = $1 AND version = $2",
+                        "UPDATE PackageVersion SET yanked = FALSE, yankReason = NULL, indexUpdate = $3 WHERE package = $1 AND version = $2",
                         package,
-                        version
+                        version,
+                        now
                     )
                     .execute(&mut *self.transaction.borrow().await)
                     .await?;
@@ -372,7 +615,8 @@ impl Database {
         {
             let mut stream = sqlx::query!(
                 "SELECT package, version, targets, nativeTargets AS nativetargets, capabilities
-                FROM PackageVersion INNER JOIN Package ON PackageVersion.package = Package.name"
+                FROM PackageVersion INNER JOIN Package ON PackageVersion.package = Package.name
+                WHERE isPublishing = FALSE"
             )
             .fetch(&mut *transaction);
             while let Some(Ok(row)) = stream.next().await {
@@ -569,7 +813,7 @@ impl Database {
             "SELECT package, version, isDeprecated AS is_deprecated, depsHasOutdated AS has_outdated, depsLastCheck AS last_check, targets
             FROM PackageVersion
             INNER JOIN Package ON PackageVersion.package = Package.name
-            WHERE yanked = FALSE"
+            WHERE yanked = FALSE AND isPublishing = FALSE"
         )
         .fetch(transaction);
         while let Some(row) = stream.next().await {
@@ -623,12 +867,12 @@ impl Database {
         &self,
         package: &str,
         version: &str,
-        has_outdated: bool,
-        has_cves: bool,
-    ) -> Result<(bool, bool), ApiError> {
+        flags: DepsAnalysisFlags,
+    ) -> Result<DepsAnalysisFlags, ApiError> {
         let now = Local::now().naive_local();
         let row = sqlx::query!(
-            "SELECT depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves
+            "SELECT depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves,
+                    depsHasYanked AS deps_has_yanked, depsHasDeprecated AS deps_has_deprecated
             FROM PackageVersion
             WHERE package = $1 AND version = $2
             LIMIT 1",
@@ -638,19 +882,24 @@ impl Database {
         .fetch_optional(&mut *self.transaction.borrow().await)
         .await?
         .ok_or_else(error_not_found)?;
-        let deps_has_outdated = row.deps_has_outdated;
-        let deps_has_cves = row.deps_has_cves;
         sqlx::query!(
-            "UPDATE PackageVersion SET depsLastCheck = $3, depsHasOutdated = $4, depsHasCVEs = $5 WHERE package = $1 AND version = $2",
+            "UPDATE PackageVersion SET depsLastCheck = $3, depsHasOutdated = $4, depsHasCVEs = $5, depsHasYanked = $6, depsHasDeprecated = $7 WHERE package = $1 AND version = $2",
             package,
             version,
             now,
-            has_outdated,
-            has_cves
+            flags.has_outdated,
+            flags.has_cves,
+            flags.has_yanked,
+            flags.has_deprecated
         )
         .execute(&mut *self.transaction.borrow().await)
         .await?;
-        Ok((deps_has_outdated, deps_has_cves))
+        Ok(DepsAnalysisFlags {
+            has_outdated: row.deps_has_outdated,
+            has_cves: row.deps_has_cves,
+            has_yanked: row.deps_has_yanked,
+            has_deprecated: row.deps_has_deprecated,
+        })
     }
 
     /// Increments the counter of downloads for a crate version
@@ -696,18 +945,37 @@ impl Database {
     pub async fn get_crate_owners(&self, package: &str) -> Result<OwnersQueryResult, ApiError> {
         let users = sqlx::query_as!(RegistryUser, "SELECT RegistryUser.id, isActive AS is_active, email, login, name, roles FROM RegistryUser INNER JOIN PackageOwner ON PackageOwner.owner = RegistryUser.id WHERE package = $1", package)
             .fetch_all(&mut *self.transaction.borrow().await).await?;
-        Ok(OwnersQueryResult { users })
+        let teams = self.get_crate_owner_teams(package).await?;
+        Ok(OwnersQueryResult { users, teams })
     }
 
     /// Add owners to a package
+    /// Teams are designated with the `team:` prefix
     pub async fn add_crate_owners(&self, package: &str, new_users: &[String]) -> Result<YesNoMsgResult, ApiError> {
         // get all current owners
         let rows = sqlx::query!("SELECT owner FROM PackageOwner WHERE package = $1", package,)
             .fetch_all(&mut *self.transaction.borrow().await)
             .await?;
+        let team_rows = sqlx::query!("SELECT team FROM PackageTeamOwner WHERE package = $1", package)
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
         // add new users
         let mut added = Vec::new();
         for new_user in new_users {
+            if let Some(team_name) = new_user.strip_prefix(TEAM_OWNER_PREFIX) {
+                let team_id = self.check_is_team(team_name).await?;
+                if team_rows.iter().all(|r| r.team != team_id) {
+                    sqlx::query!(
+                        "INSERT INTO PackageTeamOwner (package, team) VALUES ($1, $2)",
+                        package,
+                        team_id
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                    added.push(new_user.as_str());
+                }
+                continue;
+            }
             let new_uid = self.check_is_user(new_user).await?;
             if rows.iter().all(|r| r.owner != new_uid) {
                 // not already an owner
@@ -726,14 +994,37 @@ impl Database {
     }
 
     /// Remove owners from a package
+    /// Teams are designated with the `team:` prefix
     pub async fn remove_crate_owners(&self, package: &str, old_users: &[String]) -> Result<YesNoResult, ApiError> {
         // get all current owners
         let rows = sqlx::query!("SELECT owner FROM PackageOwner WHERE package = $1", package,)
             .fetch_all(&mut *self.transaction.borrow().await)
             .await?;
         let mut current_owners: Vec<i64> = rows.into_iter().map(|r| r.owner).collect();
+        let team_rows = sqlx::query!("SELECT team FROM PackageTeamOwner WHERE package = $1", package)
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        let mut current_teams: Vec<i64> = team_rows.into_iter().map(|r| r.team).collect();
         // remove old users
         for old_user in old_users {
+            if let Some(team_name) = old_user.strip_prefix(TEAM_OWNER_PREFIX) {
+                let team_id = self.check_is_team(team_name).await?;
+                if let Some(index) = current_teams.iter().position(|&x| x == team_id) {
+                    if current_owners.len() + current_teams.len() == 1 {
+                        // cannot remove the last one
+                        return Err(specialize(error_invalid_request(), String::from("Cannot remove all owners")));
+                    }
+                    sqlx::query!(
+                        "DELETE FROM PackageTeamOwner WHERE package = $1 AND team = $2",
+                        package,
+                        team_id
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                    current_teams.remove(index);
+                }
+                continue;
+            }
             let old_uid = self.check_is_user(old_user).await?;
             let index = current_owners
                 .iter()
@@ -741,7 +1032,7 @@ impl Database {
                 .find(|&(_, &x)| x == old_uid)
                 .map(|(i, _)| i);
             if let Some(index) = index {
-                if current_owners.len() == 1 {
+                if current_owners.len() + current_teams.len() == 1 {
                     // cannot remove the last one
                     return Err(specialize(error_invalid_request(), String::from("Cannot remove all owners")));
                 }
@@ -856,13 +1147,78 @@ impl Database {
     }
 
     /// Sets the deprecation status on a crate
-    pub async fn set_crate_deprecation(&self, package: &str, deprecated: bool) -> Result<(), ApiError> {
-        sqlx::query!("UPDATE Package SET isDeprecated = $2 WHERE name = $1", package, deprecated)
-            .execute(&mut *self.transaction.borrow().await)
-            .await?;
+    pub async fn set_crate_deprecation(&self, package: &str, deprecation: &CrateDeprecation) -> Result<(), ApiError> {
+        // the message and the successor are only kept for deprecated crates
+        let (message, use_instead) = if deprecation.is_deprecated {
+            (deprecation.message.as_deref(), deprecation.use_instead.as_deref())
+        } else {
+            (None, None)
+        };
+        sqlx::query!(
+            "UPDATE Package SET isDeprecated = $2, deprecationMessage = $3, deprecationUseInstead = $4 WHERE name = $1",
+            package,
+            deprecation.is_deprecated,
+            message,
+            use_instead
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
         Ok(())
     }
 
+    /// Completes an analysis of dependencies with the yank reasons and the deprecation of the local crates it depends on
+    pub async fn complete_deps_analysis(&self, analysis: &mut DepsAnalysis) -> Result<(), ApiError> {
+        for dep in analysis.direct_dependencies.iter_mut().filter(|dep| dep.registry.is_none()) {
+            if dep.is_yanked
+                && let Some(version) = &dep.resolved_version
+            {
+                dep.yank_reason = self.get_crate_yank_reason(&dep.package, version).await?;
+            }
+            dep.deprecation = self.get_crate_deprecation(&dep.package).await?;
+        }
+        for dep in analysis.yanked.iter_mut().filter(|dep| dep.registry.is_none()) {
+            dep.yank_reason = self.get_crate_yank_reason(&dep.package, &dep.version.to_string()).await?;
+        }
+        analysis.deprecated.clear();
+        for package in &analysis.local_dependencies {
+            if let Some(deprecation) = self.get_crate_deprecation(package).await? {
+                analysis.deprecated.push(DepDeprecated {
+                    package: package.clone(),
+                    deprecation,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the reason why a crate version was yanked, if any
+    async fn get_crate_yank_reason(&self, package: &str, version: &str) -> Result<Option<String>, ApiError> {
+        Ok(sqlx::query!(
+            "SELECT yankReason AS yank_reason FROM PackageVersion WHERE package = $1 AND version = $2 LIMIT 1",
+            package,
+            version
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .and_then(|row| row.yank_reason))
+    }
+
+    /// Gets the deprecation of a crate, if it is deprecated
+    async fn get_crate_deprecation(&self, package: &str) -> Result<Option<CrateDeprecation>, ApiError> {
+        Ok(sqlx::query!(
+            "SELECT isDeprecated AS is_deprecated, deprecationMessage AS message, deprecationUseInstead AS use_instead FROM Package WHERE name = $1 LIMIT 1",
+            package
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .filter(|row| row.is_deprecated)
+        .map(|row| CrateDeprecation {
+            is_deprecated: true,
+            message: row.message,
+            use_instead: row.use_instead,
+        }))
+    }
+
     /// Sets whether a crate can have versions completely removed
     pub async fn set_crate_can_remove(&self, package: &str, can_remove: bool) -> Result<(), ApiError> {
         sqlx::query!("UPDATE Package SET canRemove = $2 WHERE name = $1", package, can_remove)
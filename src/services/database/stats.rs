@@ -12,23 +12,33 @@ use crate::utils::apierror::ApiError;
 
 impl Database {
     /// Gets the global statistics for the registry
-    pub async fn get_crates_stats(&self) -> Result<GlobalStats, ApiError> {
-        let total_crates = sqlx::query!("SELECT COUNT(name) AS total_crates FROM Package")
-            .fetch_one(&mut *self.transaction.borrow().await)
-            .await?
-            .total_crates;
-        let total_downloads = sqlx::query!("SELECT SUM(downloadCount) AS total_downloads FROM PackageVersion")
-            .fetch_one(&mut *self.transaction.borrow().await)
-            .await?
-            .total_downloads
-            .unwrap_or_default();
+    /// The hidden crates are not accounted for
+    pub async fn get_crates_stats(&self, hidden: &[String]) -> Result<GlobalStats, ApiError> {
+        let hidden = serde_json::to_string(hidden)?;
+        let total_crates = sqlx::query!(
+            "SELECT COUNT(name) AS total_crates FROM Package WHERE name NOT IN (SELECT value FROM json_each($1))",
+            hidden
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .total_crates;
+        let total_downloads = sqlx::query!(
+            "SELECT SUM(downloadCount) AS total_downloads FROM PackageVersion WHERE package NOT IN (SELECT value FROM json_each($1))",
+            hidden
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .total_downloads
+        .unwrap_or_default();
 
         let rows = sqlx::query!(
             "SELECT name, version, upload
             FROM Package INNER JOIN PackageVersion ON package = name
             WHERE (SELECT COUNT(version) FROM PackageVersion WHERE package = name) = 1
+            AND name NOT IN (SELECT value FROM json_each($1))
             ORDER BY upload DESC
-            LIMIT 10"
+            LIMIT 10",
+            hidden
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await?;
@@ -43,9 +53,11 @@ impl Database {
         let rows = sqlx::query!(
             "SELECT name, SUM(downloadCount) AS download_count
             FROM Package INNER JOIN PackageVersion ON package = name
+            WHERE name NOT IN (SELECT value FROM json_each($1))
             GROUP BY package
             ORDER BY download_count DESC
-            LIMIT 10"
+            LIMIT 10",
+            hidden
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await?;
@@ -60,8 +72,10 @@ impl Database {
         let rows = sqlx::query!(
             "SELECT package, version, upload
             FROM PackageVersion
+            WHERE package NOT IN (SELECT value FROM json_each($1))
             ORDER BY upload DESC
-            LIMIT 10"
+            LIMIT 10",
+            hidden
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await?;
@@ -0,0 +1,254 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to teams of users
+
+use super::Database;
+use crate::model::teams::{Team, TeamMember, check_team_name};
+use crate::utils::apierror::{ApiError, error_conflict, error_invalid_request, error_not_found, specialize};
+
+impl Database {
+    /// Gets all the teams
+    pub async fn get_teams(&self) -> Result<Vec<Team>, ApiError> {
+        let rows = sqlx::query!("SELECT id, name FROM Team ORDER BY name")
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        let mut teams = Vec::with_capacity(rows.len());
+        for row in rows {
+            teams.push(Team {
+                id: row.id,
+                members: self.get_team_members(row.id).await?,
+                name: row.name,
+            });
+        }
+        Ok(teams)
+    }
+
+    /// Gets the members of a team
+    async fn get_team_members(&self, team_id: i64) -> Result<Vec<TeamMember>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT RegistryUser.id, email, name, isSynced AS is_synced FROM RegistryUser INNER JOIN TeamMember ON TeamMember.member = RegistryUser.id WHERE team = $1 ORDER BY email",
+            team_id
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| TeamMember {
+                id: row.id,
+                email: row.email,
+                name: row.name,
+                is_synced: row.is_synced,
+            })
+            .collect())
+    }
+
+    /// Gets the identifier of a team from its name
+    pub async fn check_is_team(&self, name: &str) -> Result<i64, ApiError> {
+        let row = sqlx::query!("SELECT id FROM Team WHERE name = $1 LIMIT 1", name)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .ok_or_else(|| specialize(error_not_found(), format!("team {name} does not exist")))?;
+        Ok(row.id)
+    }
+
+    /// Creates a new team
+    pub async fn create_team(&self, name: &str) -> Result<Team, ApiError> {
+        check_team_name(name)?;
+        if self.check_is_team(name).await.is_ok() {
+            return Err(specialize(error_conflict(), format!("team {name} already exists")));
+        }
+        let id = sqlx::query!("INSERT INTO Team (name) VALUES ($1) RETURNING id", name)
+            .fetch_one(&mut *self.transaction.borrow().await)
+            .await?
+            .id;
+        Ok(Team {
+            id,
+            name: name.to_string(),
+            members: Vec::new(),
+        })
+    }
+
+    /// Deletes a team
+    /// A team cannot be deleted while it is the only owner of a crate, or while it is used by a crate name policy
+    pub async fn delete_team(&self, name: &str) -> Result<(), ApiError> {
+        let team_id = self.check_is_team(name).await?;
+        let orphans = sqlx::query!(
+            "SELECT package FROM PackageTeamOwner WHERE team = $1
+            AND NOT EXISTS (SELECT id FROM PackageOwner WHERE PackageOwner.package = PackageTeamOwner.package)
+            AND NOT EXISTS (SELECT id FROM PackageTeamOwner AS Other WHERE Other.package = PackageTeamOwner.package AND Other.team != $1)",
+            team_id
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        if !orphans.is_empty() {
+            let packages = orphans.into_iter().map(|row| row.package).collect::<Vec<_>>();
+            return Err(specialize(
+                error_invalid_request(),
+                format!("team {name} is the only owner of crate(s) {}", packages.join(", ")),
+            ));
+        }
+        let policies = sqlx::query!("SELECT pattern FROM CrateNamePolicy WHERE team = $1", team_id)
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        if !policies.is_empty() {
+            let patterns = policies.into_iter().map(|row| row.pattern).collect::<Vec<_>>();
+            return Err(specialize(
+                error_invalid_request(),
+                format!("team {name} is used by the crate name policies for {}", patterns.join(", ")),
+            ));
+        }
+        sqlx::query!("DELETE FROM PackageTeamOwner WHERE team = $1", team_id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!("DELETE FROM PackageTeamReader WHERE team = $1", team_id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!("DELETE FROM TeamMember WHERE team = $1", team_id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!("DELETE FROM Team WHERE id = $1", team_id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Gets a team from its identifier
+    pub async fn get_team(&self, team_id: i64) -> Result<Team, ApiError> {
+        let row = sqlx::query!("SELECT name FROM Team WHERE id = $1 LIMIT 1", team_id)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .ok_or_else(error_not_found)?;
+        Ok(Team {
+            id: team_id,
+            name: row.name,
+            members: self.get_team_members(team_id).await?,
+        })
+    }
+
+    /// Renames a team
+    pub async fn rename_team(&self, team_id: i64, name: &str) -> Result<(), ApiError> {
+        check_team_name(name)?;
+        if self.check_is_team(name).await.is_ok_and(|id| id != team_id) {
+            return Err(specialize(error_conflict(), format!("team {name} already exists")));
+        }
+        sqlx::query!("UPDATE Team SET name = $2 WHERE id = $1", team_id, name)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Adds members to a team
+    /// Members that were synced from the identity provider become managed by hand
+    pub async fn add_team_members(&self, name: &str, emails: &[String]) -> Result<(), ApiError> {
+        let team_id = self.check_is_team(name).await?;
+        for email in emails {
+            let uid = self.check_is_user(email).await?;
+            self.add_team_member(team_id, uid, false).await?;
+        }
+        Ok(())
+    }
+
+    /// Adds a member to a team
+    /// When the user already is a member, a membership managed by hand remains so
+    pub async fn add_team_member(&self, team_id: i64, uid: i64, is_synced: bool) -> Result<(), ApiError> {
+        let row = sqlx::query!(
+            "SELECT id, isSynced AS is_synced FROM TeamMember WHERE team = $1 AND member = $2 LIMIT 1",
+            team_id,
+            uid
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        match row {
+            None => {
+                sqlx::query!(
+                    "INSERT INTO TeamMember (team, member, isSynced) VALUES ($1, $2, $3)",
+                    team_id,
+                    uid,
+                    is_synced
+                )
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+            }
+            Some(row) if row.is_synced && !is_synced => {
+                sqlx::query!("UPDATE TeamMember SET isSynced = FALSE WHERE id = $1", row.id)
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+            }
+            Some(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Removes a member from a team
+    pub async fn remove_team_member(&self, team_id: i64, uid: i64) -> Result<(), ApiError> {
+        sqlx::query!("DELETE FROM TeamMember WHERE team = $1 AND member = $2", team_id, uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes members from a team
+    pub async fn remove_team_members(&self, name: &str, emails: &[String]) -> Result<(), ApiError> {
+        let team_id = self.check_is_team(name).await?;
+        for email in emails {
+            let uid = self.check_is_user(email).await?;
+            self.remove_team_member(team_id, uid).await?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the membership of a user to the existing teams, from the teams given by the identity provider
+    /// Only the memberships previously synced are removed, the ones managed by hand are kept
+    pub async fn sync_user_teams(&self, uid: i64, team_names: &[&str]) -> Result<(), ApiError> {
+        let teams = sqlx::query!("SELECT id, name FROM Team")
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        let memberships = sqlx::query!("SELECT team, isSynced AS is_synced FROM TeamMember WHERE member = $1", uid)
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        for team in teams {
+            let expected = team_names.contains(&team.name.as_str());
+            let membership = memberships.iter().find(|m| m.team == team.id);
+            match membership {
+                None if expected => {
+                    sqlx::query!(
+                        "INSERT INTO TeamMember (team, member, isSynced) VALUES ($1, $2, TRUE)",
+                        team.id,
+                        uid
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                }
+                Some(membership) if membership.is_synced && !expected => {
+                    sqlx::query!("DELETE FROM TeamMember WHERE team = $1 AND member = $2", team.id, uid)
+                        .execute(&mut *self.transaction.borrow().await)
+                        .await?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the teams that own a package
+    pub async fn get_crate_owner_teams(&self, package: &str) -> Result<Vec<Team>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT Team.id, name FROM Team INNER JOIN PackageTeamOwner ON PackageTeamOwner.team = Team.id WHERE package = $1 ORDER BY name",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut teams = Vec::with_capacity(rows.len());
+        for row in rows {
+            teams.push(Team {
+                id: row.id,
+                members: self.get_team_members(row.id).await?,
+                name: row.name,
+            });
+        }
+        Ok(teams)
+    }
+}
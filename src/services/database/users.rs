@@ -7,12 +7,13 @@
 
 use std::future::Future;
 
-use chrono::Local;
+use chrono::{Local, NaiveDateTime};
 
 use super::Database;
 use crate::model::auth::{
-    Authentication, AuthenticationPrincipal, OAuthToken, ROLE_ADMIN, RegistryUserToken, RegistryUserTokenWithSecret, TokenKind,
-    TokenUsage, find_field_in_blob,
+    Authentication, AuthenticationPrincipal, OAuthLoginState, OAuthToken, ROLE_ADMIN, RegistryUserPublicKey, RegistryUserToken,
+    RegistryUserTokenWithSecret, TRUSTED_PUBLISHING_LOGIN_PREFIX, TokenExpiryNotice, TokenKind, TokenOperation, TokenScopes,
+    TokenUsage, TrustedPublisher, TrustedPublisherSpec, find_field_in_blob, find_fields_in_blob,
 };
 use crate::model::cargo::RegistryUser;
 use crate::model::config::Configuration;
@@ -20,6 +21,8 @@ use crate::model::namegen::generate_name;
 use crate::utils::apierror::{
     ApiError, error_conflict, error_forbidden, error_invalid_request, error_not_found, error_unauthorized, specialize,
 };
+use crate::utils::jwt::{Jwt, check_id_token_claims, load_jwks};
+use crate::utils::paseto::{PasetoClaims, PasetoToken, get_public_key_id, parse_public_key};
 use crate::utils::token::{check_hash, generate_token, hash_token};
 
 impl Database {
@@ -36,18 +39,30 @@ impl Database {
     }
 
     /// Attempts to login using an OAuth code
-    pub async fn login_with_oauth_code(&self, configuration: &Configuration, code: &str) -> Result<RegistryUser, ApiError> {
+    pub async fn login_with_oauth_code(
+        &self,
+        configuration: &Configuration,
+        code: &str,
+        login_state: &OAuthLoginState,
+    ) -> Result<RegistryUser, ApiError> {
         let client = reqwest::Client::new();
         // retrieve the token
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &configuration.oauth_callback_uri),
+            ("client_id", &configuration.oauth_client_id),
+        ];
+        if !configuration.oauth_client_secret.is_empty() {
+            // public clients only rely on PKCE
+            form.push(("client_secret", &configuration.oauth_client_secret));
+        }
+        if let Some(verifier) = &login_state.verifier {
+            form.push(("code_verifier", verifier));
+        }
         let response = client
             .post(&configuration.oauth_token_uri)
-            .form(&[
-                ("grant_type", "authorization_code"),
-                ("code", code),
-                ("redirect_uri", &configuration.oauth_callback_uri),
-                ("client_id", &configuration.oauth_client_id),
-                ("client_secret", &configuration.oauth_client_secret),
-            ])
+            .form(&form)
             .header(reqwest::header::ACCEPT, "application/json")
             .send()
             .await?;
@@ -57,18 +72,23 @@ impl Database {
         let body = response.bytes().await?;
         let token = serde_json::from_slice::<OAuthToken>(&body)?;
 
-        // retrieve the user profile
-        let response = client
-            .get(&configuration.oauth_userinfo_uri)
-            .header("authorization", format!("Bearer {}", token.access_token))
-            .send()
-            .await?;
-        if !response.status().is_success() {
-            return Err(specialize(error_unauthorized(), String::from("authentication failed")));
+        // retrieve the user profile, from the verified id token when possible
+        let user_info = get_oauth_user_info(configuration, &client, &token, login_state).await?;
+        let registry_user = self.resolve_oauth_user(configuration, &user_info).await?;
+        if let Some(path) = &configuration.oauth_userinfo_path_teams {
+            self.sync_user_teams(registry_user.id, &find_fields_in_blob(&user_info, path))
+                .await?;
         }
-        let body = response.bytes().await?;
-        let user_info = serde_json::from_slice::<serde_json::Value>(&body)?;
-        let email = find_field_in_blob(&user_info, &configuration.oauth_userinfo_path_email).ok_or_else(error_unauthorized)?;
+        Ok(registry_user)
+    }
+
+    /// Resolves the user that logged in from the information given by the identity provider, creating it if necessary
+    async fn resolve_oauth_user(
+        &self,
+        configuration: &Configuration,
+        user_info: &serde_json::Value,
+    ) -> Result<RegistryUser, ApiError> {
+        let email = find_field_in_blob(user_info, &configuration.oauth_userinfo_path_email).ok_or_else(error_unauthorized)?;
 
         // resolve the user
         let row = sqlx::query!(
@@ -92,23 +112,32 @@ impl Database {
             });
         }
         // create the user
+        let full_name = find_field_in_blob(user_info, &configuration.oauth_userinfo_path_fullname);
+        self.create_user(email, full_name, true).await
+    }
+
+    /// Creates a new user
+    /// The first user of the registry is an administrator
+    pub async fn create_user(&self, email: &str, full_name: Option<&str>, is_active: bool) -> Result<RegistryUser, ApiError> {
         let count = sqlx::query!("SELECT COUNT(id) AS count FROM RegistryUser")
             .fetch_one(&mut *self.transaction.borrow().await)
             .await?
             .count;
-        let mut login = email[..email.find('@').unwrap()].to_string();
-        while sqlx::query!("SELECT COUNT(id) AS count FROM RegistryUser WHERE login = $1", login)
-            .fetch_one(&mut *self.transaction.borrow().await)
-            .await?
-            .count
-            != 0
+        let mut login = email.split('@').next().unwrap_or_default().to_string();
+        while login.is_empty()
+            || sqlx::query!("SELECT COUNT(id) AS count FROM RegistryUser WHERE login = $1", login)
+                .fetch_one(&mut *self.transaction.borrow().await)
+                .await?
+                .count
+                != 0
         {
             login = generate_name();
         }
-        let full_name = find_field_in_blob(&user_info, &configuration.oauth_userinfo_path_fullname).unwrap_or(&login);
+        let full_name = full_name.unwrap_or(&login);
         let roles = if count == 0 { ROLE_ADMIN } else { "" };
         let id = sqlx::query!(
-            "INSERT INTO RegistryUser (isActive, email, login, name, roles) VALUES (TRUE, $1, $2, $3, $4) RETURNING id",
+            "INSERT INTO RegistryUser (isActive, email, login, name, roles) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+            is_active,
             email,
             login,
             full_name,
@@ -119,9 +148,9 @@ impl Database {
         .id;
         Ok(RegistryUser {
             id,
-            is_active: true,
+            is_active,
             email: email.to_string(),
-            name: login.clone(),
+            name: full_name.to_string(),
             login,
             roles: roles.to_string(),
         })
@@ -139,9 +168,10 @@ impl Database {
     }
 
     /// Updates the information of a user
+    /// The principal is `None` when the update is provisioned by the identity provider
     pub async fn update_user(
         &self,
-        principal_uid: i64,
+        principal_uid: Option<i64>,
         target: &RegistryUser,
         can_admin: bool,
     ) -> Result<RegistryUser, ApiError> {
@@ -154,7 +184,7 @@ impl Database {
             // not admin and changing roles
             return Err(specialize(error_forbidden(), String::from("only admins can change roles")));
         }
-        if can_admin && target.id == principal_uid && target.roles.split(',').all(|role| role.trim() != ROLE_ADMIN) {
+        if can_admin && Some(target.id) == principal_uid && target.roles.split(',').all(|role| role.trim() != ROLE_ADMIN) {
             // admin and removing admin role from self
             return Err(specialize(error_forbidden(), String::from("admins cannot remove themselves")));
         }
@@ -188,15 +218,29 @@ impl Database {
     }
 
     /// Attempts to deactivate a user
-    pub async fn deactivate_user(&self, principal_uid: i64, target: &str) -> Result<(), ApiError> {
+    /// All the tokens of the user are revoked, as well as the trusted publishers the user configured.
+    /// The principal is `None` when the user is deprovisioned by the identity provider
+    pub async fn deactivate_user(&self, principal_uid: Option<i64>, target: &str) -> Result<(), ApiError> {
         let target_uid = self.check_is_user(target).await?;
-        if principal_uid == target_uid {
+        if principal_uid == Some(target_uid) {
             // cannot deactivate self
             return Err(specialize(error_forbidden(), String::from("cannot self deactivate")));
         }
         sqlx::query!("UPDATE RegistryUser SET isActive = FALSE WHERE id = $1", target_uid)
             .execute(&mut *self.transaction.borrow().await)
             .await?;
+        sqlx::query!("DELETE FROM RegistryUserToken WHERE user = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!(
+            "DELETE FROM TrustedPublishingToken WHERE publisher IN (SELECT id FROM TrustedPublisher WHERE createdBy = $1)",
+            target_uid
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        sqlx::query!("DELETE FROM TrustedPublisher WHERE createdBy = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
         Ok(())
     }
 
@@ -209,21 +253,40 @@ impl Database {
     }
 
     /// Attempts to delete a user
-    pub async fn delete_user(&self, principal_uid: i64, target: &str) -> Result<(), ApiError> {
+    /// The principal is `None` when the user is deprovisioned by the identity provider
+    pub async fn delete_user(&self, principal_uid: Option<i64>, target: &str) -> Result<(), ApiError> {
         let target_uid = sqlx::query!("SELECT id FROM RegistryUser WHERE email = $1", target)
             .fetch_optional(&mut *self.transaction.borrow().await)
             .await?
             .ok_or_else(error_not_found)?
             .id;
-        if principal_uid == target_uid {
+        if principal_uid == Some(target_uid) {
             return Err(specialize(error_forbidden(), String::from("cannot delete self")));
         }
         sqlx::query!("DELETE FROM RegistryUserToken WHERE user = $1", target_uid)
             .execute(&mut *self.transaction.borrow().await)
             .await?;
+        sqlx::query!("DELETE FROM RegistryUserPublicKey WHERE user = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!(
+            "DELETE FROM TrustedPublishingToken WHERE publisher IN (SELECT id FROM TrustedPublisher WHERE createdBy = $1)",
+            target_uid
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        sqlx::query!("DELETE FROM TrustedPublisher WHERE createdBy = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
         sqlx::query!("DELETE FROM PackageOwner WHERE owner = $1", target_uid)
             .execute(&mut *self.transaction.borrow().await)
             .await?;
+        sqlx::query!("DELETE FROM PackageReader WHERE reader = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!("DELETE FROM TeamMember WHERE member = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
         sqlx::query!("DELETE FROM RegistryUser WHERE id = $1", target_uid)
             .execute(&mut *self.transaction.borrow().await)
             .await?;
@@ -233,21 +296,24 @@ impl Database {
     /// Gets the tokens for a user
     pub async fn get_tokens(&self, uid: i64) -> Result<Vec<RegistryUserToken>, ApiError> {
         let rows = sqlx::query!(
-            "SELECT id, name, lastUsed AS last_used, canWrite AS can_write, canAdmin AS can_admin FROM RegistryUserToken WHERE user = $1 ORDER BY id",
+            "SELECT id, name, lastUsed AS last_used, expiresOn AS expires_on, canWrite AS can_write, canAdmin AS can_admin, crateScopes AS crate_scopes, operationScopes AS operation_scopes FROM RegistryUserToken WHERE user = $1 ORDER BY id",
             uid
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await?;
-        Ok(rows
-            .into_iter()
-            .map(|row| RegistryUserToken {
-                id: row.id,
-                name: row.name,
-                last_used: row.last_used,
-                can_write: row.can_write,
-                can_admin: row.can_admin,
+        rows.into_iter()
+            .map(|row| {
+                Ok(RegistryUserToken {
+                    id: row.id,
+                    name: row.name,
+                    last_used: row.last_used,
+                    expires_on: row.expires_on,
+                    can_write: row.can_write,
+                    can_admin: row.can_admin,
+                    scopes: TokenScopes::parse(&row.crate_scopes, &row.operation_scopes)?,
+                })
             })
-            .collect())
+            .collect()
     }
 
     /// Creates a token for the current user
@@ -257,18 +323,25 @@ impl Database {
         name: &str,
         can_write: bool,
         can_admin: bool,
+        scopes: TokenScopes,
+        expires_on: Option<NaiveDateTime>,
     ) -> Result<RegistryUserTokenWithSecret, ApiError> {
         let token_secret = generate_token(64);
         let token_hash = hash_token(&token_secret);
         let now = Local::now().naive_local();
+        let crate_scopes = scopes.crates_to_string();
+        let operation_scopes = scopes.operations_to_string();
         let id = sqlx::query!(
-            "INSERT INTO RegistryUserToken (user, name, token, lastUsed, canWrite, canAdmin) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+            "INSERT INTO RegistryUserToken (user, name, token, lastUsed, canWrite, canAdmin, crateScopes, operationScopes, expiresOn, expiryNotified) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, FALSE) RETURNING id",
             uid,
             name,
             token_hash,
             now,
             can_write,
-            can_admin
+            can_admin,
+            crate_scopes,
+            operation_scopes,
+            expires_on
         )
         .fetch_one(&mut *self.transaction.borrow().await)
         .await?
@@ -278,8 +351,10 @@ impl Database {
             name: name.to_string(),
             secret: token_secret,
             last_used: now,
+            expires_on,
             can_write,
             can_admin,
+            scopes,
         })
     }
 
@@ -291,6 +366,102 @@ impl Database {
         Ok(())
     }
 
+    /// Gets the public keys for a user
+    pub async fn get_public_keys(&self, uid: i64) -> Result<Vec<RegistryUserPublicKey>, ApiError> {
+        let rows = sqlx::query_as!(
+            RegistryUserPublicKey,
+            "SELECT id, name, keyId AS key_id, publicKey AS public_key, lastUsed AS last_used FROM RegistryUserPublicKey WHERE user = $1 ORDER BY id",
+            uid
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Registers a public key for the current user
+    pub async fn add_public_key(&self, uid: i64, name: &str, public_key: &str) -> Result<RegistryUserPublicKey, ApiError> {
+        parse_public_key(public_key)?;
+        let public_key = public_key.trim();
+        let key_id = get_public_key_id(public_key);
+        let existing = sqlx::query!("SELECT id FROM RegistryUserPublicKey WHERE keyId = $1 LIMIT 1", key_id)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?;
+        if existing.is_some() {
+            return Err(specialize(
+                error_conflict(),
+                String::from("this public key is already registered"),
+            ));
+        }
+        let now = Local::now().naive_local();
+        let id = sqlx::query!(
+            "INSERT INTO RegistryUserPublicKey (user, name, keyId, publicKey, lastUsed) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+            uid,
+            name,
+            key_id,
+            public_key,
+            now
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(RegistryUserPublicKey {
+            id,
+            name: name.to_string(),
+            key_id,
+            public_key: public_key.to_string(),
+            last_used: now,
+        })
+    }
+
+    /// Removes a public key for the current user
+    pub async fn remove_public_key(&self, uid: i64, key_id: i64) -> Result<(), ApiError> {
+        sqlx::query!("DELETE FROM RegistryUserPublicKey WHERE user = $1 AND id = $2", uid, key_id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Checks an authentication request with an asymmetric token, using the public key with the given identifier
+    /// Returns the authentication and the verified claims
+    pub async fn check_paseto_token<F, FUT>(
+        &self,
+        token: &PasetoToken,
+        key_id: &str,
+        on_usage: &F,
+    ) -> Result<(Authentication, PasetoClaims), ApiError>
+    where
+        F: Fn(TokenUsage) -> FUT + Sync,
+        FUT: Future<Output = ()>,
+    {
+        let row = sqlx::query!(
+            "SELECT RegistryUser.id AS uid, email, RegistryUserPublicKey.id, publicKey AS public_key
+            FROM RegistryUser INNER JOIN RegistryUserPublicKey ON RegistryUser.id = RegistryUserPublicKey.user
+            WHERE isActive = TRUE AND keyId = $1 LIMIT 1",
+            key_id
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .ok_or_else(error_unauthorized)?;
+        let claims = token.verify(&row.public_key)?;
+        on_usage(TokenUsage {
+            kind: TokenKind::PublicKey,
+            token_id: row.id,
+            timestamp: Local::now().naive_local(),
+        })
+        .await;
+        let authentication = Authentication {
+            principal: AuthenticationPrincipal::User {
+                uid: row.uid,
+                email: row.email,
+            },
+            can_write: true,
+            can_admin: false,
+            scopes: TokenScopes::unrestricted(),
+            token_id: Some(format!("key:{}", row.id)),
+        };
+        Ok((authentication, claims))
+    }
+
     /// Checks an authentication request with a token
     pub async fn check_token<F, FUT>(&self, login: &str, token_secret: &str, on_usage: &F) -> Result<Authentication, ApiError>
     where
@@ -300,6 +471,9 @@ impl Database {
         if let Some(auth) = self.check_token_global(login, token_secret, &on_usage).await? {
             return Ok(auth);
         }
+        if let Some(auth) = self.check_token_trusted_publishing(login, token_secret).await? {
+            return Ok(auth);
+        }
         if let Some(auth) = self.check_token_user(login, token_secret, &on_usage).await? {
             return Ok(auth);
         }
@@ -318,7 +492,8 @@ impl Database {
         FUT: Future<Output = ()>,
     {
         let rows = sqlx::query!(
-            "SELECT RegistryUser.id AS uid, email, RegistryUserToken.id, token, canWrite AS can_write, canAdmin AS can_admin
+            "SELECT RegistryUser.id AS uid, email, RegistryUserToken.id, token, canWrite AS can_write, canAdmin AS can_admin,
+                crateScopes AS crate_scopes, operationScopes AS operation_scopes, expiresOn AS expires_on
             FROM RegistryUser INNER JOIN RegistryUserToken ON RegistryUser.id = RegistryUserToken.user
             WHERE isActive = TRUE AND login = $1",
             login
@@ -328,6 +503,7 @@ impl Database {
         for row in rows {
             if check_hash(token_secret, &row.token).is_ok() {
                 let now = Local::now().naive_local();
+                check_token_not_expired(row.expires_on, now)?;
                 on_usage(TokenUsage {
                     kind: TokenKind::User,
                     token_id: row.id,
@@ -341,6 +517,8 @@ impl Database {
                     },
                     can_write: row.can_write,
                     can_admin: row.can_admin,
+                    scopes: TokenScopes::parse(&row.crate_scopes, &row.operation_scopes)?,
+                    token_id: Some(format!("token:{}", row.id)),
                 }));
             }
         }
@@ -358,24 +536,176 @@ impl Database {
         F: Fn(TokenUsage) -> FUT + Sync,
         FUT: Future<Output = ()>,
     {
-        let row = sqlx::query!("SELECT id, token FROM RegistryGlobalToken WHERE name = $1 LIMIT 1", login)
-            .fetch_optional(&mut *self.transaction.borrow().await)
-            .await?;
+        let row = sqlx::query!(
+            "SELECT id, token, expiresOn AS expires_on FROM RegistryGlobalToken WHERE name = $1 LIMIT 1",
+            login
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
         let Some(row) = row else { return Ok(None) };
         if check_hash(token_secret, &row.token).is_ok() {
             let now = Local::now().naive_local();
+            check_token_not_expired(row.expires_on, now)?;
             on_usage(TokenUsage {
                 kind: TokenKind::Registry,
                 token_id: row.id,
                 timestamp: now,
             })
             .await;
-            Ok(Some(Authentication::new_service(login.to_string())))
+            Ok(Some(Authentication {
+                token_id: Some(format!("global:{}", row.id)),
+                ..Authentication::new_service(login.to_string())
+            }))
         } else {
             Ok(None)
         }
     }
 
+    /// Checks whether the information provided is for a short-lived token obtained by a trusted publisher
+    async fn check_token_trusted_publishing(
+        &self,
+        login: &str,
+        token_secret: &str,
+    ) -> Result<Option<Authentication>, ApiError> {
+        let Some(token_id) = login
+            .strip_prefix(TRUSTED_PUBLISHING_LOGIN_PREFIX)
+            .and_then(|id| id.parse::<i64>().ok())
+        else {
+            return Ok(None);
+        };
+        let row = sqlx::query!(
+            "SELECT TrustedPublisher.id AS publisher_id, package, createdBy AS created_by, token, expiresOn AS expires_on
+            FROM TrustedPublishingToken INNER JOIN TrustedPublisher ON TrustedPublishingToken.publisher = TrustedPublisher.id
+            INNER JOIN RegistryUser ON TrustedPublisher.createdBy = RegistryUser.id
+            WHERE RegistryUser.isActive = TRUE AND TrustedPublishingToken.id = $1 LIMIT 1",
+            token_id
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        let Some(row) = row else { return Ok(None) };
+        if check_hash(token_secret, &row.token).is_err() {
+            return Ok(None);
+        }
+        check_token_not_expired(Some(row.expires_on), Local::now().naive_local())?;
+        Ok(Some(Authentication {
+            principal: AuthenticationPrincipal::TrustedPublisher {
+                publisher_id: row.publisher_id,
+                uid: row.created_by,
+            },
+            can_write: true,
+            can_admin: false,
+            scopes: TokenScopes {
+                crates: vec![row.package],
+                operations: vec![TokenOperation::PublishUpdate],
+            },
+            token_id: Some(format!("trusted:{token_id}")),
+        }))
+    }
+
+    /// Gets the trusted publishers for a crate
+    pub async fn get_trusted_publishers(&self, package: &str) -> Result<Vec<TrustedPublisher>, ApiError> {
+        let rows = sqlx::query_as!(
+            TrustedPublisher,
+            "SELECT id, package, issuer, jwks, repository, branch, workflow, createdBy AS created_by FROM TrustedPublisher WHERE package = $1 ORDER BY id",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Gets the trusted publishers for a given issuer of OIDC tokens
+    pub async fn get_trusted_publishers_for_issuer(&self, issuer: &str) -> Result<Vec<TrustedPublisher>, ApiError> {
+        let rows = sqlx::query_as!(
+            TrustedPublisher,
+            "SELECT id, package, issuer, jwks, repository, branch, workflow, createdBy AS created_by FROM TrustedPublisher WHERE issuer = $1 ORDER BY id",
+            issuer
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Adds a trusted publisher for a crate
+    pub async fn add_trusted_publisher(
+        &self,
+        uid: i64,
+        package: &str,
+        spec: &TrustedPublisherSpec,
+    ) -> Result<TrustedPublisher, ApiError> {
+        let id = sqlx::query!(
+            "INSERT INTO TrustedPublisher (package, issuer, jwks, repository, branch, workflow, createdBy) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+            package,
+            spec.issuer,
+            spec.jwks,
+            spec.repository,
+            spec.branch,
+            spec.workflow,
+            uid
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(TrustedPublisher {
+            id,
+            package: package.to_string(),
+            issuer: spec.issuer.clone(),
+            jwks: spec.jwks.clone(),
+            repository: spec.repository.clone(),
+            branch: spec.branch.clone(),
+            workflow: spec.workflow.clone(),
+            created_by: uid,
+        })
+    }
+
+    /// Removes a trusted publisher for a crate, along with the tokens it obtained
+    pub async fn remove_trusted_publisher(&self, package: &str, publisher_id: i64) -> Result<(), ApiError> {
+        sqlx::query!(
+            "DELETE FROM TrustedPublishingToken WHERE publisher IN (SELECT id FROM TrustedPublisher WHERE package = $1 AND id = $2)",
+            package,
+            publisher_id
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM TrustedPublisher WHERE package = $1 AND id = $2",
+            package,
+            publisher_id
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Creates a short-lived token for a trusted publisher
+    /// Returns the login and the secret for the token
+    pub async fn create_trusted_publishing_token(
+        &self,
+        publisher_id: i64,
+        expires_on: NaiveDateTime,
+    ) -> Result<(String, String), ApiError> {
+        let token_secret = generate_token(64);
+        let token_hash = hash_token(&token_secret);
+        let id = sqlx::query!(
+            "INSERT INTO TrustedPublishingToken (publisher, token, expiresOn) VALUES ($1, $2, $3) RETURNING id",
+            publisher_id,
+            token_hash,
+            expires_on
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok((format!("{TRUSTED_PUBLISHING_LOGIN_PREFIX}{id}"), token_secret))
+    }
+
+    /// Deletes the expired short-lived tokens for trusted publishers
+    pub async fn delete_expired_trusted_publishing_tokens(&self, now: NaiveDateTime) -> Result<u64, ApiError> {
+        let result = sqlx::query!("DELETE FROM TrustedPublishingToken WHERE expiresOn <= $1", now)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
     /// Updates the last usage of a token
     pub async fn update_token_last_usage(&self, event: &TokenUsage) -> Result<(), ApiError> {
         if event.kind == TokenKind::User {
@@ -394,7 +724,112 @@ impl Database {
             )
             .execute(&mut *self.transaction.borrow().await)
             .await?;
+        } else if event.kind == TokenKind::PublicKey {
+            sqlx::query!(
+                "UPDATE RegistryUserPublicKey SET lastUsed = $2 WHERE id = $1",
+                event.token_id,
+                event.timestamp
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
         }
         Ok(())
     }
+
+    /// Gets the user tokens that will expire before the given limit and for which the owner was not yet notified
+    pub async fn get_expiring_tokens(&self, limit: NaiveDateTime) -> Result<Vec<TokenExpiryNotice>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT RegistryUserToken.id, RegistryUserToken.name, expiresOn AS expires_on, email
+            FROM RegistryUserToken INNER JOIN RegistryUser ON RegistryUser.id = RegistryUserToken.user
+            WHERE isActive = TRUE AND expiryNotified = FALSE AND expiresOn IS NOT NULL AND expiresOn <= $1",
+            limit
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(TokenExpiryNotice {
+                    kind: TokenKind::User,
+                    token_id: row.id,
+                    name: row.name,
+                    expires_on: row.expires_on?,
+                    recipients: vec![row.email],
+                })
+            })
+            .collect())
+    }
+
+    /// Marks that the owners of a token were notified about its upcoming expiry
+    pub async fn set_token_expiry_notified(&self, notice: &TokenExpiryNotice) -> Result<(), ApiError> {
+        if notice.kind == TokenKind::User {
+            sqlx::query!(
+                "UPDATE RegistryUserToken SET expiryNotified = TRUE WHERE id = $1",
+                notice.token_id
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        } else if notice.kind == TokenKind::Registry {
+            sqlx::query!(
+                "UPDATE RegistryGlobalToken SET expiryNotified = TRUE WHERE id = $1",
+                notice.token_id
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the user tokens that have expired and returns the number of deleted tokens
+    pub async fn delete_expired_tokens(&self, now: NaiveDateTime) -> Result<u64, ApiError> {
+        let result = sqlx::query!(
+            "DELETE FROM RegistryUserToken WHERE expiresOn IS NOT NULL AND expiresOn <= $1",
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// Checks that a token is not expired
+fn check_token_not_expired(expires_on: Option<NaiveDateTime>, now: NaiveDateTime) -> Result<(), ApiError> {
+    if expires_on.is_some_and(|expires_on| expires_on <= now) {
+        Err(specialize(error_unauthorized(), String::from("the token has expired")))
+    } else {
+        Ok(())
+    }
+}
+
+/// Gets the information about a user that logged in, from the verified id token when possible, or from the user info endpoint
+async fn get_oauth_user_info(
+    configuration: &Configuration,
+    client: &reqwest::Client,
+    token: &OAuthToken,
+    login_state: &OAuthLoginState,
+) -> Result<serde_json::Value, ApiError> {
+    if let (Some(id_token), Some(issuer), Some(jwks_uri)) =
+        (&token.id_token, &configuration.oauth_issuer, &configuration.oauth_jwks_uri)
+    {
+        let jwks = load_jwks(jwks_uri).await?;
+        let claims = Jwt::parse(id_token)?.verify(&jwks)?;
+        check_id_token_claims(
+            &claims,
+            issuer,
+            &configuration.oauth_client_id,
+            &login_state.nonce,
+            Local::now().timestamp(),
+        )?;
+        return Ok(claims);
+    }
+    let response = client
+        .get(&configuration.oauth_userinfo_uri)
+        .header("authorization", format!("Bearer {}", token.access_token))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(specialize(error_unauthorized(), String::from("authentication failed")));
+    }
+    let body = response.bytes().await?;
+    Ok(serde_json::from_slice::<serde_json::Value>(&body)?)
 }
@@ -0,0 +1,140 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the visibility of crates
+
+use super::Database;
+use crate::model::cargo::RegistryUser;
+use crate::model::packages::{CrateReaders, CrateVisibility};
+use crate::model::teams::{TEAM_OWNER_PREFIX, Team};
+use crate::utils::apierror::{ApiError, error_not_found};
+
+impl Database {
+    /// Gets the visibility of a crate
+    pub async fn get_crate_visibility(&self, package: &str) -> Result<CrateVisibility, ApiError> {
+        let row = sqlx::query!("SELECT visibility FROM Package WHERE name = $1 LIMIT 1", package)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .ok_or_else(error_not_found)?;
+        row.visibility.parse()
+    }
+
+    /// Sets the visibility of a crate
+    pub async fn set_crate_visibility(&self, package: &str, visibility: CrateVisibility) -> Result<(), ApiError> {
+        let visibility = visibility.as_str();
+        sqlx::query!("UPDATE Package SET visibility = $2 WHERE name = $1", package, visibility)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Gets the restricted crates that a user cannot read
+    /// All the restricted crates are returned when there is no user
+    pub async fn get_hidden_crates(&self, uid: Option<i64>) -> Result<Vec<String>, ApiError> {
+        let rows = sqlx::query!(
+            "SELECT name FROM Package WHERE visibility = 'restricted'
+            AND NOT EXISTS (SELECT id FROM PackageOwner WHERE package = Package.name AND owner = $1)
+            AND NOT EXISTS (SELECT id FROM PackageReader WHERE package = Package.name AND reader = $1)
+            AND NOT EXISTS (SELECT PackageTeamOwner.id FROM PackageTeamOwner INNER JOIN TeamMember ON TeamMember.team = PackageTeamOwner.team WHERE package = Package.name AND member = $1)
+            AND NOT EXISTS (SELECT PackageTeamReader.id FROM PackageTeamReader INNER JOIN TeamMember ON TeamMember.team = PackageTeamReader.team WHERE package = Package.name AND member = $1)",
+            uid
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows.into_iter().map(|row| row.name).collect())
+    }
+
+    /// Gets the readers of a crate, in addition to its owners
+    pub async fn get_crate_readers(&self, package: &str) -> Result<CrateReaders, ApiError> {
+        let users = sqlx::query_as!(
+            RegistryUser,
+            "SELECT RegistryUser.id, isActive AS is_active, email, login, name, roles FROM RegistryUser INNER JOIN PackageReader ON PackageReader.reader = RegistryUser.id WHERE package = $1",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let rows = sqlx::query!(
+            "SELECT Team.id, name FROM Team INNER JOIN PackageTeamReader ON PackageTeamReader.team = Team.id WHERE package = $1 ORDER BY name",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let teams = rows
+            .into_iter()
+            .map(|row| Team {
+                id: row.id,
+                name: row.name,
+                members: Vec::new(),
+            })
+            .collect();
+        Ok(CrateReaders { users, teams })
+    }
+
+    /// Adds readers to a crate
+    /// Teams are designated with the `team:` prefix
+    pub async fn add_crate_readers(&self, package: &str, readers: &[String]) -> Result<(), ApiError> {
+        for reader in readers {
+            if let Some(team_name) = reader.strip_prefix(TEAM_OWNER_PREFIX) {
+                let team_id = self.check_is_team(team_name).await?;
+                let exists = sqlx::query!(
+                    "SELECT id FROM PackageTeamReader WHERE package = $1 AND team = $2 LIMIT 1",
+                    package,
+                    team_id
+                )
+                .fetch_optional(&mut *self.transaction.borrow().await)
+                .await?
+                .is_some();
+                if !exists {
+                    sqlx::query!(
+                        "INSERT INTO PackageTeamReader (package, team) VALUES ($1, $2)",
+                        package,
+                        team_id
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                }
+            } else {
+                let uid = self.check_is_user(reader).await?;
+                let exists = sqlx::query!(
+                    "SELECT id FROM PackageReader WHERE package = $1 AND reader = $2 LIMIT 1",
+                    package,
+                    uid
+                )
+                .fetch_optional(&mut *self.transaction.borrow().await)
+                .await?
+                .is_some();
+                if !exists {
+                    sqlx::query!("INSERT INTO PackageReader (package, reader) VALUES ($1, $2)", package, uid)
+                        .execute(&mut *self.transaction.borrow().await)
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes readers from a crate
+    /// Teams are designated with the `team:` prefix
+    pub async fn remove_crate_readers(&self, package: &str, readers: &[String]) -> Result<(), ApiError> {
+        for reader in readers {
+            if let Some(team_name) = reader.strip_prefix(TEAM_OWNER_PREFIX) {
+                let team_id = self.check_is_team(team_name).await?;
+                sqlx::query!(
+                    "DELETE FROM PackageTeamReader WHERE package = $1 AND team = $2",
+                    package,
+                    team_id
+                )
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+            } else {
+                let uid = self.check_is_user(reader).await?;
+                sqlx::query!("DELETE FROM PackageReader WHERE package = $1 AND reader = $2", package, uid)
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -4,7 +4,7 @@
 
 //! Service to fetch data about dependency crates
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
@@ -22,7 +22,8 @@ use tokio::io::AsyncBufReadExt;
 use crate::model::cargo::{IndexCrateDependency, IndexCrateMetadata};
 use crate::model::config::{Configuration, ExternalRegistryProtocol};
 use crate::model::deps::{
-    BUILTIN_CRATES_REGISTRY_URI, DepAdvisory, DepsAnalysis, DepsAnalysisJobSpec, DepsGraph, DepsGraphCrateOrigin,
+    BUILTIN_CRATES_REGISTRY_URI, CRATES_IO_REGISTRY_URI, DepAdvisory, DepsAnalysis, DepsAnalysisJobSpec, DepsGraph,
+    DepsGraphCrateOrigin, get_crates_io_dependencies,
 };
 use crate::services::database::{db_transaction_read, db_transaction_write};
 use crate::services::emails::EmailSender;
@@ -130,99 +131,127 @@ async fn deps_worker_job_on_crate_version(
     job: &DepsAnalysisJobSpec,
 ) -> Result<(), ApiError> {
     info!("checking deps for {} {}", job.package, job.version);
-    let analysis = service_deps_checker
+    let mut analysis = service_deps_checker
         .check_crate(&job.package, &job.version, &job.targets)
         .await?;
-    let has_outdated = analysis.direct_dependencies.iter().any(|info| info.is_outdated);
-    let has_cves = !analysis.advisories.is_empty();
-    let (old_has_outdated, old_has_cves) = db_transaction_write(pool, "set_crate_deps_analysis", |database| async move {
-        database
-            .set_crate_deps_analysis(&job.package, &job.version, has_outdated, has_cves)
-            .await
+    db_transaction_read(pool, |database| {
+        let analysis = &mut analysis;
+        async move { database.complete_deps_analysis(analysis).await }
     })
     .await?;
-    if (has_outdated != old_has_outdated && configuration.deps_notify_outdated)
-        || (has_cves != old_has_cves && configuration.deps_notify_cves)
-    {
-        // must send some notification
-        let owners = db_transaction_read(pool, |database| async move { database.get_crate_owners(&job.package).await }).await?;
-        let owners = owners.users.into_iter().map(|owner| owner.email).collect::<Vec<_>>();
-        if has_outdated != old_has_outdated {
-            // new outdated dependencies ...
-            let mut body = String::new();
-            writeln!(
-                body,
-                "New outdated dependencies have been found for {} {}",
-                job.package, job.version
-            )
-            .unwrap();
-            writeln!(
-                body,
-                "See {}/crates/{}/{}",
-                configuration.web_public_uri, job.package, job.version
-            )
-            .unwrap();
-            writeln!(body).unwrap();
-            for dep in &analysis.direct_dependencies {
-                if dep.is_outdated {
-                    writeln!(
-                        body,
-                        "- {}, required {}, latest is {}",
-                        dep.package, dep.required, dep.last_version
-                    )
-                    .unwrap();
-                }
-            }
-            service_email_sender
-                .send_email(
-                    &owners,
-                    &format!("Cratery - outdated dependencies for {} {}", job.package, job.version),
+    let flags = analysis.flags();
+    let old_flags = db_transaction_write(pool, "set_crate_deps_analysis", |database| async move {
+        database.set_crate_deps_analysis(&job.package, &job.version, flags).await
+    })
+    .await?;
+    let notify_outdated = flags.has_outdated != old_flags.has_outdated && configuration.deps_notify_outdated;
+    let notify_cves = flags.has_cves != old_flags.has_cves && configuration.deps_notify_cves;
+    // only notify when yanked or deprecated dependencies appear
+    let notify_yanked = flags.has_yanked && !old_flags.has_yanked && configuration.deps_notify_yanked;
+    let notify_deprecated = flags.has_deprecated && !old_flags.has_deprecated && configuration.deps_notify_deprecated;
+    let mut notifications = Vec::new();
+    if notify_outdated {
+        // new outdated dependencies ...
+        let mut body = build_notification_body(configuration, job, "New outdated dependencies have been found");
+        for dep in &analysis.direct_dependencies {
+            if dep.is_outdated {
+                writeln!(
                     body,
+                    "- {}, required {}, latest is {}",
+                    dep.package, dep.required, dep.last_version
                 )
-                .await?;
+                .unwrap();
+            }
         }
-        if has_cves != old_has_cves {
-            // new CVEs ...
-            let mut body = String::new();
-            writeln!(
-                body,
-                "New vulnerable dependencies have been found for {} {}",
-                job.package, job.version
-            )
-            .unwrap();
+        notifications.push(("outdated", body));
+    }
+    if notify_cves {
+        // new CVEs ...
+        let mut body = build_notification_body(configuration, job, "New vulnerable dependencies have been found");
+        for adv in &analysis.advisories {
             writeln!(
                 body,
-                "See {}/crates/{}/{}",
-                configuration.web_public_uri, job.package, job.version
+                "- {} resolved version {} is vulnerable to CVE https://rustsec.org/advisories/{}.html",
+                adv.package, adv.version, adv.content.id
             )
             .unwrap();
-            writeln!(body).unwrap();
-            for adv in &analysis.advisories {
-                writeln!(
-                    body,
-                    "- {} resolved version {} is vulnerable to CVE https://rustsec.org/advisories/{}.html",
-                    adv.package, adv.version, adv.content.id
-                )
-                .unwrap();
-                writeln!(body, "  => {}", adv.content.summary).unwrap();
+            writeln!(body, "  => {}", adv.content.summary).unwrap();
+        }
+        notifications.push(("vulnerable", body));
+    }
+    if notify_yanked {
+        // new yanked dependencies ...
+        let mut body = build_notification_body(
+            configuration,
+            job,
+            "Dependencies resolving to yanked versions have been found",
+        );
+        for dep in &analysis.yanked {
+            writeln!(body, "- {} resolved version {} is yanked", dep.package, dep.version).unwrap();
+            if let Some(reason) = &dep.yank_reason {
+                writeln!(body, "  => {reason}").unwrap();
             }
-            service_email_sender
-                .send_email(
-                    &owners,
-                    &format!("Cratery - vulnerable dependencies for {} {}", job.package, job.version),
-                    body,
-                )
-                .await?;
         }
+        notifications.push(("yanked", body));
+    }
+    if notify_deprecated {
+        // new deprecated dependencies ...
+        let mut body = build_notification_body(configuration, job, "Dependencies on deprecated crates have been found");
+        for dep in &analysis.deprecated {
+            writeln!(body, "- {} is deprecated", dep.package).unwrap();
+            if let Some(message) = &dep.deprecation.message {
+                writeln!(body, "  => {message}").unwrap();
+            }
+            if let Some(use_instead) = &dep.deprecation.use_instead {
+                writeln!(body, "  => use {use_instead} instead").unwrap();
+            }
+        }
+        notifications.push(("deprecated", body));
+    }
+    if notifications.is_empty() {
+        return Ok(());
+    }
+
+    // must send some notification
+    let owners = db_transaction_read(pool, |database| async move { database.get_crate_owners(&job.package).await }).await?;
+    let owners = owners.users.into_iter().map(|owner| owner.email).collect::<Vec<_>>();
+    for (kind, body) in notifications {
+        service_email_sender
+            .send_email(
+                &owners,
+                &format!("Cratery - {kind} dependencies for {} {}", job.package, job.version),
+                body,
+            )
+            .await?;
     }
     Ok(())
 }
 
+/// Builds the beginning of the body for a notification about the dependencies of a crate version
+fn build_notification_body(configuration: &Configuration, job: &DepsAnalysisJobSpec, headline: &str) -> String {
+    let mut body = String::new();
+    writeln!(body, "{headline} for {} {}", job.package, job.version).unwrap();
+    writeln!(
+        body,
+        "See {}/crates/{}/{}",
+        configuration.web_public_uri, job.package, job.version
+    )
+    .unwrap();
+    writeln!(body).unwrap();
+    body
+}
+
 /// Service to check the dependencies of a crate
 pub trait DepsChecker {
     /// Ensures that a local cache for crates.io exists
     fn precache_crate_io(&self) -> FaillibleFuture<'_, ()>;
 
+    /// Gets the names of the crates.io crates that are dependencies of local crates, as known from the index and the analyses
+    fn get_crates_io_dependencies(&self) -> FaillibleFuture<'_, Vec<String>>;
+
+    /// Adds the crates.io crates that are direct dependencies of a newly published version
+    fn add_crates_io_dependencies<'a>(&'a self, version: &'a IndexCrateMetadata) -> FaillibleFuture<'a, ()>;
+
     /// Checks the dependencies of a local crate
     fn check_crate<'a>(
         &'a self,
@@ -251,6 +280,10 @@ pub fn get_service(
 struct DepsCheckerData {
     /// The last time a piece of data was touched
     last_touch: HashMap<String, Instant>,
+    /// The names of the crates.io crates resolved as dependencies
+    crates_io_dependencies: HashSet<String>,
+    /// Whether the direct crates.io dependencies of the local crates were loaded from the index
+    crates_io_dependencies_loaded: bool,
 }
 
 /// Service to check the dependencies of a crate
@@ -265,8 +298,6 @@ struct DepsCheckerImpl {
     service_rustsec: Arc<dyn RustSecChecker + Send + Sync>,
 }
 
-/// The URI identifying crates.io as the registry for a dependency
-const CRATES_IO_REGISTRY_URI: &str = "https://github.com/rust-lang/crates.io-index";
 /// The prefixes URI for the index for dependencies on crates.io
 const _CRATES_IO_INDEX_SPARSE_URI: &str = "https://index.crates.io/";
 /// Registry name for crates.io
@@ -280,6 +311,23 @@ impl DepsChecker for DepsCheckerImpl {
         Box::pin(async move { self.do_precache_crate_io().await })
     }
 
+    /// Gets the names of the crates.io crates that are dependencies of local crates, as known from the index and the analyses
+    fn get_crates_io_dependencies(&self) -> FaillibleFuture<'_, Vec<String>> {
+        Box::pin(async move { self.do_get_crates_io_dependencies().await })
+    }
+
+    /// Adds the crates.io crates that are direct dependencies of a newly published version
+    fn add_crates_io_dependencies<'a>(&'a self, version: &'a IndexCrateMetadata) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move {
+            self.data
+                .lock()
+                .await
+                .crates_io_dependencies
+                .extend(get_crates_io_dependencies(version).map(str::to_string));
+            Ok(())
+        })
+    }
+
     /// Checks the dependencies of a local crate
     fn check_crate<'a>(
         &'a self,
@@ -299,6 +347,19 @@ impl DepsCheckerImpl {
         Ok(())
     }
 
+    /// Gets the names of the crates.io crates that are dependencies of local crates
+    /// The direct dependencies are first loaded from the index, then completed by the analyses
+    async fn do_get_crates_io_dependencies(&self) -> Result<Vec<String>, ApiError> {
+        if !self.data.lock().await.crates_io_dependencies_loaded {
+            let versions = self.service_index.get_all_crates_data().await?;
+            let mut data = self.data.lock().await;
+            data.crates_io_dependencies
+                .extend(versions.iter().flat_map(get_crates_io_dependencies).map(str::to_string));
+            data.crates_io_dependencies_loaded = true;
+        }
+        Ok(self.data.lock().await.crates_io_dependencies.iter().cloned().collect())
+    }
+
     /// Checks the dependencies of a local crate
     async fn do_check_crate(&self, package: &str, version: &str, targets: &[String]) -> Result<DepsAnalysis, ApiError> {
         let metadata = self.service_index.get_crate_data(package).await?;
@@ -365,8 +426,11 @@ impl DepsCheckerImpl {
                     &self.configuration.self_toolchain_version_stable,
                 ))
             } else if registry == CRATES_IO_REGISTRY_URI {
-                self.get_dependency_info_git(name, CRATES_IO_NAME, CRATES_IO_REGISTRY_URI)
-                    .await
+                let versions = self
+                    .get_dependency_info_git(name, CRATES_IO_NAME, CRATES_IO_REGISTRY_URI)
+                    .await?;
+                self.data.lock().await.crates_io_dependencies.insert(name.to_string());
+                Ok(versions)
             } else if let Some(registry) = self
                 .configuration
                 .external_registries
@@ -247,7 +247,9 @@ impl DocsGeneratorImpl {
         self.update_job(job, DocGenJobState::Working, None).await?;
         worker.sender().send(JobSpecification::DocGen(job.clone())).await?;
         while let Some(update) = worker.update_receiver().recv().await {
-            let JobUpdate::DocGen(update) = update;
+            let JobUpdate::DocGen(update) = update else {
+                continue;
+            };
             self.update_job(job, update.state, update.log.as_deref()).await?;
             if update.state.is_final() {
                 return Ok(());
@@ -0,0 +1,260 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Implementation of an index generated from the database, only for the sparse protocol
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use chrono::{Local, Utc};
+
+use super::{Index, IndexFile, build_package_file_path};
+use crate::model::CrateVersion;
+use crate::model::cargo::IndexCrateMetadata;
+use crate::model::config::IndexConfig;
+use crate::model::index::IndexPushStatus;
+use crate::services::database::{db_transaction_read, db_transaction_write};
+use crate::services::storage::Storage;
+use crate::utils::FaillibleFuture;
+use crate::utils::apierror::{ApiError, error_not_found, specialize};
+use crate::utils::db::RwSqlitePool;
+
+/// An index file for a crate, as generated from the database
+struct CrateIndexFile {
+    /// The data for all the versions of the crate
+    metadata: Vec<IndexCrateMetadata>,
+    /// The file, one line per version
+    file: IndexFile,
+}
+
+/// Manages an index generated from the database
+/// The generated files are cached in memory until the crate changes
+pub struct DbIndex {
+    /// The file for the index configuration
+    config_file: IndexFile,
+    /// The database pool
+    service_db_pool: RwSqlitePool,
+    /// The storage layer, used to recover the data for versions published without it in the database
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    /// The cache of generated files, by lowercase crate name
+    cache: RwLock<HashMap<String, Arc<CrateIndexFile>>>,
+    /// Incremented each time the cache is invalidated, so that files generated in the meantime are not cached
+    generation: AtomicU64,
+}
+
+impl DbIndex {
+    /// Creates the index
+    pub fn new(
+        config: &IndexConfig,
+        service_db_pool: RwSqlitePool,
+        service_storage: Arc<dyn Storage + Send + Sync>,
+    ) -> Result<Self, ApiError> {
+        let config_file = IndexFile::new(serde_json::to_vec(&config.public)?, Utc::now());
+        Ok(Self {
+            config_file,
+            service_db_pool,
+            service_storage,
+            cache: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    /// Gets the index file for a crate, if it has any version
+    async fn get_file(&self, lowercase: &str) -> Result<Option<Arc<CrateIndexFile>>, ApiError> {
+        if let Some(file) = self.cache.read().unwrap().get(lowercase).cloned() {
+            return Ok(Some(file));
+        }
+        let generation = self.generation.load(Ordering::Acquire);
+        let (versions, last_update, is_publishing) = db_transaction_read(&self.service_db_pool, |database| async move {
+            let versions = database.get_crate_index_data(lowercase).await?;
+            let last_update = database.get_crate_index_last_update(lowercase).await?;
+            let is_publishing = database.get_crate_is_publishing(lowercase).await?;
+            Ok::<_, ApiError>((versions, last_update, is_publishing))
+        })
+        .await?;
+        if versions.is_empty() {
+            return Ok(None);
+        }
+        let mut metadata = Vec::with_capacity(versions.len());
+        for (version, yanked, data) in versions {
+            let mut data = match data {
+                Some(data) => data,
+                None => self.recover_index_data(&version).await?,
+            };
+            data.yanked = yanked;
+            metadata.push(data);
+        }
+        let mut content = Vec::new();
+        for data in &metadata {
+            serde_json::to_writer(&mut content, data)?;
+            content.push(0x0A); // add line end
+        }
+        // the content only changes on a publication, yank or unyank, which all record their time
+        let last_modified = last_update
+            .and_then(|date| date.and_local_timezone(Local).earliest())
+            .map_or_else(Utc::now, |date| date.with_timezone(&Utc));
+        let file = Arc::new(CrateIndexFile {
+            metadata,
+            file: IndexFile::new(content, last_modified),
+        });
+        // a file generated while a version is being published would miss it once the publication is completed
+        let mut cache = self.cache.write().unwrap();
+        if !is_publishing && self.generation.load(Ordering::Acquire) == generation {
+            cache.insert(lowercase.to_string(), file.clone());
+        }
+        drop(cache);
+        Ok(Some(file))
+    }
+
+    /// Recovers the data for the index of a version from the storage and records it in the database
+    async fn recover_index_data(&self, version: &CrateVersion) -> Result<IndexCrateMetadata, ApiError> {
+        let metadata = self
+            .service_storage
+            .download_crate_metadata(&version.package, &version.version)
+            .await?
+            .ok_or_else(|| {
+                specialize(
+                    error_not_found(),
+                    format!("missing metadata for {} {}", version.package, version.version),
+                )
+            })?;
+        let content = self
+            .service_storage
+            .download_crate(&version.package, &version.version)
+            .await?;
+        let data = IndexCrateMetadata::new(&metadata, &content);
+        db_transaction_write(&self.service_db_pool, "set_crate_version_index_data", |database| {
+            let data = &data;
+            async move {
+                database
+                    .set_crate_version_index_data(&version.package, &version.version, data)
+                    .await
+            }
+        })
+        .await?;
+        Ok(data)
+    }
+
+    /// Invalidates the cached file for a crate
+    fn invalidate(&self, package: &str) {
+        let mut cache = self.cache.write().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        cache.remove(&package.to_ascii_lowercase());
+    }
+}
+
+/// Gets the lowercase name of the crate for the path to its index file, if the path is valid
+fn get_package_for_path(file_path: &Path) -> Option<&str> {
+    let name = file_path.file_name()?.to_str()?;
+    if name.is_empty() || build_package_file_path(PathBuf::from("/"), name) != file_path {
+        return None;
+    }
+    Some(name)
+}
+
+impl Index for DbIndex {
+    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<IndexFile>> {
+        Box::pin(async move {
+            if file_path == Path::new("/config.json") {
+                return Ok(Some(self.config_file.clone()));
+            }
+            let Some(lowercase) = get_package_for_path(file_path) else {
+                return Ok(None);
+            };
+            let file = self.get_file(lowercase).await?;
+            Ok(file.map(|file| file.file.clone()))
+        })
+    }
+
+    fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>> {
+        Box::pin(async move { Err(error_git_not_supported()) })
+    }
+
+    fn get_upload_pack_for<'a>(&'a self, _input: &'a [u8]) -> FaillibleFuture<'a, Vec<u8>> {
+        Box::pin(async move { Err(error_git_not_supported()) })
+    }
+
+    fn publish_crate_version<'a>(&'a self, metadata: &'a IndexCrateMetadata) -> FaillibleFuture<'a, ()> {
+        // the data is recorded in the database along with the version, which becomes visible when the publication is completed
+        // the files generated until then are not cached, so that only the current one has to be dropped
+        Box::pin(async move {
+            self.invalidate(&metadata.name);
+            Ok(())
+        })
+    }
+
+    fn remove_crate_version<'a>(&'a self, package: &'a str, _version: &'a str) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move {
+            self.invalidate(package);
+            Ok(())
+        })
+    }
+
+    fn set_crate_version_yanked<'a>(&'a self, package: &'a str, _version: &'a str, _yanked: bool) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move {
+            self.invalidate(package);
+            Ok(())
+        })
+    }
+
+    fn get_crate_data<'a>(&'a self, package: &'a str) -> FaillibleFuture<'a, Vec<IndexCrateMetadata>> {
+        Box::pin(async move {
+            let file = self
+                .get_file(&package.to_ascii_lowercase())
+                .await?
+                .ok_or_else(|| specialize(error_not_found(), format!("package {package} is not in this registry")))?;
+            Ok(file.metadata.clone())
+        })
+    }
+
+    fn get_all_crates_data(&self) -> FaillibleFuture<'_, Vec<IndexCrateMetadata>> {
+        Box::pin(async move {
+            let versions = db_transaction_read(&self.service_db_pool, |database| async move {
+                database.get_crates_index_data().await
+            })
+            .await?;
+            let mut packages = versions
+                .into_iter()
+                .map(|(version, _, _)| version.package.to_ascii_lowercase())
+                .collect::<Vec<_>>();
+            packages.dedup();
+            let mut results = Vec::new();
+            for lowercase in packages {
+                if let Some(file) = self.get_file(&lowercase).await? {
+                    results.extend(file.metadata.iter().cloned());
+                }
+            }
+            Ok(results)
+        })
+    }
+
+    fn rebuild<'a>(&'a self, _versions: &'a [IndexCrateMetadata]) -> FaillibleFuture<'a, ()> {
+        // the data is recorded in the database beforehand, only drop all the generated files
+        Box::pin(async move {
+            let mut cache = self.cache.write().unwrap();
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            cache.clear();
+            drop(cache);
+            Ok(())
+        })
+    }
+
+    fn get_push_status(&self) -> Option<IndexPushStatus> {
+        None
+    }
+
+    fn wait_for_pushes(&self) -> FaillibleFuture<'_, ()> {
+        Box::pin(async move { Ok(()) })
+    }
+}
+
+/// The error when using the git protocol on this index
+fn error_git_not_supported() -> ApiError {
+    specialize(
+        error_not_found(),
+        String::from("the git protocol is not supported by an index generated from the database"),
+    )
+}
@@ -5,36 +5,58 @@
 //! Implementation of an index using a local git repository
 
 use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
 
-use log::{error, info};
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use log::{error, info, warn};
 use tokio::fs::{File, OpenOptions, create_dir_all};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 
-use super::{Index, build_package_file_path};
+use super::{Index, IndexFile, build_package_file_path};
 use crate::model::cargo::IndexCrateMetadata;
 use crate::model::config::IndexConfig;
+use crate::model::index::IndexPushStatus;
 use crate::utils::apierror::{ApiError, error_backend_failure, error_not_found, specialize};
 use crate::utils::{FaillibleFuture, execute_at_location, execute_git};
 
+/// The delay before retrying a failed push, doubled after each failure
+const PUSH_RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+/// The maximum delay between retries of a failed push
+const PUSH_RETRY_MAX_DELAY: Duration = Duration::from_mins(5);
+
 /// Manages the index on git
 pub struct GitIndex {
     inner: Mutex<GitIndexImpl>,
+    /// The pusher of the commits to the remote origin, if enabled
+    pusher: Option<Arc<GitIndexPusher>>,
 }
 
 impl GitIndex {
     /// When the application is launched
     pub async fn new(config: IndexConfig, expect_empty: bool) -> Result<Self, ApiError> {
         let inner = GitIndexImpl::new(config, expect_empty).await?;
+        let pusher = inner.pusher.clone();
         Ok(Self {
             inner: Mutex::new(inner),
+            pusher,
         })
     }
 }
 
 impl Index for GitIndex {
-    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<PathBuf>> {
-        Box::pin(async move { Ok(self.inner.lock().await.get_index_file(file_path)) })
+    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<IndexFile>> {
+        Box::pin(async move {
+            let Some(full_path) = self.inner.lock().await.get_index_file(file_path) else {
+                return Ok(None);
+            };
+            // the files are small enough for their hash to be computed on each read
+            let content = tokio::fs::read(&full_path).await?;
+            let last_modified = tokio::fs::metadata(&full_path).await?.modified()?;
+            Ok(Some(IndexFile::new(content, DateTime::<Utc>::from(last_modified))))
+        })
     }
 
     fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>> {
@@ -53,21 +75,177 @@ impl Index for GitIndex {
         Box::pin(async move { self.inner.lock().await.remove_crate_version(package, version).await })
     }
 
+    fn set_crate_version_yanked<'a>(&'a self, package: &'a str, version: &'a str, yanked: bool) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move {
+            self.inner
+                .lock()
+                .await
+                .set_crate_version_yanked(package, version, yanked)
+                .await
+        })
+    }
+
     fn get_crate_data<'a>(&'a self, package: &'a str) -> FaillibleFuture<'a, Vec<IndexCrateMetadata>> {
         Box::pin(async move { self.inner.lock().await.get_crate_data(package).await })
     }
+
+    fn get_all_crates_data(&self) -> FaillibleFuture<'_, Vec<IndexCrateMetadata>> {
+        Box::pin(async move { self.inner.lock().await.get_all_crates_data().await })
+    }
+
+    fn rebuild<'a>(&'a self, versions: &'a [IndexCrateMetadata]) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.inner.lock().await.rebuild(versions).await })
+    }
+
+    fn get_push_status(&self) -> Option<IndexPushStatus> {
+        self.pusher.as_ref().map(|pusher| pusher.get_status())
+    }
+
+    fn wait_for_pushes(&self) -> FaillibleFuture<'_, ()> {
+        Box::pin(async move {
+            match &self.pusher {
+                Some(pusher) => pusher.wait_for_pushes().await,
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+/// Pushes the commits to the remote origin in the background, so that a slow or unreachable remote does not block the index
+/// The commits made while pushing are coalesced into the next push
+struct GitIndexPusher {
+    /// The status of the pushes
+    status: std::sync::Mutex<IndexPushStatus>,
+    /// Notified when there are new commits to push
+    notify: Notify,
+    /// Notified after each attempt to push
+    pushed: Notify,
+}
+
+impl GitIndexPusher {
+    /// Creates the pusher and launches its background task
+    fn launch(location: PathBuf) -> Arc<Self> {
+        let pusher = Arc::new(Self {
+            status: std::sync::Mutex::new(IndexPushStatus::default()),
+            notify: Notify::new(),
+            pushed: Notify::new(),
+        });
+        let _handle = tokio::spawn({
+            let pusher = pusher.clone();
+            async move {
+                pusher.worker(&location).await;
+            }
+        });
+        pusher
+    }
+
+    /// Gets the current status
+    fn get_status(&self) -> IndexPushStatus {
+        let mut status = self.status.lock().unwrap().clone();
+        status.lag = status
+            .pending_since
+            .map_or(0, |since| (Local::now().naive_local() - since).num_seconds());
+        status
+    }
+
+    /// Records a new commit to push
+    fn on_commit(&self) {
+        self.on_commits(1);
+    }
+
+    /// Records new commits to push
+    fn on_commits(&self, count: usize) {
+        let mut status = self.status.lock().unwrap();
+        if status.pending_commits == 0 {
+            status.pending_since = Some(Local::now().naive_local());
+        }
+        status.pending_commits += count;
+        drop(status);
+        self.notify.notify_one();
+    }
+
+    /// Implementation of the worker, pushes the commits as they come
+    async fn worker(&self, location: &Path) -> ! {
+        loop {
+            self.notify.notified().await;
+            let mut delay = PUSH_RETRY_MIN_DELAY;
+            loop {
+                let pushing = self.status.lock().unwrap().pending_commits;
+                if pushing == 0 {
+                    break;
+                }
+                let started = Local::now().naive_local();
+                let result = execute_git(location, &["push", "origin", "master"]).await;
+                let failed = self.on_push_result(result, pushing, started);
+                self.pushed.notify_waiters();
+                if failed {
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(PUSH_RETRY_MAX_DELAY);
+                } else {
+                    delay = PUSH_RETRY_MIN_DELAY;
+                }
+            }
+        }
+    }
+
+    /// Waits until all the commits are pushed, fails as soon as an attempt to push fails
+    async fn wait_for_pushes(&self) -> Result<(), ApiError> {
+        loop {
+            // register before looking at the status, so that no attempt is missed
+            let mut pushed = pin!(self.pushed.notified());
+            pushed.as_mut().enable();
+            if self.status.lock().unwrap().pending_commits == 0 {
+                return Ok(());
+            }
+            pushed.await;
+            let last_error = self.status.lock().unwrap().last_error.clone();
+            if let Some(message) = last_error {
+                return Err(specialize(
+                    error_backend_failure(),
+                    format!("failed to push to origin: {message}"),
+                ));
+            }
+        }
+    }
+
+    /// Updates the status after an attempt to push, returns whether the attempt failed
+    fn on_push_result(&self, result: Result<(), ApiError>, pushed: usize, started: NaiveDateTime) -> bool {
+        let mut status = self.status.lock().unwrap();
+        match result {
+            Ok(()) => {
+                status.pending_commits -= pushed;
+                // the remaining commits were made while pushing
+                status.pending_since = if status.pending_commits == 0 { None } else { Some(started) };
+                status.last_push = Some(Local::now().naive_local());
+                status.last_error = None;
+                status.failures = 0;
+                false
+            }
+            Err(error) => {
+                let message = error.details.unwrap_or(error.message);
+                warn!("index: failed to push to origin: {message}");
+                status.last_error = Some(message);
+                status.failures += 1;
+                true
+            }
+        }
+    }
 }
 
 /// Manages the index on git
 struct GitIndexImpl {
     /// The configuration
     config: IndexConfig,
+    /// The pusher of the commits to the remote origin, if enabled
+    pusher: Option<Arc<GitIndexPusher>>,
 }
 
 impl GitIndexImpl {
     /// When the application is launched
     async fn new(config: IndexConfig, expect_empty: bool) -> Result<Self, ApiError> {
-        let index = Self { config };
+        let pusher = (config.remote_origin.is_some() && config.remote_push_changes)
+            .then(|| GitIndexPusher::launch(PathBuf::from(&config.location)));
+        let index = Self { config, pusher };
 
         // check for the SSH key
         if let Some(file_name) = &index.config.remote_ssh_key_file_name {
@@ -97,8 +275,12 @@ impl GitIndexImpl {
             // attempt to pull changes
             info!("index: pulling changes from origin");
             execute_git(&location, &["pull", "origin", "master"]).await?;
+            if let Some(pusher) = &index.pusher {
+                resume_pushes(&location, pusher).await?;
+            }
         }
         index.configure_user(&location).await?;
+        index.update_configuration(&location).await?;
         Ok(index)
     }
 
@@ -167,6 +349,22 @@ impl GitIndexImpl {
         Ok(())
     }
 
+    /// Updates the index configuration when it differs from the expected one
+    async fn update_configuration(&self, location: &Path) -> Result<(), ApiError> {
+        let index_config = serde_json::to_vec(&self.config.public)?;
+        let mut file_name = location.to_path_buf();
+        file_name.push("config.json");
+        if tokio::fs::read(&file_name).await.ok().as_ref() == Some(&index_config) {
+            return Ok(());
+        }
+        info!("index: updating the configuration");
+        let mut file = File::create(&file_name).await?;
+        file.write_all(&index_config).await?;
+        file.flush().await?;
+        file.sync_all().await?;
+        self.commit_changes("Update configuration").await
+    }
+
     /// Configures the git user
     async fn configure_user(&self, location: &Path) -> Result<(), ApiError> {
         execute_git(location, &["config", "user.name", &self.config.user_name]).await?;
@@ -204,8 +402,17 @@ impl GitIndexImpl {
     }
 
     /// Publish a new version for a crate
+    /// Nothing is done when the version is already in the index, so that an interrupted publication can be completed
     async fn publish_crate_version(&self, metadata: &IndexCrateMetadata) -> Result<(), ApiError> {
         let file_name = build_package_file_path(PathBuf::from(&self.config.location), &metadata.name);
+        if file_name.exists() && read_crate_versions(&file_name).await?.iter().any(|v| v.vers == metadata.vers) {
+            // the version may have been written without being committed
+            if self.has_changes().await? {
+                self.commit_changes(&format!("Publish {}:{}", &metadata.name, &metadata.vers))
+                    .await?;
+            }
+            return Ok(());
+        }
         create_dir_all(file_name.parent().unwrap()).await?;
         let buffer = serde_json::to_vec(metadata)?;
         // write to package file
@@ -224,46 +431,54 @@ impl GitIndexImpl {
     /// Completely removes a version from the registry
     async fn remove_crate_version(&self, package: &str, version: &str) -> Result<(), ApiError> {
         let file_name = build_package_file_path(PathBuf::from(&self.config.location), package);
-        create_dir_all(file_name.parent().unwrap()).await?;
-        // get the existing versions
-        let mut versions = {
-            // expect the file to be present
-            let file = OpenOptions::new().read(true).open(&file_name).await?;
-            let reader = BufReader::new(file);
-            let mut lines = reader.lines();
-            let mut versions = Vec::new();
-            while let Some(line) = lines.next_line().await? {
-                versions.push(serde_json::from_str::<IndexCrateMetadata>(&line)?);
-            }
-            versions
-        };
+        let mut versions = read_crate_versions(&file_name).await?;
         // remove the version of interest
         versions.retain(|v| v.vers != version);
-        // write back
-        {
-            let mut file = OpenOptions::new().write(true).truncate(true).open(file_name).await?;
-            for version in versions {
-                let buffer = serde_json::to_vec(&version)?;
-                file.write_all(&buffer).await?;
-                file.write_all(&[0x0A]).await?; // add line end
-            }
-            file.flush().await?;
-            file.sync_all().await?;
-        }
+        write_crate_versions(&file_name, &versions).await?;
         // commit and update
         let message = format!("Removed {package}:{version}");
         self.commit_changes(&message).await?;
         Ok(())
     }
 
+    /// Sets whether a crate version is yanked
+    async fn set_crate_version_yanked(&self, package: &str, version: &str, yanked: bool) -> Result<(), ApiError> {
+        let file_name = build_package_file_path(PathBuf::from(&self.config.location), package);
+        let mut versions = read_crate_versions(&file_name).await?;
+        let mut changed = false;
+        for data in versions.iter_mut().filter(|v| v.vers == version && v.yanked != yanked) {
+            data.yanked = yanked;
+            changed = true;
+        }
+        if !changed {
+            return Ok(());
+        }
+        write_crate_versions(&file_name, &versions).await?;
+        // commit and update
+        let message = if yanked {
+            format!("Yanked {package}:{version}")
+        } else {
+            format!("Unyanked {package}:{version}")
+        };
+        self.commit_changes(&message).await?;
+        Ok(())
+    }
+
+    /// Gets whether there are local changes to commit
+    async fn has_changes(&self) -> Result<bool, ApiError> {
+        let location = PathBuf::from(&self.config.location);
+        let status = execute_at_location(&location, "git", &["status", "--porcelain"], &[]).await?;
+        Ok(!status.is_empty())
+    }
+
     /// Commits the local changes to the index
     async fn commit_changes(&self, message: &str) -> Result<(), ApiError> {
         let location = PathBuf::from(&self.config.location);
         execute_git(&location, &["add", "."]).await?;
         execute_git(&location, &["commit", "-m", message]).await?;
         execute_git(&location, &["update-server-info"]).await?;
-        if let (Some(_), true) = (self.config.remote_origin.as_ref(), self.config.remote_push_changes) {
-            execute_git(&location, &["push", "origin", "master"]).await?;
+        if let Some(pusher) = &self.pusher {
+            pusher.on_commit();
         }
         Ok(())
     }
@@ -286,4 +501,91 @@ impl GitIndexImpl {
         }
         Ok(results)
     }
+
+    /// Gets the data for all the versions of all the crates in the index
+    async fn get_all_crates_data(&self) -> Result<Vec<IndexCrateMetadata>, ApiError> {
+        let mut results = Vec::new();
+        for file_name in list_crate_files(Path::new(&self.config.location)).await? {
+            results.append(&mut read_crate_versions(&file_name).await?);
+        }
+        Ok(results)
+    }
+
+    /// Rebuilds the index with the data for all the versions of all the crates, grouped by crate
+    async fn rebuild(&self, versions: &[IndexCrateMetadata]) -> Result<(), ApiError> {
+        let location = PathBuf::from(&self.config.location);
+        for file_name in list_crate_files(&location).await? {
+            tokio::fs::remove_file(&file_name).await?;
+        }
+        for versions in versions.chunk_by(|a, b| a.name.eq_ignore_ascii_case(&b.name)) {
+            let file_name = build_package_file_path(location.clone(), &versions[0].name);
+            create_dir_all(file_name.parent().unwrap()).await?;
+            File::create(&file_name).await?;
+            write_crate_versions(&file_name, versions).await?;
+        }
+        if !self.has_changes().await? {
+            // the index was already up to date
+            return Ok(());
+        }
+        info!("index: rebuilt from the database");
+        self.commit_changes("Rebuild the index").await
+    }
+}
+
+/// Schedules the push of the commits that were not pushed to the remote origin before the last shutdown
+async fn resume_pushes(location: &Path, pusher: &GitIndexPusher) -> Result<(), ApiError> {
+    let output = execute_at_location(location, "git", &["rev-list", "--count", "origin/master..master"], &[]).await?;
+    let count = String::from_utf8_lossy(&output).trim().parse::<usize>().unwrap_or_default();
+    if count > 0 {
+        info!("index: {count} commits are not pushed to origin yet");
+        pusher.on_commits(count);
+    }
+    Ok(())
+}
+
+/// Lists the index files for all the crates in the index
+async fn list_crate_files(location: &Path) -> Result<Vec<PathBuf>, ApiError> {
+    let mut results = Vec::new();
+    let mut directories = vec![location.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let mut entries = tokio::fs::read_dir(&directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if directory == location && (entry.file_name() == ".git" || entry.file_name() == "config.json") {
+                continue;
+            }
+            if entry.file_type().await?.is_dir() {
+                directories.push(path);
+            } else {
+                results.push(path);
+            }
+        }
+    }
+    results.sort();
+    Ok(results)
+}
+
+/// Reads the versions in the index file for a crate, the file is expected to be present
+async fn read_crate_versions(file_name: &Path) -> Result<Vec<IndexCrateMetadata>, ApiError> {
+    let file = OpenOptions::new().read(true).open(file_name).await?;
+    let reader = BufReader::new(file);
+    let mut lines = reader.lines();
+    let mut versions = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        versions.push(serde_json::from_str::<IndexCrateMetadata>(&line)?);
+    }
+    Ok(versions)
+}
+
+/// Writes back the versions in the index file for a crate
+async fn write_crate_versions(file_name: &Path, versions: &[IndexCrateMetadata]) -> Result<(), ApiError> {
+    let mut file = OpenOptions::new().write(true).truncate(true).open(file_name).await?;
+    for version in versions {
+        let buffer = serde_json::to_vec(version)?;
+        file.write_all(&buffer).await?;
+        file.write_all(&[0x0A]).await?; // add line end
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+    Ok(())
 }
@@ -4,20 +4,31 @@
 
 //! API for index manipulation
 
+mod db;
 mod git;
 
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
+use log::warn;
+
 use crate::model::cargo::IndexCrateMetadata;
-use crate::model::config::Configuration;
+use crate::model::config::{Configuration, IndexBackend};
+use crate::model::index::{
+    IndexCheckReport, IndexIssue, IndexIssueKind, IndexPushStatus, StoredCrateVersion, find_index_issues,
+};
+use crate::services::database::{db_transaction_read, db_transaction_write};
+use crate::services::storage::Storage;
 use crate::utils::FaillibleFuture;
-use crate::utils::apierror::ApiError;
+use crate::utils::apierror::{ApiError, error_backend_failure, error_conflict, specialize};
+use crate::utils::db::RwSqlitePool;
+use crate::utils::hashes::sha256;
 
 /// Index implementations
 pub trait Index {
-    /// Gets the full path to a file in the index
-    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<PathBuf>>;
+    /// Gets a file in the index
+    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<IndexFile>>;
 
     /// Gets the upload pack advertisement for /info/refs
     fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>>;
@@ -31,8 +42,69 @@ pub trait Index {
     /// Removes a crate version from the index
     fn remove_crate_version<'a>(&'a self, package: &'a str, version: &'a str) -> FaillibleFuture<'a, ()>;
 
+    /// Sets whether a crate version is yanked in the index
+    fn set_crate_version_yanked<'a>(&'a self, package: &'a str, version: &'a str, yanked: bool) -> FaillibleFuture<'a, ()>;
+
     ///  Gets the data for a crate
     fn get_crate_data<'a>(&'a self, package: &'a str) -> FaillibleFuture<'a, Vec<IndexCrateMetadata>>;
+
+    /// Gets the data for all the versions of all the crates in the index
+    fn get_all_crates_data(&self) -> FaillibleFuture<'_, Vec<IndexCrateMetadata>>;
+
+    /// Rebuilds the index with the data for all the versions of all the crates, grouped by crate
+    fn rebuild<'a>(&'a self, versions: &'a [IndexCrateMetadata]) -> FaillibleFuture<'a, ()>;
+
+    /// Gets the status of the pushes to the remote origin, if they are enabled
+    fn get_push_status(&self) -> Option<IndexPushStatus>;
+
+    /// Waits for the pending commits to be pushed to the remote origin, if the pushes are enabled
+    fn wait_for_pushes(&self) -> FaillibleFuture<'_, ()>;
+}
+
+/// A file in the index, with the information for conditional requests
+#[derive(Debug, Clone)]
+pub struct IndexFile {
+    /// The content of the file
+    pub content: Vec<u8>,
+    /// The strong entity tag for the content, quoted
+    pub etag: String,
+    /// The last time the content was modified
+    pub last_modified: DateTime<Utc>,
+}
+
+impl IndexFile {
+    /// Creates the file for a content, computing its entity tag
+    #[must_use]
+    pub fn new(content: Vec<u8>, last_modified: DateTime<Utc>) -> Self {
+        let etag = format!("\"{}\"", sha256(&content));
+        Self {
+            content,
+            etag,
+            last_modified,
+        }
+    }
+
+    /// Gets the last modification time, formatted for the `Last-Modified` header
+    #[must_use]
+    pub fn get_last_modified_header(&self) -> String {
+        self.last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+    }
+
+    /// Gets whether a client that sent the `If-None-Match` and `If-Modified-Since` headers already has this file
+    /// When both are present, `If-Modified-Since` is ignored
+    #[must_use]
+    pub fn is_not_modified(&self, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> bool {
+        if let Some(if_none_match) = if_none_match {
+            // weak comparison
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag);
+        }
+        if_modified_since
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| self.last_modified.timestamp() <= since.timestamp())
+    }
 }
 
 /// Gets path elements for a package in the file system
@@ -63,7 +135,135 @@ pub fn build_package_file_path(mut root: PathBuf, name: &str) -> PathBuf {
 }
 
 /// Gets the index service
-pub async fn get_service(config: &Configuration, expect_empty: bool) -> Result<Arc<dyn Index + Send + Sync>, ApiError> {
-    let index = git::GitIndex::new(config.get_index_git_config(), expect_empty).await?;
-    Ok(Arc::new(index))
+pub async fn get_service(
+    config: &Configuration,
+    expect_empty: bool,
+    service_db_pool: RwSqlitePool,
+    service_storage: Arc<dyn Storage + Send + Sync>,
+) -> Result<Arc<dyn Index + Send + Sync>, ApiError> {
+    match config.index.backend {
+        IndexBackend::Git => {
+            let index = git::GitIndex::new(config.get_index_git_config(), expect_empty).await?;
+            Ok(Arc::new(index))
+        }
+        IndexBackend::Database => {
+            if config.index.allow_protocol_git {
+                return Err(specialize(
+                    error_backend_failure(),
+                    String::from("the git protocol requires the git backend for the index"),
+                ));
+            }
+            Ok(Arc::new(db::DbIndex::new(&config.index, service_db_pool, service_storage)?))
+        }
+    }
+}
+
+/// Checks the consistency between the database, the index and the storage, and optionally rebuilds the index
+/// Unrecoverable versions and pending publications prevent the rebuild
+pub async fn check_consistency(
+    service_db_pool: &RwSqlitePool,
+    service_storage: &(dyn Storage + Send + Sync),
+    service_index: &(dyn Index + Send + Sync),
+    rebuild: bool,
+) -> Result<IndexCheckReport, ApiError> {
+    let (versions, publishing) = db_transaction_read(service_db_pool, |database| async move {
+        let versions = database.get_crates_index_data().await?;
+        let publishing = database.get_crate_versions_publishing().await?;
+        Ok::<_, ApiError>((versions, publishing))
+    })
+    .await?;
+    if rebuild && !publishing.is_empty() {
+        // the versions being published are not in the data, the rebuild would drop them
+        return Err(specialize(
+            error_conflict(),
+            format!(
+                "{} publication(s) are pending, the index can be rebuilt once they are completed or rolled back",
+                publishing.len()
+            ),
+        ));
+    }
+    let mut stored = Vec::with_capacity(versions.len());
+    let mut rebuilt_data = Vec::with_capacity(versions.len());
+    let mut unresolved = Vec::new();
+    for (version, yanked, data) in versions {
+        let content = match service_storage.download_crate(&version.package, &version.version).await {
+            Ok(content) => Some(content),
+            Err(error) => {
+                warn!(
+                    "index: failed to download {} {}: {}",
+                    version.package,
+                    version.version,
+                    error.details.as_deref().unwrap_or(&error.message)
+                );
+                None
+            }
+        };
+        let cksum = content.as_deref().map(sha256);
+        if rebuild {
+            let data = match (data, &content) {
+                (Some(data), _) => Some(data),
+                (None, Some(content)) => service_storage
+                    .download_crate_metadata(&version.package, &version.version)
+                    .await?
+                    .map(|metadata| IndexCrateMetadata::new(&metadata, content)),
+                (None, None) => None,
+            };
+            if let Some(mut data) = data {
+                // never trust the storage over the checksum recorded at publication
+                if let Some(cksum) = cksum.as_ref().filter(|cksum| **cksum != data.cksum) {
+                    unresolved.push(IndexIssue {
+                        package: version.package.clone(),
+                        version: version.version.clone(),
+                        kind: IndexIssueKind::ChecksumMismatch,
+                        details: format!(
+                            "the checksum is {} in the database, but {cksum} in the storage, the recorded checksum is kept",
+                            data.cksum
+                        ),
+                    });
+                }
+                data.yanked = yanked;
+                rebuilt_data.push((version.clone(), data));
+            } else {
+                unresolved.push(IndexIssue {
+                    package: version.package.clone(),
+                    version: version.version.clone(),
+                    kind: IndexIssueKind::MissingIndexData,
+                    details: String::from("the data for the index cannot be recovered, the index is not rebuilt"),
+                });
+            }
+        }
+        stored.push(StoredCrateVersion {
+            package: version.package,
+            version: version.version,
+            yanked,
+            cksum,
+        });
+    }
+    let indexed = service_index.get_all_crates_data().await?;
+    let issues = find_index_issues(&stored, &indexed);
+    // refuse to rebuild an index that would miss some versions
+    let rebuild = rebuild && !unresolved.iter().any(|issue| issue.kind == IndexIssueKind::MissingIndexData);
+    if rebuild {
+        db_transaction_write(service_db_pool, "rebuild_index", |database| {
+            let rebuilt_data = &rebuilt_data;
+            async move {
+                for (version, data) in rebuilt_data {
+                    database
+                        .set_crate_version_index_data(&version.package, &version.version, data)
+                        .await?;
+                }
+                Ok::<_, ApiError>(())
+            }
+        })
+        .await?;
+        let data = rebuilt_data.into_iter().map(|(_, data)| data).collect::<Vec<_>>();
+        service_index.rebuild(&data).await?;
+    }
+    Ok(IndexCheckReport {
+        versions: stored.len(),
+        issues,
+        rebuilt: rebuild,
+        unresolved,
+        push_error: None,
+    })
 }
@@ -16,8 +16,11 @@ pub mod deps;
 pub mod docs;
 pub mod emails;
 pub mod index;
+pub mod policy;
 pub mod rustsec;
+pub mod semver_checks;
 pub mod storage;
+pub mod tokens;
 
 /// Factory responsible for building services
 #[expect(async_fn_in_trait)]
@@ -29,7 +32,12 @@ pub trait ServiceProvider {
     fn get_storage(config: &Configuration) -> Arc<dyn storage::Storage + Send + Sync>;
 
     /// Gets the index service
-    async fn get_index(config: &Configuration, expect_empty: bool) -> Result<Arc<dyn index::Index + Send + Sync>, ApiError>;
+    async fn get_index(
+        config: &Configuration,
+        expect_empty: bool,
+        service_db_pool: RwSqlitePool,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+    ) -> Result<Arc<dyn index::Index + Send + Sync>, ApiError>;
 
     /// Gets the rustsec service
     fn get_rustsec(config: &Configuration) -> Arc<dyn rustsec::RustSecChecker + Send + Sync>;
@@ -44,6 +52,9 @@ pub trait ServiceProvider {
     /// Gets the email sender service
     fn get_email_sender(config: Arc<Configuration>) -> Arc<dyn emails::EmailSender + Send + Sync>;
 
+    /// Gets the service to check crates against the policies of the organisation
+    fn get_publish_policy(config: Arc<Configuration>) -> Arc<dyn policy::PublishPolicy + Send + Sync>;
+
     /// Gets the documentation generation service
     fn get_docs_generator(
         configuration: Arc<Configuration>,
@@ -51,6 +62,15 @@ pub trait ServiceProvider {
         service_storage: Arc<dyn storage::Storage + Send + Sync>,
         worker_nodes: WorkersManager,
     ) -> Arc<dyn docs::DocsGenerator + Send + Sync>;
+
+    /// Gets the service to check the semver compatibility of new versions
+    fn get_semver_checker(
+        configuration: Arc<Configuration>,
+        service_db_pool: RwSqlitePool,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+        service_email_sender: Arc<dyn emails::EmailSender + Send + Sync>,
+        worker_nodes: WorkersManager,
+    ) -> Arc<dyn semver_checks::SemverChecker + Send + Sync>;
 }
 
 /// Provides the standard implementations for services
@@ -70,8 +90,13 @@ impl ServiceProvider for StandardServiceProvider {
     }
 
     /// Gets the index service
-    async fn get_index(config: &Configuration, expect_empty: bool) -> Result<Arc<dyn index::Index + Send + Sync>, ApiError> {
-        index::get_service(config, expect_empty).await
+    async fn get_index(
+        config: &Configuration,
+        expect_empty: bool,
+        service_db_pool: RwSqlitePool,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+    ) -> Result<Arc<dyn index::Index + Send + Sync>, ApiError> {
+        index::get_service(config, expect_empty, service_db_pool, service_storage).await
     }
 
     /// Gets the rustsec service
@@ -93,6 +118,11 @@ impl ServiceProvider for StandardServiceProvider {
         emails::get_service(config)
     }
 
+    /// Gets the service to check crates against the policies of the organisation
+    fn get_publish_policy(config: Arc<Configuration>) -> Arc<dyn policy::PublishPolicy + Send + Sync> {
+        policy::get_service(config)
+    }
+
     /// Gets the documentation generation service
     fn get_docs_generator(
         configuration: Arc<Configuration>,
@@ -102,4 +132,21 @@ impl ServiceProvider for StandardServiceProvider {
     ) -> Arc<dyn docs::DocsGenerator + Send + Sync> {
         docs::get_service(configuration, service_db_pool, service_storage, worker_nodes)
     }
+
+    /// Gets the service to check the semver compatibility of new versions
+    fn get_semver_checker(
+        configuration: Arc<Configuration>,
+        service_db_pool: RwSqlitePool,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+        service_email_sender: Arc<dyn emails::EmailSender + Send + Sync>,
+        worker_nodes: WorkersManager,
+    ) -> Arc<dyn semver_checks::SemverChecker + Send + Sync> {
+        semver_checks::get_service(
+            configuration,
+            service_db_pool,
+            service_storage,
+            service_email_sender,
+            worker_nodes,
+        )
+    }
 }
@@ -0,0 +1,113 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service to check crates against the policies of the organisation before they are published
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use crate::model::cargo::{CrateUploadData, RegistryUser};
+use crate::model::config::{Configuration, PublishPolicyHook};
+use crate::model::policy::{PolicyCheckRequest, PolicyCheckResult};
+use crate::utils::apierror::{ApiError, error_backend_failure, specialize};
+use crate::utils::{FaillibleFuture, execute_at_location};
+
+/// Service to check crates against the policies of the organisation
+pub trait PublishPolicy {
+    /// Checks a crate version that is about to be published
+    fn check_crate<'a>(
+        &'a self,
+        package: &'a CrateUploadData,
+        uploaded_by: &'a RegistryUser,
+        is_new_crate: bool,
+    ) -> FaillibleFuture<'a, PolicyCheckResult>;
+}
+
+/// Gets the service to check crates against the policies of the organisation
+#[must_use]
+pub fn get_service(configuration: Arc<Configuration>) -> Arc<dyn PublishPolicy + Send + Sync> {
+    Arc::new(PublishPolicyImpl { configuration })
+}
+
+/// Service to check crates against the policies of the organisation, through the configured hook
+struct PublishPolicyImpl {
+    /// The app configuration
+    configuration: Arc<Configuration>,
+}
+
+impl PublishPolicy for PublishPolicyImpl {
+    /// Checks a crate version that is about to be published
+    fn check_crate<'a>(
+        &'a self,
+        package: &'a CrateUploadData,
+        uploaded_by: &'a RegistryUser,
+        is_new_crate: bool,
+    ) -> FaillibleFuture<'a, PolicyCheckResult> {
+        Box::pin(async move { self.do_check_crate(package, uploaded_by, is_new_crate).await })
+    }
+}
+
+impl PublishPolicyImpl {
+    /// Checks a crate version that is about to be published
+    async fn do_check_crate(
+        &self,
+        package: &CrateUploadData,
+        uploaded_by: &RegistryUser,
+        is_new_crate: bool,
+    ) -> Result<PolicyCheckResult, ApiError> {
+        let Some(config) = &self.configuration.publish_policy else {
+            // no hook, everything is allowed
+            return Ok(PolicyCheckResult::allowed());
+        };
+        let request = PolicyCheckRequest {
+            metadata: &package.metadata,
+            uploaded_by,
+            is_new_crate,
+            content: config.with_content.then(|| STANDARD.encode(&package.content)),
+        };
+        let request = serde_json::to_vec(&request)?;
+        let response = tokio::time::timeout(Duration::from_secs(config.timeout), self.call_hook(&config.hook, request))
+            .await
+            .map_err(|_| {
+                specialize(
+                    error_backend_failure(),
+                    String::from("the publish policy hook did not answer in time"),
+                )
+            })??;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    /// Calls the hook and gets its raw response
+    async fn call_hook(&self, hook: &PublishPolicyHook, request: Vec<u8>) -> Result<Vec<u8>, ApiError> {
+        match hook {
+            PublishPolicyHook::Command(command) => {
+                execute_at_location(Path::new(&self.configuration.data_dir), command, &[], &request).await
+            }
+            PublishPolicyHook::Http { uri, token } => {
+                let mut request = reqwest::Client::new()
+                    .post(uri)
+                    .header("content-type", "application/json")
+                    .body(request);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let response = request.send().await?;
+                if !response.status().is_success() {
+                    return Err(specialize(
+                        error_backend_failure(),
+                        format!(
+                            "the publish policy hook at {uri} failed: error code {}",
+                            response.status().as_u16()
+                        ),
+                    ));
+                }
+                Ok(response.bytes().await?.to_vec())
+            }
+        }
+    }
+}
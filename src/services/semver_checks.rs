@@ -0,0 +1,379 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Checks of the semver compatibility of new versions against the previous compatible version
+
+use std::fmt::Write;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Local;
+use flate2::bufread::GzDecoder;
+use log::{error, info};
+use tar::Archive;
+use tokio::process::Command;
+use tokio::time::interval;
+
+use crate::model::config::Configuration;
+use crate::model::semver_checks::{SemverCheckJob, SemverCheckJobSpec, SemverCheckJobState, find_semver_baseline};
+use crate::model::worker::{JobIdentifier, JobSpecification, JobUpdate, WorkersManager};
+use crate::services::database::{db_transaction_read, db_transaction_write};
+use crate::services::emails::EmailSender;
+use crate::services::storage::Storage;
+use crate::utils::FaillibleFuture;
+use crate::utils::apierror::{ApiError, error_backend_failure, specialize};
+use crate::utils::db::RwSqlitePool;
+use crate::utils::token::generate_url_safe_token;
+
+/// Service to check the semver compatibility of new versions of crates
+pub trait SemverChecker {
+    /// Gets the checks for a crate
+    fn get_checks<'a>(&'a self, package: &'a str) -> FaillibleFuture<'a, Vec<SemverCheckJob>>;
+
+    /// Queues a check for a new version, if there is a previous compatible version to compare it to
+    fn queue<'a>(&'a self, package: &'a str, version: &'a str) -> FaillibleFuture<'a, Option<SemverCheckJob>>;
+
+    /// Checks the content of a new version that is not yet stored, on this node, without recording the result
+    /// Nothing is checked when there is no previous compatible version to compare it to
+    fn check_content<'a>(
+        &'a self,
+        package: &'a str,
+        version: &'a str,
+        content: &'a [u8],
+    ) -> FaillibleFuture<'a, Option<SemverCheckJob>>;
+
+    /// Records the result of a check of the content of a new version, once the version is stored
+    fn record<'a>(&'a self, job: &'a SemverCheckJob) -> FaillibleFuture<'a, ()>;
+}
+
+/// Gets the semver checker service
+pub fn get_service(
+    configuration: Arc<Configuration>,
+    service_db_pool: RwSqlitePool,
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    service_email_sender: Arc<dyn EmailSender + Send + Sync>,
+    worker_nodes: WorkersManager,
+) -> Arc<dyn SemverChecker + Send + Sync> {
+    let service = Arc::new(SemverCheckerImpl {
+        configuration,
+        service_db_pool,
+        service_storage,
+        service_email_sender,
+        worker_nodes,
+    });
+    // launch workers
+    let _handle = tokio::spawn({
+        let service = service.clone();
+        async move {
+            service.worker().await;
+        }
+    });
+    service
+}
+
+/// Service to check the semver compatibility of new versions of crates
+#[derive(Clone)]
+struct SemverCheckerImpl {
+    /// The configuration
+    configuration: Arc<Configuration>,
+    /// The database pool
+    service_db_pool: RwSqlitePool,
+    /// The storage layer
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    /// The service to send emails
+    service_email_sender: Arc<dyn EmailSender + Send + Sync>,
+    /// The worker nodes
+    worker_nodes: WorkersManager,
+}
+
+impl SemverChecker for SemverCheckerImpl {
+    /// Gets the checks for a crate
+    fn get_checks<'a>(&'a self, package: &'a str) -> FaillibleFuture<'a, Vec<SemverCheckJob>> {
+        Box::pin(async move {
+            db_transaction_read(&self.service_db_pool, |database| async move {
+                database.get_semver_check_jobs(package).await
+            })
+            .await
+        })
+    }
+
+    /// Queues a check for a new version, if there is a previous compatible version to compare it to
+    fn queue<'a>(&'a self, package: &'a str, version: &'a str) -> FaillibleFuture<'a, Option<SemverCheckJob>> {
+        Box::pin(async move { self.create_job(package, version, SemverCheckJobState::Queued).await })
+    }
+
+    /// Checks the content of a new version that is not yet stored, on this node, without recording the result
+    fn check_content<'a>(
+        &'a self,
+        package: &'a str,
+        version: &'a str,
+        content: &'a [u8],
+    ) -> FaillibleFuture<'a, Option<SemverCheckJob>> {
+        Box::pin(async move {
+            let versions = db_transaction_read(&self.service_db_pool, |database| async move {
+                database.get_crate_active_versions(package).await
+            })
+            .await?;
+            let Some(baseline) = find_semver_baseline(version, versions.iter().map(String::as_str)) else {
+                return Ok(None);
+            };
+            let now = Local::now().naive_local();
+            let mut job = SemverCheckJob {
+                id: 0,
+                package: package.to_string(),
+                version: version.to_string(),
+                baseline,
+                state: SemverCheckJobState::Working,
+                queued_on: now,
+                started_on: now,
+                finished_on: now,
+                last_update: now,
+                output: String::new(),
+            };
+            let result = async {
+                let baseline = self.service_storage.download_crate(package, &job.baseline).await?;
+                check_semver(&job, &baseline, content).await
+            }
+            .await;
+            (job.state, job.output) = match result {
+                Ok(result) => result,
+                Err(error) => (SemverCheckJobState::Failure, error.to_string()),
+            };
+            job.finished_on = Local::now().naive_local();
+            job.last_update = job.finished_on;
+            Ok(Some(job))
+        })
+    }
+
+    /// Records the result of a check of the content of a new version, once the version is stored
+    fn record<'a>(&'a self, job: &'a SemverCheckJob) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move {
+            db_transaction_write(&self.service_db_pool, "record_semver_check_job", |database| async move {
+                let spec = SemverCheckJobSpec {
+                    package: job.package.clone(),
+                    version: job.version.clone(),
+                    baseline: job.baseline.clone(),
+                };
+                let created = database.create_semver_check_job(&spec).await?;
+                database
+                    .update_semver_check_job(created.id, job.state, Some(&job.output))
+                    .await
+            })
+            .await
+        })
+    }
+}
+
+impl SemverCheckerImpl {
+    /// Creates a job for a new version, if there is a previous compatible version to compare it to
+    async fn create_job(
+        &self,
+        package: &str,
+        version: &str,
+        state: SemverCheckJobState,
+    ) -> Result<Option<SemverCheckJob>, ApiError> {
+        db_transaction_write(&self.service_db_pool, "create_semver_check_job", |database| async move {
+            let versions = database.get_crate_active_versions(package).await?;
+            let Some(baseline) = find_semver_baseline(version, versions.iter().map(String::as_str)) else {
+                return Ok(None);
+            };
+            let spec = SemverCheckJobSpec {
+                package: package.to_string(),
+                version: version.to_string(),
+                baseline,
+            };
+            let mut job = database.create_semver_check_job(&spec).await?;
+            if state != job.state {
+                database.update_semver_check_job(job.id, state, None).await?;
+                job.state = state;
+            }
+            Ok(Some(job))
+        })
+        .await
+    }
+
+    /// Update a job
+    async fn update_job(&self, job: &SemverCheckJob, state: SemverCheckJobState, output: Option<&str>) -> Result<(), ApiError> {
+        db_transaction_write(&self.service_db_pool, "update_semver_check_job", |database| async move {
+            database.update_semver_check_job(job.id, state, output).await
+        })
+        .await
+    }
+
+    /// Gets the next job, if any
+    async fn get_next_job(&self) -> Result<Option<SemverCheckJob>, ApiError> {
+        db_transaction_read(&self.service_db_pool, |database| async move {
+            database.get_next_semver_check_job().await
+        })
+        .await
+    }
+
+    /// Implementation of the worker
+    async fn worker(&self) -> ! {
+        // check every 10 seconds
+        let mut interval = interval(Duration::from_secs(10));
+        loop {
+            interval.tick().await;
+            match self.get_next_job().await {
+                Err(e) => {
+                    error!("{e}");
+                    if let Some(backtrace) = &e.backtrace {
+                        error!("{backtrace}");
+                    }
+                }
+                Ok(Some(job)) => {
+                    if let Err(e) = self.worker_on_job(&job).await {
+                        error!("{e}");
+                        if let Some(backtrace) = &e.backtrace {
+                            error!("{backtrace}");
+                        }
+                    }
+                }
+                Ok(None) => {}
+            }
+        }
+    }
+
+    /// Executes a semver check job
+    async fn worker_on_job(&self, job: &SemverCheckJob) -> Result<(), ApiError> {
+        let (state, output) = match self.worker_execute_job(job).await {
+            Ok(result) => result,
+            Err(e) => (SemverCheckJobState::Failure, e.to_string()),
+        };
+        self.update_job(job, state, Some(&output)).await?;
+        if state == SemverCheckJobState::Breaking && self.configuration.semver_checks_notify {
+            self.send_notification(job, &output).await?;
+        }
+        Ok(())
+    }
+
+    /// Executes a semver check job
+    async fn worker_execute_job(&self, job: &SemverCheckJob) -> Result<(SemverCheckJobState, String), ApiError> {
+        if self.worker_nodes.has_workers() {
+            self.worker_execute_job_remote(job).await
+        } else {
+            self.update_job(job, SemverCheckJobState::Working, None).await?;
+            check_semver_for_job(self.service_storage.clone(), job).await
+        }
+    }
+
+    /// Executes a semver check job on a worker node
+    async fn worker_execute_job_remote(&self, job: &SemverCheckJob) -> Result<(SemverCheckJobState, String), ApiError> {
+        let mut worker = self
+            .worker_nodes
+            .get_worker_for(job.get_worker_selector(), JobIdentifier::SemverCheck(job.id))?
+            .await?;
+        self.update_job(job, SemverCheckJobState::Working, None).await?;
+        worker.sender().send(JobSpecification::SemverCheck(job.clone())).await?;
+        while let Some(update) = worker.update_receiver().recv().await {
+            let JobUpdate::SemverCheck(update) = update else {
+                continue;
+            };
+            if update.state.is_final() {
+                return Ok((update.state, update.output.unwrap_or_default()));
+            }
+        }
+        Err(specialize(
+            error_backend_failure(),
+            String::from("worker unexpectedly disconnected"),
+        ))
+    }
+
+    /// Sends the notification about breaking changes to the owners of the crate
+    async fn send_notification(&self, job: &SemverCheckJob, output: &str) -> Result<(), ApiError> {
+        let mut body = String::new();
+        writeln!(
+            body,
+            "Breaking changes have been found in {} {} compared to {}",
+            job.package, job.version, job.baseline
+        )
+        .unwrap();
+        writeln!(
+            body,
+            "See {}/crates/{}/{}",
+            self.configuration.web_public_uri, job.package, job.version
+        )
+        .unwrap();
+        writeln!(body).unwrap();
+        body.push_str(output);
+        let owners = db_transaction_read(&self.service_db_pool, |database| async move {
+            database.get_crate_owners(&job.package).await
+        })
+        .await?;
+        let owners = owners.emails();
+        self.service_email_sender
+            .send_email(
+                &owners,
+                &format!("Cratery - breaking changes in {} {}", job.package, job.version),
+                body,
+            )
+            .await
+    }
+}
+
+/// Executes a semver check job, comparing two stored versions
+pub async fn check_semver_for_job(
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    job: &SemverCheckJob,
+) -> Result<(SemverCheckJobState, String), ApiError> {
+    let content = service_storage.download_crate(&job.package, &job.version).await?;
+    let baseline = service_storage.download_crate(&job.package, &job.baseline).await?;
+    check_semver(job, &baseline, &content).await
+}
+
+/// Checks the semver compatibility of a version against its baseline, using `cargo-semver-checks`
+/// The temporary folder for the extracted packages is removed in all cases
+async fn check_semver(
+    job: &SemverCheckJob,
+    baseline: &[u8],
+    content: &[u8],
+) -> Result<(SemverCheckJobState, String), ApiError> {
+    info!("checking semver for {} {} against {}", job.package, job.version, job.baseline);
+    let temp_folder = std::env::temp_dir().join(format!("semver_{}", generate_url_safe_token(16)));
+    let result = async {
+        let current_folder = extract_content(&temp_folder.join("current"), &job.package, &job.version, content)?;
+        let baseline_folder = extract_content(&temp_folder.join("baseline"), &job.package, &job.baseline, baseline)?;
+        do_check_semver(&current_folder, &baseline_folder).await
+    }
+    .await;
+    if let Err(error) = tokio::fs::remove_dir_all(&temp_folder).await
+        && error.kind() != ErrorKind::NotFound
+    {
+        error!("failed to remove {}: {error}", temp_folder.display());
+    }
+    result
+}
+
+/// Extracts the content of a crate in a folder and gets the folder of the project
+fn extract_content(target: &Path, name: &str, version: &str, content: &[u8]) -> Result<PathBuf, ApiError> {
+    let decoder = GzDecoder::new(content);
+    let mut archive = Archive::new(decoder);
+    archive.unpack(target)?;
+    Ok(target.join(format!("{name}-{version}")))
+}
+
+/// Runs `cargo-semver-checks` on a project against a baseline
+async fn do_check_semver(project_folder: &Path, baseline_folder: &Path) -> Result<(SemverCheckJobState, String), ApiError> {
+    let mut child = Command::new("cargo")
+        .arg("semver-checks")
+        .arg("check-release")
+        .arg("--manifest-path")
+        .arg(project_folder.join("Cargo.toml"))
+        .arg("--baseline-root")
+        .arg(baseline_folder)
+        .arg("--all-features")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    drop(child.stdin.take()); // close stdin
+    let output = child.wait_with_output().await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let log = format!("-- stdout\n{stdout}\n\n-- stderr\n{stderr}");
+    Ok((SemverCheckJobState::from_output(output.status.success(), &log), log))
+}
@@ -4,8 +4,9 @@
 
 //! Storage implementations for crates data and documentation
 
+use std::collections::BTreeMap;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use flate2::bufread::GzDecoder;
@@ -15,8 +16,10 @@ use tar::Archive;
 
 use crate::model::cargo::CrateMetadata;
 use crate::model::config::{Configuration, RetryParams, StorageConfig};
+use crate::model::packages::CrateFile;
 use crate::utils::FaillibleFuture;
-use crate::utils::apierror::ApiError;
+use crate::utils::apierror::{ApiError, error_invalid_request, error_not_found, specialize};
+use crate::utils::axum::embedded::get_content_type;
 
 /// Backing storage implementations
 pub trait Storage {
@@ -26,12 +29,21 @@ pub trait Storage {
     /// Downloads a crate
     fn download_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>>;
 
+    /// Deletes the data for a crate that was never published, with its metadata, README and attestation
+    fn delete_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, ()>;
+
     /// Downloads the last metadata for a crate
     fn download_crate_metadata<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Option<CrateMetadata>>;
 
     /// Downloads the last README for a crate
     fn download_crate_readme<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>>;
 
+    /// Stores the signed attestation for the publication of a crate
+    fn store_crate_attestation<'a>(&'a self, name: &'a str, version: &'a str, content: Vec<u8>) -> FaillibleFuture<'a, ()>;
+
+    /// Downloads the signed attestation for the publication of a crate, if any
+    fn download_crate_attestation<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Option<Vec<u8>>>;
+
     /// Stores a documentation file
     fn store_doc_file<'a>(&'a self, path: &'a str, file: &'a Path) -> FaillibleFuture<'a, ()>;
 
@@ -115,6 +127,10 @@ impl Storage for StorageImpl {
         Box::pin(async move { self.download_crate(name, version).await })
     }
 
+    fn delete_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.delete_crate(name, version).await })
+    }
+
     fn download_crate_metadata<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Option<CrateMetadata>> {
         Box::pin(async move { self.download_crate_metadata(name, version).await })
     }
@@ -123,6 +139,14 @@ impl Storage for StorageImpl {
         Box::pin(async move { self.download_crate_readme(name, version).await })
     }
 
+    fn store_crate_attestation<'a>(&'a self, name: &'a str, version: &'a str, content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.store_crate_attestation(name, version, content).await })
+    }
+
+    fn download_crate_attestation<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { self.download_crate_attestation(name, version).await })
+    }
+
     fn store_doc_file<'a>(&'a self, path: &'a str, file: &'a Path) -> FaillibleFuture<'a, ()> {
         Box::pin(async move { self.store_doc_file(path, file).await })
     }
@@ -170,6 +194,19 @@ impl StorageImpl {
         }
     }
 
+    /// Deletes the data for a crate that was never published, with its metadata, README and attestation
+    async fn delete_crate(&self, name: &str, version: &str) -> Result<(), ApiError> {
+        for path in [
+            Self::data_path(name, version),
+            Self::metadata_path(name, version),
+            Self::readme_path(name, version),
+            Self::attestation_path(name, version),
+        ] {
+            self.opendal_operator.delete(&path).await?;
+        }
+        Ok(())
+    }
+
     /// Downloads the last metadata for a crate
     async fn download_crate_metadata(&self, name: &str, version: &str) -> Result<Option<CrateMetadata>, ApiError> {
         if let Ok(data) = self.read_from_file(&Self::metadata_path(name, version)).await {
@@ -186,6 +223,20 @@ impl StorageImpl {
             .map_err(ApiError::from)
     }
 
+    /// Stores the signed attestation for the publication of a crate
+    async fn store_crate_attestation(&self, name: &str, version: &str, content: Vec<u8>) -> Result<(), ApiError> {
+        self.write_to_file(&Self::attestation_path(name, version), content).await
+    }
+
+    /// Downloads the signed attestation for the publication of a crate, if any
+    async fn download_crate_attestation(&self, name: &str, version: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        match self.read_from_file(&Self::attestation_path(name, version)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Stores a documentation file
     async fn store_doc_file(&self, path: &str, file: &Path) -> Result<(), ApiError> {
         let content = tokio::fs::read(file).await?;
@@ -231,6 +282,10 @@ impl StorageImpl {
     fn readme_path(name: &str, version: &str) -> String {
         Self::crate_file_key(name, version, "readme")
     }
+
+    fn attestation_path(name: &str, version: &str) -> String {
+        Self::crate_file_key(name, version, "attestation")
+    }
 }
 
 /// Extract the content of the README from the
@@ -255,3 +310,101 @@ pub fn extract_readme(crate_content: &[u8]) -> Result<Vec<u8>, ApiError> {
 
     Ok(buffer)
 }
+
+/// Lists the files in the content of a crate, relative to its root directory
+pub fn list_crate_files(crate_content: &[u8]) -> Result<Vec<CrateFile>, ApiError> {
+    let decoder = GzDecoder::new(crate_content);
+    let mut archive = Archive::new(decoder);
+    let mut files = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?;
+        let path = path.components().skip(1).collect::<PathBuf>();
+        files.push(CrateFile {
+            path: path.to_string_lossy().to_string(),
+            size: entry.size(),
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Extracts all the files from the content of a crate, by path relative to the root directory
+/// Fails when the package has more files or more bytes in total than the specified maximums
+pub fn extract_crate_files(
+    crate_content: &[u8],
+    max_files: usize,
+    max_total_size: u64,
+) -> Result<BTreeMap<String, Vec<u8>>, ApiError> {
+    let decoder = GzDecoder::new(crate_content);
+    let mut archive = Archive::new(decoder);
+    let mut files = BTreeMap::new();
+    let mut total_size = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        if files.len() >= max_files {
+            return Err(specialize(
+                error_invalid_request(),
+                format!("the package has too many files (max {max_files})"),
+            ));
+        }
+        total_size += entry.size();
+        if total_size > max_total_size {
+            return Err(specialize(
+                error_invalid_request(),
+                format!("the package is too large (max {max_total_size} bytes)"),
+            ));
+        }
+        let path = entry.path()?.components().skip(1).collect::<PathBuf>();
+        let mut buffer = Vec::new();
+        entry.read_to_end(&mut buffer)?;
+        files.insert(path.to_string_lossy().to_string(), buffer);
+    }
+    Ok(files)
+}
+
+/// Extracts a file from the content of a crate, given its path relative to the root directory
+pub fn extract_crate_file(crate_content: &[u8], path: &str, max_size: u64) -> Result<Vec<u8>, ApiError> {
+    let decoder = GzDecoder::new(crate_content);
+    let mut archive = Archive::new(decoder);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() || entry.path()?.components().skip(1).collect::<PathBuf>() != Path::new(path)
+        {
+            continue;
+        }
+        if entry.size() > max_size {
+            return Err(specialize(
+                error_invalid_request(),
+                format!(
+                    "file {path} is too large to be displayed ({} bytes, max {max_size})",
+                    entry.size()
+                ),
+            ));
+        }
+        let mut buffer = Vec::new();
+        entry.read_to_end(&mut buffer)?;
+        return Ok(buffer);
+    }
+    Err(specialize(error_not_found(), format!("file {path} not found in the package")))
+}
+
+/// Gets the content type to serve a file from the content of a crate
+/// Only images are served as-is, textual files are served as plain text and the rest as binary
+#[must_use]
+pub fn get_crate_file_content_type(path: &str, content: &[u8]) -> &'static str {
+    let content_type = get_content_type(path);
+    if content_type.starts_with("image/") && content_type != "image/svg+xml" {
+        content_type
+    } else if std::str::from_utf8(content).is_ok_and(|text| !text.contains('\0')) {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    }
+}
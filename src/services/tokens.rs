@@ -0,0 +1,128 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Worker for the expiration of tokens
+
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Local, TimeDelta};
+use log::{error, info};
+
+use crate::model::auth::{TokenExpiryNotice, TokenKind};
+use crate::model::config::Configuration;
+use crate::services::database::db_transaction_write;
+use crate::services::emails::EmailSender;
+use crate::utils::apierror::ApiError;
+use crate::utils::db::RwSqlitePool;
+
+/// Creates a worker that periodically deletes expired tokens and notifies owners of tokens about to expire
+pub fn create_tokens_worker(
+    configuration: Arc<Configuration>,
+    service_email_sender: Arc<dyn EmailSender + Send + Sync>,
+    pool: RwSqlitePool,
+) {
+    let _handle = tokio::spawn(async move { run_tokens_worker_job(configuration, service_email_sender, pool).await });
+}
+
+async fn run_tokens_worker_job(
+    configuration: Arc<Configuration>,
+    service_email_sender: Arc<dyn EmailSender + Send + Sync + 'static>,
+    pool: RwSqlitePool,
+) -> ! {
+    let mut interval = tokio::time::interval(Duration::from_secs(configuration.tokens_check_period));
+    loop {
+        let _instant = interval.tick().await;
+        if let Err(e) = tokens_worker_job(&configuration, service_email_sender.as_ref(), &pool).await {
+            error!("{e}");
+            if let Some(backtrace) = &e.backtrace {
+                error!("{backtrace}");
+            }
+        }
+    }
+}
+
+/// A job for the worker
+async fn tokens_worker_job(
+    configuration: &Configuration,
+    service_email_sender: &(dyn EmailSender + Send + Sync),
+    pool: &RwSqlitePool,
+) -> Result<(), ApiError> {
+    let now = Local::now().naive_local();
+    let (user_count, global_count) = db_transaction_write(pool, "delete_expired_tokens", |database| async move {
+        let user_count = database.delete_expired_tokens(now).await?;
+        let global_count = database.delete_expired_global_tokens(now).await?;
+        // short-lived tokens for trusted publishers are silently deleted
+        database.delete_expired_trusted_publishing_tokens(now).await?;
+        Ok::<_, ApiError>((user_count, global_count))
+    })
+    .await?;
+    if user_count > 0 || global_count > 0 {
+        info!("deleted {user_count} expired user token(s) and {global_count} expired global token(s)");
+    }
+
+    if configuration.tokens_notify_expiry <= 0 {
+        // notifications are deactivated
+        return Ok(());
+    }
+    let limit = now + TimeDelta::days(configuration.tokens_notify_expiry);
+    let notices = db_transaction_write(pool, "get_expiring_tokens", |database| async move {
+        let mut notices = database.get_expiring_tokens(limit).await?;
+        notices.extend(database.get_expiring_global_tokens(limit).await?);
+        Ok::<_, ApiError>(notices)
+    })
+    .await?;
+    for notice in &notices {
+        // a failure for a notice shall not prevent the others
+        if let Err(e) = tokens_worker_notify(configuration, service_email_sender, pool, notice).await {
+            error!("failed to notify the expiry of {}: {e}", notice.name);
+            if let Some(backtrace) = &e.backtrace {
+                error!("{backtrace}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Notifies the owners of a token that it will expire soon
+async fn tokens_worker_notify(
+    configuration: &Configuration,
+    service_email_sender: &(dyn EmailSender + Send + Sync),
+    pool: &RwSqlitePool,
+    notice: &TokenExpiryNotice,
+) -> Result<(), ApiError> {
+    if !notice.recipients.is_empty() {
+        let (kind, page) = match notice.kind {
+            TokenKind::User => ("token", "account.html"),
+            TokenKind::Registry => ("global token", "admin-tokens.html"),
+            TokenKind::PublicKey => ("public key", "account.html"),
+        };
+        let mut body = String::new();
+        writeln!(
+            body,
+            "The {kind} {} will expire on {} and will then be deleted.",
+            notice.name,
+            notice.expires_on.format("%Y-%m-%d %H:%M:%S")
+        )
+        .unwrap();
+        writeln!(
+            body,
+            "A new token can be created at {}/webapp/{page}",
+            configuration.web_public_uri
+        )
+        .unwrap();
+        service_email_sender
+            .send_email(
+                &notice.recipients,
+                &format!("Cratery - {kind} {} is about to expire", notice.name),
+                body,
+            )
+            .await?;
+    }
+    db_transaction_write(pool, "set_token_expiry_notified", |database| async move {
+        database.set_token_expiry_notified(notice).await
+    })
+    .await
+}
@@ -0,0 +1,454 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests about the index
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use chrono::{Local, NaiveDate, Utc};
+
+use super::mocks::MockService;
+use super::{
+    ADMIN_UID, async_test, async_test_with, setup_crate_metadata, setup_create_token, setup_create_user, setup_publish_crate,
+};
+use crate::application::Application;
+use crate::model::cargo::IndexCrateMetadata;
+use crate::model::config::{Configuration, IndexBackend, IndexPublicConfig};
+use crate::model::index::{IndexIssueKind, IndexPushStatus};
+use crate::services::ServiceProvider;
+use crate::utils::apierror::ApiError;
+use crate::utils::axum::auth::{AuthData, Token};
+
+const CRATE_NAME: &str = "hello";
+const CRATE_FILE: &str = "/he/ll/hello";
+
+#[test]
+fn test_index_database() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = application.get_service_index();
+        let index_file = || async {
+            let file = index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+            String::from_utf8(file.content)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<IndexCrateMetadata>(line).map_err(ApiError::from))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let config = index.get_index_file(Path::new("/config.json")).await?.unwrap();
+        let config = serde_json::from_slice::<IndexPublicConfig>(&config.content)?;
+        assert_eq!(config.dl, application.configuration.index.public.dl);
+        assert!(index.get_index_file(Path::new(CRATE_FILE)).await?.is_none());
+
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.0.0").await?;
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        let versions = index_file().await?;
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].name, CRATE_NAME);
+        assert_eq!(versions[0].vers, "1.0.0");
+        assert_eq!(versions[1].vers, "1.1.0");
+        assert!(versions.iter().all(|v| !v.yanked && v.cksum.len() == 64));
+        assert_eq!(index.get_crate_data("Hello").await?.len(), 2);
+
+        // only the canonical path is served
+        assert!(index.get_index_file(Path::new("/he/ll/Hello")).await?.is_none());
+        assert!(index.get_index_file(Path::new("/he/xx/hello")).await?.is_none());
+        assert!(index.get_index_file(Path::new("/hello")).await?.is_none());
+
+        // changes are visible through the cache
+        application.yank_crate_version(&admin_auth, CRATE_NAME, "1.0.0", None).await?;
+        let versions = index_file().await?;
+        assert!(versions[0].yanked);
+        assert!(!versions[1].yanked);
+        application.unyank_crate_version(&admin_auth, CRATE_NAME, "1.0.0").await?;
+        assert!(!index_file().await?[0].yanked);
+
+        application.set_crate_can_remove(&admin_auth, CRATE_NAME, true).await?;
+        application.remove_crate_version(&admin_auth, CRATE_NAME, "1.1.0").await?;
+        let versions = index_file().await?;
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].vers, "1.0.0");
+
+        // the git protocol is not available
+        assert!(index.get_upload_pack_info_refs().await.is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_index_conditional_requests() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = application.get_service_index();
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.0.0").await?;
+        let file = index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+        assert_eq!(index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap().etag, file.etag);
+
+        // entity tags
+        let etag = file.etag.as_str();
+        assert!(file.is_not_modified(Some(etag), None));
+        assert!(file.is_not_modified(Some(&format!("W/{etag}")), None));
+        assert!(file.is_not_modified(Some(&format!("\"other\", {etag}")), None));
+        assert!(file.is_not_modified(Some("*"), None));
+        assert!(!file.is_not_modified(Some("\"other\""), None));
+        assert!(!file.is_not_modified(None, None));
+
+        // modification dates are ignored when there are entity tags
+        let last_modified = file.get_last_modified_header();
+        assert!(file.is_not_modified(None, Some(&last_modified)));
+        assert!(!file.is_not_modified(Some("\"other\""), Some(&last_modified)));
+        assert!(!file.is_not_modified(None, Some("Sat, 01 Jan 2000 00:00:00 GMT")));
+        assert!(!file.is_not_modified(None, Some("invalid")));
+
+        // the entity tag changes with the content
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        let updated = index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+        assert!(!updated.is_not_modified(Some(etag), None));
+        application.yank_crate_version(&admin_auth, CRATE_NAME, "1.0.0", None).await?;
+        let yanked = index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+        assert!(!yanked.is_not_modified(Some(&updated.etag), None));
+        assert!(yanked.last_modified >= file.last_modified);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_index_last_modified() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = application.get_service_index();
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.0.0").await?;
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        application
+            .db_transaction_write("test_index_update", |app| async move {
+                sqlx::query("UPDATE PackageVersion SET indexUpdate = '2020-01-02 03:04:05' WHERE version = '1.0.0'")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                sqlx::query(
+                    "UPDATE PackageVersion SET indexUpdate = NULL, upload = '2021-01-02 03:04:05' WHERE version = '1.1.0'",
+                )
+                .execute(&mut *app.database.transaction.borrow().await)
+                .await?;
+                Ok::<_, ApiError>(())
+            })
+            .await?;
+        let expected = NaiveDate::from_ymd_opt(2021, 1, 2)
+            .and_then(|date| date.and_hms_opt(3, 4, 5))
+            .and_then(|date| date.and_local_timezone(Local).earliest())
+            .unwrap()
+            .with_timezone(&Utc);
+
+        // the modification time comes from the database and does not change when the file is regenerated
+        index.set_crate_version_yanked(CRATE_NAME, "1.0.0", false).await?;
+        let file = index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+        assert_eq!(file.last_modified, expected);
+        index.set_crate_version_yanked(CRATE_NAME, "1.0.0", false).await?;
+        let regenerated = index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+        assert_eq!(regenerated.last_modified, expected);
+
+        // yanking updates the modification time
+        application.yank_crate_version(&admin_auth, CRATE_NAME, "1.0.0", None).await?;
+        let yanked = index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+        assert!(yanked.last_modified > expected);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_index_check_and_rebuild() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = application.get_service_index();
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.0.0").await?;
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        // cache the index file, then yank behind the back of the index
+        index.get_index_file(Path::new(CRATE_FILE)).await?.unwrap();
+        application
+            .db_transaction_write("test_yank", |app| async move {
+                sqlx::query("UPDATE PackageVersion SET yanked = TRUE WHERE version = '1.1.0'")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<_, ApiError>(())
+            })
+            .await?;
+
+        let report = application.check_index(&admin_auth, false).await?;
+        assert_eq!(report.versions, 2);
+        assert!(!report.rebuilt);
+        let issues = report
+            .issues
+            .iter()
+            .map(|issue| (issue.version.as_str(), issue.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(issues, vec![("1.1.0", IndexIssueKind::YankedMismatch)]);
+
+        // rebuilding repairs the index
+        let report = application.check_index(&admin_auth, true).await?;
+        assert!(report.rebuilt);
+        assert_eq!(report.issues.len(), 1);
+        let report = application.check_index(&admin_auth, false).await?;
+        assert!(report.issues.is_empty());
+        let versions = index.get_crate_data(CRATE_NAME).await?;
+        assert!(!versions[0].yanked);
+        assert!(versions[1].yanked);
+
+        // only administrators can check the index
+        let token_secret = setup_create_token(&application, ADMIN_UID, true, false).await?;
+        let auth = AuthData::from(Token {
+            id: String::from("admin"),
+            secret: token_secret,
+        });
+        assert_eq!(application.check_index(&auth, false).await.unwrap_err().http, 403);
+        setup_create_user(&application, "user", "").await?;
+        let token_secret = setup_create_token(&application, 2, true, true).await?;
+        let auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: token_secret,
+        });
+        assert_eq!(application.check_index(&auth, false).await.unwrap_err().http, 403);
+        assert_eq!(application.check_index(&auth, true).await.unwrap_err().http, 403);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_index_rebuild_unresolved() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = application.get_service_index();
+        let storage = application.get_service_storage();
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.0.0").await?;
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        let cksum = index.get_crate_data(CRATE_NAME).await?[0].cksum.clone();
+        // tamper with the .crate package of 1.0.0, lose everything about 1.1.0
+        storage
+            .store_crate(&setup_crate_metadata(CRATE_NAME, "1.0.0"), b"tampered".to_vec())
+            .await?;
+        storage.delete_crate(CRATE_NAME, "1.1.0").await?;
+        application
+            .db_transaction_write("test_lose_data", |app| async move {
+                sqlx::query("UPDATE PackageVersion SET indexData = NULL WHERE version = '1.1.0'")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<_, ApiError>(())
+            })
+            .await?;
+
+        // the index is not rebuilt when versions cannot be recovered
+        let report = application.check_index(&admin_auth, true).await?;
+        assert!(!report.rebuilt);
+        let unresolved = report
+            .unresolved
+            .iter()
+            .map(|issue| (issue.version.as_str(), issue.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            unresolved,
+            vec![
+                ("1.0.0", IndexIssueKind::ChecksumMismatch),
+                ("1.1.0", IndexIssueKind::MissingIndexData)
+            ]
+        );
+        assert_eq!(index.get_crate_data(CRATE_NAME).await?.len(), 2);
+
+        // the recorded checksum is kept and the mismatch is still reported
+        application
+            .db_transaction_write("test_remove_version", |app| async move {
+                sqlx::query("DELETE FROM PackageVersion WHERE version = '1.1.0'")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<_, ApiError>(())
+            })
+            .await?;
+        let report = application.check_index(&admin_auth, true).await?;
+        assert!(report.rebuilt);
+        assert_eq!(report.unresolved.len(), 1);
+        assert_eq!(report.unresolved[0].kind, IndexIssueKind::ChecksumMismatch);
+        let versions = index.get_crate_data(CRATE_NAME).await?;
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].cksum, cksum);
+        let report = application.check_index(&admin_auth, false).await?;
+        assert!(
+            report
+                .issues
+                .iter()
+                .any(|issue| issue.version == "1.0.0" && issue.kind == IndexIssueKind::ChecksumMismatch)
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_index_rebuild_exclusive() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.0.0").await?;
+        // a publication that is not complete yet
+        application
+            .db_transaction_write("test_publishing", |app| async move {
+                sqlx::query("UPDATE PackageVersion SET isPublishing = TRUE WHERE version = '1.0.0'")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<_, ApiError>(())
+            })
+            .await?;
+        assert_eq!(application.check_index(&admin_auth, true).await.unwrap_err().http, 409);
+        let report = application.check_index(&admin_auth, false).await?;
+        assert!(!report.rebuilt);
+
+        // the command line cannot use the index of a running registry
+        let error = Application::check_index_standalone::<MockService>(&application.configuration, false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 409);
+        Ok(())
+    })
+}
+
+/// Executes a git command for the tests
+fn git(location: &Path, args: &[&str]) -> Result<String, ApiError> {
+    let output = Command::new("git")
+        .current_dir(location)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Waits until the status of the pushes to the remote origin matches a condition
+async fn wait_for_push_status(
+    application: &Application,
+    auth_data: &AuthData,
+    condition: impl Fn(&IndexPushStatus) -> bool,
+) -> Result<IndexPushStatus, ApiError> {
+    for _ in 0..300 {
+        let status = application.get_registry_information(auth_data).await?.index_push.unwrap();
+        if condition(&status) {
+            return Ok(status);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timeout while waiting for the pushes to the remote origin");
+}
+
+/// Uses a local bare repository as the remote origin of the git index, seeded with a first commit
+fn setup_git_origin(configuration: &mut Configuration) -> Result<(), ApiError> {
+    let origin = PathBuf::from(&configuration.data_dir).join("origin.git");
+    let seed = PathBuf::from(&configuration.data_dir).join("seed");
+    std::fs::create_dir_all(&origin)?;
+    std::fs::create_dir_all(&seed)?;
+    git(&origin, &["init", "--bare", "--initial-branch=master"])?;
+    git(&seed, &["init", "--initial-branch=master"])?;
+    git(&seed, &["commit", "--allow-empty", "-m", "Initial commit"])?;
+    git(&seed, &["push", origin.to_str().unwrap(), "HEAD:master"])?;
+    configuration.index.backend = IndexBackend::Git;
+    configuration.index.location = format!("{}/index", configuration.data_dir);
+    configuration.index.remote_origin = Some(origin.to_str().unwrap().to_string());
+    configuration.index.remote_push_changes = true;
+    configuration.index.user_name = String::from("cratery");
+    configuration.index.user_email = String::from("cratery@example.com");
+    Ok(())
+}
+
+#[test]
+fn test_index_git_push() -> Result<(), ApiError> {
+    async_test_with(setup_git_origin, |application, admin_auth| async move {
+        let origin = PathBuf::from(&application.configuration.data_dir).join("origin.git");
+        let log = || git(&origin, &["log", "master", "--format=%s"]);
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.0.0").await?;
+        let status = wait_for_push_status(&application, &admin_auth, |status| status.pending_commits == 0).await?;
+        assert!(status.last_push.is_some());
+        assert!(status.last_error.is_none());
+        assert_eq!(status.lag, 0);
+        assert!(log()?.contains("Publish hello:1.0.0"));
+
+        // the remote origin is unreachable, the publication still succeeds
+        let moved = PathBuf::from(&application.configuration.data_dir).join("moved.git");
+        std::fs::rename(&origin, &moved)?;
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        let status = wait_for_push_status(&application, &admin_auth, |status| status.failures > 0).await?;
+        assert_eq!(status.pending_commits, 1);
+        assert!(status.pending_since.is_some());
+        assert!(status.last_error.is_some());
+
+        // the pushes resume when the remote origin is back
+        std::fs::rename(&moved, &origin)?;
+        let status = wait_for_push_status(&application, &admin_auth, |status| status.pending_commits == 0).await?;
+        assert_eq!(status.failures, 0);
+        assert!(status.last_error.is_none());
+        assert!(log()?.contains("Publish hello:1.1.0"));
+
+        // only administrators see the status
+        let token_secret = setup_create_token(&application, ADMIN_UID, true, false).await?;
+        let auth = AuthData::from(Token {
+            id: String::from("admin"),
+            secret: token_secret,
+        });
+        assert!(application.get_registry_information(&auth).await?.index_push.is_none());
+        setup_create_user(&application, "user", "").await?;
+        let token_secret = setup_create_token(&application, 2, true, true).await?;
+        let auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: token_secret,
+        });
+        assert!(application.get_registry_information(&auth).await?.index_push.is_none());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_index_git_push_on_launch() -> Result<(), ApiError> {
+    async_test_with(
+        |configuration| {
+            setup_git_origin(configuration)?;
+            // a commit that was not pushed before the last shutdown, with the up-to-date configuration
+            let origin = PathBuf::from(&configuration.data_dir).join("origin.git");
+            let index = PathBuf::from(&configuration.index.location);
+            git(
+                Path::new(&configuration.data_dir),
+                &["clone", origin.to_str().unwrap(), "index"],
+            )?;
+            std::fs::write(index.join("config.json"), serde_json::to_vec(&configuration.index.public)?)?;
+            git(&index, &["add", "."])?;
+            git(&index, &["commit", "-m", "Unpushed commit"])?;
+            Ok(())
+        },
+        |application, admin_auth| async move {
+            let origin = PathBuf::from(&application.configuration.data_dir).join("origin.git");
+            let status = wait_for_push_status(&application, &admin_auth, |status| status.pending_commits == 0).await?;
+            assert!(status.last_push.is_some());
+            assert!(git(&origin, &["log", "master", "--format=%s"])?.contains("Unpushed commit"));
+            Ok(())
+        },
+    )
+}
+
+#[test]
+fn test_index_git_push_on_standalone_rebuild() -> Result<(), ApiError> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(async move {
+        let mut configuration = MockService::get_configuration().await?;
+        setup_git_origin(&mut configuration)?;
+        // a crate in the index that is not in the database
+        let origin = PathBuf::from(&configuration.data_dir).join("origin.git");
+        let index = PathBuf::from(&configuration.index.location);
+        git(
+            Path::new(&configuration.data_dir),
+            &["clone", origin.to_str().unwrap(), "index"],
+        )?;
+        std::fs::write(index.join("config.json"), serde_json::to_vec(&configuration.index.public)?)?;
+        std::fs::create_dir_all(index.join("he/ll"))?;
+        std::fs::write(index.join("he/ll/hello"), b"")?;
+        git(&index, &["add", "."])?;
+        git(&index, &["commit", "-m", "Unpushed commit"])?;
+
+        // the command line pushes the rebuilt index before returning
+        let report = Application::check_index_standalone::<MockService>(&configuration, true).await?;
+        assert!(report.rebuilt);
+        assert!(report.push_error.is_none());
+        let log = git(&origin, &["log", "master", "--format=%s"])?;
+        assert!(log.contains("Unpushed commit"));
+        assert!(log.contains("Rebuild the index"));
+        tokio::fs::remove_dir_all(&configuration.data_dir).await?;
+        Ok(())
+    })
+}
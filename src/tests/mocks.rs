@@ -4,29 +4,34 @@
 
 //! Types and utilities for mocking services
 
+use std::collections::HashMap;
 use std::env::temp_dir;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use chrono::NaiveDateTime;
 use semver::Version;
 use tokio::sync::mpsc::Sender;
 
-use crate::model::cargo::{CrateMetadata, IndexCrateMetadata};
-use crate::model::config::Configuration;
+use crate::model::cargo::{CrateMetadata, CrateUploadData, IndexCrateMetadata, RegistryUser};
+use crate::model::config::{Configuration, IndexBackend};
 use crate::model::deps::DepsAnalysis;
-use crate::model::docs::{DocGenEvent, DocGenJob, DocGenJobSpec, DocGenJobState, DocGenTrigger};
+use crate::model::docs::{DocGenEvent, DocGenJob, DocGenJobSpec, DocGenTrigger};
 use crate::model::osv::SimpleAdvisory;
+use crate::model::policy::PolicyCheckResult;
+use crate::model::semver_checks::SemverCheckJob;
 use crate::model::worker::WorkersManager;
 use crate::services::ServiceProvider;
+use crate::services::database::{db_transaction_read, db_transaction_write};
 use crate::services::deps::DepsChecker;
 use crate::services::docs::DocsGenerator;
 use crate::services::emails::EmailSender;
 use crate::services::index::Index;
+use crate::services::policy::PublishPolicy;
 use crate::services::rustsec::RustSecChecker;
+use crate::services::semver_checks::SemverChecker;
 use crate::services::storage::Storage;
+use crate::tests::SCIM_TOKEN;
 use crate::utils::FaillibleFuture;
-use crate::utils::apierror::ApiError;
+use crate::utils::apierror::{ApiError, error_not_found};
 use crate::utils::db::RwSqlitePool;
 use crate::utils::token::generate_token;
 
@@ -43,18 +48,28 @@ impl ServiceProvider for MockService {
         let mut temp_dir = temp_dir();
         temp_dir.push(format!("cratery-test-{}", generate_token(16)));
         tokio::fs::create_dir_all(&temp_dir).await?;
-        Ok(Configuration {
+        let mut configuration = Configuration {
             data_dir: temp_dir.to_str().unwrap().to_string(),
+            scim_token: Some(SCIM_TOKEN.to_string()),
             ..Default::default()
-        })
+        };
+        // use the index generated from the database, which has no external dependency
+        configuration.index.backend = IndexBackend::Database;
+        configuration.index.allow_protocol_git = false;
+        Ok(configuration)
     }
 
     fn get_storage(_config: &Configuration) -> Arc<dyn Storage + Send + Sync> {
-        Arc::new(Self)
+        Arc::new(MockStorage::default())
     }
 
-    async fn get_index(_config: &Configuration, _expect_empty: bool) -> Result<Arc<dyn Index + Send + Sync>, ApiError> {
-        Ok(Arc::new(Self))
+    async fn get_index(
+        config: &Configuration,
+        expect_empty: bool,
+        service_db_pool: RwSqlitePool,
+        service_storage: Arc<dyn Storage + Send + Sync>,
+    ) -> Result<Arc<dyn Index + Send + Sync>, ApiError> {
+        crate::services::index::get_service(config, expect_empty, service_db_pool, service_storage).await
     }
 
     fn get_rustsec(_config: &Configuration) -> Arc<dyn RustSecChecker + Send + Sync> {
@@ -73,86 +88,124 @@ impl ServiceProvider for MockService {
         Arc::new(Self)
     }
 
+    fn get_publish_policy(_config: Arc<Configuration>) -> Arc<dyn PublishPolicy + Send + Sync> {
+        Arc::new(Self)
+    }
+
     fn get_docs_generator(
         _configuration: Arc<Configuration>,
-        _service_db_pool: RwSqlitePool,
+        service_db_pool: RwSqlitePool,
         _service_storage: Arc<dyn Storage + Send + Sync>,
         _worker_nodes: WorkersManager,
     ) -> Arc<dyn DocsGenerator + Send + Sync> {
+        Arc::new(MockDocsGenerator {
+            service_db_pool,
+            listeners: Mutex::default(),
+        })
+    }
+
+    fn get_semver_checker(
+        _configuration: Arc<Configuration>,
+        _service_db_pool: RwSqlitePool,
+        _service_storage: Arc<dyn Storage + Send + Sync>,
+        _service_email_sender: Arc<dyn EmailSender + Send + Sync>,
+        _worker_nodes: WorkersManager,
+    ) -> Arc<dyn SemverChecker + Send + Sync> {
         Arc::new(Self)
     }
 }
 
-impl Index for MockService {
-    fn get_index_file<'a>(&'a self, _file_path: &'a std::path::Path) -> FaillibleFuture<'a, Option<PathBuf>> {
+impl DepsChecker for MockService {
+    fn precache_crate_io(&self) -> FaillibleFuture<'_, ()> {
         resolved_default()
     }
 
-    fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>> {
-        resolved_default()
+    fn get_crates_io_dependencies(&self) -> FaillibleFuture<'_, Vec<String>> {
+        Box::pin(async { Ok(vec![String::from("serde"), String::from("tokio")]) })
     }
 
-    fn get_upload_pack_for<'a>(&'a self, _input: &'a [u8]) -> FaillibleFuture<'a, Vec<u8>> {
+    fn add_crates_io_dependencies<'a>(&'a self, _version: &'a IndexCrateMetadata) -> FaillibleFuture<'a, ()> {
         resolved_default()
     }
 
-    fn publish_crate_version<'a>(&'a self, _metadata: &'a IndexCrateMetadata) -> FaillibleFuture<'a, ()> {
+    fn check_crate<'a>(
+        &'a self,
+        _package: &'a str,
+        _version: &'a str,
+        _targets: &'a [String],
+    ) -> FaillibleFuture<'a, DepsAnalysis> {
         resolved_default()
     }
+}
 
-    fn remove_crate_version<'a>(&'a self, _package: &'a str, _version: &'a str) -> FaillibleFuture<'a, ()> {
-        resolved_default()
+/// A documentation generator that records the jobs in the database, but never executes them
+pub struct MockDocsGenerator {
+    /// The database pool
+    service_db_pool: RwSqlitePool,
+    /// The active listeners
+    listeners: Mutex<Vec<Sender<DocGenEvent>>>,
+}
+
+impl DocsGenerator for MockDocsGenerator {
+    fn get_jobs(&self) -> FaillibleFuture<'_, Vec<DocGenJob>> {
+        Box::pin(async move {
+            db_transaction_read(
+                &self.service_db_pool,
+                |database| async move { database.get_docgen_jobs().await },
+            )
+            .await
+        })
     }
 
-    fn get_crate_data<'a>(&'a self, _package: &'a str) -> FaillibleFuture<'a, Vec<IndexCrateMetadata>> {
-        resolved_default()
+    fn get_job_log(&self, job_id: i64) -> FaillibleFuture<'_, String> {
+        Box::pin(async move {
+            let job = db_transaction_read(&self.service_db_pool, |database| async move {
+                database.get_docgen_job(job_id).await
+            })
+            .await?;
+            Ok(format!("documentation for {} {}", job.package, job.version))
+        })
     }
-}
 
-impl DepsChecker for MockService {
-    fn precache_crate_io(&self) -> FaillibleFuture<'_, ()> {
-        resolved_default()
+    fn queue<'a>(&'a self, spec: &'a DocGenJobSpec, trigger: &'a DocGenTrigger) -> FaillibleFuture<'a, DocGenJob> {
+        Box::pin(async move {
+            let job = db_transaction_write(&self.service_db_pool, "create_docgen_job", |database| async move {
+                database.create_docgen_job(spec, trigger).await
+            })
+            .await?;
+            let listeners = self.listeners.lock().unwrap().clone();
+            for listener in listeners {
+                let _ = listener.send(DocGenEvent::Queued(Box::new(job.clone()))).await;
+            }
+            Ok(job)
+        })
     }
 
-    fn check_crate<'a>(
-        &'a self,
-        _package: &'a str,
-        _version: &'a str,
-        _targets: &'a [String],
-    ) -> FaillibleFuture<'a, DepsAnalysis> {
+    fn add_listener(&self, listener: Sender<DocGenEvent>) -> FaillibleFuture<'_, ()> {
+        self.listeners.lock().unwrap().push(listener);
         resolved_default()
     }
 }
 
-impl DocsGenerator for MockService {
-    fn get_jobs(&self) -> FaillibleFuture<'_, Vec<DocGenJob>> {
+impl SemverChecker for MockService {
+    fn get_checks<'a>(&'a self, _package: &'a str) -> FaillibleFuture<'a, Vec<SemverCheckJob>> {
         resolved_default()
     }
 
-    fn get_job_log(&self, _job_id: i64) -> FaillibleFuture<'_, String> {
+    fn queue<'a>(&'a self, _package: &'a str, _version: &'a str) -> FaillibleFuture<'a, Option<SemverCheckJob>> {
         resolved_default()
     }
 
-    fn queue<'a>(&'a self, spec: &'a DocGenJobSpec, trigger: &'a DocGenTrigger) -> FaillibleFuture<'a, DocGenJob> {
-        Box::pin(async {
-            Ok(DocGenJob {
-                id: -1,
-                package: spec.package.clone(),
-                version: spec.version.clone(),
-                target: spec.target.clone(),
-                use_native: false,
-                capabilities: Vec::new(),
-                state: DocGenJobState::Queued,
-                queued_on: NaiveDateTime::default(),
-                started_on: NaiveDateTime::default(),
-                finished_on: NaiveDateTime::default(),
-                last_update: NaiveDateTime::default(),
-                trigger: trigger.clone(),
-            })
-        })
+    fn check_content<'a>(
+        &'a self,
+        _package: &'a str,
+        _version: &'a str,
+        _content: &'a [u8],
+    ) -> FaillibleFuture<'a, Option<SemverCheckJob>> {
+        resolved_default()
     }
 
-    fn add_listener(&self, _listener: Sender<DocGenEvent>) -> FaillibleFuture<'_, ()> {
+    fn record<'a>(&'a self, _job: &'a SemverCheckJob) -> FaillibleFuture<'a, ()> {
         resolved_default()
     }
 }
@@ -163,18 +216,68 @@ impl EmailSender for MockService {
     }
 }
 
+/// The license rejected by the mock policy
+pub const POLICY_REJECTED_LICENSE: &str = "GPL-3.0-only";
+/// The keyword for which the mock policy emits a warning
+pub const POLICY_WARNED_KEYWORD: &str = "experimental";
+
+impl PublishPolicy for MockService {
+    fn check_crate<'a>(
+        &'a self,
+        package: &'a CrateUploadData,
+        _uploaded_by: &'a RegistryUser,
+        _is_new_crate: bool,
+    ) -> FaillibleFuture<'a, PolicyCheckResult> {
+        Box::pin(async move {
+            if package.metadata.license.as_deref() == Some(POLICY_REJECTED_LICENSE) {
+                return Ok(PolicyCheckResult {
+                    allowed: false,
+                    message: Some(format!("license {POLICY_REJECTED_LICENSE} is not allowed")),
+                    warnings: Vec::new(),
+                });
+            }
+            let mut result = PolicyCheckResult::allowed();
+            if package.metadata.keywords.iter().any(|k| k == POLICY_WARNED_KEYWORD) {
+                result.warnings.push(String::from("experimental crates are not supported"));
+            }
+            Ok(result)
+        })
+    }
+}
+
 impl RustSecChecker for MockService {
     fn check_crate<'a>(&'a self, _package: &'a str, _version: &'a Version) -> FaillibleFuture<'a, Vec<SimpleAdvisory>> {
         resolved_default()
     }
 }
 
-impl Storage for MockService {
-    fn store_crate<'a>(&'a self, _metadata: &'a CrateMetadata, _content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+/// A mocking storage, keeping the content of the crates in memory
+#[derive(Default)]
+pub struct MockStorage {
+    /// The content of the crates, per name and version
+    crates: Mutex<HashMap<(String, String), Vec<u8>>>,
+}
+
+impl Storage for MockStorage {
+    fn store_crate<'a>(&'a self, metadata: &'a CrateMetadata, content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        let key = (metadata.name.clone(), metadata.vers.clone());
+        self.crates.lock().unwrap().insert(key, content);
         resolved_default()
     }
 
-    fn download_crate<'a>(&'a self, _name: &'a str, _version: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+    fn download_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        let content = self
+            .crates
+            .lock()
+            .unwrap()
+            .get(&(name.to_string(), version.to_string()))
+            .cloned()
+            .ok_or_else(error_not_found);
+        Box::pin(async move { content })
+    }
+
+    fn delete_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, ()> {
+        self.crates.lock().unwrap().remove(&(name.to_string(), version.to_string()));
         resolved_default()
     }
 
@@ -186,6 +289,14 @@ impl Storage for MockService {
         resolved_default()
     }
 
+    fn store_crate_attestation<'a>(&'a self, _name: &'a str, _version: &'a str, _content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        resolved_default()
+    }
+
+    fn download_crate_attestation<'a>(&'a self, _name: &'a str, _version: &'a str) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        resolved_default()
+    }
+
     fn store_doc_file<'a>(&'a self, _path: &'a str, _file: &'a std::path::Path) -> FaillibleFuture<'a, ()> {
         resolved_default()
     }
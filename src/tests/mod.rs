@@ -5,33 +5,59 @@
 //! Tests
 
 use std::future::Future;
+use std::io::Write;
 use std::sync::Arc;
 
 use chrono::Local;
+use data_encoding::BASE64URL_NOPAD;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use p384::ecdsa::signature::Signer;
+use p384::ecdsa::{Signature, SigningKey};
+use ring::rand::SystemRandom;
+use ring::signature::EcdsaKeyPair;
 use tokio::runtime::Builder;
 
 use crate::application::Application;
 use crate::model::auth::ROLE_ADMIN;
+use crate::model::cargo::{CrateMetadata, CrateUploadResult};
+use crate::model::config::Configuration;
 use crate::services::ServiceProvider;
 use crate::utils::apierror::ApiError;
 use crate::utils::axum::auth::{AuthData, Token};
+use crate::utils::paseto::{PasetoToken, get_public_key_id};
 use crate::utils::token::{generate_token, hash_token};
 
+pub mod index;
 pub mod mocks;
+pub mod publish;
 pub mod security;
 
 pub const ADMIN_UID: i64 = 1;
 pub const ADMIN_NAME: &str = "admin";
+pub const SCIM_TOKEN: &str = "scim-token";
 
 /// Wrapper for async tests
 pub fn async_test<F, FUT>(payload: F) -> Result<(), ApiError>
 where
     F: FnOnce(Arc<Application>, AuthData) -> FUT,
     FUT: Future<Output = Result<(), ApiError>>,
+{
+    async_test_with(|_| Ok(()), payload)
+}
+
+/// Wrapper for async tests that require a specific configuration
+pub fn async_test_with<C, F, FUT>(configure: C, payload: F) -> Result<(), ApiError>
+where
+    C: FnOnce(&mut Configuration) -> Result<(), ApiError>,
+    F: FnOnce(Arc<Application>, AuthData) -> FUT,
+    FUT: Future<Output = Result<(), ApiError>>,
 {
     let runtime = Builder::new_current_thread().enable_all().build()?;
     runtime.block_on(async move {
-        let application = Application::launch::<mocks::MockService>(mocks::MockService::get_configuration().await?).await?;
+        let mut configuration = mocks::MockService::get_configuration().await?;
+        configure(&mut configuration)?;
+        let application = Application::launch::<mocks::MockService>(configuration).await?;
         println!("data_dir={}", &application.configuration.data_dir);
         // create the first user ad admin and its token
         setup_create_admin(&application, ADMIN_NAME).await?;
@@ -90,7 +116,7 @@ pub async fn setup_create_token(
     application.db_transaction_write("setup_create_token", {
         let token_secret = hash_token(&token_secret);
         |app| async move {
-            sqlx::query("INSERT INTO RegistryUserToken (user, name, token, lastUsed, canWrite, canAdmin) VALUES ($1, $2, $3, $4, $5, $6)")
+            sqlx::query("INSERT INTO RegistryUserToken (user, name, token, lastUsed, canWrite, canAdmin, crateScopes, operationScopes, expiresOn, expiryNotified) VALUES ($1, $2, $3, $4, $5, $6, '', '', NULL, FALSE)")
             .bind(uid)
             .bind(token_name)
             .bind(token_secret)
@@ -103,3 +129,118 @@ pub async fn setup_create_token(
     }).await?;
     Ok(token_secret)
 }
+
+/// Gets the public key in the PASERK format for a signing key
+pub fn setup_paserk_public_key(key: &SigningKey) -> String {
+    let point = key.verifying_key().to_encoded_point(true);
+    format!("k3.public.{}", BASE64URL_NOPAD.encode(point.as_bytes()))
+}
+
+/// Builds an asymmetric token, as cargo would, for the given claims
+pub fn setup_paseto_token(key: &SigningKey, url: &str, claims: &serde_json::Value) -> AuthData {
+    let public_key = setup_paserk_public_key(key);
+    let point = key.verifying_key().to_encoded_point(true);
+    let message = serde_json::to_vec(claims).unwrap();
+    let footer = serde_json::to_vec(&serde_json::json!({
+        "url": url,
+        "kip": get_public_key_id(&public_key),
+    }))
+    .unwrap();
+    let pieces: [&[u8]; 5] = [point.as_bytes(), b"v3.public.", &message, &footer, b""];
+    let mut pre_auth = (pieces.len() as u64).to_le_bytes().to_vec();
+    for piece in pieces {
+        pre_auth.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+        pre_auth.extend_from_slice(piece);
+    }
+    let signature: Signature = key.sign(&pre_auth);
+    let mut payload = message;
+    payload.extend_from_slice(&signature.to_bytes());
+    let token = format!(
+        "v3.public.{}.{}",
+        BASE64URL_NOPAD.encode(&payload),
+        BASE64URL_NOPAD.encode(&footer)
+    );
+    AuthData::from(PasetoToken::try_parse(&token).unwrap())
+}
+
+/// Builds a JWT signed with ES256, as an OIDC provider would
+pub fn setup_jwt(key: &EcdsaKeyPair, kid: &str, claims: &serde_json::Value) -> String {
+    let header = serde_json::json!({ "alg": "ES256", "typ": "JWT", "kid": kid });
+    let signed = format!(
+        "{}.{}",
+        BASE64URL_NOPAD.encode(&serde_json::to_vec(&header).unwrap()),
+        BASE64URL_NOPAD.encode(&serde_json::to_vec(claims).unwrap())
+    );
+    let signature = key.sign(&SystemRandom::new(), signed.as_bytes()).unwrap();
+    format!("{signed}.{}", BASE64URL_NOPAD.encode(signature.as_ref()))
+}
+
+/// Gets the JWKS for an ES256 key
+pub fn setup_jwks(key: &EcdsaKeyPair, kid: &str) -> serde_json::Value {
+    use ring::signature::KeyPair;
+    let point = key.public_key().as_ref();
+    serde_json::json!({
+        "keys": [{
+            "kty": "EC",
+            "kid": kid,
+            "crv": "P-256",
+            "x": BASE64URL_NOPAD.encode(&point[1..33]),
+            "y": BASE64URL_NOPAD.encode(&point[33..65]),
+        }]
+    })
+}
+
+/// Builds the metadata for a crate, as sent by cargo
+pub fn setup_crate_metadata(name: &str, vers: &str) -> CrateMetadata {
+    CrateMetadata {
+        name: name.to_string(),
+        vers: vers.to_string(),
+        description: Some(format!("The {name} crate")),
+        license: Some(String::from("MIT")),
+        ..Default::default()
+    }
+}
+
+/// Builds the content of the Cargo.toml manifest for a crate
+pub fn setup_crate_manifest(name: &str, vers: &str) -> String {
+    format!("[package]\nname = \"{name}\"\nversion = \"{vers}\"\nedition = \"2024\"\n")
+}
+
+/// Builds a .crate package with the given files, as `(path, content)`
+pub fn setup_crate_content(files: &[(&str, &[u8])]) -> Result<Vec<u8>, ApiError> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, *content)?;
+    }
+    Ok(builder.into_inner()?.finish()?)
+}
+
+/// Builds the payload for publishing a crate, as sent by cargo
+pub fn setup_crate_payload(metadata: &CrateMetadata, content: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let metadata = serde_json::to_vec(metadata)?;
+    let mut payload = Vec::new();
+    payload.write_all(&u32::try_from(metadata.len()).unwrap().to_le_bytes())?;
+    payload.write_all(&metadata)?;
+    payload.write_all(&u32::try_from(content.len()).unwrap().to_le_bytes())?;
+    payload.write_all(content)?;
+    Ok(payload)
+}
+
+/// Publishes a minimal valid crate
+pub async fn setup_publish_crate(
+    application: &Application,
+    auth_data: &AuthData,
+    name: &str,
+    vers: &str,
+) -> Result<CrateUploadResult, ApiError> {
+    let manifest = setup_crate_manifest(name, vers);
+    let content = setup_crate_content(&[
+        (&format!("{name}-{vers}/Cargo.toml"), manifest.as_bytes()),
+        (&format!("{name}-{vers}/src/lib.rs"), b"pub fn hello() {}\n"),
+    ])?;
+    let payload = setup_crate_payload(&setup_crate_metadata(name, vers), &content)?;
+    application.publish_crate_version(auth_data, &payload).await
+}
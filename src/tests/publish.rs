@@ -0,0 +1,775 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests about publishing crates
+
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use super::{
+    ADMIN_UID, async_test, setup_crate_content, setup_crate_manifest, setup_crate_metadata, setup_crate_payload,
+    setup_create_token, setup_create_user, setup_publish_crate,
+};
+use crate::model::attestation::{AttestationPublisher, AttestationStatement, PublishPredicate};
+use crate::model::cargo::{CrateMetadata, CrateUploadData, IndexCrateMetadata, RegistryUser};
+use crate::model::config::{Configuration, PublishPolicyConfig, PublishPolicyHook};
+use crate::model::deps::{DepYanked, DepsAnalysis, DepsAnalysisFlags};
+use crate::model::names::CrateNamePolicySpec;
+use crate::model::packages::CrateDeprecation;
+use crate::services::storage::{extract_crate_file, extract_crate_files, get_crate_file_content_type, list_crate_files};
+use crate::tests::mocks::{POLICY_REJECTED_LICENSE, POLICY_WARNED_KEYWORD};
+use crate::utils::apierror::ApiError;
+use crate::utils::attestation::{AttestationSigner, verify_envelope};
+use crate::utils::axum::auth::{AuthData, Token};
+use crate::utils::token::{generate_token, generate_url_safe_token};
+
+const CRATE_NAME: &str = "hello";
+const CRATE_VERSION: &str = "1.0.0";
+const USER_UID: i64 = 2;
+const USER_NAME: &str = "user";
+
+/// Builds a .crate package with a valid manifest and an additional file
+fn crate_content_with(path: &str, content: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let manifest = setup_crate_manifest(CRATE_NAME, CRATE_VERSION);
+    setup_crate_content(&[
+        (&format!("{CRATE_NAME}-{CRATE_VERSION}/Cargo.toml"), manifest.as_bytes()),
+        (path, content),
+    ])
+}
+
+/// Validates a crate with the default settings
+fn validate(payload: &[u8]) -> Result<CrateUploadData, ApiError> {
+    let mut package = CrateUploadData::new(payload)?;
+    package.validate(&[], 1024)?;
+    Ok(package)
+}
+
+#[test]
+fn test_publish_valid_crate() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let result = setup_publish_crate(&application, &admin_auth, CRATE_NAME, CRATE_VERSION).await?;
+        assert!(result.warnings.invalid_categories.is_empty());
+        let version = application
+            .db_transaction_read(|app| async move { app.database.get_crate_last_version(CRATE_NAME).await })
+            .await?;
+        assert_eq!(version, CRATE_VERSION);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_rejects_invalid_metadata() -> Result<(), ApiError> {
+    let content = crate_content_with(&format!("{CRATE_NAME}-{CRATE_VERSION}/src/lib.rs"), b"")?;
+    let valid = setup_crate_metadata(CRATE_NAME, CRATE_VERSION);
+    validate(&setup_crate_payload(&valid, &content)?)?;
+
+    let mut metadata = valid.clone();
+    metadata.vers = String::from("1.0");
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+
+    let mut metadata = valid.clone();
+    metadata.keywords = vec![String::from("-invalid")];
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+    metadata.keywords = (0..6).map(|i| format!("keyword{i}")).collect();
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+
+    let mut metadata = valid.clone();
+    metadata.license = Some(String::from("MIT OR"));
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+    metadata.license = Some(String::from("MIT/Apache-2.0"));
+    validate(&setup_crate_payload(&metadata, &content)?)?;
+
+    let mut metadata = valid;
+    metadata.rust_version = Some(String::from(">=1.70"));
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+    metadata.rust_version = Some(String::from("1.70"));
+    validate(&setup_crate_payload(&metadata, &content)?)?;
+    Ok(())
+}
+
+#[test]
+fn test_publish_invalid_categories_warnings() -> Result<(), ApiError> {
+    let content = crate_content_with(&format!("{CRATE_NAME}-{CRATE_VERSION}/src/lib.rs"), b"")?;
+    let mut metadata = setup_crate_metadata(CRATE_NAME, CRATE_VERSION);
+    metadata.categories = vec![String::from("web-programming"), String::from("unknown")];
+    let mut package = CrateUploadData::new(&setup_crate_payload(&metadata, &content)?)?;
+    let result = package.validate(&[String::from("web-programming")], 1024)?;
+    assert_eq!(result.warnings.invalid_categories, vec![String::from("unknown")]);
+    assert_eq!(package.metadata.categories, vec![String::from("web-programming")]);
+    Ok(())
+}
+
+#[test]
+fn test_publish_rejects_invalid_content() -> Result<(), ApiError> {
+    let metadata = setup_crate_metadata(CRATE_NAME, CRATE_VERSION);
+
+    // file outside of the root directory
+    let content = crate_content_with("other/src/lib.rs", b"")?;
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+
+    // file above the size limit
+    let content = crate_content_with(&format!("{CRATE_NAME}-{CRATE_VERSION}/src/lib.rs"), &[b' '; 2048])?;
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+
+    // manifest inconsistent with the metadata
+    let manifest = setup_crate_manifest(CRATE_NAME, "2.0.0");
+    let content = setup_crate_content(&[(&format!("{CRATE_NAME}-{CRATE_VERSION}/Cargo.toml"), manifest.as_bytes())])?;
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+
+    // missing manifest
+    let content = setup_crate_content(&[(&format!("{CRATE_NAME}-{CRATE_VERSION}/src/lib.rs"), b"")])?;
+    assert!(validate(&setup_crate_payload(&metadata, &content)?).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_publish_rejects_escaping_symlinks() -> Result<(), ApiError> {
+    let metadata = setup_crate_metadata(CRATE_NAME, CRATE_VERSION);
+    let manifest = setup_crate_manifest(CRATE_NAME, CRATE_VERSION);
+    let build = |target: &str| -> Result<Vec<u8>, ApiError> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(
+            &mut header,
+            format!("{CRATE_NAME}-{CRATE_VERSION}/Cargo.toml"),
+            manifest.as_bytes(),
+        )?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, format!("{CRATE_NAME}-{CRATE_VERSION}/src/link"), target)?;
+        Ok(builder.into_inner()?.finish()?)
+    };
+    validate(&setup_crate_payload(&metadata, &build("../Cargo.toml")?)?)?;
+    assert!(validate(&setup_crate_payload(&metadata, &build("../../../etc/passwd")?)?).is_err());
+    assert!(validate(&setup_crate_payload(&metadata, &build("/etc/passwd")?)?).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_publish_confusable_names() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, false).await?,
+        });
+        setup_publish_crate(&application, &admin_auth, "hello-world", CRATE_VERSION).await?;
+
+        // same names once normalized
+        assert!(
+            setup_publish_crate(&application, &user_auth, "Hello-World", CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        assert!(
+            setup_publish_crate(&application, &user_auth, "hello_world", CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        // confusable with a local crate
+        assert!(
+            setup_publish_crate(&application, &user_auth, "he11o-world", CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        assert!(
+            setup_publish_crate(&application, &user_auth, "hello-wrold", CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        // allowed for the owners of the similar crate
+        setup_publish_crate(&application, &admin_auth, "hello-worlds", CRATE_VERSION).await?;
+        // confusable with a dependency from crates.io
+        assert!(
+            setup_publish_crate(&application, &user_auth, "serde", CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        assert!(
+            setup_publish_crate(&application, &user_auth, "t0kio", CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        // different enough
+        setup_publish_crate(&application, &user_auth, "goodbye", CRATE_VERSION).await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_name_policies() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, false).await?,
+        });
+        application.create_team(&admin_auth, "net").await?;
+        let spec = CrateNamePolicySpec {
+            pattern: String::from("corp-net-*"),
+            team: Some(String::from("net")),
+            reason: None,
+        };
+        assert!(application.create_crate_name_policy(&user_auth, &spec).await.is_err());
+        let policy = application.create_crate_name_policy(&admin_auth, &spec).await?;
+        assert_eq!(policy.pattern, "corp_net_*");
+        application
+            .create_crate_name_policy(
+                &admin_auth,
+                &CrateNamePolicySpec {
+                    pattern: String::from("reserved"),
+                    team: None,
+                    reason: Some(String::from("kept for later")),
+                },
+            )
+            .await?;
+        assert_eq!(application.get_crate_name_policies(&admin_auth).await?.len(), 2);
+
+        // reserved for everyone
+        let error = setup_publish_crate(&application, &admin_auth, "reserved", CRATE_VERSION)
+            .await
+            .unwrap_err();
+        assert!(error.details.unwrap_or_default().contains("kept for later"));
+        // restricted to the team
+        assert!(
+            setup_publish_crate(&application, &user_auth, "corp_net_http", CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        application
+            .add_team_members(&admin_auth, "net", &[USER_NAME.to_string()])
+            .await?;
+        setup_publish_crate(&application, &user_auth, "corp_net_http", CRATE_VERSION).await?;
+        // a team cannot be deleted while used by a policy
+        assert!(application.delete_team(&admin_auth, "net").await.is_err());
+        application.delete_crate_name_policy(&admin_auth, policy.id).await?;
+        application.delete_team(&admin_auth, "net").await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_approval() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, false).await?,
+        });
+        let last_version = || async {
+            application
+                .db_transaction_read(|app| async move { app.database.get_crate_last_version(CRATE_NAME).await })
+                .await
+        };
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, CRATE_VERSION).await?;
+        application
+            .add_crate_owners(&admin_auth, CRATE_NAME, &[USER_NAME.to_string()])
+            .await?;
+        application.set_crate_requires_approval(&admin_auth, CRATE_NAME, true).await?;
+
+        // the new version is held back
+        let result = setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        assert_eq!(result.warnings.other.len(), 1);
+        assert_eq!(last_version().await?, CRATE_VERSION);
+        let pending = application.get_crate_pending_versions(&user_auth, CRATE_NAME).await?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].index.vers, "1.1.0");
+        assert!(
+            setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0")
+                .await
+                .is_err()
+        );
+        application.get_crate_pending_content(&user_auth, CRATE_NAME, "1.1.0").await?;
+
+        // approved by another owner
+        assert!(
+            application
+                .approve_crate_version(&admin_auth, CRATE_NAME, "1.1.0")
+                .await
+                .is_err()
+        );
+        application.approve_crate_version(&user_auth, CRATE_NAME, "1.1.0").await?;
+        assert_eq!(last_version().await?, "1.1.0");
+        assert!(
+            application
+                .get_crate_pending_versions(&user_auth, CRATE_NAME)
+                .await?
+                .is_empty()
+        );
+
+        // rejected
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.2.0").await?;
+        application.reject_crate_version(&user_auth, CRATE_NAME, "1.2.0").await?;
+        assert!(
+            application
+                .get_crate_pending_versions(&user_auth, CRATE_NAME)
+                .await?
+                .is_empty()
+        );
+        assert_eq!(last_version().await?, "1.1.0");
+        let storage = application.get_service_storage();
+        assert!(storage.download_crate(CRATE_NAME, "1.2.0").await.is_err());
+
+        // the upload was interrupted before the .crate package was stored
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.3.0").await?;
+        storage.delete_crate(CRATE_NAME, "1.3.0").await?;
+        let error = application
+            .approve_crate_version(&user_auth, CRATE_NAME, "1.3.0")
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 409);
+        assert_eq!(last_version().await?, "1.1.0");
+        let pending = application.get_crate_pending_versions(&user_auth, CRATE_NAME).await?;
+        assert_eq!(pending.len(), 1);
+        // discarded when resuming after a restart
+        application.resume_crate_version_publishes().await?;
+        assert!(
+            application
+                .get_crate_pending_versions(&user_auth, CRATE_NAME)
+                .await?
+                .is_empty()
+        );
+
+        // only the administrators can turn the approval off
+        let error = application
+            .set_crate_requires_approval(&user_auth, CRATE_NAME, false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        application.set_crate_requires_approval(&user_auth, CRATE_NAME, true).await?;
+        application
+            .set_crate_requires_approval(&admin_auth, CRATE_NAME, false)
+            .await?;
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.4.0").await?;
+        assert_eq!(last_version().await?, "1.4.0");
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_interrupted() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = application.get_service_index();
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, CRATE_VERSION).await?;
+
+        // publications interrupted before the .crate packages were stored
+        for (name, vers) in [(CRATE_NAME, "1.1.0"), ("other", CRATE_VERSION)] {
+            let manifest = setup_crate_manifest(name, vers);
+            let content = setup_crate_content(&[(&format!("{name}-{vers}/Cargo.toml"), manifest.as_bytes())])?;
+            let package = CrateUploadData::new(&setup_crate_payload(&setup_crate_metadata(name, vers), &content)?)?;
+            application
+                .db_transaction_write("test_publish_interrupted", |app| {
+                    let package = &package;
+                    async move {
+                        app.database
+                            .publish_crate_version(ADMIN_UID, package, &package.build_index_data(), &[])
+                            .await
+                    }
+                })
+                .await?;
+        }
+
+        // the versions are not visible
+        assert_eq!(index.get_crate_data(CRATE_NAME).await?.len(), 1);
+        assert_eq!(application.get_crate_info(&admin_auth, CRATE_NAME).await?.versions.len(), 1);
+        assert!(index.get_crate_data("other").await.is_err());
+        assert!(
+            setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0")
+                .await
+                .is_err()
+        );
+
+        // the mock storage does not have them, so that they are rolled back
+        application.resume_crate_version_publishes().await?;
+        let other_exists = application
+            .db_transaction_read(|app| async move { app.database.get_crate_exists("other").await })
+            .await?;
+        assert!(!other_exists);
+
+        // and can be published again
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, "1.1.0").await?;
+        setup_publish_crate(&application, &admin_auth, "other", CRATE_VERSION).await?;
+        assert_eq!(index.get_crate_data(CRATE_NAME).await?.len(), 2);
+        assert_eq!(index.get_crate_data("other").await?.len(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_interrupted_stored() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = application.get_service_index();
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, CRATE_VERSION).await?;
+
+        // publication interrupted after the .crate package was stored
+        let vers = "1.1.0";
+        let manifest = setup_crate_manifest(CRATE_NAME, vers);
+        let content = setup_crate_content(&[(&format!("{CRATE_NAME}-{vers}/Cargo.toml"), manifest.as_bytes())])?;
+        let package = CrateUploadData::new(&setup_crate_payload(&setup_crate_metadata(CRATE_NAME, vers), &content)?)?;
+        application
+            .db_transaction_write("test_publish_interrupted_stored", |app| {
+                let package = &package;
+                async move {
+                    app.database
+                        .publish_crate_version(ADMIN_UID, package, &package.build_index_data(), &[])
+                        .await
+                }
+            })
+            .await?;
+        application
+            .get_service_storage()
+            .store_crate(&package.metadata, package.content.clone())
+            .await?;
+        assert_eq!(index.get_crate_data(CRATE_NAME).await?.len(), 1);
+
+        // the publication is completed
+        application.resume_crate_version_publishes().await?;
+        let versions = index.get_crate_data(CRATE_NAME).await?;
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[1].vers, vers);
+        let info = application.get_crate_info(&admin_auth, CRATE_NAME).await?;
+        assert_eq!(info.versions.len(), 2);
+        let content = application.get_service_storage().download_crate(CRATE_NAME, vers).await?;
+        assert_eq!(content, package.content);
+
+        // nothing is left to resume
+        application.resume_crate_version_publishes().await?;
+        assert_eq!(index.get_crate_data(CRATE_NAME).await?.len(), 2);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_interrupted_first_version() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        // publication of the first version interrupted after a mismatching .crate package was stored
+        let manifest = setup_crate_manifest(CRATE_NAME, CRATE_VERSION);
+        let content = setup_crate_content(&[(&format!("{CRATE_NAME}-{CRATE_VERSION}/Cargo.toml"), manifest.as_bytes())])?;
+        let package = CrateUploadData::new(&setup_crate_payload(
+            &setup_crate_metadata(CRATE_NAME, CRATE_VERSION),
+            &content,
+        )?)?;
+        application
+            .db_transaction_write("test_publish_interrupted_first_version", |app| {
+                let package = &package;
+                async move {
+                    app.database
+                        .publish_crate_version(ADMIN_UID, package, &package.build_index_data(), &[])
+                        .await
+                }
+            })
+            .await?;
+        application
+            .get_service_storage()
+            .store_crate(&package.metadata, b"truncated".to_vec())
+            .await?;
+
+        // the crate has no visible version yet
+        let error = application.get_crate_info(&admin_auth, CRATE_NAME).await.unwrap_err();
+        assert_eq!(error.http, 404);
+
+        // the publication is rolled back and the orphan package is deleted
+        application.resume_crate_version_publishes().await?;
+        assert!(
+            application
+                .get_service_storage()
+                .download_crate(CRATE_NAME, CRATE_VERSION)
+                .await
+                .is_err()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_yank_reason_and_deprecation() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, CRATE_VERSION).await?;
+        let crate_info = || async {
+            application
+                .db_transaction_read(|app| async move {
+                    let index = IndexCrateMetadata {
+                        name: CRATE_NAME.to_string(),
+                        vers: CRATE_VERSION.to_string(),
+                        ..Default::default()
+                    };
+                    app.database.get_crate_info(CRATE_NAME, vec![index]).await
+                })
+                .await
+        };
+
+        application
+            .yank_crate_version(&admin_auth, CRATE_NAME, CRATE_VERSION, Some("broken build script"))
+            .await?;
+        let info = crate_info().await?;
+        assert_eq!(info.versions[0].yank_reason.as_deref(), Some("broken build script"));
+        application
+            .unyank_crate_version(&admin_auth, CRATE_NAME, CRATE_VERSION)
+            .await?;
+        assert!(crate_info().await?.versions[0].yank_reason.is_none());
+
+        let deprecation = CrateDeprecation {
+            is_deprecated: true,
+            message: Some(String::from("superseded")),
+            use_instead: Some(String::from("other")),
+        };
+        application
+            .set_crate_deprecation(&admin_auth, CRATE_NAME, &deprecation)
+            .await?;
+        let info = crate_info().await?;
+        assert!(info.is_deprecated);
+        assert_eq!(info.deprecation_message.as_deref(), Some("superseded"));
+        assert_eq!(info.use_instead.as_deref(), Some("other"));
+
+        // the message and successor are dropped when no longer deprecated
+        let deprecation = CrateDeprecation {
+            is_deprecated: false,
+            ..deprecation
+        };
+        application
+            .set_crate_deprecation(&admin_auth, CRATE_NAME, &deprecation)
+            .await?;
+        let info = crate_info().await?;
+        assert!(!info.is_deprecated);
+        assert!(info.deprecation_message.is_none());
+        assert!(info.use_instead.is_none());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_deps_analysis_yanked_and_deprecated() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_publish_crate(&application, &admin_auth, CRATE_NAME, CRATE_VERSION).await?;
+        application
+            .yank_crate_version(&admin_auth, CRATE_NAME, CRATE_VERSION, Some("broken build script"))
+            .await?;
+        let deprecation = CrateDeprecation {
+            is_deprecated: true,
+            message: Some(String::from("superseded")),
+            use_instead: Some(String::from("other")),
+        };
+        application
+            .set_crate_deprecation(&admin_auth, CRATE_NAME, &deprecation)
+            .await?;
+
+        // as found by the dependency checker for a transitive dependency
+        let mut analysis = DepsAnalysis {
+            yanked: vec![DepYanked {
+                registry: None,
+                package: CRATE_NAME.to_string(),
+                version: CRATE_VERSION.parse().unwrap(),
+                yank_reason: None,
+            }],
+            local_dependencies: vec![CRATE_NAME.to_string()],
+            ..Default::default()
+        };
+        application
+            .db_transaction_read(|app| {
+                let analysis = &mut analysis;
+                async move { app.database.complete_deps_analysis(analysis).await }
+            })
+            .await?;
+        assert_eq!(analysis.yanked[0].yank_reason.as_deref(), Some("broken build script"));
+        assert_eq!(analysis.deprecated.len(), 1);
+        assert_eq!(analysis.deprecated[0].deprecation.use_instead.as_deref(), Some("other"));
+        let flags = analysis.flags();
+        assert!(flags.has_yanked);
+        assert!(flags.has_deprecated);
+
+        let old_flags = application
+            .db_transaction_write("set_crate_deps_analysis", |app| async move {
+                app.database.set_crate_deps_analysis(CRATE_NAME, CRATE_VERSION, flags).await
+            })
+            .await?;
+        assert_eq!(old_flags, DepsAnalysisFlags::default());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_policy() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let publish = |metadata: CrateMetadata| {
+            let application = &application;
+            let admin_auth = &admin_auth;
+            async move {
+                let manifest = setup_crate_manifest(&metadata.name, &metadata.vers);
+                let content = setup_crate_content(&[(
+                    &format!("{}-{}/Cargo.toml", metadata.name, metadata.vers),
+                    manifest.as_bytes(),
+                )])?;
+                let payload = setup_crate_payload(&metadata, &content)?;
+                application.publish_crate_version(admin_auth, &payload).await
+            }
+        };
+
+        // rejected, nothing is stored
+        let mut metadata = setup_crate_metadata(CRATE_NAME, CRATE_VERSION);
+        metadata.license = Some(POLICY_REJECTED_LICENSE.to_string());
+        let error = publish(metadata).await.unwrap_err();
+        assert_eq!(error.http, 403);
+        let exists = application
+            .db_transaction_read(|app| async move { app.database.get_crate_exists(CRATE_NAME).await })
+            .await?;
+        assert!(!exists);
+
+        // accepted with warnings
+        let mut metadata = setup_crate_metadata(CRATE_NAME, CRATE_VERSION);
+        metadata.keywords = vec![POLICY_WARNED_KEYWORD.to_string()];
+        let result = publish(metadata).await?;
+        assert_eq!(result.warnings.other.len(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_policy_command_hook() -> Result<(), ApiError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let mut data_dir = std::env::temp_dir();
+        data_dir.push(format!("cratery-test-{}", generate_token(16)));
+        tokio::fs::create_dir_all(&data_dir).await?;
+        let mut script = data_dir.clone();
+        script.push("policy.sh");
+        tokio::fs::write(
+            &script,
+            "#!/bin/sh\ncat > /dev/null\necho '{\"allowed\": false, \"message\": \"missing repository\"}'\n",
+        )
+        .await?;
+        tokio::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).await?;
+
+        let configuration = Configuration {
+            data_dir: data_dir.to_str().unwrap().to_string(),
+            publish_policy: Some(PublishPolicyConfig {
+                hook: PublishPolicyHook::Command(script.to_str().unwrap().to_string()),
+                with_content: true,
+                timeout: 10,
+            }),
+            ..Default::default()
+        };
+        let service = crate::services::policy::get_service(Arc::new(configuration));
+        let package = CrateUploadData {
+            metadata: setup_crate_metadata(CRATE_NAME, CRATE_VERSION),
+            content: Vec::new(),
+        };
+        let user = RegistryUser {
+            id: 1,
+            is_active: true,
+            email: String::from("admin@example.com"),
+            login: String::from("admin"),
+            name: String::from("admin"),
+            roles: String::new(),
+        };
+        let result = service.check_crate(&package, &user, true).await?;
+        assert!(!result.allowed);
+        assert_eq!(result.message.as_deref(), Some("missing repository"));
+        tokio::fs::remove_dir_all(&data_dir).await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_attestation() -> Result<(), ApiError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let mut key_file = std::env::temp_dir();
+        key_file.push(format!("cratery-test-{}.pem", generate_url_safe_token(16)));
+        let signer = AttestationSigner::load(&key_file).await?;
+        // the generated key is only readable by its owner and is reloaded from disk
+        let mode = tokio::fs::metadata(&key_file).await?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let public_key = signer.public_key();
+        assert_eq!(
+            AttestationSigner::load(&key_file).await?.public_key().key_id,
+            public_key.key_id
+        );
+        // a key file that other users can read is refused
+        tokio::fs::set_permissions(&key_file, std::fs::Permissions::from_mode(0o644)).await?;
+        assert!(AttestationSigner::load(&key_file).await.is_err());
+        tokio::fs::remove_file(&key_file).await?;
+
+        let content = crate_content_with(
+            &format!("{CRATE_NAME}-{CRATE_VERSION}/.cargo_vcs_info.json"),
+            br#"{"git": {"sha1": "0123456789abcdef0123456789abcdef01234567"}, "path_in_vcs": "crates/hello"}"#,
+        )?;
+        let package = validate(&setup_crate_payload(
+            &setup_crate_metadata(CRATE_NAME, CRATE_VERSION),
+            &content,
+        )?)?;
+        let index_data = package.build_index_data();
+        let predicate = PublishPredicate {
+            registry: String::from("https://localhost"),
+            publisher: AttestationPublisher {
+                id: USER_UID,
+                login: USER_NAME.to_string(),
+                email: USER_NAME.to_string(),
+                name: USER_NAME.to_string(),
+            },
+            token: Some(String::from("user:1")),
+            published_at: chrono::Local::now().naive_local(),
+            vcs: package.get_vcs_info()?,
+        };
+        let statement = AttestationStatement::new(CRATE_NAME, CRATE_VERSION, &index_data.cksum, predicate);
+        let envelope = signer.sign(&statement)?;
+
+        let verified = verify_envelope(&envelope, &public_key)?;
+        assert_eq!(verified.subject[0].name, format!("{CRATE_NAME}-{CRATE_VERSION}.crate"));
+        assert_eq!(verified.subject[0].digest.sha256, index_data.cksum);
+        let vcs = verified.predicate.vcs.clone().unwrap();
+        assert_eq!(vcs.git.unwrap().sha1, "0123456789abcdef0123456789abcdef01234567");
+        assert_eq!(vcs.path_in_vcs, "crates/hello");
+
+        // a tampered payload is rejected
+        let mut tampered = envelope;
+        tampered.payload = STANDARD.encode(serde_json::to_vec(&AttestationStatement::new(
+            CRATE_NAME,
+            "2.0.0",
+            &index_data.cksum,
+            verified.predicate,
+        ))?);
+        assert!(verify_envelope(&tampered, &public_key).is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_browse_crate_files() -> Result<(), ApiError> {
+    let content = crate_content_with(&format!("{CRATE_NAME}-{CRATE_VERSION}/src/lib.rs"), b"pub fn hello() {}\n")?;
+    let files = list_crate_files(&content)?;
+    let paths = files.iter().map(|file| file.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(paths, vec!["Cargo.toml", "src/lib.rs"]);
+    assert_eq!(files[1].size, 18);
+
+    let file = extract_crate_file(&content, "src/lib.rs", 1024)?;
+    assert_eq!(file, b"pub fn hello() {}\n");
+    assert_eq!(get_crate_file_content_type("src/lib.rs", &file), "text/plain; charset=utf-8");
+    assert_eq!(get_crate_file_content_type("logo.png", &[0x89, 0x50]), "image/png");
+    assert_eq!(
+        get_crate_file_content_type("data.bin", &[0xff, 0x00]),
+        "application/octet-stream"
+    );
+    // the file is too large
+    assert_eq!(extract_crate_file(&content, "src/lib.rs", 8).unwrap_err().http, 400);
+    // the file does not exist
+    assert_eq!(extract_crate_file(&content, "src/main.rs", 1024).unwrap_err().http, 404);
+
+    // all the files are extracted within the limits
+    let files = extract_crate_files(&content, 2, 1024)?;
+    assert_eq!(files["src/lib.rs"], b"pub fn hello() {}\n");
+    // too many files
+    assert_eq!(extract_crate_files(&content, 1, 1024).unwrap_err().http, 400);
+    // too many bytes in total
+    assert_eq!(extract_crate_files(&content, 2, 32).unwrap_err().http, 400);
+    Ok(())
+}
@@ -4,12 +4,32 @@
 
 //! Tests about security checks
 
+use std::net::IpAddr;
+use std::time::Duration;
+
+use axum::http::{HeaderMap, HeaderValue};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{Local, TimeDelta, Utc};
+use p384::ecdsa::SigningKey;
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair};
+
 use super::{async_test, setup_create_user_inactive};
 use crate::application::Application;
-use crate::model::auth::ROLE_ADMIN;
-use crate::tests::{ADMIN_NAME, ADMIN_UID, setup_create_token, setup_create_user};
-use crate::utils::apierror::ApiError;
+use crate::model::audit::AuditQuery;
+use crate::model::auth::{ROLE_ADMIN, TokenOperation, TokenScopes, TrustedPublisherSpec};
+use crate::model::docs::DocGenEvent;
+use crate::model::packages::{CrateDeprecation, CrateVisibility};
+use crate::model::scim::{ScimGroup, ScimListQuery, ScimPatchRequest, ScimUser};
+use crate::tests::{
+    ADMIN_NAME, ADMIN_UID, SCIM_TOKEN, setup_create_token, setup_create_user, setup_jwks, setup_jwt, setup_paserk_public_key,
+    setup_paseto_token, setup_publish_crate,
+};
+use crate::utils::apierror::{ApiError, error_conflict};
 use crate::utils::axum::auth::{AuthData, Token};
+use crate::utils::axum::extractors::ClientIp;
+use crate::utils::jwt::{Jwks, Jwt, check_id_token_claims};
 
 /// Creates authentication data for the admin in read-only
 async fn create_auth_admin_ro(application: &Application) -> Result<AuthData, ApiError> {
@@ -33,6 +53,36 @@ async fn create_auth_user_ro(application: &Application) -> Result<AuthData, ApiE
     }))
 }
 
+/// Creates an authentication for a user that can write
+async fn create_auth_user_rw(application: &Application) -> Result<AuthData, ApiError> {
+    setup_create_user(application, USER_NAME, "").await?;
+    let user_token = setup_create_token(application, USER_UID, true, true).await?;
+    Ok(AuthData::from(Token {
+        id: String::from("user"),
+        secret: user_token,
+    }))
+}
+
+/// Creates the crate for the tests of trusted publishers
+async fn setup_trusted_crate(application: &Application) -> Result<(), ApiError> {
+    application
+        .db_transaction_write("test_create_crate", |app| async move {
+            sqlx::query("INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove, visibility, requiresApproval) VALUES ('mycrate', 'mycrate', '', '', '', FALSE, FALSE, 'internal', FALSE)")
+                .execute(&mut *app.database.transaction.borrow().await)
+                .await?;
+            Ok::<(), ApiError>(())
+        })
+        .await
+}
+
+/// Builds a deprecation status without message nor successor
+fn deprecation(is_deprecated: bool) -> CrateDeprecation {
+    CrateDeprecation {
+        is_deprecated,
+        ..Default::default()
+    }
+}
+
 #[test]
 fn test_basic_auth_admin_token() -> Result<(), ApiError> {
     async_test(|application, admin_auth| async move {
@@ -131,3 +181,854 @@ fn test_get_users_admin_only() -> Result<(), ApiError> {
         Ok(())
     })
 }
+
+#[test]
+fn test_scoped_token_restrictions() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let token = application
+            .create_token(
+                &admin_auth,
+                "scoped",
+                true,
+                true,
+                TokenScopes::parse("mycorp-net-*", "yank")?,
+                None,
+            )
+            .await?;
+        let scoped_auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: token.secret,
+        });
+        // allowed operation on a matching crate goes through to the crate checks
+        let error = application
+            .yank_crate_version(&scoped_auth, "mycorp-net-http", "1.0.0", None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 400);
+        // matching crate, but operation not in the scopes
+        let error = application
+            .add_crate_owners(&scoped_auth, "mycorp-net-http", &[String::from("user")])
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        let error = application
+            .set_crate_deprecation(&scoped_auth, "mycorp-net-http", &deprecation(true))
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        // allowed operation, but crate not in the scopes
+        let error = application
+            .yank_crate_version(&scoped_auth, "mycorp-web", "1.0.0", None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        // a scoped token cannot create new tokens
+        let error = application
+            .create_token(&scoped_auth, "escape", true, true, TokenScopes::unrestricted(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        // a scoped token cannot administrate the registry, even with canAdmin
+        let error = application.get_users(&scoped_auth).await.unwrap_err();
+        assert_eq!(error.http, 403);
+        setup_create_user(&application, USER_NAME, "").await?;
+        let error = application.deactivate_user(&scoped_auth, USER_NAME).await.unwrap_err();
+        assert_eq!(error.http, 403);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_scoped_token_invalid_scopes() {
+    assert!(TokenScopes::parse("mycorp-*", "publish-new,publish-update").is_ok());
+    assert!(TokenScopes::parse("my*corp", "").is_err());
+    assert!(TokenScopes::parse("", "delete").is_err());
+}
+
+#[test]
+fn test_expired_token_rejected() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let now = Local::now().naive_local();
+        // expiry must be in the future
+        assert!(
+            application
+                .create_token(
+                    &admin_auth,
+                    "past",
+                    false,
+                    false,
+                    TokenScopes::unrestricted(),
+                    Some(now - TimeDelta::days(1))
+                )
+                .await
+                .is_err()
+        );
+        let token = application
+            .create_token(
+                &admin_auth,
+                "future",
+                false,
+                false,
+                TokenScopes::unrestricted(),
+                Some(now + TimeDelta::days(1)),
+            )
+            .await?;
+        let auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: token.secret,
+        });
+        assert!(application.get_current_user(&auth).await.is_ok());
+        // force the expiry
+        application
+            .db_transaction_write("test_expire_token", |app| async move {
+                sqlx::query("UPDATE RegistryUserToken SET expiresOn = $1 WHERE id = $2")
+                    .bind(now - TimeDelta::minutes(1))
+                    .bind(token.id)
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<(), ApiError>(())
+            })
+            .await?;
+        let error = application.get_current_user(&auth).await.unwrap_err();
+        assert_eq!(error.http, 401);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_asymmetric_token() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let key = SigningKey::from_slice(&[7; 48]).unwrap();
+        let url = format!("sparse+{}/", application.configuration.web_public_uri);
+        let now = Utc::now();
+        let claims = serde_json::json!({ "iat": now.to_rfc3339() });
+        // unknown key
+        assert!(
+            application
+                .get_current_user(&setup_paseto_token(&key, &url, &claims))
+                .await
+                .is_err()
+        );
+        assert!(
+            application
+                .add_public_key(&admin_auth, "invalid", "k3.public.AAAA")
+                .await
+                .is_err()
+        );
+        application
+            .add_public_key(&admin_auth, "laptop", &setup_paserk_public_key(&key))
+            .await?;
+        let user = application.get_current_user(&setup_paseto_token(&key, &url, &claims)).await?;
+        assert_eq!(user.id, ADMIN_UID);
+        // a key cannot be used to manage keys and tokens
+        assert!(
+            application
+                .get_public_keys(&setup_paseto_token(&key, &url, &claims))
+                .await
+                .is_err()
+        );
+        // wrong registry
+        let other = setup_paseto_token(&key, "sparse+https://example.com/", &claims);
+        assert!(application.get_current_user(&other).await.is_err());
+        // too old
+        let old = serde_json::json!({ "iat": (now - TimeDelta::hours(1)).to_rfc3339() });
+        assert!(
+            application
+                .get_current_user(&setup_paseto_token(&key, &url, &old))
+                .await
+                .is_err()
+        );
+        // a token for a mutation cannot be used for something else
+        let yank = serde_json::json!({ "iat": now.to_rfc3339(), "mutation": "yank", "name": "foo", "vers": "1.0.0" });
+        assert!(
+            application
+                .get_current_user(&setup_paseto_token(&key, &url, &yank))
+                .await
+                .is_err()
+        );
+        let yank = setup_paseto_token(&key, &url, &yank);
+        let error = application.yank_crate_version(&yank, "bar", "1.0.0", None).await.unwrap_err();
+        assert_eq!(error.http, 401);
+        // the key is removed
+        let keys = application.get_public_keys(&admin_auth).await?;
+        assert_eq!(keys.len(), 1);
+        application.remove_public_key(&admin_auth, keys[0].id).await?;
+        assert!(
+            application
+                .get_current_user(&setup_paseto_token(&key, &url, &claims))
+                .await
+                .is_err()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_trusted_publishing() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let jwks = format!("{}/jwks.json", application.configuration.data_dir);
+        tokio::fs::write(&jwks, serde_json::to_vec(&setup_jwks(&key, "ci")).unwrap()).await?;
+        setup_trusted_crate(&application).await?;
+        let issuer = "https://token.actions.githubusercontent.com";
+        let spec = TrustedPublisherSpec {
+            issuer: issuer.to_string(),
+            jwks,
+            repository: String::from("mycorp/mycrate"),
+            branch: Some(String::from("main")),
+            workflow: Some(String::from("release.yml")),
+        };
+        // a publisher with an unreachable JWKS does not prevent the others
+        let broken = TrustedPublisherSpec {
+            jwks: format!("{}/missing.json", application.configuration.data_dir),
+            ..spec.clone()
+        };
+        application.add_trusted_publisher(&admin_auth, "mycrate", &broken).await?;
+        application.add_trusted_publisher(&admin_auth, "mycrate", &spec).await?;
+        let now = Utc::now().timestamp();
+        let mut claims = serde_json::json!({
+            "iss": issuer,
+            "aud": application.configuration.web_public_uri,
+            "exp": now + 300,
+            "repository": "mycorp/mycrate",
+            "ref": "refs/heads/main",
+            "job_workflow_ref": "mycorp/mycrate/.github/workflows/release.yml@refs/heads/main",
+        });
+        let token = application
+            .exchange_trusted_publishing_token(&AuthData::default(), &setup_jwt(&key, "ci", &claims), None)
+            .await?;
+        let decoded = String::from_utf8(STANDARD.decode(token.token.strip_prefix("Basic ").unwrap()).unwrap()).unwrap();
+        let (login, secret) = decoded.split_once(':').unwrap();
+        let ci_auth = AuthData::from(Token {
+            id: login.to_string(),
+            secret: secret.to_string(),
+        });
+        // the token can only publish new versions of the crate
+        let authentication = application.authenticate(&ci_auth).await?;
+        assert!(
+            authentication
+                .check_can_operate_on_crate("mycrate", Some(TokenOperation::PublishUpdate))
+                .is_ok()
+        );
+        assert!(
+            authentication
+                .check_can_operate_on_crate("other", Some(TokenOperation::PublishUpdate))
+                .is_err()
+        );
+        let error = application
+            .yank_crate_version(&ci_auth, "mycrate", "1.0.0", None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        assert!(application.get_trusted_publishers(&ci_auth, "mycrate").await.is_err());
+        // claims that do not match
+        claims["ref"] = serde_json::json!("refs/heads/feature");
+        let jwt = setup_jwt(&key, "ci", &claims);
+        assert!(
+            application
+                .exchange_trusted_publishing_token(&AuthData::default(), &jwt, None)
+                .await
+                .is_err()
+        );
+        claims["ref"] = serde_json::json!("refs/heads/main");
+        claims["exp"] = serde_json::json!(now - 3600);
+        let jwt = setup_jwt(&key, "ci", &claims);
+        assert!(
+            application
+                .exchange_trusted_publishing_token(&AuthData::default(), &jwt, None)
+                .await
+                .is_err()
+        );
+        // signed by another key
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let other = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        claims["exp"] = serde_json::json!(now + 300);
+        let jwt = setup_jwt(&other, "ci", &claims);
+        assert!(
+            application
+                .exchange_trusted_publishing_token(&AuthData::default(), &jwt, None)
+                .await
+                .is_err()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_trusted_publishing_deactivated_creator() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let jwks = format!("{}/jwks.json", application.configuration.data_dir);
+        tokio::fs::write(&jwks, serde_json::to_vec(&setup_jwks(&key, "ci")).unwrap()).await?;
+        setup_trusted_crate(&application).await?;
+        // the publisher is configured by another administrator
+        setup_create_user(&application, USER_NAME, ROLE_ADMIN).await?;
+        let other_admin = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, USER_UID, true, true).await?,
+        });
+        let issuer = "https://token.actions.githubusercontent.com";
+        let spec = TrustedPublisherSpec {
+            issuer: issuer.to_string(),
+            jwks,
+            repository: String::from("mycorp/mycrate"),
+            branch: None,
+            workflow: None,
+        };
+        application.add_trusted_publisher(&other_admin, "mycrate", &spec).await?;
+        let claims = serde_json::json!({
+            "iss": issuer,
+            "aud": application.configuration.web_public_uri,
+            "exp": Utc::now().timestamp() + 300,
+            "repository": "mycorp/mycrate",
+        });
+        let jwt = setup_jwt(&key, "ci", &claims);
+        let exchange = || async {
+            let token = application
+                .exchange_trusted_publishing_token(&AuthData::default(), &jwt, None)
+                .await?;
+            let decoded = String::from_utf8(STANDARD.decode(token.token.strip_prefix("Basic ").unwrap()).unwrap()).unwrap();
+            let (login, secret) = decoded.split_once(':').unwrap();
+            Ok::<_, ApiError>(AuthData::from(Token {
+                id: login.to_string(),
+                secret: secret.to_string(),
+            }))
+        };
+
+        // the tokens stop working as soon as the creator is inactive
+        let ci_auth = exchange().await?;
+        application.authenticate(&ci_auth).await?;
+        application
+            .db_transaction_write("test_deactivate", |app| async move {
+                sqlx::query("UPDATE RegistryUser SET isActive = FALSE WHERE id = $1")
+                    .bind(USER_UID)
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<(), ApiError>(())
+            })
+            .await?;
+        assert_eq!(application.authenticate(&ci_auth).await.unwrap_err().http, 401);
+
+        // deactivating the creator removes the trusted publishers
+        application
+            .db_transaction_write("test_reactivate", |app| async move {
+                sqlx::query("UPDATE RegistryUser SET isActive = TRUE WHERE id = $1")
+                    .bind(USER_UID)
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<(), ApiError>(())
+            })
+            .await?;
+        application.authenticate(&ci_auth).await?;
+        application.deactivate_user(&admin_auth, USER_NAME).await?;
+        assert!(application.authenticate(&ci_auth).await.is_err());
+        assert!(application.get_trusted_publishers(&admin_auth, "mycrate").await?.is_empty());
+        assert!(exchange().await.is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_trusted_publishers_admin_only() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_trusted_crate(&application).await?;
+        let user_auth = create_auth_user_rw(&application).await?;
+        application
+            .db_transaction_write("test_add_owner", |app| async move {
+                sqlx::query("INSERT INTO PackageOwner (package, owner) VALUES ('mycrate', $1)")
+                    .bind(USER_UID)
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<(), ApiError>(())
+            })
+            .await?;
+        let spec = TrustedPublisherSpec {
+            issuer: String::from("https://token.actions.githubusercontent.com"),
+            jwks: String::from("/etc/passwd"),
+            repository: String::from("mycorp/mycrate"),
+            branch: None,
+            workflow: None,
+        };
+        // an owner cannot configure trusted publishers
+        let error = application
+            .add_trusted_publisher(&user_auth, "mycrate", &spec)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        let publisher = application.add_trusted_publisher(&admin_auth, "mycrate", &spec).await?;
+        let error = application
+            .remove_trusted_publisher(&user_auth, "mycrate", publisher.id)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_oidc_id_token() -> Result<(), ApiError> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+    let jwks = serde_json::from_value::<Jwks>(setup_jwks(&key, "idp")).unwrap();
+    let issuer = "https://idp.example.com";
+    let now = Utc::now().timestamp();
+    let mut claims = serde_json::json!({
+        "iss": issuer,
+        "aud": "cratery",
+        "exp": now + 300,
+        "nonce": "abc",
+        "email": "someone@example.com",
+    });
+    let id_token = setup_jwt(&key, "idp", &claims);
+    let verified = Jwt::parse(&id_token)?.verify(&jwks)?;
+    check_id_token_claims(&verified, issuer, "cratery", "abc", now)?;
+    // replayed for another login
+    assert!(check_id_token_claims(&verified, issuer, "cratery", "xyz", now).is_err());
+    // intended for another client
+    assert!(check_id_token_claims(&verified, issuer, "other", "abc", now).is_err());
+    // expired
+    assert!(check_id_token_claims(&verified, issuer, "cratery", "abc", now + 3600).is_err());
+    // tampered claims
+    claims["email"] = serde_json::Value::String(String::from("admin@example.com"));
+    let (_, signature) = id_token.rsplit_once('.').unwrap();
+    let forged = setup_jwt(&key, "idp", &claims);
+    let (signed, _) = forged.rsplit_once('.').unwrap();
+    let tampered = format!("{signed}.{signature}");
+    assert!(Jwt::parse(&tampered)?.verify(&jwks).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_team_ownership() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        application
+            .db_transaction_write("test_create_crate", |app| async move {
+                sqlx::query("INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove, visibility, requiresApproval) VALUES ('mycrate', 'mycrate', '', '', '', FALSE, FALSE, 'internal', FALSE)")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                sqlx::query("INSERT INTO PackageOwner (package, owner) VALUES ('mycrate', $1)")
+                    .bind(ADMIN_UID)
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<(), ApiError>(())
+            })
+            .await?;
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, false).await?,
+        });
+        // only admins manage teams
+        let error = application.create_team(&user_auth, "platform").await.unwrap_err();
+        assert_eq!(error.http, 403);
+        application.create_team(&admin_auth, "platform").await?;
+        let error = application.create_team(&admin_auth, "platform").await.unwrap_err();
+        assert_eq!(error.http, error_conflict().http);
+        application
+            .add_crate_owners(&admin_auth, "mycrate", &[String::from("team:platform")])
+            .await?;
+        let owners = application.get_crate_owners(&admin_auth, "mycrate").await?;
+        assert_eq!(owners.teams.len(), 1);
+
+        // not yet a member
+        let error = application
+            .set_crate_deprecation(&user_auth, "mycrate", &deprecation(true))
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        application
+            .add_team_members(&admin_auth, "platform", &[USER_NAME.to_string()])
+            .await?;
+        application
+            .set_crate_deprecation(&user_auth, "mycrate", &deprecation(true))
+            .await?;
+
+        // memberships added by hand are not removed by the sync
+        let sync = |teams: &'static [&'static str]| {
+            application.db_transaction_write("test_sync_teams", move |app| async move {
+                app.database.sync_user_teams(USER_UID, teams).await
+            })
+        };
+        sync(&[]).await?;
+        application
+            .set_crate_deprecation(&user_auth, "mycrate", &deprecation(false))
+            .await?;
+        application
+            .remove_team_members(&admin_auth, "platform", &[USER_NAME.to_string()])
+            .await?;
+        sync(&["platform", "unknown"]).await?;
+        application
+            .set_crate_deprecation(&user_auth, "mycrate", &deprecation(true))
+            .await?;
+        sync(&[]).await?;
+        let error = application
+            .set_crate_deprecation(&user_auth, "mycrate", &deprecation(false))
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+
+        // a team that is the only owner cannot be deleted
+        application
+            .remove_crate_owners(&admin_auth, "mycrate", &[ADMIN_NAME.to_string()])
+            .await?;
+        let error = application.delete_team(&admin_auth, "platform").await.unwrap_err();
+        assert_eq!(error.http, 400);
+        let error = application
+            .remove_crate_owners(&admin_auth, "mycrate", &[String::from("team:platform")])
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 400);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_scim_provisioning() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let mut scim_auth = AuthData::default();
+        scim_auth.bearer = Some(String::from("wrong"));
+        let error = application
+            .scim_get_users(&scim_auth, &ScimListQuery::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 401);
+        let error = application
+            .scim_get_users(&admin_auth, &ScimListQuery::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 401);
+        scim_auth.bearer = Some(SCIM_TOKEN.to_string());
+
+        // provision a user
+        let user = serde_json::from_str::<ScimUser>(
+            r#"{"schemas":["urn:ietf:params:scim:schemas:core:2.0:User"],"userName":"jane@example.com","name":{"givenName":"Jane","familyName":"Doe"},"active":"True"}"#,
+        )?;
+        let user = application.scim_create_user(&scim_auth, &user).await?;
+        let id = user.id.clone().unwrap();
+        assert_eq!(user.display_name.as_deref(), Some("Jane Doe"));
+        assert!(user.active);
+        let error = application
+            .scim_create_user(
+                &scim_auth,
+                &ScimUser::from_user(&application.get_users(&admin_auth).await?[0], ""),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, error_conflict().http);
+        let query = ScimListQuery {
+            filter: Some(String::from(r#"userName eq "jane@example.com""#)),
+            ..Default::default()
+        };
+        assert_eq!(application.scim_get_users(&scim_auth, &query).await?.total_results, 1);
+
+        // deactivating the user revokes its tokens
+        let uid = id.parse::<i64>()?;
+        let secret = setup_create_token(&application, uid, true, false).await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("jane"),
+            secret,
+        });
+        application.get_current_user(&user_auth).await?;
+        let patch = serde_json::from_str::<ScimPatchRequest>(
+            r#"{"Operations":[{"op":"Replace","path":"active","value":"False"},{"op":"replace","path":"displayName","value":"Jane D."}]}"#,
+        )?;
+        let user = application.scim_patch_user(&scim_auth, &id, &patch).await?;
+        assert!(!user.active);
+        assert_eq!(user.display_name.as_deref(), Some("Jane D."));
+        application.get_current_user(&user_auth).await.unwrap_err();
+
+        // groups are teams
+        let group =
+            serde_json::from_str::<ScimGroup>(&format!(r#"{{"displayName":"platform","members":[{{"value":"{id}"}}]}}"#))?;
+        let group = application.scim_create_group(&scim_auth, &group).await?;
+        let group_id = group.id.clone().unwrap();
+        let teams = application.get_teams(&admin_auth).await?;
+        assert_eq!(teams[0].name, "platform");
+        assert!(teams[0].members[0].is_synced);
+        let patch = serde_json::from_str::<ScimPatchRequest>(&format!(
+            r#"{{"Operations":[{{"op":"remove","path":"members[value eq \"{id}\"]"}}]}}"#
+        ))?;
+        let group = application.scim_patch_group(&scim_auth, &group_id, &patch).await?;
+        assert!(group.members.is_empty());
+        application.scim_delete_group(&scim_auth, &group_id).await?;
+        assert!(application.get_teams(&admin_auth).await?.is_empty());
+
+        // deprovision the user
+        application.scim_delete_user(&scim_auth, &id).await?;
+        let error = application.scim_get_user(&scim_auth, &id).await.unwrap_err();
+        assert_eq!(error.http, 404);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_crate_visibility() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        application
+            .db_transaction_write("test_create_crate", |app| async move {
+                sqlx::query("INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove, visibility, requiresApproval) VALUES ('mycrate', 'mycrate', '', '', '', FALSE, FALSE, 'internal', FALSE)")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                sqlx::query("INSERT INTO PackageOwner (package, owner) VALUES ('mycrate', $1)")
+                    .bind(ADMIN_UID)
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                sqlx::query("INSERT INTO PackageVersion (package, version, description, upload, uploadedBy, yanked, downloadCount, downloads, depsLastCheck, depsHasOutdated, depsHasCVEs, depsHasYanked, depsHasDeprecated, isPublishing) VALUES ('mycrate', '1.0.0', '', 0, $1, FALSE, 0, NULL, 0, FALSE, FALSE, FALSE, FALSE, FALSE)")
+                    .bind(ADMIN_UID)
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await?;
+                Ok::<(), ApiError>(())
+            })
+            .await?;
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, false, false).await?,
+        });
+        let index_file = "/my/cr/mycrate";
+
+        // internal crates can be read by all users
+        application.get_crate_owners(&user_auth, "mycrate").await?;
+        application.check_can_read_index_file(&user_auth, index_file).await?;
+        let results = application.search_crates(&user_auth, "my", None, None).await?;
+        assert_eq!(results.crates.len(), 1);
+
+        // only owners can change the visibility
+        let error = application
+            .set_crate_visibility(&user_auth, "mycrate", CrateVisibility::Restricted)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        application
+            .set_crate_visibility(&admin_auth, "mycrate", CrateVisibility::Restricted)
+            .await?;
+
+        // restricted crates are hidden from other users
+        let error = application.get_crate_owners(&user_auth, "mycrate").await.unwrap_err();
+        assert_eq!(error.http, 404);
+        let error = application
+            .check_can_read_index_file(&user_auth, index_file)
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 404);
+        application.check_can_read_index_file(&user_auth, "/config.json").await?;
+        let results = application.search_crates(&user_auth, "my", None, None).await?;
+        assert!(results.crates.is_empty());
+        assert_eq!(application.get_crates_stats(&user_auth).await?.total_crates, 0);
+        application.check_can_read_index_file(&admin_auth, index_file).await?;
+        assert_eq!(application.get_crates_stats(&admin_auth).await?.total_crates, 1);
+        // the git index cannot be filtered and is refused
+        let error = application.check_can_read_git_index(&user_auth).await.unwrap_err();
+        assert_eq!(error.http, 403);
+        application.check_can_read_git_index(&admin_auth).await?;
+
+        // readers, directly or through a team
+        application
+            .add_crate_readers(&admin_auth, "mycrate", &[USER_NAME.to_string()])
+            .await?;
+        application.check_can_read_index_file(&user_auth, index_file).await?;
+        application
+            .remove_crate_readers(&admin_auth, "mycrate", &[USER_NAME.to_string()])
+            .await?;
+        application.check_can_read_crate(&user_auth, "mycrate").await.unwrap_err();
+        application.create_team(&admin_auth, "readers").await?;
+        application
+            .add_team_members(&admin_auth, "readers", &[USER_NAME.to_string()])
+            .await?;
+        application
+            .add_crate_readers(&admin_auth, "mycrate", &[String::from("team:readers")])
+            .await?;
+        application.check_can_read_crate(&user_auth, "mycrate").await?;
+        application.check_can_read_git_index(&user_auth).await?;
+        let readers = application.get_crate_readers(&admin_auth, "mycrate").await?;
+        assert!(readers.users.is_empty());
+        assert_eq!(readers.teams.len(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_doc_gen_jobs_visibility() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_publish_crate(&application, &admin_auth, "mycrate", "1.0.0").await?;
+        setup_publish_crate(&application, &admin_auth, "public", "1.0.0").await?;
+        application
+            .set_crate_visibility(&admin_auth, "mycrate", CrateVisibility::Restricted)
+            .await?;
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, false, false).await?,
+        });
+
+        // the jobs and logs for restricted crates are hidden
+        let jobs = application.get_doc_gen_jobs(&admin_auth).await?;
+        let hidden_job = jobs.iter().find(|job| job.package == "mycrate").unwrap().id;
+        let public_job = jobs.iter().find(|job| job.package == "public").unwrap().id;
+        let jobs = application.get_doc_gen_jobs(&user_auth).await?;
+        assert!(jobs.iter().all(|job| job.package == "public"));
+        assert!(!jobs.is_empty());
+        let error = application.get_doc_gen_job_log(&user_auth, hidden_job).await.unwrap_err();
+        assert_eq!(error.http, 404);
+        application.get_doc_gen_job_log(&user_auth, public_job).await?;
+        application.get_doc_gen_job_log(&admin_auth, hidden_job).await?;
+
+        // and so are their updates
+        let mut updates = application.get_doc_gen_job_updates(&user_auth).await?;
+        setup_publish_crate(&application, &admin_auth, "mycrate", "1.1.0").await?;
+        setup_publish_crate(&application, &admin_auth, "public", "1.1.0").await?;
+        let event = tokio::time::timeout(Duration::from_secs(5), updates.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let DocGenEvent::Queued(job) = event else {
+            panic!("expected a queued job");
+        };
+        assert_eq!(job.package, "public");
+        assert!(updates.try_recv().is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_audit_log() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        let mut admin_auth = admin_auth;
+        admin_auth.client_ip = Some(IpAddr::from([10, 0, 0, 1]));
+        application.deactivate_user(&admin_auth, USER_NAME).await?;
+        application.reactivate_user(&admin_auth, USER_NAME).await?;
+        application.create_team(&admin_auth, "auditors").await?;
+
+        // a failed operation is not recorded
+        application.create_team(&admin_auth, "auditors").await.unwrap_err();
+
+        let events = application.get_audit_events(&admin_auth, &AuditQuery::default()).await?;
+        assert_eq!(events.len(), 3);
+        // the most recent first
+        assert_eq!(events[0].action, "create_team");
+        assert_eq!(events[0].target, "auditors");
+        assert_eq!(events[2].action, "deactivate_user");
+        assert_eq!(events[2].actor, ADMIN_NAME);
+        assert!(events[2].token_id.as_deref().is_some_and(|id| id.starts_with("token:")));
+        assert_eq!(events[2].client_ip.as_deref(), Some("10.0.0.1"));
+
+        // filters
+        let query = AuditQuery {
+            target: Some(USER_NAME.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(application.get_audit_events(&admin_auth, &query).await?.len(), 2);
+        let query = AuditQuery {
+            action: Some(String::from("reactivate_user")),
+            ..Default::default()
+        };
+        assert_eq!(application.get_audit_events(&admin_auth, &query).await?.len(), 1);
+        let query = AuditQuery {
+            since: Some(Local::now().naive_local() + TimeDelta::hours(1)),
+            ..Default::default()
+        };
+        assert!(application.get_audit_events(&admin_auth, &query).await?.is_empty());
+        let query = AuditQuery {
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(application.get_audit_events(&admin_auth, &query).await?.len(), 1);
+
+        // SCIM operations are recorded as such
+        let mut scim_auth = AuthData::default();
+        scim_auth.bearer = Some(SCIM_TOKEN.to_string());
+        application
+            .scim_create_group(&scim_auth, &serde_json::from_str(r#"{"displayName":"provisioned"}"#)?)
+            .await?;
+        let events = application.export_audit_events(&admin_auth, &AuditQuery::default()).await?;
+        assert_eq!(events[0].actor, "scim");
+        assert_eq!(events[0].action, "scim_create_group");
+
+        // the audit log is for admins only
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, false, true).await?,
+        });
+        let error = application
+            .get_audit_events(&user_auth, &AuditQuery::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.http, 403);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_rate_limits() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        let secret = setup_create_token(&application, USER_UID, false, false).await?;
+        let mut wrong_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: String::from("wrong"),
+        });
+        wrong_auth.client_ip = Some(IpAddr::from([10, 0, 0, 1]));
+        let mut user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret,
+        });
+        user_auth.client_ip = wrong_auth.client_ip;
+
+        // the client is locked out after too many failed authentications
+        for _ in 0..application.configuration.rate_limit_auth_failures {
+            let error = application.get_current_user(&wrong_auth).await.unwrap_err();
+            assert_eq!(error.http, 401);
+        }
+        let error = application.get_current_user(&wrong_auth).await.unwrap_err();
+        assert_eq!(error.http, 429);
+        let error = application.get_current_user(&user_auth).await.unwrap_err();
+        assert_eq!(error.http, 429);
+        assert!(application.get_retry_after(&user_auth).is_some());
+        application.get_current_user(&admin_auth).await?;
+        assert!(application.get_retry_after(&admin_auth).is_none());
+        // the login is locked out from other clients too
+        user_auth.client_ip = Some(IpAddr::from([10, 0, 0, 3]));
+        let error = application.get_current_user(&user_auth).await.unwrap_err();
+        assert_eq!(error.http, 429);
+
+        // expensive operations are limited per client IP
+        let mut admin_auth = admin_auth;
+        admin_auth.client_ip = Some(IpAddr::from([10, 0, 0, 2]));
+        for _ in 0..application.configuration.rate_limit_requests {
+            application.search_crates(&admin_auth, "my", None, None).await?;
+        }
+        let error = application.search_crates(&admin_auth, "my", None, None).await.unwrap_err();
+        assert_eq!(error.http, 429);
+        let mut other_auth = AuthData::default();
+        other_auth.client_ip = admin_auth.client_ip;
+        let error = application.search_crates(&other_auth, "my", None, None).await.unwrap_err();
+        assert_eq!(error.http, 429);
+        // and per authenticated user, once the credentials are verified
+        admin_auth.client_ip = Some(IpAddr::from([10, 0, 0, 4]));
+        let error = application.search_crates(&admin_auth, "my", None, None).await.unwrap_err();
+        assert_eq!(error.http, 429);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_client_ip_behind_proxies() {
+    let proxy = IpAddr::from([10, 0, 0, 1]);
+    let client = IpAddr::from([192, 168, 0, 1]);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", HeaderValue::from_static("1.2.3.4, 192.168.0.1"));
+
+    // the header is ignored when the peer is not a trusted proxy
+    assert_eq!(ClientIp(Some(client)).behind_proxies(&headers, &[]).0, Some(client));
+    assert_eq!(ClientIp(Some(client)).behind_proxies(&headers, &[proxy]).0, Some(client));
+    // the client is the last address that is not a trusted proxy, the first ones can be forged
+    assert_eq!(ClientIp(Some(proxy)).behind_proxies(&headers, &[proxy]).0, Some(client));
+    headers.insert("x-forwarded-for", HeaderValue::from_static("1.2.3.4, 192.168.0.1, 10.0.0.1"));
+    assert_eq!(ClientIp(Some(proxy)).behind_proxies(&headers, &[proxy]).0, Some(client));
+}
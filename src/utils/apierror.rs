@@ -106,8 +106,14 @@ pub fn error_not_found() -> ApiError {
 #[must_use]
 pub fn error_conflict() -> ApiError {
     ApiError::new(
-        408,
+        409,
         "The request could not be processed because of conflict in the current state of the resource.",
         None,
     )
 }
+
+/// Error when the client sent too many requests in a given amount of time
+#[must_use]
+pub fn error_too_many_requests() -> ApiError {
+    ApiError::new(429, "Too many requests, retry later.", None)
+}
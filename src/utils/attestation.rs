@@ -0,0 +1,168 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Utility APIs for signing attestations with the registry key, using DSSE envelopes
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use log::info;
+use ring::rand::SystemRandom;
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+use super::apierror::{ApiError, error_backend_failure, error_invalid_request, specialize};
+use super::hashes::sha256;
+use crate::model::attestation::{
+    ATTESTATION_ALGORITHM, AttestationEnvelope, AttestationPublicKey, AttestationSignature, AttestationStatement,
+    IN_TOTO_PAYLOAD_TYPE,
+};
+
+/// The label for PEM private keys in the PKCS#8 format
+const PEM_LABEL_PRIVATE_KEY: &str = "PRIVATE KEY";
+/// The label for PEM public keys in the SPKI format
+const PEM_LABEL_PUBLIC_KEY: &str = "PUBLIC KEY";
+/// The DER prefix of a SPKI structure for an Ed25519 public key, followed by the 32 bytes of the key
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+/// The signer of attestations, using the registry key
+pub struct AttestationSigner {
+    /// The key pair
+    key_pair: Ed25519KeyPair,
+    /// The identifier of the key
+    key_id: String,
+}
+
+impl AttestationSigner {
+    /// Loads the registry key from a file in the PEM format (PKCS#8)
+    /// When the file does not exist, a new key is generated and written to it, readable only by the current user
+    /// A key file that other users can access is refused
+    pub async fn load(path: &Path) -> Result<Self, ApiError> {
+        if tokio::fs::metadata(path).await.is_err() {
+            info!("attestation key file is inaccessible => generate a new key");
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| {
+                specialize(
+                    error_backend_failure(),
+                    String::from("failed to generate the attestation key"),
+                )
+            })?;
+            let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(path).await?;
+            file.write_all(to_pem(PEM_LABEL_PRIVATE_KEY, pkcs8.as_ref()).as_bytes())
+                .await?;
+            file.sync_all().await?;
+        }
+        let mode = tokio::fs::metadata(path).await?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(specialize(
+                error_backend_failure(),
+                format!(
+                    "the attestation key file {} must only be accessible by its owner, found mode {:o}",
+                    path.display(),
+                    mode & 0o777
+                ),
+            ));
+        }
+        let content = tokio::fs::read_to_string(path).await?;
+        Self::from_pem(&content)
+    }
+
+    /// Builds the signer from a key in the PEM format (PKCS#8)
+    pub fn from_pem(content: &str) -> Result<Self, ApiError> {
+        let der = from_pem(PEM_LABEL_PRIVATE_KEY, content)?;
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der)
+            .map_err(|e| specialize(error_invalid_request(), format!("invalid attestation key: {e}")))?;
+        let key_id = sha256(key_pair.public_key().as_ref());
+        Ok(Self { key_pair, key_id })
+    }
+
+    /// Gets the public key to verify the attestations
+    #[must_use]
+    pub fn public_key(&self) -> AttestationPublicKey {
+        let mut spki = ED25519_SPKI_PREFIX.to_vec();
+        spki.extend_from_slice(self.key_pair.public_key().as_ref());
+        AttestationPublicKey {
+            key_id: self.key_id.clone(),
+            algorithm: ATTESTATION_ALGORITHM.to_string(),
+            public_key: to_pem(PEM_LABEL_PUBLIC_KEY, &spki),
+        }
+    }
+
+    /// Signs a statement and wraps it into an envelope
+    pub fn sign(&self, statement: &AttestationStatement) -> Result<AttestationEnvelope, ApiError> {
+        let payload = serde_json::to_vec(statement)?;
+        let signature = self.key_pair.sign(&pre_auth_encoding(IN_TOTO_PAYLOAD_TYPE, &payload));
+        Ok(AttestationEnvelope {
+            payload_type: IN_TOTO_PAYLOAD_TYPE.to_string(),
+            payload: STANDARD.encode(&payload),
+            signatures: vec![AttestationSignature {
+                keyid: self.key_id.clone(),
+                sig: STANDARD.encode(signature.as_ref()),
+            }],
+        })
+    }
+}
+
+/// Verifies the signature of an envelope against a public key and gets the signed statement
+pub fn verify_envelope(
+    envelope: &AttestationEnvelope,
+    public_key: &AttestationPublicKey,
+) -> Result<AttestationStatement, ApiError> {
+    let spki = from_pem(PEM_LABEL_PUBLIC_KEY, &public_key.public_key)?;
+    let raw_key = spki
+        .strip_prefix(&ED25519_SPKI_PREFIX)
+        .ok_or_else(|| specialize(error_invalid_request(), String::from("expected an Ed25519 public key")))?;
+    let signature = envelope
+        .signatures
+        .iter()
+        .find(|signature| signature.keyid == public_key.key_id)
+        .ok_or_else(|| specialize(error_invalid_request(), String::from("no signature for this key")))?;
+    let payload = STANDARD.decode(&envelope.payload)?;
+    let sig = STANDARD.decode(&signature.sig)?;
+    UnparsedPublicKey::new(&ED25519, raw_key)
+        .verify(&pre_auth_encoding(&envelope.payload_type, &payload), &sig)
+        .map_err(|_| specialize(error_invalid_request(), String::from("invalid signature")))?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// Computes the pre-authentication encoding (PAE) of DSSE, the message that is actually signed
+fn pre_auth_encoding(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut result = format!("DSSEv1 {} {payload_type} {} ", payload_type.len(), payload.len()).into_bytes();
+    result.extend_from_slice(payload);
+    result
+}
+
+/// Encodes DER data in the PEM format
+fn to_pem(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let mut result = format!("-----BEGIN {label}-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        result.push_str(&String::from_utf8_lossy(line));
+        result.push('\n');
+    }
+    result.push_str("-----END ");
+    result.push_str(label);
+    result.push_str("-----\n");
+    result
+}
+
+/// Decodes DER data from the PEM format
+fn from_pem(label: &str, content: &str) -> Result<Vec<u8>, ApiError> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    let start = content
+        .find(&begin)
+        .ok_or_else(|| specialize(error_invalid_request(), format!("expected a PEM {label}")))?
+        + begin.len();
+    let length = content[start..]
+        .find(&end)
+        .ok_or_else(|| specialize(error_invalid_request(), format!("expected a PEM {label}")))?;
+    let encoded = content[start..start + length]
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    Ok(STANDARD.decode(encoded)?)
+}
@@ -5,6 +5,7 @@
 //! Authentication management
 
 use std::borrow::Cow;
+use std::net::IpAddr;
 use std::sync::Arc;
 
 use axum::RequestPartsExt;
@@ -15,9 +16,10 @@ use base64::prelude::BASE64_STANDARD;
 use cookie::time::OffsetDateTime;
 use cookie::{Cookie, CookieJar, Expiration, Key, SameSite};
 
-use super::extractors::Cookies;
+use super::extractors::{ClientIp, Cookies};
 use crate::model::auth::Authentication;
 use crate::utils::apierror::ApiError;
+use crate::utils::paseto::PasetoToken;
 
 /// An authentication token
 #[derive(Debug, Clone)]
@@ -66,6 +68,11 @@ pub trait AxumStateForCookies {
 
     /// Gets the cookie key
     fn get_cookie_key(&self) -> &Key;
+
+    /// Gets the IPs of the trusted reverse proxies that set the `X-Forwarded-For` header
+    fn get_trusted_proxies(&self) -> &[IpAddr] {
+        &[]
+    }
 }
 
 /// Authentication data for a request
@@ -80,6 +87,12 @@ pub struct AuthData {
     pub cookie_jar: CookieJar,
     /// The authentication token, if any
     pub token: Option<Token>,
+    /// The asymmetric token (PASETO), if any
+    pub paseto: Option<PasetoToken>,
+    /// The bearer token, if any
+    pub bearer: Option<String>,
+    /// The IP of the client, if known
+    pub client_ip: Option<IpAddr>,
 }
 
 impl Default for AuthData {
@@ -90,6 +103,9 @@ impl Default for AuthData {
             cookie_key: Key::from(&[0; 64]),
             cookie_jar: CookieJar::default(),
             token: None,
+            paseto: None,
+            bearer: None,
+            client_ip: None,
         }
     }
 }
@@ -102,6 +118,18 @@ impl From<Token> for AuthData {
             cookie_key: Key::from(&[0; 64]),
             cookie_jar: CookieJar::default(),
             token: Some(token),
+            paseto: None,
+            bearer: None,
+            client_ip: None,
+        }
+    }
+}
+
+impl From<PasetoToken> for AuthData {
+    fn from(paseto: PasetoToken) -> Self {
+        Self {
+            paseto: Some(paseto),
+            ..Default::default()
         }
     }
 }
@@ -115,16 +143,26 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &Arc<S>) -> Result<Self, Self::Rejection> {
         let cookie_key = state.get_cookie_key().clone();
         let cookie_jar = parts.extract::<Cookies>().await?.0;
-        let token = parts
-            .headers
-            .get("authorization")
-            .and_then(|header| header.to_str().ok().and_then(Token::try_parse));
+        let client_ip = parts
+            .extract::<ClientIp>()
+            .await?
+            .behind_proxies(&parts.headers, state.get_trusted_proxies())
+            .0;
+        let header = parts.headers.get("authorization").and_then(|header| header.to_str().ok());
+        let token = header.and_then(Token::try_parse);
+        let paseto = header.and_then(PasetoToken::try_parse);
+        let bearer = header
+            .and_then(|header| header.trim().strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
         Ok(Self {
             cookie_domain: state.get_domain(),
             cookie_id_name: state.get_id_cookie_name(),
             cookie_key,
             cookie_jar,
             token,
+            paseto,
+            bearer,
+            client_ip,
         })
     }
 }
@@ -191,6 +229,37 @@ impl AuthData {
         self.create_expired_cookie(&self.cookie_id_name.clone(), true)
     }
 
+    /// Gets the value of a private cookie, if any
+    #[must_use]
+    pub fn get_private_cookie(&self, name: &str) -> Option<String> {
+        self.cookie_jar
+            .private(&self.cookie_key)
+            .get(name)
+            .map(|cookie| cookie.value().to_string())
+    }
+
+    /// Gets the keys identifying the client for rate limiting before its authentication, i.e. its IP
+    /// The login it claims is not a key until verified, so that a client cannot exhaust the limit of another login
+    #[must_use]
+    pub fn rate_limit_keys(&self) -> Vec<String> {
+        self.client_ip
+            .map(|client_ip| format!("ip:{client_ip}"))
+            .into_iter()
+            .collect()
+    }
+
+    /// Gets the keys identifying the client for the failed authentications, i.e. its IP and the login it claims
+    #[must_use]
+    pub fn auth_failure_keys(&self) -> Vec<String> {
+        let mut keys = self.rate_limit_keys();
+        if let Some(token) = &self.token {
+            keys.push(format!("login:{}", token.id));
+        } else if let Some(footer) = self.paseto.as_ref().and_then(|paseto| paseto.footer().ok()) {
+            keys.push(format!("key:{}", footer.kip));
+        }
+        keys
+    }
+
     /// Try to authenticate this request
     ///
     /// # Errors
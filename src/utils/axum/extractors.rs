@@ -10,6 +10,7 @@ use std::ops::{Deref, DerefMut};
 
 use axum::RequestPartsExt;
 use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::HeaderMap;
 use axum::http::request::Parts;
 use base64::Engine;
 use base64::prelude::BASE64_URL_SAFE;
@@ -28,12 +29,6 @@ where
     type Rejection = ();
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        if let Some(forwarded) = parts.headers.get("x-forwarded-for")
-            && let Ok(forwarded) = forwarded.to_str()
-            && let Some(Ok(client_ip)) = forwarded.split(',').next().map(str::trim).map(str::parse)
-        {
-            return Ok(Self(Some(client_ip)));
-        }
         match parts.extract::<ConnectInfo<SocketAddr>>().await {
             Ok(ConnectInfo(addr)) => Ok(Self(Some(addr.ip()))),
             Err(_) => Ok(Self(None)),
@@ -41,6 +36,36 @@ where
     }
 }
 
+impl ClientIp {
+    /// Gets the IP of the client behind trusted reverse proxies, using the `X-Forwarded-For` header
+    /// The header is only used when the peer is a trusted proxy, the client is the last address that is not one
+    #[must_use]
+    pub fn behind_proxies(self, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> Self {
+        let Some(mut client_ip) = self.0 else {
+            return self;
+        };
+        if !trusted_proxies.contains(&client_ip) {
+            return self;
+        }
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        for address in forwarded.rsplit(',').map(str::trim) {
+            let Ok(address) = address.parse::<IpAddr>() else {
+                break;
+            };
+            client_ip = address;
+            if !trusted_proxies.contains(&address) {
+                break;
+            }
+        }
+        Self(Some(client_ip))
+    }
+}
+
 impl fmt::Display for ClientIp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.0 {
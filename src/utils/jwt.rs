@@ -0,0 +1,249 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Utility APIs for the verification of JSON Web Tokens (JWT) against a JSON Web Key Set (JWKS)
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use data_encoding::BASE64URL_NOPAD;
+use ring::signature::{ECDSA_P256_SHA256_FIXED, RSA_PKCS1_2048_8192_SHA256, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde_derive::Deserialize;
+
+use super::apierror::{ApiError, error_backend_failure, error_unauthorized, specialize};
+
+/// The allowed clock skew when checking the validity of a token, in seconds
+const MAX_CLOCK_SKEW: i64 = 60;
+
+/// The duration for which a loaded JWKS is reused
+const JWKS_CACHE_DURATION: Duration = Duration::from_mins(10);
+
+/// The header of a JWT
+#[derive(Debug, Clone, Deserialize)]
+struct JwtHeader {
+    /// The signature algorithm
+    alg: String,
+    /// The identifier of the signing key, if any
+    kid: Option<String>,
+}
+
+/// A set of JSON web keys
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwks {
+    /// The keys
+    pub keys: Vec<Jwk>,
+}
+
+/// A JSON web key
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    /// The key type (`RSA` or `EC`)
+    pub kty: String,
+    /// The key identifier
+    pub kid: Option<String>,
+    /// The RSA modulus
+    pub n: Option<String>,
+    /// The RSA exponent
+    pub e: Option<String>,
+    /// The elliptic curve
+    pub crv: Option<String>,
+    /// The x coordinate of an elliptic curve point
+    pub x: Option<String>,
+    /// The y coordinate of an elliptic curve point
+    pub y: Option<String>,
+}
+
+/// A JWT, not yet verified
+#[derive(Debug, Clone)]
+pub struct Jwt<'a> {
+    /// The header
+    header: JwtHeader,
+    /// The signed input (header and payload)
+    signed: &'a str,
+    /// The decoded payload
+    payload: Vec<u8>,
+    /// The decoded signature
+    signature: Vec<u8>,
+}
+
+impl<'a> Jwt<'a> {
+    /// Parses a JWT in the compact serialization
+    pub fn parse(input: &'a str) -> Result<Self, ApiError> {
+        let invalid = || specialize(error_unauthorized(), String::from("malformed JWT"));
+        let input = input.trim();
+        let (signed, signature) = input.rsplit_once('.').ok_or_else(invalid)?;
+        let (header, payload) = signed.split_once('.').ok_or_else(invalid)?;
+        let header = BASE64URL_NOPAD.decode(header.as_bytes()).map_err(|_| invalid())?;
+        let header = serde_json::from_slice::<JwtHeader>(&header).map_err(|_| invalid())?;
+        let payload = BASE64URL_NOPAD.decode(payload.as_bytes()).map_err(|_| invalid())?;
+        let signature = BASE64URL_NOPAD.decode(signature.as_bytes()).map_err(|_| invalid())?;
+        Ok(Self {
+            header,
+            signed,
+            payload,
+            signature,
+        })
+    }
+
+    /// Gets the claims, without verification
+    pub fn unverified_claims(&self) -> Result<serde_json::Value, ApiError> {
+        serde_json::from_slice(&self.payload)
+            .map_err(|_| specialize(error_unauthorized(), String::from("malformed claims in JWT")))
+    }
+
+    /// Verifies the signature of this token against a set of keys and gets the claims
+    pub fn verify(&self, jwks: &Jwks) -> Result<serde_json::Value, ApiError> {
+        let verified = jwks
+            .keys
+            .iter()
+            .filter(|key| self.header.kid.is_none() || key.kid == self.header.kid)
+            .any(|key| self.verify_with(key));
+        if verified {
+            self.unverified_claims()
+        } else {
+            Err(specialize(
+                error_unauthorized(),
+                String::from("invalid signature for the JWT"),
+            ))
+        }
+    }
+
+    /// Verifies the signature of this token with a key
+    fn verify_with(&self, key: &Jwk) -> bool {
+        let decode = |value: Option<&String>| value.and_then(|value| BASE64URL_NOPAD.decode(value.as_bytes()).ok());
+        match (self.header.alg.as_str(), key.kty.as_str()) {
+            ("RS256", "RSA") => {
+                let (Some(n), Some(e)) = (decode(key.n.as_ref()), decode(key.e.as_ref())) else {
+                    return false;
+                };
+                RsaPublicKeyComponents { n, e }
+                    .verify(&RSA_PKCS1_2048_8192_SHA256, self.signed.as_bytes(), &self.signature)
+                    .is_ok()
+            }
+            ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+                let (Some(x), Some(y)) = (decode(key.x.as_ref()), decode(key.y.as_ref())) else {
+                    return false;
+                };
+                let mut point = vec![0x04];
+                point.extend_from_slice(&x);
+                point.extend_from_slice(&y);
+                UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+                    .verify(self.signed.as_bytes(), &self.signature)
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Loads a JWKS from a location, either a local file or an HTTP(S) URL
+pub async fn load_jwks(location: &str) -> Result<Jwks, ApiError> {
+    let content = if location.starts_with("https://") || location.starts_with("http://") {
+        let response = reqwest::get(location).await?;
+        if !response.status().is_success() {
+            return Err(specialize(
+                error_backend_failure(),
+                format!("failed to fetch the JWKS at {location}"),
+            ));
+        }
+        response.bytes().await?.to_vec()
+    } else {
+        tokio::fs::read(location.strip_prefix("file://").unwrap_or(location)).await?
+    };
+    Ok(serde_json::from_slice(&content)?)
+}
+
+/// A cache of the JWKS per issuer, so that they are not loaded again for each token
+#[derive(Debug, Default)]
+pub struct JwksCache {
+    /// The loaded JWKS per issuer and location
+    entries: Mutex<HashMap<(String, String), JwksCacheEntry>>,
+}
+
+/// A JWKS in cache
+#[derive(Debug)]
+struct JwksCacheEntry {
+    /// The time the JWKS was loaded
+    loaded: Instant,
+    /// The JWKS
+    jwks: Arc<Jwks>,
+}
+
+impl JwksCache {
+    /// Gets the JWKS for an issuer at a location, loading it when not in cache or outdated
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the JWKS cannot be loaded
+    ///
+    /// # Panics
+    ///
+    /// Panic when the lock is poisoned
+    pub async fn get(&self, issuer: &str, location: &str) -> Result<Arc<Jwks>, ApiError> {
+        let key = (issuer.to_string(), location.to_string());
+        if let Some(entry) = self.entries.lock().unwrap().get(&key)
+            && entry.loaded.elapsed() < JWKS_CACHE_DURATION
+        {
+            return Ok(entry.jwks.clone());
+        }
+        let jwks = Arc::new(load_jwks(location).await?);
+        self.entries.lock().unwrap().insert(
+            key,
+            JwksCacheEntry {
+                loaded: Instant::now(),
+                jwks: jwks.clone(),
+            },
+        );
+        Ok(jwks)
+    }
+}
+
+/// Checks the registered claims of a verified token: issuer, audience and validity period
+pub fn check_registered_claims(claims: &serde_json::Value, issuer: &str, audience: &str, now: i64) -> Result<(), ApiError> {
+    if claims.get("iss").and_then(serde_json::Value::as_str) != Some(issuer) {
+        return Err(specialize(
+            error_unauthorized(),
+            String::from("unexpected issuer for the JWT"),
+        ));
+    }
+    let audience_matches = match claims.get("aud") {
+        Some(serde_json::Value::String(value)) => value == audience,
+        Some(serde_json::Value::Array(values)) => values.iter().any(|value| value.as_str() == Some(audience)),
+        _ => false,
+    };
+    if !audience_matches {
+        return Err(specialize(
+            error_unauthorized(),
+            String::from("unexpected audience for the JWT"),
+        ));
+    }
+    let expiration = claims.get("exp").and_then(serde_json::Value::as_i64).unwrap_or(0);
+    if expiration + MAX_CLOCK_SKEW < now {
+        return Err(specialize(error_unauthorized(), String::from("the JWT has expired")));
+    }
+    let not_before = claims.get("nbf").and_then(serde_json::Value::as_i64).unwrap_or(0);
+    if not_before - MAX_CLOCK_SKEW > now {
+        return Err(specialize(error_unauthorized(), String::from("the JWT is not yet valid")));
+    }
+    Ok(())
+}
+
+/// Checks the claims of an OIDC id token, including the nonce sent in the authentication request
+pub fn check_id_token_claims(
+    claims: &serde_json::Value,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<(), ApiError> {
+    check_registered_claims(claims, issuer, client_id, now)?;
+    if claims.get("nonce").and_then(serde_json::Value::as_str) != Some(nonce) {
+        return Err(specialize(
+            error_unauthorized(),
+            String::from("unexpected nonce for the id token"),
+        ));
+    }
+    Ok(())
+}
@@ -14,10 +14,14 @@ use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 
 pub mod apierror;
+pub mod attestation;
 pub mod axum;
 pub mod concurrent;
 pub mod db;
 pub mod hashes;
+pub mod jwt;
+pub mod paseto;
+pub mod ratelimit;
 pub mod shared;
 pub mod sigterm;
 pub mod token;
@@ -41,7 +45,7 @@ where
 #[must_use]
 pub fn stale_instant() -> Instant {
     let now = Instant::now();
-    now.checked_sub(Duration::from_secs(60 * 60 * 24 * 7)).unwrap()
+    now.checked_sub(Duration::from_hours(24 * 7)).unwrap()
 }
 
 /// Execute a git command
@@ -57,6 +61,7 @@ pub async fn execute_at_location(location: &Path, command: &str, args: &[&str],
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
+        .kill_on_drop(true)
         .spawn()?;
     child.stdin.as_mut().unwrap().write_all(input).await?;
     let output = child.wait_with_output().await?;
@@ -0,0 +1,204 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Utility APIs for cargo asymmetric tokens, using PASETO v3.public (RFC 3231)
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta};
+use data_encoding::BASE64URL_NOPAD;
+use p384::ecdsa::signature::Verifier;
+use p384::ecdsa::{Signature, VerifyingKey};
+use ring::digest::{SHA384, digest};
+use serde_derive::Deserialize;
+
+use super::apierror::{ApiError, error_invalid_request, error_unauthorized, specialize};
+
+/// The header for PASETO v3.public tokens
+pub const PASETO_V3_PUBLIC_HEADER: &str = "v3.public.";
+/// The header for PASERK public keys for version 3
+const PASERK_V3_PUBLIC_HEADER: &str = "k3.public.";
+/// The header for PASERK public key identifiers for version 3
+const PASERK_V3_PID_HEADER: &str = "k3.pid.";
+/// The length of a signature (r || s for P-384)
+const SIGNATURE_LENGTH: usize = 96;
+/// The length of a compressed P-384 public key
+const COMPRESSED_KEY_LENGTH: usize = 49;
+/// The length of the hash used for key identifiers
+const PID_HASH_LENGTH: usize = 33;
+/// The maximum difference between the issue time of a token and now, in minutes
+const MAX_CLOCK_SKEW: i64 = 15;
+
+/// A PASETO v3.public token, not yet verified
+#[derive(Debug, Clone)]
+pub struct PasetoToken {
+    /// The signed message
+    message: Vec<u8>,
+    /// The signature for the message
+    signature: Vec<u8>,
+    /// The raw footer
+    footer: Vec<u8>,
+}
+
+/// The footer of an asymmetric token produced by cargo
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasetoFooter {
+    /// The url of the registry the token is intended for
+    pub url: String,
+    /// The PASERK identifier of the public key
+    pub kip: String,
+}
+
+/// The claims in an asymmetric token produced by cargo
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasetoClaims {
+    /// The time the token was issued, in the RFC 3339 format
+    pub iat: String,
+    /// The subject, only provided when logging in
+    pub sub: Option<String>,
+    /// The mutation being performed, if any
+    pub mutation: Option<String>,
+    /// The name of the crate for the mutation
+    pub name: Option<String>,
+    /// The version of the crate for the mutation
+    pub vers: Option<String>,
+    /// The checksum of the crate for a publish mutation
+    pub cksum: Option<String>,
+    /// The challenge the registry may have issued
+    pub challenge: Option<String>,
+    /// The version of the claims
+    pub v: Option<u8>,
+}
+
+/// A mutation expected by the registry, to be matched against the claims in an asymmetric token
+#[derive(Debug, Clone, Copy)]
+pub struct PasetoMutation<'a> {
+    /// The mutation, e.g. `publish`, `yank`, `unyank` or `owners`
+    pub mutation: &'a str,
+    /// The name of the crate
+    pub name: &'a str,
+    /// The version of the crate, if relevant
+    pub vers: Option<&'a str>,
+    /// The checksum of the crate, if relevant
+    pub cksum: Option<&'a str>,
+}
+
+impl PasetoToken {
+    /// Try to parse a token, expecting a PASETO v3.public token with a footer
+    #[must_use]
+    pub fn try_parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let rest = input.strip_prefix(PASETO_V3_PUBLIC_HEADER)?;
+        let (payload, footer) = rest.split_once('.')?;
+        let payload = BASE64URL_NOPAD.decode(payload.as_bytes()).ok()?;
+        let footer = BASE64URL_NOPAD.decode(footer.as_bytes()).ok()?;
+        if payload.len() <= SIGNATURE_LENGTH {
+            return None;
+        }
+        let (message, signature) = payload.split_at(payload.len() - SIGNATURE_LENGTH);
+        Some(Self {
+            message: message.to_vec(),
+            signature: signature.to_vec(),
+            footer,
+        })
+    }
+
+    /// Gets the footer for this token
+    pub fn footer(&self) -> Result<PasetoFooter, ApiError> {
+        serde_json::from_slice(&self.footer)
+            .map_err(|_| specialize(error_unauthorized(), String::from("invalid footer for the asymmetric token")))
+    }
+
+    /// Verifies the signature of this token with the public key in the PASERK format and gets the claims
+    pub fn verify(&self, public_key: &str) -> Result<PasetoClaims, ApiError> {
+        let key_bytes = parse_public_key(public_key)?;
+        let key = VerifyingKey::from_sec1_bytes(&key_bytes).map_err(|_| error_unauthorized())?;
+        let signature = Signature::from_slice(&self.signature).map_err(|_| error_unauthorized())?;
+        let pre_auth = pre_auth_encode(&[
+            &key_bytes,
+            PASETO_V3_PUBLIC_HEADER.as_bytes(),
+            &self.message,
+            &self.footer,
+            b"",
+        ]);
+        key.verify(&pre_auth, &signature).map_err(|_| {
+            specialize(
+                error_unauthorized(),
+                String::from("invalid signature for the asymmetric token"),
+            )
+        })?;
+        serde_json::from_slice(&self.message)
+            .map_err(|_| specialize(error_unauthorized(), String::from("invalid claims for the asymmetric token")))
+    }
+}
+
+impl PasetoClaims {
+    /// Checks the claims against the current UTC time and the expected mutation, if any
+    /// When no mutation is expected, the token must not claim one
+    pub fn check(&self, now: NaiveDateTime, expected: Option<&PasetoMutation<'_>>) -> Result<(), ApiError> {
+        let issued_at = DateTime::parse_from_rfc3339(&self.iat)
+            .map_err(|_| {
+                specialize(
+                    error_unauthorized(),
+                    String::from("invalid issue time for the asymmetric token"),
+                )
+            })?
+            .naive_utc();
+        if (now - issued_at).abs() > TimeDelta::minutes(MAX_CLOCK_SKEW) {
+            return Err(specialize(
+                error_unauthorized(),
+                String::from("the asymmetric token was not issued recently"),
+            ));
+        }
+        let matches = expected.map_or_else(
+            || self.mutation.is_none(),
+            |expected| {
+                self.mutation.as_deref() == Some(expected.mutation)
+                    && self.name.as_deref() == Some(expected.name)
+                    && self.vers.as_deref() == expected.vers
+                    && (expected.cksum.is_none() || self.cksum.as_deref() == expected.cksum)
+            },
+        );
+        if matches {
+            Ok(())
+        } else {
+            Err(specialize(
+                error_unauthorized(),
+                String::from("the asymmetric token is not valid for this operation"),
+            ))
+        }
+    }
+}
+
+/// Parses a public key in the PASERK format (`k3.public.xxx`) and gets the compressed point
+pub fn parse_public_key(paserk: &str) -> Result<Vec<u8>, ApiError> {
+    let invalid = || specialize(error_invalid_request(), String::from("expected a PASERK k3.public key"));
+    let data = paserk.trim().strip_prefix(PASERK_V3_PUBLIC_HEADER).ok_or_else(invalid)?;
+    let bytes = BASE64URL_NOPAD.decode(data.as_bytes()).map_err(|_| invalid())?;
+    if bytes.len() != COMPRESSED_KEY_LENGTH || VerifyingKey::from_sec1_bytes(&bytes).is_err() {
+        return Err(invalid());
+    }
+    Ok(bytes)
+}
+
+/// Gets the PASERK identifier (`k3.pid.xxx`) for a public key in the PASERK format
+#[must_use]
+pub fn get_public_key_id(paserk: &str) -> String {
+    let mut data = String::from(PASERK_V3_PID_HEADER);
+    data.push_str(paserk.trim());
+    let hash = digest(&SHA384, data.as_bytes());
+    format!(
+        "{PASERK_V3_PID_HEADER}{}",
+        BASE64URL_NOPAD.encode(&hash.as_ref()[..PID_HASH_LENGTH])
+    )
+}
+
+/// Pre-authentication encoding (PAE) of multiple pieces
+fn pre_auth_encode(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(8 + pieces.iter().map(|piece| piece.len() + 8).sum::<usize>());
+    result.extend_from_slice(&(pieces.len() as u64).to_le_bytes());
+    for piece in pieces {
+        result.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+        result.extend_from_slice(piece);
+    }
+    result
+}
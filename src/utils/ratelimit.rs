@@ -0,0 +1,109 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Utility to limit the rate of events using sliding windows
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The number of tracked keys above which the keys without recent events are purged
+const PURGE_THRESHOLD: usize = 1024;
+
+/// A limiter of the rate of events per key (e.g. a client IP or a login), within a sliding window
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The maximum number of events within the window, zero to deactivate the limiter
+    max_events: usize,
+    /// The duration of the sliding window
+    window: Duration,
+    /// The times of the recent events for each key
+    events: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    /// Creates a new limiter
+    #[must_use]
+    pub fn new(max_events: usize, window: Duration) -> Self {
+        Self {
+            max_events,
+            window,
+            events: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks whether new events are allowed for all the keys
+    /// When the limit is reached for a key, returns the duration after which a new event will be allowed
+    ///
+    /// # Errors
+    ///
+    /// Returns the duration to wait before retrying when the limit is reached
+    ///
+    /// # Panics
+    ///
+    /// Panic when the lock is poisoned
+    pub fn check<K: AsRef<str>>(&self, keys: &[K]) -> Result<(), Duration> {
+        if self.max_events == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut events = self.events.lock().unwrap();
+        let mut retry_after = None;
+        for key in keys {
+            if let Some(times) = events.get_mut(key.as_ref()) {
+                self.prune(times, now);
+                if times.len() >= self.max_events
+                    && let Some(oldest) = times.front()
+                {
+                    let wait = self.window.saturating_sub(now.duration_since(*oldest));
+                    retry_after = Some(retry_after.map_or(wait, |current: Duration| current.max(wait)));
+                }
+            }
+        }
+        retry_after.map_or(Ok(()), Err)
+    }
+
+    /// Records an event for all the keys
+    ///
+    /// # Panics
+    ///
+    /// Panic when the lock is poisoned
+    pub fn record<K: AsRef<str>>(&self, keys: &[K]) {
+        if self.max_events == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= PURGE_THRESHOLD {
+            events.retain(|_, times| {
+                self.prune(times, now);
+                !times.is_empty()
+            });
+        }
+        for key in keys {
+            let times = events.entry(key.as_ref().to_string()).or_default();
+            self.prune(times, now);
+            times.push_back(now);
+        }
+        drop(events);
+    }
+
+    /// Checks whether a new event is allowed for all the keys and records it if so
+    ///
+    /// # Errors
+    ///
+    /// Returns the duration to wait before retrying when the limit is reached
+    pub fn hit<K: AsRef<str>>(&self, keys: &[K]) -> Result<(), Duration> {
+        self.check(keys)?;
+        self.record(keys);
+        Ok(())
+    }
+
+    /// Removes the events that are outside the window
+    fn prune(&self, times: &mut VecDeque<Instant>, now: Instant) {
+        while times.front().is_some_and(|time| now.duration_since(*time) >= self.window) {
+            times.pop_front();
+        }
+    }
+}
@@ -5,7 +5,7 @@
 //! Utility APIs for token generation and management
 
 use base64::Engine;
-use base64::engine::general_purpose::STANDARD;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
 use data_encoding::HEXLOWER;
 use rand::Rng;
 use rand::distr::StandardUniform;
@@ -25,6 +25,18 @@ pub fn generate_token(length: usize) -> String {
     STANDARD.encode(&bytes)
 }
 
+/// Generates a token that can be used as is in URLs
+#[must_use]
+pub fn generate_url_safe_token(length: usize) -> String {
+    let bytes_count = length * 3 / 4;
+    let rng = rand::rng();
+    let bytes = rng
+        .sample_iter::<u8, _>(StandardUniform)
+        .take(bytes_count)
+        .collect::<Vec<_>>();
+    URL_SAFE_NO_PAD.encode(&bytes)
+}
+
 /// Computes the SHA256 digest of bytes
 #[must_use]
 pub fn sha256(buffer: &[u8]) -> String {
@@ -21,10 +21,11 @@ async function apiMe() {
   return await onResponseJson(response);
 }
 
-async function apiOAuthLoginWithCode(code) {
+async function apiOAuthLoginWithCode(code, state) {
   const response = await fetch("/api/v1/oauth/code", {
     method: "POST",
-    body: code,
+    headers: [["content-type", "application/json"]],
+    body: JSON.stringify({ code, state }),
   });
   return await onResponseJson(response);
 }
@@ -41,9 +42,10 @@ async function apiGetUserTokens() {
   return await onResponseJson(response);
 }
 
-async function apiCreateUserToken(name, canWrite, canAdmin) {
+async function apiCreateUserToken(name, canWrite, canAdmin, crates, operations, expiresOn) {
+  const expiry = expiresOn === null ? "" : `&expiresOn=${encodeURIComponent(expiresOn)}`;
   const response = await fetch(
-    `/api/v1/me/tokens?canWrite=${canWrite}&canAdmin=${canAdmin}`,
+    `/api/v1/me/tokens?canWrite=${canWrite}&canAdmin=${canAdmin}&crates=${encodeURIComponent(crates.join(","))}&operations=${encodeURIComponent(operations.join(","))}${expiry}`,
     {
       method: "PUT",
       body: name,
@@ -59,13 +61,34 @@ async function apiRevokeUserToken(token_id) {
   return await onResponseJson(response);
 }
 
+async function apiGetUserPublicKeys() {
+  const response = await fetch("/api/v1/me/keys");
+  return await onResponseJson(response);
+}
+
+async function apiAddUserPublicKey(name, publicKey) {
+  const response = await fetch(`/api/v1/me/keys?name=${encodeURIComponent(name)}`, {
+    method: "PUT",
+    body: publicKey,
+  });
+  return await onResponseJson(response);
+}
+
+async function apiRemoveUserPublicKey(key_id) {
+  const response = await fetch(`/api/v1/me/keys/${key_id}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
 async function apiGetGlobalTokens() {
   const response = await fetch("/api/v1/admin/tokens");
   return await onResponseJson(response);
 }
 
-async function apiCreateGlobalToken(name) {
-  const response = await fetch("/api/v1/admin/tokens", {
+async function apiCreateGlobalToken(name, expiresOn) {
+  const expiry = expiresOn === null ? "" : `?expiresOn=${encodeURIComponent(expiresOn)}`;
+  const response = await fetch(`/api/v1/admin/tokens${expiry}`, {
     method: "PUT",
     body: name,
   });
@@ -79,6 +102,43 @@ async function apiRevokeGlobalToken(token_id) {
   return await onResponseJson(response);
 }
 
+async function apiGetTeams() {
+  const response = await fetch("/api/v1/admin/teams");
+  return await onResponseJson(response);
+}
+
+async function apiCreateTeam(name) {
+  const response = await fetch(`/api/v1/admin/teams/${encodeURIComponent(name)}`, {
+    method: "PUT",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiDeleteTeam(name) {
+  const response = await fetch(`/api/v1/admin/teams/${encodeURIComponent(name)}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiAddTeamMember(name, email) {
+  const response = await fetch(`/api/v1/admin/teams/${encodeURIComponent(name)}/members`, {
+    method: "PUT",
+    body: JSON.stringify({ users: [email] }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiRemoveTeamMember(name, email) {
+  const response = await fetch(`/api/v1/admin/teams/${encodeURIComponent(name)}/members`, {
+    method: "DELETE",
+    body: JSON.stringify({ users: [email] }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
 async function apiGetDocGenJobs() {
   const response = await fetch("/api/v1/admin/jobs/docgen");
   return await onResponseJson(response);
@@ -174,6 +234,30 @@ async function apiGetCrateReadmeAt(crate, version) {
   }
 }
 
+async function apiGetCrateFiles(crate, version) {
+  const response = await fetch(`/api/v1/crates/${crate}/${version}/files`);
+  return await onResponseJson(response);
+}
+
+async function apiGetCrateFile(crate, version, path) {
+  const response = await fetch(`/api/v1/crates/${crate}/${version}/files/${path}`);
+  if (response.status !== 200) {
+    throw await response.json();
+  } else {
+    return response;
+  }
+}
+
+async function apiGetCrateVersionsDiff(crate, from, to) {
+  const response = await fetch(`/api/v1/crates/${crate}/diff/${from}/${to}`);
+  return await onResponseJson(response);
+}
+
+async function apiGetCrateSemverChecks(crate) {
+  const response = await fetch(`/api/v1/crates/${crate}/semverchecks`);
+  return await onResponseJson(response);
+}
+
 async function apiGetCrateOwners(crate) {
   const response = await fetch(`/api/v1/crates/${crate}/owners`);
   return await onResponseJson(response);
@@ -225,10 +309,10 @@ async function apiSetCrateCapabilities(crate, capabilities) {
   return await onResponseJson(response);
 }
 
-async function apiSetCrateDeprecation(crate, isDeprecated) {
+async function apiSetCrateDeprecation(crate, isDeprecated, message, useInstead) {
   const response = await fetch(`/api/v1/crates/${crate}/deprecated`, {
     method: "PATCH",
-    body: JSON.stringify(isDeprecated),
+    body: JSON.stringify({ isDeprecated, message, useInstead }),
     headers: [["content-type", "application/json"]],
   });
   return await onResponseJson(response);
@@ -243,6 +327,66 @@ async function apiSetCrateCanRemove(crate, canRemove) {
   return await onResponseJson(response);
 }
 
+async function apiSetCrateVisibility(crate, visibility) {
+  const response = await fetch(`/api/v1/crates/${crate}/visibility`, {
+    method: "PATCH",
+    body: JSON.stringify(visibility),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiSetCrateRequiresApproval(crate, requiresApproval) {
+  const response = await fetch(`/api/v1/crates/${crate}/approval`, {
+    method: "PATCH",
+    body: JSON.stringify(requiresApproval),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetCratePendingVersions(crate) {
+  const response = await fetch(`/api/v1/crates/${crate}/pending`);
+  return await onResponseJson(response);
+}
+
+async function apiApproveCrateVersion(crate, version) {
+  const response = await fetch(`/api/v1/crates/${crate}/pending/${version}/approve`, {
+    method: "POST",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiRejectCrateVersion(crate, version) {
+  const response = await fetch(`/api/v1/crates/${crate}/pending/${version}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetCrateReaders(crate) {
+  const response = await fetch(`/api/v1/crates/${crate}/readers`);
+  return await onResponseJson(response);
+}
+
+async function apiAddCrateReader(crate, email) {
+  const response = await fetch(`/api/v1/crates/${crate}/readers`, {
+    method: "PUT",
+    body: JSON.stringify({ users: [email] }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiRemoveCrateReader(crate, email) {
+  const response = await fetch(`/api/v1/crates/${crate}/readers`, {
+    method: "DELETE",
+    body: JSON.stringify({ users: [email] }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
 async function apiRegenCrateDoc(crate, version) {
   const response = await fetch(`/api/v1/crates/${crate}/${version}/docsregen`, {
     method: "POST",
@@ -30,9 +30,12 @@ pub fn get_resources() -> EmbeddedResources {
     add!(resources, "admin.html");
     add!(resources, "admin-users.html");
     add!(resources, "admin-tokens.html");
+    add!(resources, "admin-teams.html");
     add!(resources, "admin-jobs-docgen.html");
     add!(resources, "admin-workers.html");
     add!(resources, "crate.html");
+    add!(resources, "crate-source.html");
+    add!(resources, "crate-diff.html");
     add!(resources, "oauthcallback.html");
     // CSS
     add!(resources, "index.css");
@@ -20,6 +20,7 @@ use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 
 use crate::model::config::{Configuration, ExternalRegistry, NodeRole, NodeRoleWorker};
 use crate::model::docs::{DocGenJobState, DocGenJobUpdate};
+use crate::model::semver_checks::{SemverCheckJob, SemverCheckJobState, SemverCheckJobUpdate};
 use crate::model::worker::{JobSpecification, JobUpdate, WorkerDescriptor};
 use crate::services::{ServiceProvider, StandardServiceProvider};
 use crate::utils::apierror::{ApiError, error_backend_failure, specialize};
@@ -170,7 +171,10 @@ async fn worker_on_job<S>(sender: Arc<Mutex<S>>, job: JobSpecification, config:
 where
     S: Sink<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
 {
-    let JobSpecification::DocGen(job) = job;
+    let job = match job {
+        JobSpecification::DocGen(job) => job,
+        JobSpecification::SemverCheck(job) => return worker_on_semver_check_job(sender, job, config).await,
+    };
     let service_storage = StandardServiceProvider::get_storage(config);
     match crate::services::docs::generate_doc_for_job(config, service_storage, &job).await {
         Ok((state, log)) => {
@@ -208,3 +212,34 @@ where
     }
     Ok(())
 }
+
+/// Executes a semver check job
+async fn worker_on_semver_check_job<S>(
+    sender: Arc<Mutex<S>>,
+    job: SemverCheckJob,
+    config: &Configuration,
+) -> Result<(), ApiError>
+where
+    S: Sink<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+{
+    let service_storage = StandardServiceProvider::get_storage(config);
+    let (state, output) = match crate::services::semver_checks::check_semver_for_job(service_storage, &job).await {
+        Ok(result) => result,
+        Err(error) => (SemverCheckJobState::Failure, format!("{error}")),
+    };
+    let now = Local::now().naive_local();
+    sender
+        .lock()
+        .await
+        .send(Message::Text(
+            serde_json::to_string(&JobUpdate::SemverCheck(SemverCheckJobUpdate {
+                job_id: job.id,
+                state,
+                last_update: now,
+                output: Some(output),
+            }))?
+            .into(),
+        ))
+        .await?;
+    Ok(())
+}